@@ -337,7 +337,7 @@ pub async fn get_pg_conn(
     basedomain: &str,
     spec: &CoreDBSpec,
 ) -> Result<types::ConnectionInfo, ConductorError> {
-    let (postgres_user_secret, app_user_secret) = get_secret_for_db(client, name).await?;
+    let (postgres_user_secret, app_user_secret) = get_secret_for_db(client.clone(), name).await?;
 
     let postgres_data =
         postgres_user_secret
@@ -365,6 +365,17 @@ pub async fn get_pg_conn(
         None
     };
 
+    // Dedicated networking endpoints are written into the status by the operator
+    // once the load balancers are provisioned
+    let coredb_api: Api<CoreDB> = Api::namespaced(client, name);
+    let dedicated_endpoints = coredb_api
+        .get_opt(name)
+        .await?
+        .and_then(|cdb| cdb.status)
+        .and_then(|status| status.dedicated_networking)
+        .map(|dn| dn.endpoints)
+        .unwrap_or_default();
+
     // Create ConnectionInfo for the postgres user
     // The user and password are base64 encoded when passed back to the control-plane
     let postgres_conn = types::ConnectionInfo {
@@ -375,6 +386,7 @@ pub async fn get_pg_conn(
         password: postgres_pw,
         app_user,
         app_password: app_pw,
+        dedicated_endpoints,
     };
 
     Ok(postgres_conn)
@@ -246,6 +246,7 @@ async fn run(metrics: CustomMetrics) -> Result<(), ConductorError> {
                 spec: None,
                 status: None,
                 connection: None,
+                app_services: vec![],
             };
             let msg_id = queue.send(&data_plane_events_queue, &error_event).await?;
             error!(
@@ -479,6 +480,7 @@ async fn run(metrics: CustomMetrics) -> Result<(), ConductorError> {
                     org_id: read_msg.message.org_id,
                     inst_id: read_msg.message.inst_id,
                     event_type: report_event,
+                    app_services: types::app_services_status(current_spec.status.as_ref()),
                     spec: Some(current_spec.spec),
                     status: current_spec.status,
                     connection: Some(conn_info),
@@ -536,6 +538,7 @@ async fn run(metrics: CustomMetrics) -> Result<(), ConductorError> {
                     spec: None,
                     status: None,
                     connection: None,
+                    app_services: vec![],
                 }
             }
             Event::Restart => {
@@ -585,6 +588,7 @@ async fn run(metrics: CustomMetrics) -> Result<(), ConductorError> {
                     org_id: read_msg.message.org_id,
                     inst_id: read_msg.message.inst_id,
                     event_type: Event::Restarted,
+                    app_services: types::app_services_status(current_resource.status.as_ref()),
                     spec: Some(current_resource.spec),
                     status: current_resource.status,
                     connection: conn_info.ok(),
@@ -986,6 +990,7 @@ async fn init_custom_s3_backup_configuration(
             enabled: false,
             snapshot_class: None,
         }),
+        ..Default::default()
     };
 
     coredb_spec.backup = backup;
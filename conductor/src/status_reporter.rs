@@ -115,6 +115,7 @@ async fn send_status_update(
         spec: Some(coredb.spec.clone()),
         status: coredb.status.clone(),
         connection: Some(conn_info),
+        app_services: types::app_services_status(coredb.status.as_ref()),
     };
     let msg_id = response_queue
         .send(&data_plane_events_queue, &response)
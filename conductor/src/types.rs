@@ -1,7 +1,10 @@
 use serde::{Deserialize, Serialize};
 
 use crate::types;
-use controller::apis::coredb_types::{CoreDBSpec, CoreDBStatus};
+use controller::{
+    apis::coredb_types::{CoreDBSpec, CoreDBStatus, DedicatedNetworkingEndpoint},
+    app_service::types::AppServiceStatus,
+};
 
 /// incoming message from control plane
 #[derive(Debug, Deserialize, Serialize)]
@@ -46,6 +49,17 @@ pub struct StateToControlPlane {
     pub spec: Option<CoreDBSpec>,
     pub status: Option<CoreDBStatus>,
     pub connection: Option<types::ConnectionInfo>,
+    /// Health and rollout state of the appServices, as reported in the CoreDB status
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub app_services: Vec<AppServiceStatus>,
+}
+
+/// The appService statuses of a CoreDB status, reported on their own so the control plane
+/// does not need to know the layout of the status
+pub fn app_services_status(status: Option<&CoreDBStatus>) -> Vec<AppServiceStatus> {
+    status
+        .and_then(|status| status.app_services.clone())
+        .unwrap_or_default()
 }
 
 #[derive(Debug)]
@@ -63,4 +77,7 @@ pub struct ConnectionInfo {
     pub password: String,
     pub app_user: String,
     pub app_password: String,
+    /// External endpoints of dedicated networking, as reported in the CoreDB status
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub dedicated_endpoints: Vec<DedicatedNetworkingEndpoint>,
 }
@@ -2,7 +2,7 @@ use crate::{
     apis::postgres_parameters::{
         merge_pg_configs, ConfigValue, MergeError, PgConfig, DISALLOWED_CONFIGS, MULTI_VAL_CONFIGS,
     },
-    app_service::types::AppService,
+    app_service::types::{AppJob, AppJobStatus, AppService, AppServiceStatus},
     defaults,
     extensions::types::{Extension, ExtensionStatus, TrunkInstall, TrunkInstallStatus},
     postgres_exporter::PostgresMetrics,
@@ -10,7 +10,10 @@ use crate::{
 
 use k8s_openapi::{
     api::core::v1::ResourceRequirements,
-    apimachinery::pkg::{api::resource::Quantity, apis::meta::v1::ObjectMeta},
+    apimachinery::pkg::{
+        api::resource::Quantity,
+        apis::meta::v1::{Condition, ObjectMeta},
+    },
 };
 
 use crate::cloudnativepg::clusters::{ClusterAffinity, ClusterTopologySpreadConstraints};
@@ -206,131 +209,1459 @@ pub struct VolumeSnapshot {
     pub snapshot_class: Option<String>,
 }
 
-/// CoreDB Backup configuration
-/// The backup configuration for the CoreDB instance to facilitate database
-/// backups uploads to an S3 compatible object store or using Volume Snapshots
-/// For WAL archive uploads utilite an S3 compatible object store.
-///
-/// **Example**: A typical S3 backup configuration using IAM Role for authentication
-/// with Volume Snapshots enabled
+/// CoreDB Backup configuration
+/// The backup configuration for the CoreDB instance to facilitate database
+/// backups uploads to an S3 compatible object store or using Volume Snapshots
+/// For WAL archive uploads utilite an S3 compatible object store.
+///
+/// **Example**: A typical S3 backup configuration using IAM Role for authentication
+/// with Volume Snapshots enabled
+///
+/// See `ServiceAccountTemplate` for to map the IAM role ARN to a Kubernetes service account.
+///
+/// ```yaml
+/// apiVersion: coredb.io/v1alpha1
+/// kind: CoreDB
+/// metadata:
+///  name: test-db
+/// spec:
+///   backup:
+///     destinationPath: s3://my-bucket/my-backups
+///     encryption: AES256
+///     retentionPolicy: "30" #30 days
+///     s3Credentials:
+///       inheritFromIAMRole: true
+///     schedule: "0 0 * * *" #every day at midnight
+///     volumeSnapshots:
+///       enabled: true
+///       snapshotClass: my-snapshot-class-name
+/// ```
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema)]
+#[allow(non_snake_case)]
+pub struct Backup {
+    /// The S3 bucket path to store backups in
+    #[serde(default = "defaults::default_destination_path")]
+    pub destinationPath: Option<String>,
+
+    /// The S3 encryption algorithm to use for backups
+    #[serde(default = "defaults::default_encryption")]
+    pub encryption: Option<String>,
+
+    /// The number of days to retain backups for. Ignored when `retention` is set.
+    #[serde(default = "defaults::default_retention_policy")]
+    pub retentionPolicy: Option<String>,
+
+    /// Grandfather-father-son retention of the backups and volume snapshots, see `BackupRetention`
+    ///
+    /// **Default**: `None`, backups are kept for `retentionPolicy` days
+    pub retention: Option<BackupRetention>,
+
+    /// The backup schedule set with cron syntax
+    #[serde(default = "defaults::default_backup_schedule")]
+    pub schedule: Option<String>,
+
+    /// The S3 compatable endpoint URL
+    #[serde(default, rename = "endpointURL")]
+    pub endpoint_url: Option<String>,
+
+    /// The S3 credentials to use for backups (if not using IAM Role)
+    #[serde(rename = "s3Credentials")]
+    pub s3_credentials: Option<S3Credentials>,
+
+    /// The Google Cloud credentials to use for backups
+    #[serde(rename = "googleCredentials")]
+    pub google_credentials: Option<GoogleCredentials>,
+
+    /// Enable using Volume Snapshots for backups instead of Object Storage
+    #[serde(
+        default = "defaults::default_volume_snapshot",
+        rename = "volumeSnapshot"
+    )]
+    pub volume_snapshot: Option<VolumeSnapshot>,
+
+    /// Named restore points to create on the running instance with `pg_create_restore_point`.
+    /// Each name is created once, and the LSN of the restore point is reported in
+    /// `status.restore_points`. Use `restore.recoveryTargetName` to restore to one of them.
+    ///
+    /// **Default**: `[]`
+    #[serde(default, rename = "restorePoints")]
+    pub restore_points: Vec<String>,
+
+    /// Periodically restore the latest backup into a throwaway cluster to prove it is usable.
+    ///
+    /// **Default**: disabled
+    pub verification: Option<BackupVerification>,
+}
+
+/// BackupRetention keeps the newest completed backup of each of the `hourly` most recent
+/// hours, `daily` days, `weekly` ISO weeks and `monthly` months which have a backup,
+/// and deletes the other
+/// completed `Backup` objects of the instance along with their volume snapshots. The
+/// most recent completed backup is always kept, and backups which are not completed
+/// are never deleted. At least one of the counts must be set.
+///
+/// The object store keeps the base backups and WAL of the longest window, or of
+/// `retentionPolicy` when it is longer, so that every retained backup can be
+/// restored. With `dryRun`, nothing is deleted, the backups which would be deleted
+/// are listed in `status.backup_retention`, and the object store keeps using
+/// `retentionPolicy`.
+///
+/// **Example**: Keep 24 hourly, 7 daily, 4 weekly and 6 monthly backups
+///
+/// ```yaml
+/// apiVersion: coredb.io/v1alpha1
+/// kind: CoreDB
+/// metadata:
+///   name: test-db
+/// spec:
+///   backup:
+///     schedule: "0 * * * *"
+///     retention:
+///       hourly: 24
+///       daily: 7
+///       weekly: 4
+///       monthly: 6
+/// ```
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema, PartialEq)]
+pub struct BackupRetention {
+    /// The number of hourly backups to keep
+    ///
+    /// **Default**: 0
+    #[serde(default)]
+    pub hourly: u32,
+
+    /// The number of daily backups to keep
+    ///
+    /// **Default**: 0
+    #[serde(default)]
+    pub daily: u32,
+
+    /// The number of weekly backups to keep
+    ///
+    /// **Default**: 0
+    #[serde(default)]
+    pub weekly: u32,
+
+    /// The number of monthly backups to keep
+    ///
+    /// **Default**: 0
+    #[serde(default)]
+    pub monthly: u32,
+
+    /// Only report the backups which would be deleted
+    ///
+    /// **Default**: false
+    #[serde(default, rename = "dryRun")]
+    pub dry_run: bool,
+}
+
+impl BackupRetention {
+    // Whether none of the counts is set, in which case no backup is deleted
+    pub fn is_empty(&self) -> bool {
+        self.hourly == 0 && self.daily == 0 && self.weekly == 0 && self.monthly == 0
+    }
+
+    // The number of days covered by the longest window, used as the retention policy
+    // of the object store
+    pub fn window_days(&self) -> u32 {
+        [
+            self.hourly.div_ceil(24),
+            self.daily,
+            self.weekly * 7,
+            self.monthly * 31,
+        ]
+        .into_iter()
+        .max()
+        .unwrap_or_default()
+        .max(1)
+    }
+}
+
+/// The backups retained by `spec.backup.retention`
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema, PartialEq)]
+pub struct BackupRetentionStatus {
+    pub dry_run: bool,
+    /// The completed backups which are kept
+    pub retained: Vec<String>,
+    /// The backups which would be deleted, in dry-run mode
+    #[serde(default)]
+    pub would_delete: Vec<String>,
+    /// The backups deleted by the last run which deleted any
+    #[serde(default)]
+    pub deleted: Vec<String>,
+    pub applied_at: Option<DateTime<Utc>>,
+}
+
+/// BackupVerification test-restores the latest completed backup of the instance on a
+/// schedule. The backup is restored into a temporary single-instance cluster named
+/// `<instance>-verify`, the `query` is run against it, and the cluster is deleted. The
+/// check fails when the restore does not complete within `timeoutMinutes`, or when the
+/// query errors or returns `false`.
+///
+/// The outcome is reported in `status.backup_verification` and in the
+/// `doc_ctrl_reconcile_backup_verifications` and
+/// `doc_ctrl_reconcile_backup_verification_last_success_seconds` metrics.
+///
+/// **Example**: Verify the latest backup every Sunday
+///
+/// ```yaml
+/// apiVersion: coredb.io/v1alpha1
+/// kind: CoreDB
+/// metadata:
+///   name: test-db
+/// spec:
+///   backup:
+///     destinationPath: s3://my-bucket/my-backups
+///     verification:
+///       schedule: "0 3 * * SUN"
+///       query: "SELECT count(*) > 0 FROM pg_class"
+/// ```
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema, PartialEq)]
+pub struct BackupVerification {
+    /// The cron schedule of the verifications, in UTC
+    ///
+    /// **Default**: `0 3 * * SUN`
+    #[serde(default = "defaults::default_backup_verification_schedule")]
+    pub schedule: String,
+
+    /// The kind of backups to verify
+    ///
+    /// **Default**: `ObjectStore`
+    #[serde(default)]
+    pub method: BackupVerificationMethod,
+
+    /// The SQL sanity check to run against the restored backup
+    ///
+    /// **Default**: `SELECT 1`
+    #[serde(default = "defaults::default_backup_verification_query")]
+    pub query: String,
+
+    /// The database to run the query in
+    ///
+    /// **Default**: `postgres`
+    #[serde(default = "defaults::default_backup_verification_database")]
+    pub database: String,
+
+    /// How long the restore can take before the verification fails
+    ///
+    /// **Default**: 120
+    #[serde(
+        default = "defaults::default_backup_verification_timeout_minutes",
+        rename = "timeoutMinutes"
+    )]
+    pub timeout_minutes: i64,
+}
+
+/// BackupVerificationMethod selects the backups to verify
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Default, JsonSchema, PartialEq, Eq)]
+pub enum BackupVerificationMethod {
+    /// Base backups in the object store
+    #[default]
+    ObjectStore,
+    /// Volume snapshot backups
+    VolumeSnapshot,
+}
+
+/// The phase of the last backup verification
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, JsonSchema, PartialEq, Eq)]
+pub enum BackupVerificationPhase {
+    Restoring,
+    Succeeded,
+    Failed,
+}
+
+/// The status of the backup verifications of the instance
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema, PartialEq)]
+pub struct BackupVerificationStatus {
+    pub phase: BackupVerificationPhase,
+    /// The name of the Backup being verified, or last verified
+    pub backup: String,
+    /// The completion time of that Backup
+    pub backup_completed_at: Option<DateTime<Utc>>,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub message: Option<String>,
+    /// The completion time of the last backup which was verified successfully
+    pub last_verified_backup: Option<DateTime<Utc>>,
+}
+
+/// Restore configuration provides a way to restore a database from a backup
+/// stored in an S3 compatible object store.
+///
+/// **Example**: A typical S3 restore configuration using IAM Role for authentication
+///
+/// ```yaml
+/// apiVersion: coredb.io/v1alpha1
+/// kind: CoreDB
+/// metadata:
+///   name: test-db-restore
+/// spec:
+///   restore:
+///     serverName: test-db
+///     s3Credentials:
+///       inheritFromIAMRole: true
+/// ```
+///
+/// **Example**: Restore up to, but excluding, a transaction
+///
+/// ```yaml
+/// apiVersion: coredb.io/v1alpha1
+/// kind: CoreDB
+/// metadata:
+///   name: test-db-restore
+/// spec:
+///   restore:
+///     serverName: test-db
+///     recoveryTargetXid: "1234"
+///     recoveryTargetInclusive: false
+///     s3Credentials:
+///       inheritFromIAMRole: true
+/// ```
+///
+/// For more information plese read through the [cloudnative-pg documentation](https://cloudnative-pg.io/documentation/1.20/recovery/#pitr-from-an-object-store)
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema)]
+pub struct Restore {
+    /// The name of the instance you wish to restore.  This maps to the `Backup`
+    /// `destinationPath` field for the original instance.
+    ///
+    /// **Example**: If you have an instance with `spec.backup.destinationPath`
+    /// set to `s3://my-bucket/test-db` then you would set `serverName` to `test-db`.
+    ///
+    /// This assumes you are keeping the backups in the new instance in the same
+    /// root bucket path of `s3://my-bucket/`.
+    #[serde(rename = "serverName")]
+    pub server_name: String,
+
+    /// The object storage path and bucket name of the instance you wish to restore from.  This maps to the `Backup`
+    /// `destinationPath` field for the original instance.
+    ///
+    /// **Example**: If you have an instance with `spec.backup.destinationPath`
+    /// set to `s3://my-bucket/v2/test-db` then you would set `backupsPath` to `s3://my-bucket/v2/test-db`.
+    /// And backups are saved in that bucket under `s3://my-bucket/v2/test-db/server_name`
+    #[serde(rename = "backupsPath")]
+    pub backups_path: Option<String>,
+
+    /// recovery_target_time is the time base target for point-in-time recovery.
+    #[serde(rename = "recoveryTargetTime")]
+    pub recovery_target_time: Option<String>,
+
+    /// recovery_target_lsn is the LSN to recover up to, e.g. `0/3000060`.
+    #[serde(rename = "recoveryTargetLsn")]
+    pub recovery_target_lsn: Option<String>,
+
+    /// recovery_target_xid is the transaction ID to recover up to.
+    #[serde(rename = "recoveryTargetXid")]
+    pub recovery_target_xid: Option<String>,
+
+    /// recovery_target_name is a restore point created with `pg_create_restore_point`
+    /// to recover up to, see `backup.restorePoints`.
+    #[serde(rename = "recoveryTargetName")]
+    pub recovery_target_name: Option<String>,
+
+    /// recovery_target_inclusive stops the recovery just after the recovery target
+    /// when `true`, or just before it when `false`. Only one recovery target can be
+    /// set at a time.
+    ///
+    /// **Default**: true
+    #[serde(rename = "recoveryTargetInclusive")]
+    pub recovery_target_inclusive: Option<bool>,
+
+    /// endpointURL is the S3 compatible endpoint URL
+    #[serde(default, rename = "endpointURL")]
+    pub endpoint_url: Option<String>,
+
+    /// s3Credentials is the S3 credentials to use for restores.
+    #[serde(rename = "s3Credentials")]
+    pub s3_credentials: Option<S3Credentials>,
+
+    /// googleCredentials is the Google Cloud credentials to use for restores.
+    #[serde(rename = "googleCredentials")]
+    pub google_credentials: Option<GoogleCredentials>,
+
+    /// volumeSnapshot is a boolean to enable restoring from a Volume Snapshot
+    #[serde(rename = "volumeSnapshot")]
+    pub volume_snapshot: Option<bool>,
+}
+
+/// ReplicaOf makes the instance a read-only replica of another instance, typically
+/// running in another region or Kubernetes cluster. The replica is bootstrapped from
+/// the object store backups of the source, like a restore, and then keeps following
+/// it by replaying the WAL archived by the source. When `streaming` is set, the
+/// replica streams WAL directly from the source and only falls back to the archive.
+///
+/// While the instance is a replica, the operator does not change anything inside
+/// Postgres: extensions, databases, roles and logical replication are not reconciled.
+/// The connection secret of the replica keeps its own password, while the roles are
+/// those of the source.
+///
+/// Set `promote` to `true` to turn the replica into an independent primary. Keep
+/// `replicaOf` in the spec after the promotion, since the bootstrap of an instance
+/// cannot change.
+///
+/// **Example**: A replica of an instance backed up to `s3://my-bucket/v2/test-db`
+///
+/// ```yaml
+/// apiVersion: coredb.io/v1alpha1
+/// kind: CoreDB
+/// metadata:
+///   name: test-db-replica
+/// spec:
+///   replicaOf:
+///     serverName: test-db
+///     backupsPath: s3://my-bucket/v2/test-db
+///     s3Credentials:
+///       inheritFromIAMRole: true
+///     streaming:
+///       host: test-db.us-east-1.example.com
+///       passwordSecret:
+///         name: test-db-superuser
+///   backup:
+///     destinationPath: s3://my-bucket-replica/v2/test-db-replica
+/// ```
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema)]
+pub struct ReplicaOf {
+    /// The name of the source instance in its object store, see `restore.serverName`
+    #[serde(rename = "serverName")]
+    pub server_name: String,
+
+    /// The object storage path of the backups of the source, see `restore.backupsPath`
+    #[serde(rename = "backupsPath")]
+    pub backups_path: Option<String>,
+
+    /// endpointURL is the S3 compatible endpoint URL
+    #[serde(default, rename = "endpointURL")]
+    pub endpoint_url: Option<String>,
+
+    /// s3Credentials is the S3 credentials to use to read the backups of the source
+    #[serde(rename = "s3Credentials")]
+    pub s3_credentials: Option<S3Credentials>,
+
+    /// Stream WAL from the source over a Postgres connection
+    ///
+    /// **Default**: `None`, WAL is only shipped through the object store
+    pub streaming: Option<ReplicaStreaming>,
+
+    /// Promote the replica to an independent primary
+    ///
+    /// **Default**: false
+    #[serde(default)]
+    pub promote: bool,
+}
+
+impl ReplicaOf {
+    // The replica is bootstrapped exactly like a restore of the source without a
+    // recovery target
+    pub fn as_restore(&self) -> Restore {
+        Restore {
+            server_name: self.server_name.clone(),
+            backups_path: self.backups_path.clone(),
+            recovery_target_time: None,
+            recovery_target_lsn: None,
+            recovery_target_xid: None,
+            recovery_target_name: None,
+            recovery_target_inclusive: None,
+            endpoint_url: self.endpoint_url.clone(),
+            s3_credentials: self.s3_credentials.clone(),
+            google_credentials: None,
+            volume_snapshot: None,
+        }
+    }
+}
+
+/// ReplicaStreaming is the connection used to stream WAL from the source of a replica
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema)]
+pub struct ReplicaStreaming {
+    /// The host of the read-write service of the source
+    pub host: String,
+
+    /// The port of the source
+    ///
+    /// **Default**: 5432
+    #[serde(default = "defaults::default_replica_streaming_port")]
+    pub port: i32,
+
+    /// The secret holding the password of the `postgres` user of the source
+    #[serde(rename = "passwordSecret")]
+    pub password_secret: RolePasswordSecret,
+}
+
+/// Hibernation stops the instance automatically, either during scheduled windows
+/// (e.g. nights and weekends) or after it has had no client connections for a while.
+///
+/// The operator hibernates the instance by setting `spec.stop` to `true`, exactly like
+/// a manual stop. An instance hibernated by the policy is woken up by setting
+/// `spec.stop` back to `false` at the next `wake` time of a schedule, or at any time
+/// by the control plane. A window which is already active when the instance is woken
+/// up manually does not stop it again; only the next `sleep` time does.
+///
+/// Idle detection counts the client connections in `pg_stat_activity`, ignoring
+/// the connections of the operator, the metrics exporter, the pooler and replication.
+/// The reason of the last hibernation is reported in `status.hibernation`.
+///
+/// **Example**: Stop the instance on weeknights and weekends, and after an hour without connections
+///
+/// ```yaml
+/// apiVersion: coredb.io/v1alpha1
+/// kind: CoreDB
+/// metadata:
+///   name: test-db
+/// spec:
+///   hibernation:
+///     idleTimeoutMinutes: 60
+///     schedules:
+///       - sleep: "0 20 * * MON-FRI"
+///         wake: "0 7 * * MON-FRI"
+/// ```
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema)]
+pub struct Hibernation {
+    /// Windows during which the instance is stopped
+    ///
+    /// **Default**: `[]`
+    #[serde(default)]
+    pub schedules: Vec<HibernationSchedule>,
+
+    /// Stop the instance after it had no client connections for this many minutes
+    ///
+    /// **Default**: `None`, idle instances are not stopped
+    #[serde(rename = "idleTimeoutMinutes")]
+    pub idle_timeout_minutes: Option<i64>,
+}
+
+/// HibernationSchedule is a hibernation window delimited by two cron expressions, in UTC.
+/// Prefer day names (`MON-FRI`) for the day of the week, since numbers start at 1 for Sunday.
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema, PartialEq)]
+pub struct HibernationSchedule {
+    /// The cron expression at which the instance is stopped
+    pub sleep: String,
+
+    /// The cron expression at which the instance is started again
+    pub wake: String,
+}
+
+/// HibernationReason is the reason why the instance was last stopped
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, JsonSchema, PartialEq, Eq)]
+pub enum HibernationReason {
+    /// `spec.stop` was set by the control plane or a user
+    Manual,
+    /// A window of `spec.hibernation.schedules` started
+    Schedule,
+    /// The instance had no client connections for `spec.hibernation.idleTimeoutMinutes`
+    Idle,
+}
+
+/// HibernationStatus records the hibernation state observed by the operator
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema, PartialEq)]
+pub struct HibernationStatus {
+    /// Whether the instance is hibernated
+    pub hibernated: bool,
+    /// The reason of the last hibernation
+    pub reason: Option<HibernationReason>,
+    /// The last time the instance was hibernated or woken up
+    pub last_transition_time: Option<DateTime<Utc>>,
+    /// The time since which the instance has had no client connections
+    pub idle_since: Option<DateTime<Utc>>,
+}
+
+/// StorageAutoscaling grows the data volume of the instance before it fills up.
+///
+/// The operator checks the usage of the data volume of the primary with `df`. When it
+/// reaches `thresholdPercent`, `spec.storage` is increased by `step`, up to `maxSize`.
+/// After growing, the volume is not grown again for `cooldownMinutes`, since cloud
+/// volumes can only be resized every few hours. The storage class must allow volume
+/// expansion. Each change is reported with a Kubernetes event on the CoreDB.
+///
+/// **Example**: Grow by 10Gi when 80% full, up to 100Gi
+///
+/// ```yaml
+/// apiVersion: coredb.io/v1alpha1
+/// kind: CoreDB
+/// metadata:
+///   name: test-db
+/// spec:
+///   storage: 10Gi
+///   storageAutoscaling:
+///     thresholdPercent: 80
+///     step: 10Gi
+///     maxSize: 100Gi
+/// ```
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema, PartialEq)]
+pub struct StorageAutoscaling {
+    /// The usage of the data volume, in percent, from which it is grown
+    ///
+    /// **Default**: 80
+    #[serde(
+        default = "defaults::default_storage_autoscaling_threshold_percent",
+        rename = "thresholdPercent"
+    )]
+    pub threshold_percent: u8,
+
+    /// How much to grow the data volume by
+    ///
+    /// **Default**: 10Gi
+    #[serde(default = "defaults::default_storage_autoscaling_step")]
+    pub step: Quantity,
+
+    /// The size the data volume is never grown past
+    #[serde(rename = "maxSize")]
+    pub max_size: Quantity,
+
+    /// The minimum time between two resizes
+    ///
+    /// **Default**: 360
+    #[serde(
+        default = "defaults::default_storage_autoscaling_cooldown_minutes",
+        rename = "cooldownMinutes"
+    )]
+    pub cooldown_minutes: i64,
+}
+
+/// The status of `spec.storageAutoscaling`
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema, PartialEq)]
+pub struct StorageAutoscalingStatus {
+    /// The usage of the data volume, in percent, when it was last checked
+    pub used_percent: Option<u8>,
+    pub last_scaled_at: Option<DateTime<Utc>>,
+    pub last_scaled_to: Option<Quantity>,
+    /// Whether the data volume reached `maxSize`
+    #[serde(default)]
+    pub max_size_reached: bool,
+}
+
+/// NetworkPolicy restricts the egress of the instance and its app services.
+///
+/// Every namespace gets the default network policies of the platform, which include
+/// egress to the public internet. `egress` allows additional destinations, and with
+/// `allowInternetEgress: false` the blanket internet egress is removed, so the pods can
+/// only reach the platform services and the destinations listed here. Backups to an
+/// object store then need the object store endpoint in `egress`.
+///
+/// **Example**: Reach a foreign data wrapper target and an S3 endpoint only
+///
+/// ```yaml
+/// apiVersion: coredb.io/v1alpha1
+/// kind: CoreDB
+/// metadata:
+///   name: test-db
+/// spec:
+///   networkPolicy:
+///     allowInternetEgress: false
+///     egress:
+///       - cidrs: ["52.216.0.0/15"]
+///         ports:
+///           - port: 443
+///       - services:
+///           - name: warehouse-rw
+///             namespace: org-acme-inst-warehouse
+///         ports:
+///           - port: 5432
+/// ```
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema, PartialEq)]
+pub struct NetworkPolicy {
+    /// The destinations the instance pods are allowed to reach
+    #[serde(default)]
+    pub egress: Vec<EgressRule>,
+
+    /// Allow egress to any public address
+    ///
+    /// **Default**: true
+    #[serde(
+        default = "defaults::default_allow_internet_egress",
+        rename = "allowInternetEgress"
+    )]
+    pub allow_internet_egress: bool,
+
+    /// Apply `egress` to the app service pods as well
+    ///
+    /// **Default**: true
+    #[serde(
+        default = "defaults::default_network_policy_apply_to_app_services",
+        rename = "applyToAppServices"
+    )]
+    pub apply_to_app_services: bool,
+}
+
+/// EgressRule allows traffic to the listed CIDRs and services on the listed ports
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema, Default, PartialEq)]
+pub struct EgressRule {
+    /// IPv4 or IPv6 CIDR blocks
+    #[serde(default)]
+    pub cidrs: Vec<String>,
+
+    /// Services in the cluster, resolved to the pods they select
+    #[serde(default)]
+    pub services: Vec<EgressService>,
+
+    /// The destination ports. All ports are allowed when empty.
+    #[serde(default)]
+    pub ports: Vec<EgressPort>,
+}
+
+#[derive(
+    Deserialize, Serialize, Clone, Debug, JsonSchema, Default, PartialEq, Eq, PartialOrd, Ord,
+)]
+pub struct EgressService {
+    pub name: String,
+    pub namespace: String,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema, PartialEq)]
+pub struct EgressPort {
+    pub port: i32,
+
+    /// TCP, UDP or SCTP
+    ///
+    /// **Default**: TCP
+    #[serde(default = "defaults::default_egress_protocol")]
+    pub protocol: String,
+}
+
+/// Tls configures the server certificate of the instance and client certificate
+/// authentication.
+///
+/// The server certificate comes from `serverSecret` when set, a `kubernetes.io/tls`
+/// Secret with `tls.crt`, `tls.key` and `ca.crt`, or is otherwise issued for the
+/// instance service names by the cert-manager `issuer`. Without both, the shared CA
+/// (`USE_SHARED_CA`) or the CNPG generated certificates are used as before.
+///
+/// **Example**: Server certificate from an Issuer and certificate login for two roles
+///
+/// ```yaml
+/// apiVersion: coredb.io/v1alpha1
+/// kind: CoreDB
+/// metadata:
+///   name: test-db
+/// spec:
+///   tls:
+///     issuer:
+///       name: customer-ca
+///       kind: Issuer
+///     clientAuth:
+///       caSecret: customer-client-ca
+///       mode: verify-full
+///       roles:
+///         - app
+///         - reporting
+/// ```
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema, Default, PartialEq)]
+pub struct Tls {
+    /// The name of a Secret in the instance namespace holding the server certificate.
+    /// Takes precedence over `issuer`.
+    #[serde(rename = "serverSecret", skip_serializing_if = "Option::is_none")]
+    pub server_secret: Option<String>,
+
+    /// The cert-manager Issuer or ClusterIssuer that issues the server certificate
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub issuer: Option<TlsIssuer>,
+
+    /// Authenticate roles with client certificates signed by a customer CA
+    #[serde(rename = "clientAuth", skip_serializing_if = "Option::is_none")]
+    pub client_auth: Option<TlsClientAuth>,
+}
+
+/// A reference to a cert-manager issuer
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema, Default, PartialEq)]
+pub struct TlsIssuer {
+    pub name: String,
+
+    /// `Issuer` or `ClusterIssuer`
+    ///
+    /// **Default**: Issuer
+    #[serde(default = "defaults::default_tls_issuer_kind")]
+    pub kind: String,
+}
+
+/// TlsClientAuth configures client certificate authentication for `roles`
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema, Default, PartialEq)]
+pub struct TlsClientAuth {
+    /// The name of a Secret with the `ca.crt` and `ca.key` of the CA signing the client
+    /// certificates. CNPG uses the key to issue the `streaming_replica` certificate.
+    #[serde(rename = "caSecret")]
+    pub ca_secret: String,
+
+    /// How client certificates are checked, see `ClientCertMode`
+    ///
+    /// **Default**: verify-full
+    #[serde(default)]
+    pub mode: ClientCertMode,
+
+    /// The roles that have to present a client certificate whose common name is the
+    /// role name
+    #[serde(default)]
+    pub roles: Vec<String>,
+}
+
+/// ClientCertMode selects the pg_hba rule used for the roles of `TlsClientAuth`
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema, Default, PartialEq)]
+pub enum ClientCertMode {
+    /// Log in with the certificate alone (`cert` method)
+    #[serde(rename = "cert")]
+    Cert,
+    /// Require both the password and a certificate (`clientcert=verify-full`)
+    #[default]
+    #[serde(rename = "verify-full")]
+    VerifyFull,
+}
+
+/// The certificates in use by the instance
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema, PartialEq)]
+pub struct TlsStatus {
+    /// The Secret holding the server certificate
+    pub server_secret: Option<String>,
+    /// When the server certificate expires
+    pub server_certificate_expiration: Option<DateTime<Utc>>,
+    /// The expiration of every certificate of the cluster, by Secret name
+    #[serde(default)]
+    pub expirations: BTreeMap<String, DateTime<Utc>>,
+}
+
+/// HbaRule is a host-based authentication rule added to `pg_hba.conf`. Rules are
+/// checked in order, before the defaults of the instance, and the first matching rule
+/// decides how a connection is authenticated. The operator rejects invalid rules, and
+/// `trust` for anything other than local connections.
+///
+/// **Example**: Only scram over TLS from outside, `readonly` from internal networks only
+///
+/// ```yaml
+/// apiVersion: coredb.io/v1alpha1
+/// kind: CoreDB
+/// metadata:
+///   name: test-db
+/// spec:
+///   hba:
+///     - type: host
+///       user: readonly
+///       address: 10.0.0.0/8
+///       method: scram-sha-256
+///     - type: host
+///       user: readonly
+///       address: 0.0.0.0/0
+///       method: reject
+///     - type: hostnossl
+///       address: 0.0.0.0/0
+///       method: reject
+///     - type: hostssl
+///       address: 0.0.0.0/0
+///       method: scram-sha-256
+/// ```
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema, ToSchema, PartialEq)]
+pub struct HbaRule {
+    /// The connection type
+    #[serde(rename = "type")]
+    pub r#type: HbaConnectionType,
+
+    /// The database names the rule applies to, comma separated
+    ///
+    /// **Default**: all
+    #[serde(default = "defaults::default_hba_all")]
+    pub database: String,
+
+    /// The role names the rule applies to, comma separated
+    ///
+    /// **Default**: all
+    #[serde(default = "defaults::default_hba_all")]
+    pub user: String,
+
+    /// The client address as CIDR, `all` to match any address, or `samehost`/`samenet`.
+    /// Required for all types except `local`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub address: Option<String>,
+
+    /// The authentication method
+    pub method: HbaMethod,
+
+    /// Additional options of the method, for example `clientcert=verify-full`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub options: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema, ToSchema, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum HbaConnectionType {
+    Local,
+    Host,
+    HostSsl,
+    HostNoSsl,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema, ToSchema, PartialEq)]
+pub enum HbaMethod {
+    #[serde(rename = "scram-sha-256")]
+    ScramSha256,
+    #[serde(rename = "md5")]
+    Md5,
+    #[serde(rename = "cert")]
+    Cert,
+    #[serde(rename = "peer")]
+    Peer,
+    #[serde(rename = "reject")]
+    Reject,
+    #[serde(rename = "trust")]
+    Trust,
+}
+
+impl HbaRule {
+    /// Checks the rule can be written to `pg_hba.conf` safely
+    pub fn validate(&self) -> Result<(), String> {
+        let is_valid_name_list = |names: &str| {
+            !names.is_empty()
+                && names.split(',').all(|n| {
+                    !n.is_empty()
+                        && n.chars()
+                            .all(|c| c.is_alphanumeric() || "_-.$+@\"".contains(c))
+                })
+        };
+        if !is_valid_name_list(&self.database) {
+            return Err(format!("invalid database '{}'", self.database));
+        }
+        if !is_valid_name_list(&self.user) {
+            return Err(format!("invalid user '{}'", self.user));
+        }
+        if let Some(options) = &self.options {
+            if options.contains(['\n', '\r', '#']) {
+                return Err(format!("invalid options '{options}'"));
+            }
+        }
+
+        match (&self.r#type, &self.address) {
+            (HbaConnectionType::Local, Some(_)) => {
+                return Err("local rules do not take an address".to_string())
+            }
+            (HbaConnectionType::Local, None) => return Ok(()),
+            (_, None) => return Err("address is required for host rules".to_string()),
+            (_, Some(address)) if !is_valid_hba_address(address) => {
+                return Err(format!("invalid address '{address}'"))
+            }
+            _ => {}
+        }
+
+        if self.method == HbaMethod::Peer {
+            return Err("peer is only supported for local rules".to_string());
+        }
+        if self.method == HbaMethod::Trust && !self.is_loopback() {
+            return Err("trust is only allowed for local connections".to_string());
+        }
+        Ok(())
+    }
+
+    fn is_loopback(&self) -> bool {
+        matches!(
+            self.address.as_deref(),
+            Some("samehost") | Some("127.0.0.1/32") | Some("::1/128")
+        )
+    }
+
+    /// The rule as a `pg_hba.conf` line
+    pub fn to_hba_line(&self) -> String {
+        let r#type = match self.r#type {
+            HbaConnectionType::Local => "local",
+            HbaConnectionType::Host => "host",
+            HbaConnectionType::HostSsl => "hostssl",
+            HbaConnectionType::HostNoSsl => "hostnossl",
+        };
+        let method = match self.method {
+            HbaMethod::ScramSha256 => "scram-sha-256",
+            HbaMethod::Md5 => "md5",
+            HbaMethod::Cert => "cert",
+            HbaMethod::Peer => "peer",
+            HbaMethod::Reject => "reject",
+            HbaMethod::Trust => "trust",
+        };
+        [
+            Some(r#type),
+            Some(self.database.as_str()),
+            Some(self.user.as_str()),
+            self.address.as_deref(),
+            Some(method),
+            self.options.as_deref(),
+        ]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>()
+        .join(" ")
+    }
+}
+
+// Accepts IPv4 and IPv6 CIDR blocks, and the all/samehost/samenet keywords
+fn is_valid_hba_address(address: &str) -> bool {
+    if matches!(address, "samehost" | "samenet" | "all") {
+        return true;
+    }
+    let Some((ip, prefix)) = address.split_once('/') else {
+        return false;
+    };
+    match ip.parse::<std::net::IpAddr>() {
+        Ok(std::net::IpAddr::V4(_)) => prefix.parse::<u8>().is_ok_and(|p| p <= 32),
+        Ok(std::net::IpAddr::V6(_)) => prefix.parse::<u8>().is_ok_and(|p| p <= 128),
+        Err(_) => false,
+    }
+}
+
+/// PgUpgrade enables declarative major version upgrades of Postgres.
+///
+/// When enabled and `spec.image` is changed to an image of a newer Postgres major
+/// version, the operator performs a logical cutover instead of rolling the existing
+/// cluster: the data is imported into a temporary staging cluster running the new
+/// version, the original cluster is recreated on the new version from the staging
+/// cluster, and `trunk_installs` and `extensions` are reconciled again afterwards.
+/// Progress is reported in `status.pg_upgrade`. If the staging import does not
+/// complete within `timeoutMinutes`, the upgrade is rolled back by restoring the
+/// previous `spec.image`.
+///
+/// Before the import starts, writes to the instance are blocked so that no write made
+/// after the import is lost: connections over the network are rejected, except those
+/// of the temporary `tembo_upgrade` role the import uses, the open connections are
+/// terminated, and local sessions are made read-only with
+/// `default_transaction_read_only`. Connections are refused until the instance is
+/// recreated on the new version, and `tembo_upgrade` is dropped afterwards.
+///
+/// Upgrades require backups to be enabled. Before the original cluster is removed, a
+/// final backup of the previous version is taken and recorded in
+/// `status.pg_upgrade.final_backup`, and the operator never deletes it. The staging
+/// cluster is kept until the recreated cluster is ready. If the recreated cluster is not
+/// ready within `timeoutMinutes`, the upgrade is reported as `RestoreFailed`, and
+/// completes if the cluster becomes ready later. To roll back after the switchover,
+/// restore the final backup into a new instance running the previous image: set
+/// `spec.restore.serverName` to `status.pg_upgrade.restore_server_name`, and
+/// `spec.image` to `status.pg_upgrade.from_image`.
+///
+/// Downgrades to an older major version are always refused.
+///
+/// **Example**: Upgrade an instance from Postgres 15 to Postgres 16
+///
+/// ```yaml
+/// apiVersion: coredb.io/v1alpha1
+/// kind: CoreDB
+/// metadata:
+///   name: test-db
+/// spec:
+///   image: quay.io/tembo/standard-cnpg:16-bffd097
+///   pgUpgrade:
+///     enabled: true
+///     timeoutMinutes: 120
+/// ```
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema, Default)]
+pub struct PgUpgrade {
+    /// Allow the operator to upgrade the instance to a newer Postgres major version
+    ///
+    /// **Default**: false.
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// The time allowed for the data import into the new major version before
+    /// the upgrade is rolled back.
+    ///
+    /// **Default**: 120.
+    #[serde(
+        default = "defaults::default_pg_upgrade_timeout_minutes",
+        rename = "timeoutMinutes"
+    )]
+    pub timeout_minutes: i64,
+}
+
+/// The phase of a Postgres major version upgrade
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema, PartialEq)]
+pub enum PgUpgradePhase {
+    /// The upgrade was requested but is not allowed, see `message`
+    Blocked,
+    /// The data is being imported into the staging cluster on the new version
+    Importing,
+    /// A final backup of the previous version is taken, then the original cluster is removed
+    SwitchingOver,
+    /// The original cluster is being recreated on the new version from the staging cluster
+    Restoring,
+    /// The original cluster was removed but was not recreated on the new version in
+    /// time, see `message`. The data is kept in the staging cluster and the final backup.
+    RestoreFailed,
+    /// The upgrade finished successfully
+    Completed,
+    /// The upgrade failed before the switchover and the previous image was restored
+    RolledBack,
+}
+
+/// The status of a Postgres major version upgrade
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema, PartialEq)]
+pub struct PgUpgradeStatus {
+    pub phase: PgUpgradePhase,
+    pub from_image: String,
+    pub to_image: String,
+    pub from_version: u32,
+    pub to_version: u32,
+    pub started_at: Option<DateTime<Utc>>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub message: Option<String>,
+    /// The name of the `Backup` of the previous version taken before the switchover,
+    /// to roll back the upgrade
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub final_backup: Option<String>,
+    /// The `spec.restore.serverName` restoring the final backup into a new instance
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub restore_server_name: Option<String>,
+    /// When the original cluster was removed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub switched_over_at: Option<DateTime<Utc>>,
+}
+
+/// Database describes a database that the operator creates and keeps owned by
+/// the given role. Databases removed from `spec.databases` are not dropped.
+///
+/// **Example**: Create an `analytics` database owned by the `reporting` role
+///
+/// ```yaml
+/// apiVersion: coredb.io/v1alpha1
+/// kind: CoreDB
+/// metadata:
+///   name: test-db
+/// spec:
+///   databases:
+///     - name: analytics
+///       owner: reporting
+///   roles:
+///     - name: reporting
+/// ```
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema, PartialEq)]
+pub struct Database {
+    /// The name of the database
+    pub name: String,
+
+    /// The role owning the database
+    ///
+    /// **Default**: postgres
+    #[serde(default = "defaults::default_database_owner")]
+    pub owner: String,
+}
+
+/// Role describes a Postgres role that the operator creates and keeps in sync
+/// with the spec. For every role with `login` enabled, a connection secret named
+/// `<coredb-name>-<role-name>-connection` is generated with the same keys as the
+/// superuser connection secret. Roles removed from `spec.roles` are not dropped.
+///
+/// The roles managed by the operator (`postgres`, `streaming_replica`,
+/// `cnpg_pooler_pgbouncer`, `readonly`, `tembo_replication` and `tembo_upgrade`) and the predefined
+/// `pg_*` roles are reserved and can not be declared. Roles can not be members of them
+/// either, except of the monitoring roles `pg_monitor`, `pg_read_all_settings`,
+/// `pg_read_all_stats` and `pg_stat_scan_tables`.
+///
+/// **Example**: A login role with its password read from an existing secret
+///
+/// ```yaml
+/// apiVersion: coredb.io/v1alpha1
+/// kind: CoreDB
+/// metadata:
+///   name: test-db
+/// spec:
+///   roles:
+///     - name: reporting
+///       connectionLimit: 10
+///       memberOf:
+///         - pg_read_all_stats
+///       passwordSecret:
+///         name: reporting-password
+///         key: password
+///       grants:
+///         - database: analytics
+///           schema: public
+///           privileges:
+///             - USAGE
+///             - SELECT
+/// ```
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema, PartialEq)]
+pub struct Role {
+    /// The name of the role
+    pub name: String,
+
+    /// Allow the role to log in
+    ///
+    /// **Default**: true
+    #[serde(default = "defaults::default_role_login")]
+    pub login: bool,
+
+    /// The maximum number of concurrent connections of the role, `-1` means no limit
+    ///
+    /// **Default**: -1
+    #[serde(
+        rename = "connectionLimit",
+        default = "defaults::default_role_connection_limit"
+    )]
+    pub connection_limit: i32,
+
+    /// The roles this role is a member of, which can not be reserved roles
+    ///
+    /// **Default**: `[]`
+    #[serde(rename = "memberOf", default)]
+    pub member_of: Vec<String>,
+
+    /// The secret holding the password of the role. When not set, a password is
+    /// generated and stored in the connection secret of the role.
+    ///
+    /// **Default**: `None`
+    #[serde(rename = "passwordSecret")]
+    pub password_secret: Option<RolePasswordSecret>,
+
+    /// Privileges granted to the role on schemas and the tables they contain
+    ///
+    /// **Default**: `[]`
+    #[serde(default)]
+    pub grants: Vec<SchemaGrant>,
+}
+
+/// RolePasswordSecret references the key of a secret holding a role password
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema, PartialEq)]
+pub struct RolePasswordSecret {
+    /// The name of the secret in the namespace of the instance
+    pub name: String,
+
+    /// The key of the password in the secret
+    ///
+    /// **Default**: password
+    #[serde(default = "defaults::default_role_password_secret_key")]
+    pub key: String,
+}
+
+/// SchemaGrant grants privileges on a schema of a database. `USAGE` and `CREATE`
+/// apply to the schema itself, the other privileges apply to all current and
+/// future tables of the schema.
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema, PartialEq)]
+pub struct SchemaGrant {
+    /// The database containing the schema
+    pub database: String,
+
+    /// The schema to grant privileges on
+    ///
+    /// **Default**: public
+    #[serde(default = "defaults::default_grant_schema")]
+    pub schema: String,
+
+    /// The privileges to grant
+    pub privileges: Vec<SchemaPrivilege>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, JsonSchema, PartialEq, Eq)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum SchemaPrivilege {
+    Usage,
+    Create,
+    Select,
+    Insert,
+    Update,
+    Delete,
+    Truncate,
+    References,
+    Trigger,
+}
+
+impl SchemaPrivilege {
+    pub fn as_sql(&self) -> &'static str {
+        match self {
+            SchemaPrivilege::Usage => "USAGE",
+            SchemaPrivilege::Create => "CREATE",
+            SchemaPrivilege::Select => "SELECT",
+            SchemaPrivilege::Insert => "INSERT",
+            SchemaPrivilege::Update => "UPDATE",
+            SchemaPrivilege::Delete => "DELETE",
+            SchemaPrivilege::Truncate => "TRUNCATE",
+            SchemaPrivilege::References => "REFERENCES",
+            SchemaPrivilege::Trigger => "TRIGGER",
+        }
+    }
+
+    // Returns true if the privilege applies to the schema rather than its tables
+    pub fn is_schema_privilege(&self) -> bool {
+        matches!(self, SchemaPrivilege::Usage | SchemaPrivilege::Create)
+    }
+}
+
+/// LogicalReplication declares the publications and subscriptions of the instance.
+/// The operator sets `wal_level` to `logical` when the instance has publications.
+/// Publications and subscriptions removed from the spec are dropped.
 ///
-/// See `ServiceAccountTemplate` for to map the IAM role ARN to a Kubernetes service account.
+/// **Example**: Publish the `orders` table, and subscribe to a publication of
+/// another instance
 ///
 /// ```yaml
 /// apiVersion: coredb.io/v1alpha1
 /// kind: CoreDB
 /// metadata:
-///  name: test-db
+///   name: test-db
 /// spec:
-///   backup:
-///     destinationPath: s3://my-bucket/my-backups
-///     encryption: AES256
-///     retentionPolicy: "30" #30 days
-///     s3Credentials:
-///       inheritFromIAMRole: true
-///     schedule: "0 0 * * *" #every day at midnight
-///     volumeSnapshots:
-///       enabled: true
-///       snapshotClass: my-snapshot-class-name
+///   logicalReplication:
+///     allowedSubscriberNamespaces:
+///       - org-acme-inst-analytics
+///     publications:
+///       - name: orders_pub
+///         database: app
+///         tables:
+///           - public.orders
+///     subscriptions:
+///       - name: customers_sub
+///         database: app
+///         publications:
+///           - customers_pub
+///         source:
+///           coreDB:
+///             name: org-acme-inst-crm
+///             namespace: org-acme-inst-crm
+///             database: app
 /// ```
-#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema)]
-#[allow(non_snake_case)]
-pub struct Backup {
-    /// The S3 bucket path to store backups in
-    #[serde(default = "defaults::default_destination_path")]
-    pub destinationPath: Option<String>,
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema, Default, PartialEq)]
+pub struct LogicalReplication {
+    /// Publications to create on the instance
+    ///
+    /// **Default**: `[]`
+    #[serde(default)]
+    pub publications: Vec<Publication>,
 
-    /// The S3 encryption algorithm to use for backups
-    #[serde(default = "defaults::default_encryption")]
-    pub encryption: Option<String>,
+    /// Subscriptions to create on the instance
+    ///
+    /// **Default**: `[]`
+    #[serde(default)]
+    pub subscriptions: Vec<Subscription>,
 
-    /// The number of days to retain backups for
-    #[serde(default = "defaults::default_retention_policy")]
-    pub retentionPolicy: Option<String>,
+    /// Namespaces of the instances allowed to subscribe to the publications of this
+    /// instance. The network policies of the instance are opened to them, and
+    /// subscriptions of instances in other namespaces are refused.
+    ///
+    /// Subscribers connect with the `tembo_replication` role, which can only replicate
+    /// and read the published tables. Its password is kept in the
+    /// `<coredb-name>-replication` secret.
+    ///
+    /// **Default**: `[]`
+    #[serde(rename = "allowedSubscriberNamespaces", default)]
+    pub allowed_subscriber_namespaces: Vec<String>,
+}
 
-    /// The backup schedule set with cron syntax
-    #[serde(default = "defaults::default_backup_schedule")]
-    pub schedule: Option<String>,
+/// Publication describes a logical replication publication
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema, PartialEq)]
+pub struct Publication {
+    /// The name of the publication
+    pub name: String,
 
-    /// The S3 compatable endpoint URL
-    #[serde(default, rename = "endpointURL")]
-    pub endpoint_url: Option<String>,
+    /// The database to create the publication in
+    ///
+    /// **Default**: postgres
+    #[serde(default = "defaults::default_replication_database")]
+    pub database: String,
 
-    /// The S3 credentials to use for backups (if not using IAM Role)
-    #[serde(rename = "s3Credentials")]
-    pub s3_credentials: Option<S3Credentials>,
+    /// Publish all tables of the database, including tables created in the future
+    ///
+    /// **Default**: false
+    #[serde(rename = "allTables", default)]
+    pub all_tables: bool,
 
-    /// The Google Cloud credentials to use for backups
-    #[serde(rename = "googleCredentials")]
-    pub google_credentials: Option<GoogleCredentials>,
+    /// The tables to publish, optionally qualified with their schema
+    ///
+    /// **Default**: `[]`
+    #[serde(default)]
+    pub tables: Vec<String>,
+}
 
-    /// Enable using Volume Snapshots for backups instead of Object Storage
+/// Subscription describes a logical replication subscription
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema, PartialEq)]
+pub struct Subscription {
+    /// The name of the subscription
+    pub name: String,
+
+    /// The database to create the subscription in
+    ///
+    /// **Default**: postgres
+    #[serde(default = "defaults::default_replication_database")]
+    pub database: String,
+
+    /// The publications to subscribe to
+    pub publications: Vec<String>,
+
+    /// The publisher of the publications
+    pub source: SubscriptionSource,
+
+    /// Enable the subscription
+    ///
+    /// **Default**: true
+    #[serde(default = "defaults::default_subscription_enabled")]
+    pub enabled: bool,
+
+    /// Copy the existing data of the published tables when the subscription is created
+    ///
+    /// **Default**: true
     #[serde(
-        default = "defaults::default_volume_snapshot",
-        rename = "volumeSnapshot"
+        rename = "copyData",
+        default = "defaults::default_subscription_copy_data"
     )]
-    pub volume_snapshot: Option<VolumeSnapshot>,
+    pub copy_data: bool,
 }
 
-/// Restore configuration provides a way to restore a database from a backup
-/// stored in an S3 compatible object store.
-///
-/// **Example**: A typical S3 restore configuration using IAM Role for authentication
-///
-/// ```yaml
-/// apiVersion: coredb.io/v1alpha1
-/// kind: CoreDB
-/// metadata:
-///   name: test-db-restore
-/// spec:
-///   restore:
-///     serverName: test-db
-///     s3Credentials:
-///       inheritFromIAMRole: true
-/// ```
-///
-/// For more information plese read through the [cloudnative-pg documentation](https://cloudnative-pg.io/documentation/1.20/recovery/#pitr-from-an-object-store)
-#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema)]
-pub struct Restore {
-    /// The name of the instance you wish to restore.  This maps to the `Backup`
-    /// `destinationPath` field for the original instance.
-    ///
-    /// **Example**: If you have an instance with `spec.backup.destinationPath`
-    /// set to `s3://my-bucket/test-db` then you would set `serverName` to `test-db`.
-    ///
-    /// This assumes you are keeping the backups in the new instance in the same
-    /// root bucket path of `s3://my-bucket/`.
-    #[serde(rename = "serverName")]
-    pub server_name: String,
+/// SubscriptionSource is either another CoreDB or an external Postgres whose
+/// connection string is stored in a secret. Exactly one of them must be set.
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema, PartialEq)]
+pub struct SubscriptionSource {
+    /// Another CoreDB publishing the publications. Its `allowedSubscriberNamespaces`
+    /// must include the namespace of this instance.
+    #[serde(rename = "coreDB")]
+    pub core_db: Option<SubscriptionCoreDBSource>,
+
+    /// A secret holding the connection string of an external Postgres
+    #[serde(rename = "connectionSecret")]
+    pub connection_secret: Option<SubscriptionConnectionSecret>,
+}
 
-    /// The object storage path and bucket name of the instance you wish to restore from.  This maps to the `Backup`
-    /// `destinationPath` field for the original instance.
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema, PartialEq)]
+pub struct SubscriptionCoreDBSource {
+    /// The name of the CoreDB
+    pub name: String,
+
+    /// The namespace of the CoreDB
+    pub namespace: String,
+
+    /// The database holding the publications
     ///
-    /// **Example**: If you have an instance with `spec.backup.destinationPath`
-    /// set to `s3://my-bucket/v2/test-db` then you would set `backupsPath` to `s3://my-bucket/v2/test-db`.
-    /// And backups are saved in that bucket under `s3://my-bucket/v2/test-db/server_name`
-    #[serde(rename = "backupsPath")]
-    pub backups_path: Option<String>,
+    /// **Default**: postgres
+    #[serde(default = "defaults::default_replication_database")]
+    pub database: String,
+}
 
-    /// recovery_target_time is the time base target for point-in-time recovery.
-    #[serde(rename = "recoveryTargetTime")]
-    pub recovery_target_time: Option<String>,
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema, PartialEq)]
+pub struct SubscriptionConnectionSecret {
+    /// The name of the secret in the namespace of the instance
+    pub name: String,
 
-    /// endpointURL is the S3 compatible endpoint URL
-    #[serde(default, rename = "endpointURL")]
-    pub endpoint_url: Option<String>,
+    /// The key of the connection string in the secret
+    ///
+    /// **Default**: uri
+    #[serde(default = "defaults::default_subscription_connection_secret_key")]
+    pub key: String,
+}
 
-    /// s3Credentials is the S3 credentials to use for restores.
-    #[serde(rename = "s3Credentials")]
-    pub s3_credentials: Option<S3Credentials>,
+/// The status of a logical replication slot of the instance
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema, PartialEq)]
+pub struct ReplicationSlotStatus {
+    pub name: String,
+    pub database: String,
+    pub active: bool,
+    /// The amount of WAL retained for the slot, in bytes
+    pub lag_bytes: i64,
+}
 
-    /// googleCredentials is the Google Cloud credentials to use for restores.
-    #[serde(rename = "googleCredentials")]
-    pub google_credentials: Option<GoogleCredentials>,
+/// The status of the logical replication of the instance
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema, PartialEq, Default)]
+pub struct LogicalReplicationStatus {
+    #[serde(default)]
+    pub publications: Vec<ManagedObjectStatus>,
+    #[serde(default)]
+    pub subscriptions: Vec<ManagedObjectStatus>,
+    #[serde(default)]
+    pub slots: Vec<ReplicationSlotStatus>,
+}
 
-    /// volumeSnapshot is a boolean to enable restoring from a Volume Snapshot
-    #[serde(rename = "volumeSnapshot")]
-    pub volume_snapshot: Option<bool>,
+/// The result of reconciling a database or role declared in the spec
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema, PartialEq)]
+pub struct ManagedObjectStatus {
+    pub name: String,
+    /// The database containing the object, for objects that are local to a database
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub database: Option<String>,
+    /// True when the object matches the spec after the last reconcile
+    pub synced: bool,
+    /// Differences between the spec and the object found during the last reconcile
+    #[serde(default)]
+    pub drift: Vec<String>,
 }
 
 /// A connection pooler is a tool used to manage database connections, sitting
@@ -401,6 +1732,70 @@ pub struct PgBouncer {
     /// This is the same format as what is set for a Kubernetes Pod.
     /// See [https://kubernetes.io/docs/concepts/configuration/manage-resources-containers/](https://kubernetes.io/docs/concepts/configuration/manage-resources-containers/)
     pub resources: Option<PoolerTemplateSpecContainersResources>,
+
+    /// Per-database and per-user pool overrides. Each route is served by its own
+    /// pooler named `<instance>-pooler-<route name>`, which only accepts clients
+    /// for the listed databases and users.
+    ///
+    /// **Default**: empty
+    ///
+    /// ```yaml
+    /// routes:
+    ///   - name: migrations
+    ///     users: ["migrator"]
+    ///     poolMode: session
+    ///     poolSize: 5
+    /// ```
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub routes: Vec<PgBouncerRoute>,
+
+    /// Deploy an additional pooler named `<instance>-pooler-ro` that targets the
+    /// read-only (`-ro`) service of the cluster.
+    ///
+    /// **Default**: false.
+    #[serde(default, rename = "readOnly")]
+    pub read_only: bool,
+
+    /// Export the pgbouncer `SHOW POOLS` and `SHOW STATS` metrics of every pooler
+    /// through a PodMonitor.
+    ///
+    /// **Default**: true.
+    #[serde(default = "defaults::default_pooler_monitoring")]
+    pub monitoring: bool,
+}
+
+/// PgBouncerRoute overrides the pool settings for a set of databases and/or users
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema, ToSchema, Default)]
+pub struct PgBouncerRoute {
+    /// The name of the route, used as suffix of the pooler name. Must be a valid
+    /// DNS label and must not be `ro`.
+    pub name: String,
+
+    /// The databases served by this route. An empty list matches all databases.
+    #[serde(default)]
+    pub databases: Vec<String>,
+
+    /// The users served by this route. An empty list matches all users.
+    #[serde(default)]
+    pub users: Vec<String>,
+
+    /// The pool mode for this route. Defaults to the pool mode of the pooler.
+    #[serde(default, rename = "poolMode", skip_serializing_if = "Option::is_none")]
+    pub pool_mode: Option<PoolerPgbouncerPoolMode>,
+
+    /// The number of server connections allowed per user/database pair
+    /// (`default_pool_size`).
+    #[serde(default, rename = "poolSize", skip_serializing_if = "Option::is_none")]
+    pub pool_size: Option<i32>,
+
+    /// The number of additional connections allowed when the pool is exhausted
+    /// (`reserve_pool_size`).
+    #[serde(
+        default,
+        rename = "reservePoolSize",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub reserve_pool_size: Option<i32>,
 }
 
 #[derive(Deserialize, Serialize, Clone, Debug, JsonSchema, Default)]
@@ -429,6 +1824,85 @@ pub struct DedicatedNetworking {
     /// **Default**: LoadBalancer.
     #[serde(default = "defaults::default_service_type")]
     pub serviceType: String,
+
+    /// Additional annotations to set on the dedicated Services, for example to
+    /// configure the cloud load balancer. The annotations set by the operator, such
+    /// as the external-dns hostname and the load balancer scheme, can not be
+    /// overridden.
+    ///
+    /// ```yaml
+    /// dedicatedNetworking:
+    ///   enabled: true
+    ///   annotations:
+    ///     service.beta.kubernetes.io/aws-load-balancer-cross-zone-load-balancing-enabled: "true"
+    /// ```
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub annotations: BTreeMap<String, String>,
+
+    /// Request a static IP address for the primary load balancer. Whether this is
+    /// honored depends on the cloud provider.
+    #[serde(
+        default,
+        rename = "loadBalancerIP",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub load_balancer_ip: Option<String>,
+
+    /// The hostname to publish for the primary load balancer through external-dns.
+    /// It must be a single label under the namespace, `<label>.<namespace>.<basedomain>`.
+    ///
+    /// **Default**: `dedicated.<namespace>.<basedomain>`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hostname: Option<String>,
+
+    /// The hostname to publish for the standby load balancer through external-dns.
+    /// It must be a single label under the namespace, `<label>.<namespace>.<basedomain>`.
+    ///
+    /// **Default**: `dedicated-ro.<namespace>.<basedomain>`
+    #[serde(
+        default,
+        rename = "standbyHostname",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub standby_hostname: Option<String>,
+
+    /// The port exposed by the primary load balancer.
+    ///
+    /// **Default**: 5432
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub port: Option<i32>,
+
+    /// The port exposed by the standby load balancer.
+    ///
+    /// **Default**: 5432
+    #[serde(
+        default,
+        rename = "standbyPort",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub standby_port: Option<i32>,
+}
+
+/// DedicatedNetworkingStatus reports the endpoints exposed by dedicated networking
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema, Default, PartialEq)]
+pub struct DedicatedNetworkingStatus {
+    pub endpoints: Vec<DedicatedNetworkingEndpoint>,
+}
+
+/// DedicatedNetworkingEndpoint is an external endpoint of a dedicated Service
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema, Default, PartialEq)]
+pub struct DedicatedNetworkingEndpoint {
+    /// The name of the Service
+    pub service: String,
+    /// Whether the endpoint serves the standby (read-only) instances
+    pub standby: bool,
+    /// The hostname published through external-dns
+    pub hostname: String,
+    pub port: i32,
+    /// The IP addresses or hostnames assigned to the load balancer, empty while
+    /// it is being provisioned
+    #[serde(default)]
+    pub addresses: Vec<String>,
 }
 
 impl DedicatedNetworking {
@@ -483,6 +1957,12 @@ pub struct CoreDBSpec {
     #[serde(default = "defaults::default_storage")]
     pub storage: Quantity,
 
+    /// Grow `storage` automatically when the data volume fills up, see `StorageAutoscaling`.
+    ///
+    /// **Default**: disabled
+    #[serde(rename = "storageAutoscaling")]
+    pub storage_autoscaling: Option<StorageAutoscaling>,
+
     /// **DEPRECATED** The storage size for the sharedir volume.
     /// This is no longer used and will be removed in a future release.
     #[serde(default = "defaults::default_sharedir_storage")]
@@ -586,6 +2066,9 @@ pub struct CoreDBSpec {
 
     /// List of IPv4 CIDR blocks to allow access to the Postgres instance.
     ///
+    /// The `gateway-api` ingress provider can not enforce the list, so Postgres is
+    /// only exposed through it when the list is unset or only holds `0.0.0.0/0`.
+    ///
     /// **Default**: Allow all
     #[serde(rename = "ipAllowList")]
     pub ip_allow_list: Option<Vec<String>>,
@@ -635,6 +2118,13 @@ pub struct CoreDBSpec {
     #[serde(rename = "appServices")]
     pub app_services: Option<Vec<AppService>>,
 
+    /// Jobs run to completion next to the Postgres instance, once, on every change
+    /// of the job or on a cron schedule. See `AppJob`.
+    ///
+    /// **Default**: `[]`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub jobs: Vec<AppJob>,
+
     /// The restore configuration provides a way to restore a database from a backup
     /// stored in an S3 compatible object store.
     ///
@@ -680,6 +2170,60 @@ pub struct CoreDBSpec {
     /// **Default**: `None`
     #[serde(rename = "topologySpreadConstraints")]
     pub topology_spread_constraints: Option<Vec<ClusterTopologySpreadConstraints>>,
+
+    /// The pgUpgrade configuration allows the operator to upgrade the instance to
+    /// a newer Postgres major version when `spec.image` changes.
+    ///
+    /// **Default**: disabled
+    #[serde(rename = "pgUpgrade")]
+    pub pg_upgrade: Option<PgUpgrade>,
+
+    /// Databases to create and keep owned by the given roles.
+    ///
+    /// **Default**: `[]`
+    #[serde(default)]
+    pub databases: Vec<Database>,
+
+    /// Roles to create and keep in sync, along with their connection secrets.
+    ///
+    /// **Default**: `[]`
+    #[serde(default)]
+    pub roles: Vec<Role>,
+
+    /// Logical replication publications and subscriptions of the instance.
+    ///
+    /// **Default**: disabled
+    #[serde(rename = "logicalReplication")]
+    pub logical_replication: Option<LogicalReplication>,
+
+    /// Run the instance as a replica of another instance, see `ReplicaOf`.
+    /// Takes precedence over `restore`.
+    ///
+    /// **Default**: `None`
+    #[serde(rename = "replicaOf")]
+    pub replica_of: Option<ReplicaOf>,
+
+    /// Stop the instance automatically on a schedule or when idle, see `Hibernation`.
+    ///
+    /// **Default**: disabled
+    pub hibernation: Option<Hibernation>,
+
+    /// Server certificate and client certificate authentication, see `Tls`.
+    ///
+    /// **Default**: disabled
+    pub tls: Option<Tls>,
+
+    /// Host-based authentication rules added to `pg_hba.conf`, see `HbaRule`.
+    ///
+    /// **Default**: empty
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub hba: Vec<HbaRule>,
+
+    /// Egress restrictions of the instance and its app services, see `NetworkPolicy`.
+    ///
+    /// **Default**: disabled
+    #[serde(rename = "networkPolicy")]
+    pub network_policy: Option<NetworkPolicy>,
 }
 
 impl CoreDBSpec {
@@ -826,19 +2370,14 @@ impl CoreDBSpec {
     // `self.image`. Defaults to `15` if the version cannot be parsed from the
     // image name.
     pub fn pg_major(&self) -> u32 {
-        let parts: Vec<&str> = self.image.split(':').collect();
-        if parts.len() < 2 {
-            return 15;
-        }
+        pg_major_from_image(&self.image)
+    }
 
-        parts[1]
-            .chars()
-            .skip_while(|ch| !ch.is_ascii_digit())
-            .take_while(|ch| ch.is_ascii_digit())
-            .fold(None, |acc, ch| {
-                ch.to_digit(10).map(|b| acc.unwrap_or(0) * 10 + b)
-            })
-            .unwrap_or(15)
+    // Returns true while the instance follows another instance as a read-only replica
+    pub fn is_replica(&self) -> bool {
+        self.replica_of
+            .as_ref()
+            .is_some_and(|replica_of| !replica_of.promote)
     }
 
     // Returns the path to the Postgres shared directory for this spec.
@@ -869,6 +2408,33 @@ impl CoreDBSpec {
     }
 }
 
+// Returns the major version of Postgres parsed from the tag of a Postgres image.
+// Defaults to `15` if the version cannot be parsed from the image name.
+pub fn pg_major_from_image(image: &str) -> u32 {
+    let parts: Vec<&str> = image.split(':').collect();
+    if parts.len() < 2 {
+        return 15;
+    }
+
+    parts[1]
+        .chars()
+        .skip_while(|ch| !ch.is_ascii_digit())
+        .take_while(|ch| ch.is_ascii_digit())
+        .fold(None, |acc, ch| {
+            ch.to_digit(10).map(|b| acc.unwrap_or(0) * 10 + b)
+        })
+        .unwrap_or(15)
+}
+
+/// A restore point created on the instance
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema, PartialEq)]
+pub struct RestorePointStatus {
+    pub name: String,
+    /// The LSN of the restore point
+    pub lsn: String,
+    pub created_at: DateTime<Utc>,
+}
+
 /// The status object of `CoreDB`
 #[derive(Deserialize, Serialize, Clone, Default, Debug, JsonSchema)]
 #[allow(non_snake_case)]
@@ -886,6 +2452,47 @@ pub struct CoreDBStatus {
     #[deprecated(note = "This field is deprecated and it is no longer used")]
     pub last_fully_reconciled_at: Option<DateTime<Utc>>,
     pub last_archiver_status: Option<DateTime<Utc>>,
+    /// Kubernetes-style conditions reported by each step of the reconcile loop,
+    /// summarized by the `Ready` condition.
+    pub conditions: Option<Vec<Condition>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pg_upgrade: Option<PgUpgradeStatus>,
+    /// The status of the databases in `spec.databases`
+    #[serde(default)]
+    pub databases: Option<Vec<ManagedObjectStatus>>,
+    /// The status of the roles in `spec.roles`
+    #[serde(default)]
+    pub roles: Option<Vec<ManagedObjectStatus>>,
+    /// The status of `spec.logicalReplication` and the lag of the logical replication slots
+    #[serde(default)]
+    pub logical_replication: Option<LogicalReplicationStatus>,
+    /// The restore points created from `spec.backup.restorePoints`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub restore_points: Option<Vec<RestorePointStatus>>,
+    /// The last backup verification, see `spec.backup.verification`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backup_verification: Option<BackupVerificationStatus>,
+    /// The backups retained by `spec.backup.retention`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backup_retention: Option<BackupRetentionStatus>,
+    /// The usage and resizes of the data volume, see `spec.storageAutoscaling`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub storage_autoscaling: Option<StorageAutoscalingStatus>,
+    /// The hibernation state of the instance and the reason of the last hibernation
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hibernation: Option<HibernationStatus>,
+    /// The expiration of the certificates of the instance, see `spec.tls`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tls: Option<TlsStatus>,
+    /// The external endpoints of dedicated networking, see `spec.dedicatedNetworking`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dedicated_networking: Option<DedicatedNetworkingStatus>,
+    /// The last run of each job in `spec.jobs`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub jobs: Option<Vec<AppJobStatus>>,
+    /// The health and rollout state of each appService in `spec.appServices`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub app_services: Option<Vec<AppServiceStatus>>,
 }
 
 #[cfg(test)]
@@ -1258,4 +2865,83 @@ mod tests {
             assert_eq!(lib, spec.lib_dir(), "{name} lib_dir");
         }
     }
+
+    #[test]
+    fn test_hba_rule_validate() {
+        let rule = |r#type: HbaConnectionType, address: Option<&str>, method: HbaMethod| HbaRule {
+            r#type,
+            database: "all".to_string(),
+            user: "all".to_string(),
+            address: address.map(str::to_string),
+            method,
+            options: None,
+        };
+
+        assert!(rule(
+            HbaConnectionType::Host,
+            Some("10.0.0.0/8"),
+            HbaMethod::ScramSha256
+        )
+        .validate()
+        .is_ok());
+        assert!(rule(
+            HbaConnectionType::HostSsl,
+            Some("fd00::/8"),
+            HbaMethod::Cert
+        )
+        .validate()
+        .is_ok());
+        assert!(rule(HbaConnectionType::Local, None, HbaMethod::Trust)
+            .validate()
+            .is_ok());
+        assert!(
+            rule(HbaConnectionType::HostSsl, Some("all"), HbaMethod::Cert)
+                .validate()
+                .is_ok()
+        );
+        assert!(rule(
+            HbaConnectionType::Host,
+            Some("127.0.0.1/32"),
+            HbaMethod::Trust
+        )
+        .validate()
+        .is_ok());
+
+        assert!(
+            rule(HbaConnectionType::Host, Some("0.0.0.0/0"), HbaMethod::Trust)
+                .validate()
+                .is_err()
+        );
+        assert!(rule(HbaConnectionType::Host, None, HbaMethod::Md5)
+            .validate()
+            .is_err());
+        assert!(rule(HbaConnectionType::Host, Some("all"), HbaMethod::Trust)
+            .validate()
+            .is_err());
+        assert!(
+            rule(HbaConnectionType::Host, Some("10.0.0.0/33"), HbaMethod::Md5)
+                .validate()
+                .is_err()
+        );
+        assert!(
+            rule(HbaConnectionType::Local, Some("10.0.0.0/8"), HbaMethod::Md5)
+                .validate()
+                .is_err()
+        );
+
+        let mut injected = rule(HbaConnectionType::Host, Some("10.0.0.0/8"), HbaMethod::Md5);
+        injected.user = "all 0.0.0.0/0 trust".to_string();
+        assert!(injected.validate().is_err());
+
+        let mut with_options = rule(
+            HbaConnectionType::HostSsl,
+            Some("0.0.0.0/0"),
+            HbaMethod::ScramSha256,
+        );
+        with_options.options = Some("clientcert=verify-full".to_string());
+        assert_eq!(
+            with_options.to_hba_line(),
+            "hostssl all all 0.0.0.0/0 scram-sha-256 clientcert=verify-full"
+        );
+    }
 }
@@ -1,12 +1,14 @@
 use crate::{
+    ingress::valid_cidrs,
     ingress_route_crd::{
         IngressRoute, IngressRouteRoutes, IngressRouteRoutesKind, IngressRouteRoutesMiddlewares,
         IngressRouteRoutesServices, IngressRouteRoutesServicesKind, IngressRouteSpec,
         IngressRouteTls,
     },
     traefik::middlewares_crd::{
-        Middleware as TraefikMiddleware, MiddlewareHeaders, MiddlewareReplacePathRegex,
-        MiddlewareSpec, MiddlewareStripPrefix,
+        Middleware as TraefikMiddleware, MiddlewareBasicAuth, MiddlewareBuffering,
+        MiddlewareForwardAuth, MiddlewareHeaders, MiddlewareIpAllowList, MiddlewareRateLimit,
+        MiddlewareReplacePathRegex, MiddlewareSpec, MiddlewareStripPrefix,
     },
     Result,
 };
@@ -97,13 +99,20 @@ fn generate_ingress_tcp(
     }
 }
 
+// matches no client, 0.0.0.0 is never the source address of a connection
+const DENY_ALL_SOURCE_RANGE: &str = "0.0.0.0/32";
+
 // creates traefik middleware objects
 // named `<coredb-name>-<specified-middleware-name>`
+// ipAllowList middlewares without a source range use the ipAllowList of the instance
+// forwardAuth middlewares with an address outside of the namespace are not created, so the
+// routes using them are not served
 fn generate_middlewares(
     coredb_name: &str,
     namespace: &str,
     oref: OwnerReference,
     middlewares: Vec<Middleware>,
+    ip_allow_list: &[String],
 ) -> Vec<MiddleWareWrapper> {
     let mut traefik_middlwares: Vec<MiddleWareWrapper> = Vec::new();
     let mut labels: BTreeMap<String, String> = BTreeMap::new();
@@ -111,81 +120,128 @@ fn generate_middlewares(
     labels.insert("coredb.io/name".to_owned(), coredb_name.to_string());
 
     for mw in middlewares {
-        let traefik_mw = match mw {
-            Middleware::CustomRequestHeaders(mw) => {
-                let mw_name = format!("{}-{}", coredb_name, mw.name);
-                let mwh = MiddlewareHeaders {
+        let mw_name = format!("{}-{}", coredb_name, mw.name());
+        let spec = match mw {
+            Middleware::CustomRequestHeaders(mw) => MiddlewareSpec {
+                headers: Some(MiddlewareHeaders {
                     custom_request_headers: Some(mw.config),
                     ..MiddlewareHeaders::default()
-                };
-                let tmw = TraefikMiddleware {
-                    metadata: ObjectMeta {
-                        name: Some(mw_name.clone()),
-                        namespace: Some(namespace.to_owned()),
-                        owner_references: Some(vec![oref.clone()]),
-                        labels: Some(labels.clone()),
-                        ..ObjectMeta::default()
-                    },
-                    spec: MiddlewareSpec {
-                        headers: Some(mwh),
-                        ..MiddlewareSpec::default()
-                    },
-                };
-                MiddleWareWrapper {
-                    name: mw_name,
-                    mw: tmw,
-                }
-            }
-            Middleware::StripPrefix(mw) => {
-                let mw_name = format!("{}-{}", coredb_name, mw.name);
-                let mwsp = MiddlewareStripPrefix {
+                }),
+                ..MiddlewareSpec::default()
+            },
+            Middleware::StripPrefix(mw) => MiddlewareSpec {
+                strip_prefix: Some(MiddlewareStripPrefix {
                     force_slash: None,
                     prefixes: Some(mw.config),
-                };
-                let tmw = TraefikMiddleware {
-                    metadata: ObjectMeta {
-                        name: Some(mw_name.clone()),
-                        namespace: Some(namespace.to_owned()),
-                        owner_references: Some(vec![oref.clone()]),
-                        labels: Some(labels.clone()),
-                        ..ObjectMeta::default()
-                    },
-                    spec: MiddlewareSpec {
-                        strip_prefix: Some(mwsp),
-                        ..MiddlewareSpec::default()
-                    },
-                };
-                MiddleWareWrapper {
-                    name: mw_name,
-                    mw: tmw,
-                }
-            }
-            Middleware::ReplacePathRegex(mw) => {
-                let mw_name = format!("{}-{}", coredb_name, mw.name);
-                let mwrpr = MiddlewareReplacePathRegex {
+                }),
+                ..MiddlewareSpec::default()
+            },
+            Middleware::ReplacePathRegex(mw) => MiddlewareSpec {
+                replace_path_regex: Some(MiddlewareReplacePathRegex {
                     regex: Some(mw.config.regex),
                     replacement: Some(mw.config.replacement),
+                }),
+                ..MiddlewareSpec::default()
+            },
+            Middleware::RateLimit(mw) => MiddlewareSpec {
+                rate_limit: Some(MiddlewareRateLimit {
+                    average: Some(mw.config.average),
+                    burst: mw.config.burst,
+                    period: mw.config.period.map(IntOrString::String),
+                    source_criterion: None,
+                }),
+                ..MiddlewareSpec::default()
+            },
+            Middleware::BasicAuth(mw) => MiddlewareSpec {
+                basic_auth: Some(MiddlewareBasicAuth {
+                    secret: Some(mw.config.secret),
+                    realm: mw.config.realm,
+                    remove_header: mw.config.remove_header,
+                    ..MiddlewareBasicAuth::default()
+                }),
+                ..MiddlewareSpec::default()
+            },
+            Middleware::ForwardAuth(mw) => {
+                if !mw.config.is_namespace_address(namespace) {
+                    error!(
+                        "ns: {}, forwardAuth address {} of Middleware {} is not a service of the namespace, skipping",
+                        namespace, mw.config.address, mw_name
+                    );
+                    continue;
+                }
+                MiddlewareSpec {
+                    forward_auth: Some(MiddlewareForwardAuth {
+                        address: Some(mw.config.address),
+                        auth_request_headers: mw.config.auth_request_headers,
+                        auth_response_headers: mw.config.auth_response_headers,
+                        trust_forward_header: mw.config.trust_forward_header,
+                        ..MiddlewareForwardAuth::default()
+                    }),
+                    ..MiddlewareSpec::default()
+                }
+            }
+            Middleware::IpAllowList(mw) => {
+                // same as the instance, no allow list means all IPs are allowed. An allow
+                // list without any valid entry denies all of them.
+                let valid_ips = match (mw.config.source_range, ip_allow_list) {
+                    (None, []) => vec!["0.0.0.0/0".to_string()],
+                    (source_range, _) => {
+                        let source_range = source_range.unwrap_or_else(|| ip_allow_list.to_vec());
+                        let valid_ips = valid_cidrs(&source_range);
+                        if valid_ips.is_empty() {
+                            error!(
+                                "ns: {}, no valid IP address or CIDR block in Middleware {}, denying all IPs",
+                                namespace, mw_name
+                            );
+                            vec![DENY_ALL_SOURCE_RANGE.to_string()]
+                        } else {
+                            valid_ips
+                        }
+                    }
                 };
-                let tmw = TraefikMiddleware {
-                    metadata: ObjectMeta {
-                        name: Some(mw_name.clone()),
-                        namespace: Some(namespace.to_owned()),
-                        owner_references: Some(vec![oref.clone()]),
-                        labels: Some(labels.clone()),
-                        ..ObjectMeta::default()
-                    },
-                    spec: MiddlewareSpec {
-                        replace_path_regex: Some(mwrpr),
-                        ..MiddlewareSpec::default()
-                    },
-                };
-                MiddleWareWrapper {
-                    name: mw_name,
-                    mw: tmw,
+                MiddlewareSpec {
+                    ip_allow_list: Some(MiddlewareIpAllowList {
+                        source_range: Some(valid_ips),
+                        ..MiddlewareIpAllowList::default()
+                    }),
+                    ..MiddlewareSpec::default()
                 }
             }
+            Middleware::Cors(mw) => MiddlewareSpec {
+                headers: Some(MiddlewareHeaders {
+                    access_control_allow_origin_list: Some(mw.config.allow_origins),
+                    access_control_allow_methods: mw.config.allow_methods,
+                    access_control_allow_headers: mw.config.allow_headers,
+                    access_control_expose_headers: mw.config.expose_headers,
+                    access_control_allow_credentials: mw.config.allow_credentials,
+                    access_control_max_age: mw.config.max_age,
+                    add_vary_header: Some(true),
+                    ..MiddlewareHeaders::default()
+                }),
+                ..MiddlewareSpec::default()
+            },
+            Middleware::RequestBodyLimit(mw) => MiddlewareSpec {
+                buffering: Some(MiddlewareBuffering {
+                    max_request_body_bytes: Some(mw.config.max_request_body_bytes),
+                    ..MiddlewareBuffering::default()
+                }),
+                ..MiddlewareSpec::default()
+            },
         };
-        traefik_middlwares.push(traefik_mw);
+        let tmw = TraefikMiddleware {
+            metadata: ObjectMeta {
+                name: Some(mw_name.clone()),
+                namespace: Some(namespace.to_owned()),
+                owner_references: Some(vec![oref.clone()]),
+                labels: Some(labels.clone()),
+                ..ObjectMeta::default()
+            },
+            spec,
+        };
+        traefik_middlwares.push(MiddleWareWrapper {
+            name: mw_name,
+            mw: tmw,
+        });
     }
     traefik_middlwares
 }
@@ -316,12 +372,18 @@ pub async fn reconcile_ingress(
     desired_routes: Vec<IngressRouteRoutes>,
     desired_middlewares: Vec<Middleware>,
     entry_points: Vec<String>,
+    ip_allow_list: &[String],
 ) -> Result<(), kube::Error> {
     let ingress_api: Api<IngressRoute> = Api::namespaced(client.clone(), ns);
 
     let middleware_api: Api<TraefikMiddleware> = Api::namespaced(client.clone(), ns);
-    let desired_middlewares =
-        generate_middlewares(coredb_name, ns, oref.clone(), desired_middlewares);
+    let desired_middlewares = generate_middlewares(
+        coredb_name,
+        ns,
+        oref.clone(),
+        desired_middlewares,
+        ip_allow_list,
+    );
     let actual_mw_names = get_middlewares(client.clone(), ns, coredb_name).await?;
     let desired_mw_names = desired_middlewares
         .iter()
@@ -403,6 +465,54 @@ pub async fn reconcile_ingress(
     }
 }
 
+// delete_traefik_app_routes removes the IngressRoute, IngressRouteTCPs and Middlewares of
+// the app services of an instance, used when the instance is exposed through Gateway API.
+// The given middlewares are kept, for the HTTPRoute filters referencing them.
+pub async fn delete_traefik_app_routes(
+    client: Client,
+    coredb_name: &str,
+    ns: &str,
+    oref: OwnerReference,
+    app_names: &[String],
+    middlewares: Vec<Middleware>,
+    ip_allow_list: &[String],
+) -> Result<(), kube::Error> {
+    let result: Result<(), kube::Error> = async {
+        reconcile_ingress(
+            client.clone(),
+            coredb_name,
+            ns,
+            oref.clone(),
+            vec![],
+            middlewares,
+            vec![],
+            ip_allow_list,
+        )
+        .await?;
+        for app_name in app_names {
+            reconcile_ingress_tcp(
+                client.clone(),
+                coredb_name,
+                ns,
+                oref.clone(),
+                vec![],
+                vec![],
+                vec![],
+                app_name,
+                &[],
+            )
+            .await?;
+        }
+        Ok(())
+    }
+    .await;
+    match result {
+        // Nothing to clean up when the Traefik CRDs are not installed
+        Err(kube::Error::Api(ae)) if ae.code == 404 => Ok(()),
+        other => other,
+    }
+}
+
 pub async fn reconcile_ingress_tcp(
     client: Client,
     coredb_name: &str,
@@ -413,12 +523,14 @@ pub async fn reconcile_ingress_tcp(
     desired_middlewares: Vec<Middleware>,
     entry_points_tcp: Vec<String>,
     app_name: &str,
+    ip_allow_list: &[String],
 ) -> Result<(), kube::Error> {
     let ingress_api: Api<IngressRouteTCP> = Api::namespaced(client.clone(), ns);
     let name = format!("{}-{}", coredb_name, app_name);
 
     let middleware_api: Api<TraefikMiddleware> = Api::namespaced(client.clone(), ns);
-    let desired_middlewares = generate_middlewares(&name, ns, oref.clone(), desired_middlewares);
+    let desired_middlewares =
+        generate_middlewares(&name, ns, oref.clone(), desired_middlewares, ip_allow_list);
     let actual_mw_names = get_middlewares(client.clone(), ns, &name).await?;
     let desired_mw_names = desired_middlewares
         .iter()
@@ -577,3 +689,125 @@ async fn get_middlewares(
         .map(|d| d.metadata.name.to_owned().expect("no name on resource"))
         .collect())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generate_middlewares() {
+        let middlewares: Vec<Middleware> = serde_json::from_value(serde_json::json!([
+            {"rateLimit": {"name": "limit", "config": {"average": 100, "period": "1m"}}},
+            {"ipAllowList": {"name": "instance-allow-list"}},
+            {"ipAllowList": {"name": "allow-list", "config": {"sourceRange": ["not-an-ip"]}}},
+            {"cors": {"name": "cors", "config": {"allowOrigins": ["https://example.com"]}}},
+            {"requestBodyLimit": {"name": "body", "config": {"maxRequestBodyBytes": 1024}}},
+            {"forwardAuth": {"name": "auth", "config": {"address": "http://test-auth.ns.svc:8080/verify"}}},
+            {"forwardAuth": {"name": "external-auth", "config": {"address": "https://auth.example.com/verify"}}},
+        ]))
+        .unwrap();
+        let ip_allow_list = vec!["10.0.0.0/8".to_string(), "10.0.0.256".to_string()];
+
+        let mws = generate_middlewares(
+            "test",
+            "ns",
+            OwnerReference::default(),
+            middlewares,
+            &ip_allow_list,
+        );
+        let names: Vec<&str> = mws.iter().map(|mw| mw.name.as_str()).collect();
+        assert_eq!(
+            names,
+            vec![
+                "test-limit",
+                "test-instance-allow-list",
+                "test-allow-list",
+                "test-cors",
+                "test-body",
+                "test-auth"
+            ]
+        );
+
+        let rate_limit = mws[0].mw.spec.rate_limit.as_ref().unwrap();
+        assert_eq!(rate_limit.average, Some(100));
+        assert_eq!(
+            rate_limit.period,
+            Some(IntOrString::String("1m".to_string()))
+        );
+
+        // defaults to the valid entries of the instance ipAllowList
+        let allow_list = mws[1].mw.spec.ip_allow_list.as_ref().unwrap();
+        assert_eq!(
+            allow_list.source_range,
+            Some(vec!["10.0.0.0/8".to_string()])
+        );
+
+        // denies all IPs when no entry is valid
+        let allow_list = mws[2].mw.spec.ip_allow_list.as_ref().unwrap();
+        assert_eq!(
+            allow_list.source_range,
+            Some(vec![DENY_ALL_SOURCE_RANGE.to_string()])
+        );
+
+        let cors = mws[3].mw.spec.headers.as_ref().unwrap();
+        assert_eq!(
+            cors.access_control_allow_origin_list,
+            Some(vec!["https://example.com".to_string()])
+        );
+        assert_eq!(cors.add_vary_header, Some(true));
+
+        let buffering = mws[4].mw.spec.buffering.as_ref().unwrap();
+        assert_eq!(buffering.max_request_body_bytes, Some(1024));
+
+        // the authentication service must be in the namespace of the instance
+        let forward_auth = mws[5].mw.spec.forward_auth.as_ref().unwrap();
+        assert_eq!(
+            forward_auth.address.as_deref(),
+            Some("http://test-auth.ns.svc:8080/verify")
+        );
+
+        // allows all IPs only without any allow list
+        let instance_allow_list: Vec<Middleware> = serde_json::from_value(serde_json::json!([
+            {"ipAllowList": {"name": "instance-allow-list"}},
+            {"ipAllowList": {"name": "empty-allow-list", "config": {"sourceRange": []}}},
+        ]))
+        .unwrap();
+        let mws = generate_middlewares(
+            "test",
+            "ns",
+            OwnerReference::default(),
+            instance_allow_list.clone(),
+            &[],
+        );
+        let source_ranges: Vec<_> = mws
+            .iter()
+            .map(|mw| {
+                mw.mw
+                    .spec
+                    .ip_allow_list
+                    .as_ref()
+                    .unwrap()
+                    .source_range
+                    .clone()
+            })
+            .collect();
+        assert_eq!(
+            source_ranges,
+            vec![
+                Some(vec!["0.0.0.0/0".to_string()]),
+                Some(vec![DENY_ALL_SOURCE_RANGE.to_string()])
+            ]
+        );
+        let mws = generate_middlewares(
+            "test",
+            "ns",
+            OwnerReference::default(),
+            instance_allow_list,
+            &["not-an-ip".to_string()],
+        );
+        assert_eq!(
+            mws[0].mw.spec.ip_allow_list.as_ref().unwrap().source_range,
+            Some(vec![DENY_ALL_SOURCE_RANGE.to_string()])
+        );
+    }
+}
@@ -0,0 +1,705 @@
+use crate::{
+    apis::coredb_types::CoreDB,
+    app_service::{
+        manager::{container_security_context, generate_user_env_var, to_delete, Fnv1a},
+        types::{AppJob, AppJobPhase, AppJobRun, AppJobStatus},
+    },
+    cloudnativepg::{cnpg_utils::cron_schedule, placement::cnpg_placement::PlacementConfig},
+    dedicated_networking::is_dns_label,
+    patch_cdb_status_merge,
+    validation::cron_error,
+    Context,
+};
+use chrono::{DateTime, Utc};
+use k8s_openapi::api::{
+    batch::v1::{CronJob, CronJobSpec, Job, JobSpec, JobTemplateSpec},
+    core::v1::{Container, PodSpec, PodTemplateSpec},
+};
+use kube::{
+    api::{Api, DeleteParams, ListParams, ObjectMeta, Patch, PatchParams, ResourceExt},
+    runtime::controller::Action,
+    Resource,
+};
+use serde_json::json;
+use std::{collections::BTreeMap, sync::Arc, time::Duration};
+use tracing::{debug, error, info};
+
+pub const JOB_COMPONENT_NAME: &str = "appJob";
+
+// hash of the job spec, a new run of an OnSpecChange job starts when it changes
+fn job_spec_hash(job: &AppJob) -> String {
+    let mut hasher = Fnv1a::default();
+    hasher.write(serde_json::to_string(job).unwrap_or_default().as_bytes());
+    hasher.finish()
+}
+
+// the Job of each run of an OnSpecChange job has its own name, so a new run never reuses the
+// finished Job of a previous run
+fn job_resource_name(coredb_name: &str, job: &AppJob, spec_hash: &str) -> String {
+    match job.run {
+        AppJobRun::OnSpecChange => format!("{}-{}-{}", coredb_name, job.name, &spec_hash[..8]),
+        _ => format!("{}-{}", coredb_name, job.name),
+    }
+}
+
+// the names of CronJobs are limited to 52 characters, the CronJob controller appends 11 characters
+// to the names of the Jobs it creates. The names of Jobs are limited to 63 characters, they are
+// set as a label of their pods.
+const MAX_CRON_JOB_NAME_LENGTH: usize = 52;
+const MAX_JOB_NAME_LENGTH: usize = 63;
+
+/// The fields of the job at `index` that Kubernetes would reject, relative to the job, e.g.
+/// `.schedule`. The length of the resource names is only checked when the name of the
+/// instance is provided.
+pub(crate) fn app_job_errors(
+    jobs: &[AppJob],
+    index: usize,
+    coredb_name: Option<&str>,
+) -> Vec<(String, String)> {
+    let job = &jobs[index];
+    let mut errors = vec![];
+    if jobs[..index].iter().any(|other| other.name == job.name) {
+        errors.push((String::new(), format!("duplicate job name '{}'", job.name)));
+    }
+    if !is_dns_label(&job.name) {
+        errors.push((
+            ".name".to_string(),
+            format!(
+                "'{}' must consist of lowercase alphanumeric characters or '-', and start and end with an alphanumeric character",
+                job.name
+            ),
+        ));
+    } else if let Some(coredb_name) = coredb_name {
+        // OnSpecChange jobs have the 9 characters of the spec hash appended
+        let max_length = match job.run {
+            AppJobRun::OnCreate => MAX_JOB_NAME_LENGTH,
+            AppJobRun::OnSpecChange => MAX_JOB_NAME_LENGTH - 9,
+            AppJobRun::Schedule => MAX_CRON_JOB_NAME_LENGTH,
+        };
+        let resource_name = format!("{}-{}", coredb_name, job.name);
+        if resource_name.len() > max_length {
+            errors.push((
+                ".name".to_string(),
+                format!("'{resource_name}' must be at most {max_length} characters"),
+            ));
+        }
+    }
+    for (j, env) in job.env.iter().flatten().enumerate() {
+        if !env.has_single_source() {
+            errors.push((
+                format!(".env[{j}]"),
+                "exactly one of value, valueFromPlatform, valueFromSecret and valueFromConfigMap must be set".to_string(),
+            ));
+        }
+    }
+    match (&job.run, &job.schedule) {
+        (AppJobRun::Schedule, None) => errors.push((
+            ".schedule".to_string(),
+            "required when run is Schedule".to_string(),
+        )),
+        (AppJobRun::Schedule, Some(schedule)) => {
+            // Kubernetes CronJobs do not support the seconds field
+            if schedule.split_whitespace().count() != 5 {
+                errors.push((
+                    ".schedule".to_string(),
+                    format!("invalid cron schedule '{schedule}': expected 5 fields"),
+                ));
+            } else if let Err(e) = cron_schedule(schedule) {
+                errors.push((
+                    ".schedule".to_string(),
+                    format!("invalid cron schedule '{schedule}': {}", cron_error(&e)),
+                ));
+            }
+        }
+        (_, Some(_)) => errors.push((
+            ".schedule".to_string(),
+            "only used when run is Schedule".to_string(),
+        )),
+        (_, None) => {}
+    }
+    errors
+}
+
+fn generate_labels(resource_name: &str, coredb_name: &str) -> BTreeMap<String, String> {
+    BTreeMap::from([
+        ("app".to_owned(), resource_name.to_string()),
+        ("component".to_owned(), JOB_COMPONENT_NAME.to_string()),
+        ("coredb.io/name".to_owned(), coredb_name.to_string()),
+    ])
+}
+
+// templates the pods of a job, with the same isolation as the AppService containers
+fn generate_pod_template(
+    job: &AppJob,
+    coredb_name: &str,
+    resource_name: &str,
+    namespace: &str,
+    labels: BTreeMap<String, String>,
+    placement: Option<&PlacementConfig>,
+) -> PodTemplateSpec {
+    let env = job
+        .env
+        .iter()
+        .flatten()
+        .filter_map(|env| generate_user_env_var(env.clone(), coredb_name, namespace, resource_name))
+        .collect();
+
+    PodTemplateSpec {
+        metadata: Some(ObjectMeta {
+            labels: Some(labels),
+            ..ObjectMeta::default()
+        }),
+        spec: Some(PodSpec {
+            affinity: placement.and_then(|p| p.combine_affinity_items()),
+            containers: vec![Container {
+                args: job.args.clone(),
+                command: job.command.clone(),
+                env: Some(env),
+                image: Some(job.image.clone()),
+                name: job.name.clone(),
+                resources: Some(job.resources.clone()),
+                security_context: Some(container_security_context()),
+                ..Container::default()
+            }],
+            node_selector: placement.and_then(|p| p.node_selector.clone()),
+            restart_policy: Some("Never".to_string()),
+            tolerations: placement.map(|p| p.tolerations.clone()),
+            ..PodSpec::default()
+        }),
+    }
+}
+
+// templates the Kubernetes Job of a one-shot run
+fn generate_job(
+    job: &AppJob,
+    cdb: &CoreDB,
+    resource_name: &str,
+    placement: Option<&PlacementConfig>,
+) -> Job {
+    let coredb_name = cdb.name_any();
+    let namespace = cdb.namespace().unwrap_or_default();
+    let labels = generate_labels(resource_name, &coredb_name);
+    Job {
+        metadata: ObjectMeta {
+            name: Some(resource_name.to_string()),
+            namespace: Some(namespace.clone()),
+            labels: Some(labels.clone()),
+            owner_references: cdb.controller_owner_ref(&()).map(|oref| vec![oref]),
+            ..ObjectMeta::default()
+        },
+        spec: Some(JobSpec {
+            backoff_limit: Some(job.backoff_limit),
+            template: generate_pod_template(
+                job,
+                &coredb_name,
+                resource_name,
+                &namespace,
+                labels,
+                placement,
+            ),
+            ..JobSpec::default()
+        }),
+        ..Job::default()
+    }
+}
+
+// templates the Kubernetes CronJob of a scheduled job
+fn generate_cron_job(
+    job: &AppJob,
+    cdb: &CoreDB,
+    resource_name: &str,
+    placement: Option<&PlacementConfig>,
+) -> CronJob {
+    let coredb_name = cdb.name_any();
+    let namespace = cdb.namespace().unwrap_or_default();
+    let labels = generate_labels(resource_name, &coredb_name);
+    // the Jobs created by the CronJob are owned by it, they must not be listed as
+    // jobs of the instance or they would be reaped
+    let mut run_labels = labels.clone();
+    run_labels.remove("component");
+
+    CronJob {
+        metadata: ObjectMeta {
+            name: Some(resource_name.to_string()),
+            namespace: Some(namespace.clone()),
+            labels: Some(labels),
+            owner_references: cdb.controller_owner_ref(&()).map(|oref| vec![oref]),
+            ..ObjectMeta::default()
+        },
+        spec: Some(CronJobSpec {
+            schedule: job.schedule.clone().unwrap_or_default(),
+            time_zone: Some("Etc/UTC".to_string()),
+            concurrency_policy: Some("Forbid".to_string()),
+            // scheduled jobs do not run while the instance is stopped
+            suspend: Some(cdb.spec.stop),
+            job_template: JobTemplateSpec {
+                metadata: Some(ObjectMeta {
+                    labels: Some(run_labels.clone()),
+                    ..ObjectMeta::default()
+                }),
+                spec: Some(JobSpec {
+                    backoff_limit: Some(job.backoff_limit),
+                    template: generate_pod_template(
+                        job,
+                        &coredb_name,
+                        resource_name,
+                        &namespace,
+                        run_labels,
+                        placement,
+                    ),
+                    ..JobSpec::default()
+                }),
+            },
+            ..CronJobSpec::default()
+        }),
+        ..CronJob::default()
+    }
+}
+
+// a finished run is not repeated, unless the spec of an OnSpecChange job changed since
+fn is_finished_run(job: &AppJob, status: &AppJobStatus, spec_hash: &str) -> bool {
+    matches!(status.phase, AppJobPhase::Succeeded | AppJobPhase::Failed)
+        && (job.run == AppJobRun::OnCreate || status.spec_hash.as_deref() == Some(spec_hash))
+}
+
+// the status of a one-shot run from the conditions of its Job
+fn job_run_status(
+    job: &AppJob,
+    k8s_job: &Job,
+    spec_hash: &str,
+    now: DateTime<Utc>,
+) -> AppJobStatus {
+    let status = k8s_job.status.clone().unwrap_or_default();
+    let finished = status
+        .conditions
+        .iter()
+        .flatten()
+        .find(|c| (c.type_ == "Complete" || c.type_ == "Failed") && c.status == "True");
+    let (phase, finished_at, message) = match finished {
+        Some(condition) if condition.type_ == "Complete" => (
+            AppJobPhase::Succeeded,
+            status.completion_time.map(|t| t.0),
+            None,
+        ),
+        Some(condition) => (
+            AppJobPhase::Failed,
+            condition.last_transition_time.as_ref().map(|t| t.0),
+            condition.message.clone(),
+        ),
+        None => (AppJobPhase::Running, None, None),
+    };
+    AppJobStatus {
+        name: job.name.clone(),
+        phase,
+        job: Some(k8s_job.name_any()),
+        spec_hash: Some(spec_hash.to_string()),
+        started_at: Some(status.start_time.map(|t| t.0).unwrap_or(now)),
+        finished_at,
+        message,
+    }
+}
+
+// the status of the last scheduled run of a CronJob
+fn cron_job_status(job: &AppJob, cron_job: &CronJob, spec_hash: &str) -> AppJobStatus {
+    let status = cron_job.status.clone().unwrap_or_default();
+    let last_schedule = status.last_schedule_time.map(|t| t.0);
+    let last_success = status.last_successful_time.map(|t| t.0);
+    let (phase, finished_at, message) = match (last_schedule, last_success) {
+        _ if status
+            .active
+            .as_ref()
+            .is_some_and(|active| !active.is_empty()) =>
+        {
+            (AppJobPhase::Running, None, None)
+        }
+        (None, _) => (AppJobPhase::Pending, None, None),
+        (Some(scheduled), Some(succeeded)) if succeeded >= scheduled => {
+            (AppJobPhase::Succeeded, Some(succeeded), None)
+        }
+        (Some(_), _) => (
+            AppJobPhase::Failed,
+            None,
+            Some("The last scheduled run did not succeed".to_string()),
+        ),
+    };
+    AppJobStatus {
+        name: job.name.clone(),
+        phase,
+        job: Some(cron_job.name_any()),
+        spec_hash: Some(spec_hash.to_string()),
+        started_at: last_schedule,
+        finished_at,
+        message,
+    }
+}
+
+fn requeue(message: String) -> Action {
+    error!("{}", message);
+    Action::requeue(Duration::from_secs(300))
+}
+
+// gets all names of the AppJob resources of a kind that belong to the coredb
+async fn get_job_resources<K>(api: &Api<K>, coredb_name: &str) -> Result<Vec<String>, Action>
+where
+    K: Resource + Clone + serde::de::DeserializeOwned + std::fmt::Debug,
+{
+    let label_selector = format!(
+        "component={},coredb.io/name={}",
+        JOB_COMPONENT_NAME, coredb_name
+    );
+    let lp = ListParams::default().labels(&label_selector).timeout(10);
+    let resources = api
+        .list(&lp)
+        .await
+        .map_err(|e| requeue(format!("Failed to list jobs of {}: {}", coredb_name, e)))?;
+    Ok(resources.items.iter().map(|r| r.name_any()).collect())
+}
+
+async fn delete_job_resources<K>(
+    api: &Api<K>,
+    desired: Vec<String>,
+    actual: Vec<String>,
+) -> Result<(), Action>
+where
+    K: Resource + Clone + serde::de::DeserializeOwned + std::fmt::Debug,
+{
+    for name in to_delete(desired, actual).unwrap_or_default() {
+        // delete the pods along with the Jobs, they would be orphaned otherwise
+        api.delete(&name, &DeleteParams::background())
+            .await
+            .map_err(|e| requeue(format!("Failed to delete job {}: {}", name, e)))?;
+        debug!("Deleted job {}", name);
+    }
+    Ok(())
+}
+
+/// Runs the jobs in `spec.jobs` and records their last run in `status.jobs`.
+///
+/// `OnCreate` and `OnSpecChange` jobs get a Kubernetes Job per run, and the Job of the last
+/// run is kept for its logs. `Schedule` jobs are run by a Kubernetes CronJob. Jobs and
+/// CronJobs of jobs removed from the spec are deleted.
+///
+/// Invalid jobs are not run, their previous run is kept as is. Returns the reasons they are
+/// invalid, if any.
+pub async fn reconcile_app_jobs(
+    cdb: &CoreDB,
+    ctx: Arc<Context>,
+    placement: Option<PlacementConfig>,
+) -> Result<Option<String>, Action> {
+    let name = cdb.name_any();
+    let namespace = cdb
+        .namespace()
+        .ok_or_else(|| requeue(format!("Namespace is empty for instance: {}.", name)))?;
+    let coredbs: Api<CoreDB> = Api::namespaced(ctx.client.clone(), &namespace);
+    let job_api: Api<Job> = Api::namespaced(ctx.client.clone(), &namespace);
+    let cron_job_api: Api<CronJob> = Api::namespaced(ctx.client.clone(), &namespace);
+    let ps = PatchParams::apply("cntrlr").force();
+    let previous = cdb.status.as_ref().and_then(|s| s.jobs.clone());
+    let now = Utc::now();
+
+    let mut statuses: Vec<AppJobStatus> = Vec::new();
+    let mut desired_jobs: Vec<String> = Vec::new();
+    let mut desired_cron_jobs: Vec<String> = Vec::new();
+    let mut invalid: Vec<String> = Vec::new();
+    for (i, job) in cdb.spec.jobs.iter().enumerate() {
+        let spec_hash = job_spec_hash(job);
+        let resource_name = job_resource_name(&name, job, &spec_hash);
+        let previous_status = previous
+            .iter()
+            .flatten()
+            .find(|status| status.name == job.name);
+
+        let errors = app_job_errors(&cdb.spec.jobs, i, Some(&name));
+        if !errors.is_empty() {
+            let errors: Vec<String> = errors
+                .into_iter()
+                .map(|(field, message)| format!("spec.jobs[{i}]{field}: {message}"))
+                .collect();
+            error!("Not running invalid job of {}: {}", name, errors.join("; "));
+            invalid.extend(errors);
+            // a duplicate job shares the status and resources of the first one
+            if cdb.spec.jobs[..i]
+                .iter()
+                .any(|other| other.name == job.name)
+            {
+                continue;
+            }
+            // the previous run is kept, so fixing the job does not run an OnCreate job again
+            let status = match previous_status {
+                Some(status) => {
+                    desired_jobs.extend(status.job.clone());
+                    desired_cron_jobs.extend(status.job.clone());
+                    status.clone()
+                }
+                None => AppJobStatus {
+                    name: job.name.clone(),
+                    phase: AppJobPhase::Invalid,
+                    job: None,
+                    spec_hash: None,
+                    started_at: None,
+                    finished_at: None,
+                    message: None,
+                },
+            };
+            statuses.push(status);
+            continue;
+        }
+
+        let status = match job.run {
+            AppJobRun::Schedule => {
+                let cron_job = generate_cron_job(job, cdb, &resource_name, placement.as_ref());
+                let applied = cron_job_api
+                    .patch(&resource_name, &ps, &Patch::Apply(&cron_job))
+                    .await
+                    .map_err(|e| {
+                        requeue(format!("Failed to apply CronJob {}: {}", resource_name, e))
+                    })?;
+                desired_cron_jobs.push(resource_name);
+                cron_job_status(job, &applied, &spec_hash)
+            }
+            _ => match previous_status {
+                Some(status) if is_finished_run(job, status, &spec_hash) => {
+                    desired_jobs.extend(status.job.clone());
+                    status.clone()
+                }
+                _ if cdb.spec.stop => AppJobStatus {
+                    name: job.name.clone(),
+                    phase: AppJobPhase::Pending,
+                    job: None,
+                    spec_hash: Some(spec_hash),
+                    started_at: None,
+                    finished_at: None,
+                    message: Some("The instance is stopped".to_string()),
+                },
+                _ => {
+                    let k8s_job = match job_api.get_opt(&resource_name).await.map_err(|e| {
+                        requeue(format!("Failed to get Job {}: {}", resource_name, e))
+                    })? {
+                        Some(k8s_job) => k8s_job,
+                        None => {
+                            info!("Starting job {} of {}", job.name, name);
+                            let k8s_job =
+                                generate_job(job, cdb, &resource_name, placement.as_ref());
+                            job_api
+                                .patch(&resource_name, &ps, &Patch::Apply(&k8s_job))
+                                .await
+                                .map_err(|e| {
+                                    requeue(format!(
+                                        "Failed to create Job {}: {}",
+                                        resource_name, e
+                                    ))
+                                })?
+                        }
+                    };
+                    desired_jobs.push(resource_name);
+                    job_run_status(job, &k8s_job, &spec_hash, now)
+                }
+            },
+        };
+        statuses.push(status);
+    }
+
+    let actual_jobs = get_job_resources(&job_api, &name).await?;
+    delete_job_resources(&job_api, desired_jobs, actual_jobs).await?;
+    let actual_cron_jobs = get_job_resources(&cron_job_api, &name).await?;
+    delete_job_resources(&cron_job_api, desired_cron_jobs, actual_cron_jobs).await?;
+
+    let statuses = (!statuses.is_empty()).then_some(statuses);
+    if statuses != previous {
+        let patch_status = json!({
+            "apiVersion": "coredb.io/v1alpha1",
+            "kind": "CoreDB",
+            "status": {
+                "jobs": statuses
+            }
+        });
+        patch_cdb_status_merge(&coredbs, &name, patch_status).await?;
+    }
+    Ok((!invalid.is_empty()).then(|| format!("Invalid jobs are not run: {}", invalid.join("; "))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::apis::coredb_types::CoreDBSpec;
+    use k8s_openapi::{
+        api::batch::v1::{CronJobStatus, JobCondition, JobSpec, JobStatus},
+        apimachinery::pkg::apis::meta::v1::Time,
+    };
+
+    fn app_job(run: &str) -> AppJob {
+        serde_json::from_value(json!({
+            "name": "migrate",
+            "image": "quay.io/myorg/migrations:v2",
+            "command": ["/migrate", "up"],
+            "run": run,
+            "env": [{"name": "DATABASE_URL", "valueFromPlatform": "ReadWriteConnection"}],
+        }))
+        .unwrap()
+    }
+
+    fn coredb() -> CoreDB {
+        let mut cdb = CoreDB::new("test", CoreDBSpec::default());
+        cdb.metadata.namespace = Some("org-test".to_string());
+        cdb.metadata.uid = Some("1234".to_string());
+        cdb
+    }
+
+    #[test]
+    fn test_generate_job() {
+        let job = app_job("OnSpecChange");
+        assert_eq!(job.backoff_limit, 3);
+        let spec_hash = job_spec_hash(&job);
+        let resource_name = job_resource_name("test", &job, &spec_hash);
+        assert_eq!(resource_name, format!("test-migrate-{}", &spec_hash[..8]));
+        assert_eq!(
+            job_resource_name("test", &app_job("OnCreate"), &spec_hash),
+            "test-migrate"
+        );
+
+        let k8s_job = generate_job(&job, &coredb(), &resource_name, None);
+        let pod_spec = k8s_job.spec.unwrap().template.spec.unwrap();
+        assert_eq!(pod_spec.restart_policy, Some("Never".to_string()));
+        let env = pod_spec.containers[0].env.clone().unwrap();
+        assert_eq!(
+            env[0]
+                .value_from
+                .as_ref()
+                .unwrap()
+                .secret_key_ref
+                .as_ref()
+                .unwrap()
+                .name,
+            "test-apps"
+        );
+
+        let mut scheduled = app_job("Schedule");
+        scheduled.schedule = Some("0 3 * * *".to_string());
+        let mut cdb = coredb();
+        cdb.spec.stop = true;
+        let cron_job = generate_cron_job(&scheduled, &cdb, "test-migrate", None);
+        let spec = cron_job.spec.unwrap();
+        assert_eq!(spec.schedule, "0 3 * * *");
+        assert_eq!(spec.suspend, Some(true));
+        // the Jobs of the CronJob are not reaped as jobs of the instance
+        let run_labels = spec.job_template.metadata.unwrap().labels.unwrap();
+        assert!(!run_labels.contains_key("component"));
+    }
+
+    #[test]
+    fn test_app_job_errors() {
+        let mut scheduled = app_job("Schedule");
+        scheduled.schedule = Some("0 3 * * *".to_string());
+        let jobs = vec![app_job("OnCreate"), scheduled.clone()];
+        assert_eq!(
+            app_job_errors(&jobs, 1, Some("test")),
+            vec![(String::new(), "duplicate job name 'migrate'".to_string())]
+        );
+        assert!(app_job_errors(&jobs, 0, Some("test")).is_empty());
+
+        // the names of the Jobs and CronJobs fit the limits of Kubernetes, with the spec hash
+        // of OnSpecChange jobs
+        let coredb_name = "a".repeat(40);
+        let mut on_create = app_job("OnCreate");
+        on_create.name = "b".repeat(22);
+        assert!(app_job_errors(&[on_create.clone()], 0, Some(&coredb_name)).is_empty());
+        let on_spec_change = AppJob {
+            run: AppJobRun::OnSpecChange,
+            ..on_create.clone()
+        };
+        assert_eq!(
+            app_job_errors(&[on_spec_change], 0, Some(&coredb_name))[0].1,
+            format!(
+                "'{}-{}' must be at most 54 characters",
+                coredb_name, on_create.name
+            )
+        );
+        scheduled.name = on_create.name.clone();
+        assert_eq!(
+            app_job_errors(&[scheduled.clone()], 0, Some(&coredb_name))[0].1,
+            format!(
+                "'{}-{}' must be at most 52 characters",
+                coredb_name, on_create.name
+            )
+        );
+        assert!(app_job_errors(&[scheduled], 0, None).is_empty());
+
+        let mut invalid = app_job("OnCreate");
+        invalid.name = "-migrate".to_string();
+        invalid.env.as_mut().unwrap()[0].value = Some("postgres://".to_string());
+        invalid.schedule = Some("0 3 * * *".to_string());
+        let fields: Vec<String> = app_job_errors(&[invalid], 0, Some("test"))
+            .into_iter()
+            .map(|(field, _)| field)
+            .collect();
+        assert_eq!(fields, vec![".name", ".env[0]", ".schedule"]);
+    }
+
+    #[test]
+    fn test_job_run_status() {
+        let job = app_job("OnSpecChange");
+        let spec_hash = job_spec_hash(&job);
+        let now = Utc::now();
+        let mut k8s_job = Job {
+            metadata: ObjectMeta {
+                name: Some("test-migrate".to_string()),
+                ..ObjectMeta::default()
+            },
+            spec: Some(JobSpec::default()),
+            status: Some(JobStatus::default()),
+        };
+        let status = job_run_status(&job, &k8s_job, &spec_hash, now);
+        assert_eq!(status.phase, AppJobPhase::Running);
+        assert_eq!(status.started_at, Some(now));
+        assert!(!is_finished_run(&job, &status, &spec_hash));
+
+        k8s_job.status = Some(JobStatus {
+            conditions: Some(vec![JobCondition {
+                type_: "Failed".to_string(),
+                status: "True".to_string(),
+                message: Some("Job has reached the specified backoff limit".to_string()),
+                last_transition_time: Some(Time(now)),
+                ..JobCondition::default()
+            }]),
+            ..JobStatus::default()
+        });
+        let status = job_run_status(&job, &k8s_job, &spec_hash, now);
+        assert_eq!(status.phase, AppJobPhase::Failed);
+        assert_eq!(status.finished_at, Some(now));
+        assert!(is_finished_run(&job, &status, &spec_hash));
+        // a change of the spec starts a new run, unless the job only runs on create
+        assert!(!is_finished_run(&job, &status, "another hash"));
+        assert!(is_finished_run(
+            &app_job("OnCreate"),
+            &status,
+            "another hash"
+        ));
+    }
+
+    #[test]
+    fn test_cron_job_status() {
+        let job = app_job("Schedule");
+        let earlier = Utc::now() - chrono::Duration::hours(1);
+        let now = Utc::now();
+        let cron_job = |last_schedule: Option<DateTime<Utc>>,
+                        last_success: Option<DateTime<Utc>>| CronJob {
+            status: Some(CronJobStatus {
+                active: None,
+                last_schedule_time: last_schedule.map(Time),
+                last_successful_time: last_success.map(Time),
+            }),
+            ..CronJob::default()
+        };
+        let phase = |cron_job: CronJob| cron_job_status(&job, &cron_job, "hash").phase;
+        assert_eq!(phase(cron_job(None, None)), AppJobPhase::Pending);
+        assert_eq!(
+            phase(cron_job(Some(now), Some(now))),
+            AppJobPhase::Succeeded
+        );
+        assert_eq!(
+            phase(cron_job(Some(now), Some(earlier))),
+            AppJobPhase::Failed
+        );
+        assert_eq!(phase(cron_job(Some(now), None)), AppJobPhase::Failed);
+    }
+}
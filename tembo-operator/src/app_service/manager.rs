@@ -4,13 +4,19 @@ use crate::{
 };
 use k8s_openapi::{
     api::{
-        apps::v1::{Deployment, DeploymentSpec},
+        apps::v1::{Deployment, DeploymentSpec, DeploymentStrategy, RollingUpdateDeployment},
+        autoscaling::v2::{
+            CrossVersionObjectReference, HorizontalPodAutoscaler, HorizontalPodAutoscalerSpec,
+            MetricIdentifier, MetricSpec, MetricTarget, PodsMetricSource, ResourceMetricSource,
+        },
         core::v1::{
-            Capabilities, Container, ContainerPort, EnvVar, EnvVarSource, HTTPGetAction,
+            Capabilities, ConfigMap, ConfigMapKeySelector, Container, ContainerPort,
+            ContainerStatus, EnvVar, EnvVarSource, Event as CoreEvent, HTTPGetAction, Pod,
             PodSecurityContext, PodSpec, PodTemplateSpec, Probe, Secret, SecretKeySelector,
             SecretVolumeSource, SecurityContext, Service, ServicePort, ServiceSpec, Volume,
             VolumeMount,
         },
+        policy::v1::{PodDisruptionBudget, PodDisruptionBudgetSpec},
     },
     apimachinery::pkg::{
         apis::meta::v1::{LabelSelector, OwnerReference},
@@ -20,30 +26,42 @@ use k8s_openapi::{
 };
 use kube::{
     api::{Api, ListParams, ObjectMeta, Patch, PatchParams, ResourceExt},
+    core::NamespaceResourceScope,
     runtime::controller::Action,
     Client, Resource,
 };
 use lazy_static::lazy_static;
+use serde::{de::DeserializeOwned, Serialize};
 use std::{
     collections::{BTreeMap, HashMap},
+    fmt::Debug,
     sync::Arc,
     time::Duration,
 };
 
 use crate::{
-    app_service::ingress::{generate_ingress_tcp_routes, reconcile_ingress_tcp},
+    app_service::ingress::{
+        delete_traefik_app_routes, generate_ingress_tcp_routes, reconcile_ingress_tcp,
+    },
+    config::{Config, IngressProvider},
+    gateway::{delete_app_routes, reconcile_app_routes},
     traefik::ingress_route_tcp_crd::IngressRouteTCPRoutes,
 };
 use tracing::{debug, error, warn};
 
 use super::{
     ingress::{generate_ingress_routes, reconcile_ingress},
-    types::{AppService, EnvVarRef, Middleware, COMPONENT_NAME},
+    types::{
+        AppAutoscaling, AppPodDisruptionBudget, AppService, AppServicePhase, AppServiceStatus,
+        EnvVarRef, Middleware, COMPONENT_NAME,
+    },
 };
 
 use crate::{app_service::types::IngressType, secret::fetch_all_decoded_data_from_secret};
 
 const APP_CONTAINER_PORT_PREFIX: &str = "app-";
+const DEFAULT_TARGET_CPU_UTILIZATION_PERCENTAGE: i32 = 80;
+const ENV_SOURCES_HASH_ANNOTATION: &str = "tembo.io/env-sources-hash";
 
 lazy_static! {
     static ref FORWARDED_ENV_VARS: Vec<EnvVar> = {
@@ -95,6 +113,8 @@ struct AppServiceResources {
     deployment: Deployment,
     name: String,
     service: Option<Service>,
+    hpa: Option<HorizontalPodAutoscaler>,
+    pdb: Option<PodDisruptionBudget>,
     ingress_routes: Option<Vec<IngressRouteRoutes>>,
     ingress_tcp_routes: Option<Vec<IngressRouteTCPRoutes>>,
     entry_points: Option<Vec<String>>,
@@ -103,6 +123,7 @@ struct AppServiceResources {
 }
 
 // generates Kubernetes Deployment and Service templates for a AppService
+#[allow(clippy::too_many_arguments)]
 fn generate_resource(
     appsvc: &AppService,
     coredb_name: &str,
@@ -111,6 +132,7 @@ fn generate_resource(
     domain: Option<String>,
     annotations: &BTreeMap<String, String>,
     placement: Option<PlacementConfig>,
+    env_sources_hash: Option<String>,
 ) -> AppServiceResources {
     let resource_name = format!("{}-{}", coredb_name, appsvc.name.clone());
     let service = appsvc.routing.as_ref().map(|_| {
@@ -131,7 +153,28 @@ fn generate_resource(
         oref.clone(),
         annotations,
         placement.clone(),
+        env_sources_hash,
     );
+    let hpa = appsvc.autoscaling.as_ref().map(|autoscaling| {
+        generate_hpa(
+            autoscaling,
+            coredb_name,
+            &resource_name,
+            namespace,
+            oref.clone(),
+            annotations,
+        )
+    });
+    let pdb = appsvc.pod_disruption_budget.as_ref().map(|pdb| {
+        generate_pdb(
+            pdb,
+            coredb_name,
+            &resource_name,
+            namespace,
+            oref.clone(),
+            annotations,
+        )
+    });
 
     let maybe_podmonitor = generate_podmonitor(appsvc, &resource_name, namespace, annotations);
 
@@ -141,6 +184,8 @@ fn generate_resource(
             deployment,
             name: resource_name,
             service,
+            hpa,
+            pdb,
             ingress_routes: None,
             ingress_tcp_routes: None,
             entry_points: None,
@@ -209,6 +254,8 @@ fn generate_resource(
         deployment,
         name: resource_name,
         service,
+        hpa,
+        pdb,
         ingress_routes,
         ingress_tcp_routes,
         entry_points,
@@ -277,7 +324,207 @@ fn generate_service(
     }
 }
 
+// maps an env var of the AppService spec to a container env var
+// secrets and configmaps can only be referenced by name, so they are always read from the instance namespace
+pub(crate) fn generate_user_env_var(
+    env: super::types::EnvVar,
+    coredb_name: &str,
+    namespace: &str,
+    resource_name: &str,
+) -> Option<EnvVar> {
+    let apps_connection_secret_name = format!("{}-apps", coredb_name);
+    let secret_key_ref = |name: String, key: &str, optional: Option<bool>| EnvVarSource {
+        secret_key_ref: Some(SecretKeySelector {
+            name,
+            key: key.to_string(),
+            optional,
+        }),
+        ..EnvVarSource::default()
+    };
+    match env {
+        // Value provided
+        super::types::EnvVar {
+            value: Some(value), ..
+        } => Some(EnvVar {
+            name: env.name,
+            value: Some(value),
+            ..EnvVar::default()
+        }),
+        // EnvVarRef provided, and no Value
+        super::types::EnvVar {
+            value_from_platform: Some(platform_ref),
+            ..
+        } => {
+            let value_from = match platform_ref {
+                EnvVarRef::ReadOnlyConnection => {
+                    secret_key_ref(apps_connection_secret_name, "ro_uri", None)
+                }
+                EnvVarRef::ReadWriteConnection => {
+                    secret_key_ref(apps_connection_secret_name, "rw_uri", None)
+                }
+                EnvVarRef::SuperuserConnection => {
+                    secret_key_ref(format!("{}-connection", coredb_name), "rw_uri", None)
+                }
+                // the key is only present when the pooler is enabled
+                EnvVarRef::PoolerConnection => {
+                    secret_key_ref(apps_connection_secret_name, "pooler_uri", Some(true))
+                }
+                EnvVarRef::InstanceHostname => {
+                    return Some(EnvVar {
+                        name: env.name,
+                        value: Some(format!(
+                            "{}-rw.{}.svc.cluster.local",
+                            coredb_name, namespace
+                        )),
+                        ..EnvVar::default()
+                    });
+                }
+            };
+            Some(EnvVar {
+                name: env.name,
+                value_from: Some(value_from),
+                ..EnvVar::default()
+            })
+        }
+        super::types::EnvVar {
+            value_from_secret: Some(key_ref),
+            ..
+        } => Some(EnvVar {
+            name: env.name,
+            value_from: Some(secret_key_ref(key_ref.name, &key_ref.key, None)),
+            ..EnvVar::default()
+        }),
+        super::types::EnvVar {
+            value_from_config_map: Some(key_ref),
+            ..
+        } => Some(EnvVar {
+            name: env.name,
+            value_from: Some(EnvVarSource {
+                config_map_key_ref: Some(ConfigMapKeySelector {
+                    name: key_ref.name,
+                    key: key_ref.key,
+                    optional: None,
+                }),
+                ..EnvVarSource::default()
+            }),
+            ..EnvVar::default()
+        }),
+        // everything missing, skip it
+        _ => {
+            error!(
+                "ns: {}, AppService: {}, env var: {} is missing value, valueFromPlatform, valueFromSecret or valueFromConfigMap",
+                namespace, resource_name, env.name
+            );
+            None
+        }
+    }
+}
+
+// names of the Secrets and ConfigMaps explicitly referenced by the env vars of an AppService.
+// The platform secrets are left out, so existing AppServices are not rolled out by operator upgrades.
+fn referenced_env_sources(appsvc: &AppService) -> (Vec<String>, Vec<String>) {
+    let mut secrets: Vec<String> = Vec::new();
+    let mut config_maps: Vec<String> = Vec::new();
+    for env in appsvc.env.iter().flatten() {
+        if env.value.is_some() || env.value_from_platform.is_some() {
+            continue;
+        }
+        match (&env.value_from_secret, &env.value_from_config_map) {
+            (Some(key_ref), _) => secrets.push(key_ref.name.clone()),
+            (None, Some(key_ref)) => config_maps.push(key_ref.name.clone()),
+            (None, None) => {}
+        }
+    }
+    secrets.sort();
+    secrets.dedup();
+    config_maps.sort();
+    config_maps.dedup();
+    (secrets, config_maps)
+}
+
+// hashes the data of the Secrets and ConfigMaps referenced by the env vars of an AppService
+// env vars are only read when a container starts, so this is added to the pod template to roll out the
+// Deployment when any of them changes. None when nothing is referenced.
+async fn env_sources_hash(
+    client: &Client,
+    namespace: &str,
+    appsvc: &AppService,
+) -> Result<Option<String>, Error> {
+    let (secret_names, config_map_names) = referenced_env_sources(appsvc);
+    if secret_names.is_empty() && config_map_names.is_empty() {
+        return Ok(None);
+    }
+    let secret_api: Api<Secret> = Api::namespaced(client.clone(), namespace);
+    let config_map_api: Api<ConfigMap> = Api::namespaced(client.clone(), namespace);
+
+    let mut hasher = Fnv1a::default();
+    for name in secret_names {
+        hasher.write(format!("secret/{name}").as_bytes());
+        // a missing secret hashes like an empty one, the pods can not start without it anyway
+        let secret = secret_api.get_opt(&name).await?;
+        for (key, value) in secret.and_then(|s| s.data).unwrap_or_default() {
+            hasher.write(key.as_bytes());
+            hasher.write(&value.0);
+        }
+    }
+    for name in config_map_names {
+        hasher.write(format!("configmap/{name}").as_bytes());
+        let config_map = config_map_api.get_opt(&name).await?;
+        for (key, value) in config_map.and_then(|c| c.data).unwrap_or_default() {
+            hasher.write(key.as_bytes());
+            hasher.write(value.as_bytes());
+        }
+    }
+    Ok(Some(hasher.finish()))
+}
+
+// 64-bit FNV-1a, the hash of the std library is not stable across Rust releases,
+// which would roll out every AppService on operator upgrades
+pub(crate) struct Fnv1a(u64);
+
+impl Default for Fnv1a {
+    fn default() -> Self {
+        Self(0xcbf29ce484222325)
+    }
+}
+
+impl Fnv1a {
+    // prefixed with the length, so ("ab", "c") and ("a", "bc") differ
+    pub(crate) fn write(&mut self, bytes: &[u8]) {
+        let len = (bytes.len() as u64).to_le_bytes();
+        for byte in len.iter().chain(bytes) {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+    }
+
+    pub(crate) fn finish(&self) -> String {
+        format!("{:016x}", self.0)
+    }
+}
+
+// https://tembo.io/docs/tembo-cloud/security/#tenant-isolation
+// These configs are the same as CNPG configs
+pub(crate) fn container_security_context() -> SecurityContext {
+    SecurityContext {
+        run_as_user: Some(65534),
+        allow_privilege_escalation: Some(false),
+        capabilities: Some(Capabilities {
+            drop: Some(vec!["ALL".to_string()]),
+            ..Capabilities::default()
+        }),
+        privileged: Some(false),
+        run_as_non_root: Some(true),
+        // This part maybe we disable if we need
+        // or we can mount ephemeral or persistent
+        // volumes if we need to write somewhere
+        read_only_root_filesystem: Some(true),
+        ..SecurityContext::default()
+    }
+}
+
 // templates a single Kubernetes Deployment for an AppService
+#[allow(clippy::too_many_arguments)]
 fn generate_deployment(
     appsvc: &AppService,
     coredb_name: &str,
@@ -286,6 +533,7 @@ fn generate_deployment(
     oref: OwnerReference,
     annotations: &BTreeMap<String, String>,
     placement: Option<PlacementConfig>,
+    env_sources_hash: Option<String>,
 ) -> Deployment {
     let mut labels: BTreeMap<String, String> = BTreeMap::new();
     labels.insert("app".to_owned(), resource_name.to_string());
@@ -346,23 +594,7 @@ fn generate_deployment(
         None
     };
 
-    // https://tembo.io/docs/tembo-cloud/security/#tenant-isolation
-    // These configs are the same as CNPG configs
-    let security_context = SecurityContext {
-        run_as_user: Some(65534),
-        allow_privilege_escalation: Some(false),
-        capabilities: Some(Capabilities {
-            drop: Some(vec!["ALL".to_string()]),
-            ..Capabilities::default()
-        }),
-        privileged: Some(false),
-        run_as_non_root: Some(true),
-        // This part maybe we disable if we need
-        // or we can mount ephemeral or persistent
-        // volumes if we need to write somewhere
-        read_only_root_filesystem: Some(true),
-        ..SecurityContext::default()
-    };
+    let security_context = container_security_context();
 
     // ensure hyphen in env var name (cdb name allows hyphen)
     let cdb_name_env = coredb_name.to_uppercase().replace('-', "_");
@@ -467,41 +699,7 @@ fn generate_deployment(
     // including the valueFromX values
     if let Some(envs) = appsvc.env.clone() {
         for env in envs {
-            let evar: Option<EnvVar> = match (env.value, env.value_from_platform) {
-                // Value provided
-                (Some(e), _) => Some(EnvVar {
-                    name: env.name,
-                    value: Some(e),
-                    ..EnvVar::default()
-                }),
-                // EnvVarRef provided, and no Value
-                (None, Some(e)) => {
-                    let secret_key = match e {
-                        EnvVarRef::ReadOnlyConnection => "ro_uri",
-                        EnvVarRef::ReadWriteConnection => "rw_uri",
-                    };
-                    Some(EnvVar {
-                        name: env.name,
-                        value_from: Some(EnvVarSource {
-                            secret_key_ref: Some(SecretKeySelector {
-                                name: apps_connection_secret_name.clone(),
-                                key: secret_key.to_string(),
-                                ..SecretKeySelector::default()
-                            }),
-                            ..EnvVarSource::default()
-                        }),
-                        ..EnvVar::default()
-                    })
-                }
-                // everything missing, skip it
-                _ => {
-                    error!(
-                        "ns: {}, AppService: {}, env var: {} is missing value or valueFromPlatform",
-                        namespace, resource_name, env.name
-                    );
-                    None
-                }
-            };
+            let evar = generate_user_env_var(env, coredb_name, namespace, resource_name);
             if let Some(e) = evar {
                 env_vars.set(&e.name, e.clone());
             }
@@ -587,16 +785,40 @@ fn generate_deployment(
         ..PodSpec::default()
     };
 
+    let mut pod_metadata = deployment_metadata.clone();
+    if let Some(hash) = env_sources_hash {
+        pod_metadata
+            .annotations
+            .get_or_insert_with(BTreeMap::new)
+            .insert(ENV_SOURCES_HASH_ANNOTATION.to_string(), hash);
+    }
+
     let pod_template_spec = PodTemplateSpec {
-        metadata: Some(deployment_metadata.clone()),
+        metadata: Some(pod_metadata),
         spec: Some(pod_spec),
     };
 
+    // leave the replicas to the HorizontalPodAutoscaler when autoscaling is configured.
+    // invalid specs are rejected by the admission webhook, but it may not be deployed
+    let replicas = match appsvc.autoscaling {
+        Some(_) => None,
+        None => Some(appsvc.replicas.unwrap_or(1).max(0)),
+    };
+    let strategy = appsvc.strategy.as_ref().map(|s| DeploymentStrategy {
+        type_: Some("RollingUpdate".to_string()),
+        rolling_update: Some(RollingUpdateDeployment {
+            max_surge: s.max_surge.clone(),
+            max_unavailable: s.max_unavailable.clone(),
+        }),
+    });
+
     let deployment_spec = DeploymentSpec {
+        replicas,
         selector: LabelSelector {
             match_labels: Some(labels.clone()),
             ..LabelSelector::default()
         },
+        strategy,
         template: pod_template_spec,
         ..DeploymentSpec::default()
     };
@@ -607,6 +829,131 @@ fn generate_deployment(
     }
 }
 
+// labels of the AppService resources, also used to select its pods
+fn generate_labels(resource_name: &str, coredb_name: &str) -> BTreeMap<String, String> {
+    BTreeMap::from([
+        ("app".to_owned(), resource_name.to_string()),
+        ("component".to_owned(), COMPONENT_NAME.to_string()),
+        ("coredb.io/name".to_owned(), coredb_name.to_string()),
+    ])
+}
+
+// templates the HorizontalPodAutoscaler for an AppService
+fn generate_hpa(
+    autoscaling: &AppAutoscaling,
+    coredb_name: &str,
+    resource_name: &str,
+    namespace: &str,
+    oref: OwnerReference,
+    annotations: &BTreeMap<String, String>,
+) -> HorizontalPodAutoscaler {
+    // bounds the HorizontalPodAutoscaler would be rejected with are clamped, the admission
+    // webhook may not be deployed
+    let max_replicas = autoscaling.max_replicas.max(1);
+    let min_replicas = autoscaling.min_replicas.clamp(1, max_replicas);
+    let mut metrics: Vec<MetricSpec> = Vec::new();
+    // scale on CPU by default, unless only custom metrics are configured
+    let target_cpu = match autoscaling.target_cpu_utilization_percentage {
+        Some(target) => Some(target),
+        None if autoscaling.metrics.is_empty() => Some(DEFAULT_TARGET_CPU_UTILIZATION_PERCENTAGE),
+        None => None,
+    };
+    if let Some(target) = target_cpu {
+        metrics.push(MetricSpec {
+            type_: "Resource".to_string(),
+            resource: Some(ResourceMetricSource {
+                name: "cpu".to_string(),
+                target: MetricTarget {
+                    type_: "Utilization".to_string(),
+                    average_utilization: Some(target),
+                    ..MetricTarget::default()
+                },
+            }),
+            ..MetricSpec::default()
+        });
+    }
+    for metric in autoscaling.metrics.iter() {
+        metrics.push(MetricSpec {
+            type_: "Pods".to_string(),
+            pods: Some(PodsMetricSource {
+                metric: MetricIdentifier {
+                    name: metric.name.clone(),
+                    selector: None,
+                },
+                target: MetricTarget {
+                    type_: "AverageValue".to_string(),
+                    average_value: Some(metric.average_value.clone()),
+                    ..MetricTarget::default()
+                },
+            }),
+            ..MetricSpec::default()
+        });
+    }
+
+    HorizontalPodAutoscaler {
+        metadata: ObjectMeta {
+            name: Some(resource_name.to_string()),
+            namespace: Some(namespace.to_owned()),
+            labels: Some(generate_labels(resource_name, coredb_name)),
+            owner_references: Some(vec![oref]),
+            annotations: Some(annotations.clone()),
+            ..ObjectMeta::default()
+        },
+        spec: Some(HorizontalPodAutoscalerSpec {
+            scale_target_ref: CrossVersionObjectReference {
+                api_version: Some("apps/v1".to_string()),
+                kind: "Deployment".to_string(),
+                name: resource_name.to_string(),
+            },
+            min_replicas: Some(min_replicas),
+            max_replicas,
+            metrics: Some(metrics),
+            ..HorizontalPodAutoscalerSpec::default()
+        }),
+        ..HorizontalPodAutoscaler::default()
+    }
+}
+
+// templates the PodDisruptionBudget for an AppService
+fn generate_pdb(
+    pdb: &AppPodDisruptionBudget,
+    coredb_name: &str,
+    resource_name: &str,
+    namespace: &str,
+    oref: OwnerReference,
+    annotations: &BTreeMap<String, String>,
+) -> PodDisruptionBudget {
+    let labels = generate_labels(resource_name, coredb_name);
+    // allow a single pod to be evicted at once when no budget is given. Only one of them
+    // can be set, minAvailable is kept if both are
+    let max_unavailable = match (&pdb.min_available, &pdb.max_unavailable) {
+        (None, None) => Some(IntOrString::Int(1)),
+        (Some(_), _) => None,
+        (None, max_unavailable) => max_unavailable.clone(),
+    };
+
+    PodDisruptionBudget {
+        metadata: ObjectMeta {
+            name: Some(resource_name.to_string()),
+            namespace: Some(namespace.to_owned()),
+            labels: Some(labels.clone()),
+            owner_references: Some(vec![oref]),
+            annotations: Some(annotations.clone()),
+            ..ObjectMeta::default()
+        },
+        spec: Some(PodDisruptionBudgetSpec {
+            min_available: pdb.min_available.clone(),
+            max_unavailable,
+            selector: Some(LabelSelector {
+                match_labels: Some(labels),
+                ..LabelSelector::default()
+            }),
+            ..PodDisruptionBudgetSpec::default()
+        }),
+        ..PodDisruptionBudget::default()
+    }
+}
+
 // gets all names of AppService Deployments in the namespace that have the label "component=AppService"
 async fn get_appservice_deployments(
     client: &Client,
@@ -668,6 +1015,27 @@ async fn get_appservice_services(
         .collect())
 }
 
+// gets all names of AppService resources of a kind in the namespace
+// that have the label "component=AppService" and belong to the coredb
+async fn get_appservice_resources<K>(
+    client: &Client,
+    namespace: &str,
+    coredb_name: &str,
+) -> Result<Vec<String>, Error>
+where
+    K: Resource<Scope = NamespaceResourceScope> + Clone + DeserializeOwned + Debug,
+    <K as Resource>::DynamicType: Default,
+{
+    let label_selector = format!(
+        "component={},coredb.io/name={}",
+        COMPONENT_NAME, coredb_name
+    );
+    let api: Api<K> = Api::namespaced(client.clone(), namespace);
+    let lp = ListParams::default().labels(&label_selector).timeout(10);
+    let resources = api.list(&lp).await.map_err(Error::KubeError)?;
+    Ok(resources.items.iter().map(|r| r.name_any()).collect())
+}
+
 // determines AppService deployments
 pub fn to_delete(desired: Vec<String>, actual: Vec<String>) -> Option<Vec<String>> {
     let mut to_delete: Vec<String> = Vec::new();
@@ -709,6 +1077,13 @@ async fn apply_resources(resources: Vec<AppServiceResources>, client: &Client, n
                 );
             }
         }
+        if let Some(hpa) = &res.hpa {
+            has_errors |=
+                !apply_resource(client, ns, &res.name, hpa, "HorizontalPodAutoscaler").await;
+        }
+        if let Some(pdb) = &res.pdb {
+            has_errors |= !apply_resource(client, ns, &res.name, pdb, "PodDisruptionBudget").await;
+        }
         if res.service.is_none() {
             continue;
         }
@@ -779,6 +1154,57 @@ async fn apply_resources(resources: Vec<AppServiceResources>, client: &Client, n
     has_errors
 }
 
+// applies an optional AppService resource, returning whether it succeeded
+async fn apply_resource<K>(client: &Client, ns: &str, name: &str, resource: &K, kind: &str) -> bool
+where
+    K: Resource<Scope = NamespaceResourceScope> + Clone + DeserializeOwned + Serialize + Debug,
+    <K as Resource>::DynamicType: Default,
+{
+    let api: Api<K> = Api::namespaced(client.clone(), ns);
+    let ps = PatchParams::apply("cntrlr").force();
+    match api.patch(name, &ps, &Patch::Apply(resource)).await {
+        Ok(_) => {
+            debug!("ns: {}, applied AppService {}: {}", ns, kind, name);
+            true
+        }
+        Err(e) => {
+            error!(
+                "ns: {}, failed to apply AppService {}: {}, error: {}",
+                ns, kind, name, e
+            );
+            false
+        }
+    }
+}
+
+// deletes the AppService resources of a kind that are no longer desired, returning whether all deletes succeeded
+async fn delete_resources<K>(client: &Client, ns: &str, names: Vec<String>, kind: &str) -> bool
+where
+    K: Resource<Scope = NamespaceResourceScope> + Clone + DeserializeOwned + Debug,
+    <K as Resource>::DynamicType: Default,
+{
+    let api: Api<K> = Api::namespaced(client.clone(), ns);
+    let mut succeeded = true;
+    for name in names {
+        match api.delete(&name, &Default::default()).await {
+            Ok(_) => {
+                debug!(
+                    "ns: {}, successfully deleted AppService {}: {}",
+                    ns, kind, name
+                );
+            }
+            Err(e) => {
+                succeeded = false;
+                error!(
+                    "ns: {}, Failed to delete AppService {}: {}, error: {}",
+                    ns, kind, name, e
+                );
+            }
+        }
+    }
+    succeeded
+}
+
 // generate_appsvc_annotations generates the annotations for the AppService resources
 fn generate_appsvc_annotations(cdb: &CoreDB) -> BTreeMap<String, String> {
     cdb.metadata.annotations.as_ref().map_or_else(
@@ -928,6 +1354,58 @@ pub async fn reconcile_app_services(
         }
     };
 
+    // reap any HorizontalPodAutoscalers and PodDisruptionBudgets that are no longer desired
+    let desired_hpas: Vec<String> = appsvcs
+        .iter()
+        .filter(|a| a.autoscaling.is_some())
+        .map(|a| format!("{}-{}", coredb_name, a.name))
+        .collect();
+    match get_appservice_resources::<HorizontalPodAutoscaler>(&client, &ns, &coredb_name).await {
+        Ok(actual_hpas) => {
+            if let Some(to_delete) = to_delete(desired_hpas, actual_hpas) {
+                has_errors |= !delete_resources::<HorizontalPodAutoscaler>(
+                    &client,
+                    &ns,
+                    to_delete,
+                    "HorizontalPodAutoscaler",
+                )
+                .await;
+            }
+        }
+        Err(e) => {
+            has_errors = true;
+            error!(
+                "ns: {}, failed to get AppService HorizontalPodAutoscalers: {}",
+                ns, e
+            );
+        }
+    }
+    let desired_pdbs: Vec<String> = appsvcs
+        .iter()
+        .filter(|a| a.pod_disruption_budget.is_some())
+        .map(|a| format!("{}-{}", coredb_name, a.name))
+        .collect();
+    match get_appservice_resources::<PodDisruptionBudget>(&client, &ns, &coredb_name).await {
+        Ok(actual_pdbs) => {
+            if let Some(to_delete) = to_delete(desired_pdbs, actual_pdbs) {
+                has_errors |= !delete_resources::<PodDisruptionBudget>(
+                    &client,
+                    &ns,
+                    to_delete,
+                    "PodDisruptionBudget",
+                )
+                .await;
+            }
+        }
+        Err(e) => {
+            has_errors = true;
+            error!(
+                "ns: {}, failed to get AppService PodDisruptionBudgets: {}",
+                ns, e
+            );
+        }
+    }
+
     let domain = match std::env::var("DATA_PLANE_BASEDOMAIN") {
         Ok(domain) => Some(domain),
         Err(_) => {
@@ -936,22 +1414,40 @@ pub async fn reconcile_app_services(
         }
     };
     // Iterate over each AppService and process routes
-    let resources: Vec<AppServiceResources> = appsvcs
-        .iter()
-        .map(|appsvc| {
-            generate_resource(
-                appsvc,
-                &coredb_name,
-                &ns,
-                oref.clone(),
-                domain.to_owned(),
-                &annotations,
-                placement.clone(),
-            )
-        })
-        .collect();
+    let mut resources: Vec<AppServiceResources> = Vec::new();
+    for appsvc in appsvcs.iter() {
+        let env_sources_hash = match env_sources_hash(&client, &ns, appsvc).await {
+            Ok(hash) => hash,
+            Err(e) => {
+                error!(
+                    "ns: {}, failed to read the env var sources of AppService: {}, error: {}",
+                    ns, appsvc.name, e
+                );
+                return Err(Action::requeue(Duration::from_secs(300)));
+            }
+        };
+        resources.push(generate_resource(
+            appsvc,
+            &coredb_name,
+            &ns,
+            oref.clone(),
+            domain.to_owned(),
+            &annotations,
+            placement.clone(),
+            env_sources_hash,
+        ));
+    }
     let apply_errored = apply_resources(resources.clone(), &client, &ns).await;
 
+    // the status only reports on the AppServices, failing to read it does not fail the reconcile
+    let ingress_domain = domain.as_deref().filter(|_| !cdb.spec.disable_ingress);
+    if let Err(e) = reconcile_app_services_status(cdb, &client, &appsvcs, ingress_domain).await {
+        warn!(
+            "ns: {}, failed to update the status of the AppServices: {}",
+            ns, e
+        );
+    }
+
     // Collect routes and middlewares only if `disable_ingress` is false.
     let desired_routes: Vec<IngressRouteRoutes> = if cdb.spec.disable_ingress {
         vec![]
@@ -1003,8 +1499,49 @@ pub async fn reconcile_app_services(
             .collect::<Vec<String>>()
     };
 
+    let cfg = Config::default();
+    // With the Gateway API provider, HTTPRoutes and TLSRoutes replace the Traefik objects
+    if let (Some(domain), IngressProvider::GatewayApi) = (&domain, &cfg.ingress_provider) {
+        if let Err(e) = reconcile_app_routes(client.clone(), cdb, &cfg, domain, &appsvcs).await {
+            error!(
+                "Failed to update/apply Gateway API routes {}.{}: {}",
+                ns, coredb_name, e
+            );
+            has_errors = true;
+        }
+        let app_names: Vec<String> = appsvcs.iter().map(|a| a.name.clone()).collect();
+        // A Gateway implemented by Traefik applies the Middlewares referenced by the HTTPRoute
+        let middlewares = if cfg.gateway_traefik_middlewares {
+            desired_middlewares.clone()
+        } else {
+            vec![]
+        };
+        if let Err(e) = delete_traefik_app_routes(
+            client.clone(),
+            &coredb_name,
+            &ns,
+            oref.clone(),
+            &app_names,
+            middlewares,
+            &cdb.spec.ip_allow_list.clone().unwrap_or_default(),
+        )
+        .await
+        {
+            error!(
+                "Failed to delete Traefik routes {}.{}: {}",
+                ns, coredb_name, e
+            );
+            has_errors = true;
+        }
     // Only reconcile IngressRoute and IngressRouteTCP if DATA_PLANE_BASEDOMAIN is set
-    if domain.is_some() {
+    } else if domain.is_some() {
+        if let Err(e) = delete_app_routes(client.clone(), cdb).await {
+            error!(
+                "Failed to delete Gateway API routes {}.{}: {}",
+                ns, coredb_name, e
+            );
+            has_errors = true;
+        }
         match reconcile_ingress(
             client.clone(),
             &coredb_name,
@@ -1013,6 +1550,7 @@ pub async fn reconcile_app_services(
             desired_routes,
             desired_middlewares.clone(),
             desired_entry_points,
+            &cdb.spec.ip_allow_list.clone().unwrap_or_default(),
         )
         .await
         {
@@ -1042,6 +1580,7 @@ pub async fn reconcile_app_services(
                 vec![],
                 desired_entry_points_tcp.clone(),
                 &app_name,
+                &cdb.spec.ip_allow_list.clone().unwrap_or_default(),
             )
             .await
             {
@@ -1064,6 +1603,192 @@ pub async fn reconcile_app_services(
     Ok(())
 }
 
+// the URLs of the http routes of an AppService with an ingress path
+fn ingress_urls(appsvc: &AppService, coredb_name: &str, domain: &str) -> Vec<String> {
+    let mut urls: Vec<String> = appsvc
+        .routing
+        .iter()
+        .flatten()
+        .filter(|r| r.ingress_type == Some(IngressType::http))
+        .filter_map(|r| r.ingress_path.as_ref())
+        .map(|path| format!("https://{}.{}{}", coredb_name, domain, path))
+        .collect();
+    urls.dedup();
+    urls
+}
+
+// summarizes the health and rollout state of an AppService from its Deployment, pods and the
+// Unhealthy events of the pods
+fn generate_app_service_status(
+    appsvc: &AppService,
+    deployment: Option<&Deployment>,
+    pods: &[Pod],
+    unhealthy_events: &[CoreEvent],
+    ingress_urls: Vec<String>,
+) -> AppServiceStatus {
+    let spec = deployment.and_then(|d| d.spec.as_ref());
+    let status = deployment
+        .and_then(|d| d.status.clone())
+        .unwrap_or_default();
+    let image = spec.and_then(|s| {
+        s.template
+            .spec
+            .as_ref()?
+            .containers
+            .iter()
+            .find(|c| c.name == appsvc.name)?
+            .image
+            .clone()
+    });
+    let desired_replicas = spec.and_then(|s| s.replicas).unwrap_or(1);
+    let ready_replicas = status.ready_replicas.unwrap_or(0);
+    let updated_replicas = status.updated_replicas.unwrap_or(0);
+
+    let container_statuses: Vec<&ContainerStatus> = pods
+        .iter()
+        .filter_map(|p| p.status.as_ref()?.container_statuses.as_ref())
+        .flatten()
+        .filter(|c| c.name == appsvc.name)
+        .collect();
+    let restarts = container_statuses.iter().map(|c| c.restart_count).sum();
+    let last_restart_reason = container_statuses
+        .iter()
+        .filter_map(|c| c.last_state.as_ref()?.terminated.as_ref())
+        .max_by_key(|t| t.finished_at.as_ref().map(|f| f.0))
+        .map(|t| match &t.reason {
+            Some(reason) => format!("{} (exit code {})", reason, t.exit_code),
+            None => format!("exit code {}", t.exit_code),
+        });
+    let waiting_reason = container_statuses
+        .iter()
+        .filter_map(|c| c.state.as_ref()?.waiting.as_ref()?.reason.clone())
+        .next();
+
+    let pod_names: Vec<String> = pods.iter().map(|p| p.name_any()).collect();
+    let events: Vec<&CoreEvent> = unhealthy_events
+        .iter()
+        .filter(|e| {
+            e.involved_object
+                .name
+                .as_ref()
+                .is_some_and(|name| pod_names.contains(name))
+        })
+        .collect();
+    let probe_failures = events.iter().map(|e| e.count.unwrap_or(1)).sum();
+    let last_probe_failure = events
+        .iter()
+        .max_by_key(|e| e.last_timestamp.as_ref().map(|t| t.0))
+        .and_then(|e| e.message.clone());
+
+    let progress_deadline_exceeded = status.conditions.iter().flatten().any(|c| {
+        c.type_ == "Progressing" && c.reason.as_deref() == Some("ProgressDeadlineExceeded")
+    });
+    let phase = if progress_deadline_exceeded {
+        AppServicePhase::Failed
+    } else if waiting_reason.is_some() {
+        AppServicePhase::Degraded
+    } else if deployment.is_some()
+        && ready_replicas >= desired_replicas
+        && updated_replicas >= desired_replicas
+    {
+        AppServicePhase::Available
+    } else {
+        AppServicePhase::Progressing
+    };
+
+    AppServiceStatus {
+        name: appsvc.name.clone(),
+        phase,
+        image,
+        desired_replicas,
+        ready_replicas,
+        updated_replicas,
+        restarts,
+        last_restart_reason,
+        waiting_reason,
+        probe_failures,
+        last_probe_failure,
+        ingress_urls,
+    }
+}
+
+// updates status.app_services from the Deployments and pods of the AppServices
+async fn reconcile_app_services_status(
+    cdb: &CoreDB,
+    client: &Client,
+    appsvcs: &[AppService],
+    ingress_domain: Option<&str>,
+) -> Result<(), Error> {
+    let ns = cdb.namespace().unwrap_or_default();
+    let coredb_name = cdb.name_any();
+
+    let statuses = if appsvcs.is_empty() {
+        None
+    } else {
+        let deployments = get_appservice_deployment_objects(client, &ns, &coredb_name).await?;
+        let label_selector = format!(
+            "component={},coredb.io/name={}",
+            COMPONENT_NAME, coredb_name
+        );
+        let pod_api: Api<Pod> = Api::namespaced(client.clone(), &ns);
+        let pods = pod_api
+            .list(&ListParams::default().labels(&label_selector))
+            .await?
+            .items;
+        // the kubelet reports failed probes as Unhealthy events of the pod
+        let event_api: Api<CoreEvent> = Api::namespaced(client.clone(), &ns);
+        let unhealthy_events = event_api
+            .list(&ListParams::default().fields("reason=Unhealthy,involvedObject.kind=Pod"))
+            .await?
+            .items;
+
+        Some(
+            appsvcs
+                .iter()
+                .map(|appsvc| {
+                    let resource_name = format!("{}-{}", coredb_name, appsvc.name);
+                    let deployment = deployments.iter().find(|d| d.name_any() == resource_name);
+                    let app_pods: Vec<Pod> = pods
+                        .iter()
+                        .filter(|p| p.labels().get("app") == Some(&resource_name))
+                        .cloned()
+                        .collect();
+                    let urls = ingress_domain
+                        .map(|domain| ingress_urls(appsvc, &coredb_name, domain))
+                        .unwrap_or_default();
+                    generate_app_service_status(
+                        appsvc,
+                        deployment,
+                        &app_pods,
+                        &unhealthy_events,
+                        urls,
+                    )
+                })
+                .collect::<Vec<AppServiceStatus>>(),
+        )
+    };
+
+    if cdb.status.as_ref().and_then(|s| s.app_services.as_ref()) == statuses.as_ref() {
+        return Ok(());
+    }
+    let coredbs: Api<CoreDB> = Api::namespaced(client.clone(), &ns);
+    let patch_status = serde_json::json!({
+        "apiVersion": "coredb.io/v1alpha1",
+        "kind": "CoreDB",
+        "status": {
+            "app_services": statuses
+        }
+    });
+    let pp = PatchParams {
+        field_manager: Some("cntrlr".to_string()),
+        ..PatchParams::default()
+    };
+    coredbs
+        .patch_status(&coredb_name, &pp, &Patch::Merge(patch_status))
+        .await?;
+    Ok(())
+}
+
 pub async fn prepare_apps_connection_secret(client: Client, cdb: &CoreDB) -> Result<(), Error> {
     let namespace = cdb.namespace().unwrap();
     let cdb_name = cdb.metadata.name.clone().unwrap();
@@ -1080,7 +1805,7 @@ pub async fn prepare_apps_connection_secret(client: Client, cdb: &CoreDB) -> Res
     let mut new_secret_data = BTreeMap::new();
     for (key, value) in original_secret_data {
         match key.as_str() {
-            "r_uri" | "ro_uri" | "rw_uri" => {
+            "r_uri" | "ro_uri" | "rw_uri" | "pooler_uri" => {
                 let new_value = format!("{}?application_name=tembo-apps", value);
                 new_secret_data.insert(key, new_value);
             }
@@ -1317,4 +2042,374 @@ mod tests {
         assert_eq!(manager.lookup.len(), 6);
         assert_eq!(manager.vars[5].value, None);
     }
+
+    #[test]
+    fn test_generate_scaling_resources() {
+        let appsvc: AppService = serde_yaml::from_str(
+            r#"
+            name: postgrest
+            image: postgrest/postgrest:v12.2.8
+            replicas: 3
+            strategy:
+              maxSurge: 25%
+              maxUnavailable: 0
+            podDisruptionBudget: {}
+            "#,
+        )
+        .unwrap();
+        let oref = OwnerReference::default();
+        let annotations = BTreeMap::new();
+
+        let resources = generate_resource(
+            &appsvc,
+            "test",
+            "default",
+            oref.clone(),
+            None,
+            &annotations,
+            None,
+            None,
+        );
+        let spec = resources.deployment.spec.unwrap();
+        assert_eq!(spec.replicas, Some(3));
+        let rolling_update = spec.strategy.unwrap().rolling_update.unwrap();
+        assert_eq!(
+            rolling_update.max_surge,
+            Some(IntOrString::String("25%".to_string()))
+        );
+        assert_eq!(rolling_update.max_unavailable, Some(IntOrString::Int(0)));
+        assert!(resources.hpa.is_none());
+        let pdb_spec = resources.pdb.unwrap().spec.unwrap();
+        assert_eq!(pdb_spec.max_unavailable, Some(IntOrString::Int(1)));
+        assert_eq!(
+            pdb_spec.selector.unwrap().match_labels,
+            spec.selector.match_labels
+        );
+        // only one of minAvailable and maxUnavailable is set
+        let pdb = generate_pdb(
+            &AppPodDisruptionBudget {
+                min_available: Some(IntOrString::Int(2)),
+                max_unavailable: Some(IntOrString::Int(1)),
+            },
+            "test",
+            "test-postgrest",
+            "default",
+            oref.clone(),
+            &annotations,
+        );
+        let pdb_spec = pdb.spec.unwrap();
+        assert_eq!(pdb_spec.min_available, Some(IntOrString::Int(2)));
+        assert_eq!(pdb_spec.max_unavailable, None);
+
+        // the HorizontalPodAutoscaler manages the replicas
+        let appsvc: AppService = serde_yaml::from_str(
+            r#"
+            name: postgrest
+            image: postgrest/postgrest:v12.2.8
+            replicas: 3
+            autoscaling:
+              maxReplicas: 5
+              metrics:
+                - name: http_requests_per_second
+                  averageValue: "100"
+            "#,
+        )
+        .unwrap();
+        let resources = generate_resource(
+            &appsvc,
+            "test",
+            "default",
+            oref,
+            None,
+            &annotations,
+            None,
+            None,
+        );
+        assert_eq!(resources.deployment.spec.unwrap().replicas, None);
+        assert!(resources.pdb.is_none());
+        let hpa_spec = resources.hpa.unwrap().spec.unwrap();
+        assert_eq!(hpa_spec.scale_target_ref.name, "test-postgrest");
+        assert_eq!(hpa_spec.min_replicas, Some(1));
+        assert_eq!(hpa_spec.max_replicas, 5);
+        // no CPU target when only custom metrics are configured
+        let metrics = hpa_spec.metrics.unwrap();
+        assert_eq!(metrics.len(), 1);
+        assert_eq!(metrics[0].type_, "Pods");
+
+        // invalid bounds are clamped
+        let hpa = generate_hpa(
+            &AppAutoscaling {
+                min_replicas: 3,
+                max_replicas: 2,
+                target_cpu_utilization_percentage: None,
+                metrics: vec![],
+            },
+            "test",
+            "test-postgrest",
+            "default",
+            OwnerReference::default(),
+            &annotations,
+        );
+        let hpa_spec = hpa.spec.unwrap();
+        assert_eq!((hpa_spec.min_replicas, hpa_spec.max_replicas), (Some(2), 2));
+
+        let autoscaling = AppAutoscaling {
+            min_replicas: 2,
+            max_replicas: 4,
+            target_cpu_utilization_percentage: None,
+            metrics: vec![],
+        };
+        let hpa = generate_hpa(
+            &autoscaling,
+            "test",
+            "test-postgrest",
+            "default",
+            OwnerReference::default(),
+            &annotations,
+        );
+        let metrics = hpa.spec.unwrap().metrics.unwrap();
+        assert_eq!(metrics[0].type_, "Resource");
+        assert_eq!(
+            metrics[0]
+                .resource
+                .as_ref()
+                .unwrap()
+                .target
+                .average_utilization,
+            Some(DEFAULT_TARGET_CPU_UTILIZATION_PERCENTAGE)
+        );
+    }
+
+    #[test]
+    fn test_generate_user_env_vars() {
+        let appsvc: AppService = serde_yaml::from_str(
+            r#"
+            name: embeddings
+            image: quay.io/tembo/vector-serve:latest
+            env:
+              - name: OPENAI_API_KEY
+                valueFromSecret:
+                  name: openai
+                  key: api_key
+              - name: MODEL
+                valueFromConfigMap:
+                  name: embeddings-config
+                  key: model
+              - name: DATABASE_URL
+                valueFromPlatform: PoolerConnection
+              - name: ADMIN_DATABASE_URL
+                valueFromPlatform: SuperuserConnection
+              - name: PGHOST
+                valueFromPlatform: InstanceHostname
+              - name: MISSING
+            "#,
+        )
+        .unwrap();
+        let env: Vec<EnvVar> = appsvc
+            .env
+            .clone()
+            .unwrap()
+            .into_iter()
+            .filter_map(|e| generate_user_env_var(e, "test", "org-test", "test-embeddings"))
+            .collect();
+        assert_eq!(env.len(), 5);
+
+        let secret_ref = env[0]
+            .value_from
+            .as_ref()
+            .unwrap()
+            .secret_key_ref
+            .as_ref()
+            .unwrap();
+        assert_eq!(
+            (secret_ref.name.as_str(), secret_ref.key.as_str()),
+            ("openai", "api_key")
+        );
+        let config_map_ref = env[1]
+            .value_from
+            .as_ref()
+            .unwrap()
+            .config_map_key_ref
+            .as_ref()
+            .unwrap();
+        assert_eq!(
+            (config_map_ref.name.as_str(), config_map_ref.key.as_str()),
+            ("embeddings-config", "model")
+        );
+        let pooler_ref = env[2]
+            .value_from
+            .as_ref()
+            .unwrap()
+            .secret_key_ref
+            .as_ref()
+            .unwrap();
+        assert_eq!(pooler_ref.name, "test-apps");
+        assert_eq!(pooler_ref.key, "pooler_uri");
+        assert_eq!(pooler_ref.optional, Some(true));
+        let superuser_ref = env[3]
+            .value_from
+            .as_ref()
+            .unwrap()
+            .secret_key_ref
+            .as_ref()
+            .unwrap();
+        assert_eq!(superuser_ref.name, "test-connection");
+        assert_eq!(
+            env[4].value,
+            Some("test-rw.org-test.svc.cluster.local".to_string())
+        );
+
+        // only the valueFromSecret and valueFromConfigMap sources roll out the Deployment,
+        // not the platform secrets
+        let (secrets, config_maps) = referenced_env_sources(&appsvc);
+        assert_eq!(secrets, vec!["openai"]);
+        assert_eq!(config_maps, vec!["embeddings-config"]);
+        let (secrets, config_maps) = referenced_env_sources(&AppService::default());
+        assert!(secrets.is_empty() && config_maps.is_empty());
+
+        let deployment = generate_deployment(
+            &appsvc,
+            "test",
+            "test-embeddings",
+            "org-test",
+            OwnerReference::default(),
+            &BTreeMap::new(),
+            None,
+            Some("0123456789abcdef".to_string()),
+        );
+        let pod_annotations = deployment
+            .spec
+            .unwrap()
+            .template
+            .metadata
+            .unwrap()
+            .annotations;
+        assert_eq!(
+            pod_annotations.unwrap()[ENV_SOURCES_HASH_ANNOTATION],
+            "0123456789abcdef"
+        );
+        assert!(!deployment
+            .metadata
+            .annotations
+            .unwrap()
+            .contains_key(ENV_SOURCES_HASH_ANNOTATION));
+    }
+
+    #[test]
+    fn test_fnv1a() {
+        let hash = |parts: &[&str]| {
+            let mut hasher = Fnv1a::default();
+            for part in parts {
+                hasher.write(part.as_bytes());
+            }
+            hasher.0
+        };
+        assert_eq!(hash(&["ab", "c"]), hash(&["ab", "c"]));
+        assert_ne!(hash(&["ab", "c"]), hash(&["a", "bc"]));
+        assert_ne!(hash(&["key", "value1"]), hash(&["key", "value2"]));
+    }
+
+    #[test]
+    fn test_generate_app_service_status() {
+        let appsvc: AppService = serde_yaml::from_str(
+            r#"
+            name: postgrest
+            image: postgrest/postgrest:v12.2.8
+            replicas: 2
+            routing:
+              - port: 3000
+                ingressPath: /rest/v1
+              - port: 3000
+                ingressPath: /graphql/v1
+              - port: 5432
+                ingressType: tcp
+            "#,
+        )
+        .unwrap();
+        let mut deployment = generate_deployment(
+            &appsvc,
+            "test",
+            "test-postgrest",
+            "org-test",
+            OwnerReference::default(),
+            &BTreeMap::new(),
+            None,
+            None,
+        );
+        let pod: Pod = serde_json::from_value(serde_json::json!({
+            "metadata": {"name": "test-postgrest-abc"},
+            "status": {"containerStatuses": [{
+                "name": "postgrest",
+                "image": "postgrest/postgrest:v12.2.8",
+                "imageID": "",
+                "ready": false,
+                "restartCount": 4,
+                "state": {"waiting": {"reason": "CrashLoopBackOff"}},
+                "lastState": {"terminated": {"exitCode": 137, "reason": "OOMKilled"}},
+            }]},
+        }))
+        .unwrap();
+        let event: CoreEvent = serde_json::from_value(serde_json::json!({
+            "metadata": {"name": "test-postgrest-abc.1"},
+            "involvedObject": {"kind": "Pod", "name": "test-postgrest-abc"},
+            "reason": "Unhealthy",
+            "message": "Readiness probe failed: connection refused",
+            "count": 3,
+        }))
+        .unwrap();
+        let other_event: CoreEvent = serde_json::from_value(serde_json::json!({
+            "metadata": {"name": "other-pod.1"},
+            "involvedObject": {"kind": "Pod", "name": "other-pod"},
+            "reason": "Unhealthy",
+        }))
+        .unwrap();
+
+        let urls = ingress_urls(&appsvc, "test", "data-1.example.com");
+        assert_eq!(
+            urls,
+            vec![
+                "https://test.data-1.example.com/rest/v1",
+                "https://test.data-1.example.com/graphql/v1"
+            ]
+        );
+        let status = generate_app_service_status(
+            &appsvc,
+            Some(&deployment),
+            &[pod],
+            &[event, other_event],
+            urls,
+        );
+        assert_eq!(status.phase, AppServicePhase::Degraded);
+        assert_eq!(
+            status.image,
+            Some("postgrest/postgrest:v12.2.8".to_string())
+        );
+        assert_eq!(status.desired_replicas, 2);
+        assert_eq!(status.ready_replicas, 0);
+        assert_eq!(status.restarts, 4);
+        assert_eq!(
+            status.last_restart_reason,
+            Some("OOMKilled (exit code 137)".to_string())
+        );
+        assert_eq!(status.waiting_reason, Some("CrashLoopBackOff".to_string()));
+        assert_eq!(status.probe_failures, 3);
+        assert_eq!(
+            status.last_probe_failure,
+            Some("Readiness probe failed: connection refused".to_string())
+        );
+
+        deployment.status = Some(
+            serde_json::from_value(serde_json::json!({
+                "readyReplicas": 2,
+                "updatedReplicas": 2,
+            }))
+            .unwrap(),
+        );
+        let status = generate_app_service_status(&appsvc, Some(&deployment), &[], &[], vec![]);
+        assert_eq!(status.phase, AppServicePhase::Available);
+
+        let status = generate_app_service_status(&appsvc, None, &[], &[], vec![]);
+        assert_eq!(status.phase, AppServicePhase::Progressing);
+        assert_eq!(status.image, None);
+    }
 }
@@ -1,3 +1,4 @@
 pub mod ingress;
+pub mod jobs;
 pub mod manager;
 pub mod types;
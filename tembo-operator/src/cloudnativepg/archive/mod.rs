@@ -1 +1,2 @@
+pub(crate) mod restore_points;
 pub(crate) mod wal;
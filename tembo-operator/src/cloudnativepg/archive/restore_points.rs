@@ -0,0 +1,134 @@
+use crate::{
+    apis::coredb_types::{CoreDB, RestorePointStatus},
+    databases::{psql_error, quote_literal},
+    patch_cdb_status_merge, Context,
+};
+use chrono::Utc;
+use kube::{runtime::controller::Action, Api, ResourceExt};
+use serde_json::json;
+use std::sync::Arc;
+use tracing::{error, info};
+
+// Restore point names are limited to MAXFNAMELEN - 1 bytes by Postgres
+const MAX_RESTORE_POINT_NAME_LENGTH: usize = 63;
+
+fn create_restore_point_query(name: &str) -> String {
+    format!("SELECT pg_create_restore_point({});", quote_literal(name))
+}
+
+// Names of spec.backup.restorePoints which have not been created yet
+fn pending_restore_points<'a>(
+    requested: &'a [String],
+    created: &[RestorePointStatus],
+) -> Vec<&'a str> {
+    let mut pending: Vec<&str> = Vec::new();
+    for name in requested {
+        if created.iter().any(|point| &point.name == name) || pending.contains(&name.as_str()) {
+            continue;
+        }
+        if name.is_empty() || name.len() > MAX_RESTORE_POINT_NAME_LENGTH {
+            error!(
+                "Restore point name '{}' must be between 1 and {} bytes long, skipping",
+                name, MAX_RESTORE_POINT_NAME_LENGTH
+            );
+            continue;
+        }
+        pending.push(name);
+    }
+    pending
+}
+
+/// Creates the named restore points of `spec.backup.restorePoints` which do not exist yet,
+/// and records their LSN in `status.restore_points`.
+///
+/// Restore points removed from the spec are kept in the status, since they remain in
+/// the WAL archive and can still be used as a recovery target.
+pub async fn reconcile_restore_points(cdb: &CoreDB, ctx: Arc<Context>) -> Result<(), Action> {
+    // Restore points can't be created during recovery
+    if cdb.spec.is_replica() {
+        return Ok(());
+    }
+    let mut created = cdb
+        .status
+        .as_ref()
+        .and_then(|s| s.restore_points.clone())
+        .unwrap_or_default();
+    let pending = pending_restore_points(&cdb.spec.backup.restore_points, &created);
+    if pending.is_empty() {
+        return Ok(());
+    }
+
+    let name = cdb.name_any();
+    for restore_point in pending {
+        let output = cdb
+            .psql(
+                create_restore_point_query(restore_point),
+                "postgres".to_string(),
+                ctx.clone(),
+            )
+            .await?;
+        let lsn = match output.get_field(0) {
+            Some(lsn) if output.success && !lsn.is_empty() => lsn,
+            _ => {
+                error!(
+                    "Failed to create restore point {} for {}: {}",
+                    restore_point,
+                    name,
+                    psql_error(&output)
+                );
+                continue;
+            }
+        };
+        info!(
+            "Created restore point {} at {} for {}",
+            restore_point, lsn, name
+        );
+        created.push(RestorePointStatus {
+            name: restore_point.to_string(),
+            lsn,
+            created_at: Utc::now(),
+        });
+    }
+
+    let coredbs: Api<CoreDB> = Api::namespaced(ctx.client.clone(), &cdb.namespace().unwrap());
+    let patch_status = json!({
+        "apiVersion": "coredb.io/v1alpha1",
+        "kind": "CoreDB",
+        "status": {
+            "restore_points": created
+        }
+    });
+    patch_cdb_status_merge(&coredbs, &name, patch_status).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pending_restore_points() {
+        let requested = vec![
+            "before-migration".to_string(),
+            "after-migration".to_string(),
+            "after-migration".to_string(),
+            "x".repeat(64),
+        ];
+        let created = vec![RestorePointStatus {
+            name: "before-migration".to_string(),
+            lsn: "0/3000060".to_string(),
+            created_at: Utc::now(),
+        }];
+        assert_eq!(
+            pending_restore_points(&requested, &created),
+            vec!["after-migration"]
+        );
+    }
+
+    #[test]
+    fn test_create_restore_point_query() {
+        assert_eq!(
+            create_restore_point_query("it's"),
+            "SELECT pg_create_restore_point('it''s');"
+        );
+    }
+}
@@ -0,0 +1,532 @@
+use crate::{
+    apis::coredb_types::{
+        BackupVerification, BackupVerificationMethod, BackupVerificationPhase,
+        BackupVerificationStatus, CoreDB,
+    },
+    cloudnativepg::{
+        backups::Backup,
+        clusters::{
+            Cluster, ClusterBootstrap, ClusterBootstrapRecovery, ClusterBootstrapRecoveryBackup,
+            ClusterBootstrapRecoveryVolumeSnapshots,
+            ClusterBootstrapRecoveryVolumeSnapshotsStorage,
+            ClusterBootstrapRecoveryVolumeSnapshotsWalStorage, ClusterMonitoring,
+        },
+        cnpg::cnpg_cluster_from_cdb,
+        cnpg_utils::{backup_completed_at, last_cron_occurrence},
+        major_upgrade::{delete_cluster, get_cluster_by_name, is_cluster_ready},
+    },
+    databases::psql_error,
+    patch_cdb_status_merge,
+    psql::PsqlCommand,
+    trunk::extensions_that_require_load,
+    Context,
+};
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use k8s_openapi::api::core::v1::Pod;
+use kube::{
+    api::{ListParams, Patch, PatchParams},
+    runtime::controller::Action,
+    Api, ResourceExt,
+};
+use serde_json::json;
+use std::{collections::BTreeMap, sync::Arc};
+use tokio::time::Duration;
+use tracing::{debug, error, info, instrument, warn};
+
+// verification_cluster_name returns the name of the throwaway cluster restoring a backup
+pub(crate) fn verification_cluster_name(name: &str) -> String {
+    format!("{}-verify", name)
+}
+
+fn backup_method_name(method: BackupVerificationMethod) -> &'static str {
+    match method {
+        BackupVerificationMethod::ObjectStore => "barmanObjectStore",
+        BackupVerificationMethod::VolumeSnapshot => "volumeSnapshot",
+    }
+}
+
+// latest_completed_backup returns the most recent completed backup taken with the method
+fn latest_completed_backup(
+    backups: Vec<Backup>,
+    method: BackupVerificationMethod,
+) -> Option<Backup> {
+    backups
+        .into_iter()
+        .filter(|backup| {
+            backup.status.as_ref().is_some_and(|s| {
+                s.phase.as_deref() == Some("completed")
+                    && s.method.as_deref() == Some(backup_method_name(method))
+            })
+        })
+        .filter(|backup| backup_completed_at(backup).is_some())
+        .max_by_key(backup_completed_at)
+}
+
+// The volume snapshots of a volume snapshot backup, by type
+fn backup_volume_snapshot(backup: &Backup, snapshot_type: &str) -> Option<String> {
+    backup
+        .status
+        .as_ref()?
+        .snapshot_backup_status
+        .as_ref()?
+        .elements
+        .as_ref()?
+        .iter()
+        .find(|element| element.r#type == snapshot_type)
+        .map(|element| element.name.clone())
+}
+
+fn verification_bootstrap(backup: &Backup, method: BackupVerificationMethod) -> ClusterBootstrap {
+    let recovery = match method {
+        BackupVerificationMethod::ObjectStore => ClusterBootstrapRecovery {
+            backup: Some(ClusterBootstrapRecoveryBackup {
+                name: backup.name_any(),
+                endpoint_ca: None,
+            }),
+            ..ClusterBootstrapRecovery::default()
+        },
+        BackupVerificationMethod::VolumeSnapshot => ClusterBootstrapRecovery {
+            volume_snapshots: Some(ClusterBootstrapRecoveryVolumeSnapshots {
+                storage: ClusterBootstrapRecoveryVolumeSnapshotsStorage {
+                    name: backup_volume_snapshot(backup, "PG_DATA")
+                        .unwrap_or_else(|| backup.name_any()),
+                    kind: "VolumeSnapshot".to_string(),
+                    api_group: Some("snapshot.storage.k8s.io".to_string()),
+                },
+                wal_storage: backup_volume_snapshot(backup, "PG_WAL").map(|name| {
+                    ClusterBootstrapRecoveryVolumeSnapshotsWalStorage {
+                        name,
+                        kind: "VolumeSnapshot".to_string(),
+                        api_group: Some("snapshot.storage.k8s.io".to_string()),
+                    }
+                }),
+                ..ClusterBootstrapRecoveryVolumeSnapshots::default()
+            }),
+            ..ClusterBootstrapRecovery::default()
+        },
+    };
+    ClusterBootstrap {
+        recovery: Some(ClusterBootstrapRecovery {
+            database: Some("app".to_string()),
+            owner: Some("app".to_string()),
+            ..recovery
+        }),
+        ..ClusterBootstrap::default()
+    }
+}
+
+// verification_cluster_from_cdb returns a single-instance copy of the Cluster of the
+// instance, bootstrapped from the backup
+fn verification_cluster_from_cdb(
+    cdb: &CoreDB,
+    backup: &Backup,
+    method: BackupVerificationMethod,
+    requires_load: BTreeMap<String, String>,
+) -> Cluster {
+    let mut cluster = cnpg_cluster_from_cdb(cdb, None, requires_load);
+    cluster.metadata.name = Some(verification_cluster_name(&cdb.name_any()));
+    if let Some(annotations) = cluster.metadata.annotations.as_mut() {
+        annotations.remove("cnpg.io/fencedInstances");
+        annotations.remove("cnpg.io/hibernation");
+    }
+    cluster.spec.instances = 1;
+    cluster.spec.bootstrap = Some(verification_bootstrap(backup, method));
+    cluster.spec.external_clusters = None;
+    cluster.spec.replica = None;
+    // The verification cluster must never write into the backups of the instance
+    cluster.spec.backup = None;
+    cluster.spec.monitoring = Some(ClusterMonitoring {
+        enable_pod_monitor: Some(false),
+        ..ClusterMonitoring::default()
+    });
+    // Libraries installed with trunk are not available in the new pod
+    if let Some(postgresql) = cluster.spec.postgresql.as_mut() {
+        postgresql.shared_preload_libraries = None;
+    }
+    cluster
+}
+
+// The check fails when the query errors or returns false
+fn check_passed(success: bool, first_field: Option<&str>) -> bool {
+    success && !matches!(first_field, Some("f") | Some("false"))
+}
+
+// run_check runs the sanity check query on the primary of the verification cluster
+async fn run_check(
+    verification: &BackupVerification,
+    cluster_name: &str,
+    namespace: &str,
+    ctx: Arc<Context>,
+) -> Result<(), String> {
+    let pods: Api<Pod> = Api::namespaced(ctx.client.clone(), namespace);
+    let lp = ListParams::default().labels(&format!("cnpg.io/cluster={cluster_name},role=primary"));
+    let pod_name = pods
+        .list(&lp)
+        .await
+        .map_err(|e| format!("Error listing pods of {}: {}", cluster_name, e))?
+        .items
+        .into_iter()
+        .find_map(|pod| pod.metadata.name)
+        .ok_or_else(|| format!("No primary pod found for {}", cluster_name))?;
+
+    let output = PsqlCommand::new(
+        pod_name,
+        namespace.to_string(),
+        verification.query.clone(),
+        verification.database.clone(),
+        ctx,
+    )
+    .execute()
+    .await
+    .map_err(|_| "Error running the verification query".to_string())?;
+
+    let first_field = output.get_field(0);
+    if check_passed(output.success, first_field.as_deref()) {
+        Ok(())
+    } else if output.success {
+        Err("The verification query returned false".to_string())
+    } else {
+        Err(format!(
+            "The verification query failed: {}",
+            psql_error(&output)
+        ))
+    }
+}
+
+fn finish(status: &mut BackupVerificationStatus, result: Result<(), String>, now: DateTime<Utc>) {
+    status.finished_at = Some(now);
+    match result {
+        Ok(()) => {
+            status.phase = BackupVerificationPhase::Succeeded;
+            status.message = None;
+            status.last_verified_backup = status.backup_completed_at;
+        }
+        Err(message) => {
+            status.phase = BackupVerificationPhase::Failed;
+            status.message = Some(message);
+        }
+    }
+}
+
+// is_verification_due returns true when the schedule fired since the last verification started
+fn is_verification_due(
+    schedule: &str,
+    previous: Option<&BackupVerificationStatus>,
+    now: DateTime<Utc>,
+) -> bool {
+    match last_cron_occurrence(schedule, now) {
+        Some(due) => previous.is_none_or(|status| status.started_at < due),
+        None => false,
+    }
+}
+
+async fn patch_verification_status(
+    coredbs: &Api<CoreDB>,
+    name: &str,
+    status: &BackupVerificationStatus,
+) -> Result<(), Action> {
+    let patch_status = json!({
+        "apiVersion": "coredb.io/v1alpha1",
+        "kind": "CoreDB",
+        "status": {
+            "backup_verification": status
+        }
+    });
+    patch_cdb_status_merge(coredbs, name, patch_status).await
+}
+
+/// Verifies the backups of the instance according to `spec.backup.verification`.
+///
+/// When the schedule fires, the latest completed backup is restored into a throwaway
+/// cluster. Once the cluster is ready, the sanity check query is run against it and the
+/// cluster is deleted. The outcome is recorded in `status.backup_verification` and in
+/// the backup verification metrics.
+///
+/// The verification runs alongside the reconcile loop and never blocks it.
+#[instrument(skip(cdb, ctx), fields(trace_id, instance_name = %cdb.name_any()))]
+pub async fn reconcile_backup_verification(cdb: &CoreDB, ctx: Arc<Context>) -> Result<(), Action> {
+    let name = cdb.name_any();
+    let namespace = cdb.namespace().ok_or_else(|| {
+        error!("Namespace is empty for instance: {}.", name);
+        Action::requeue(Duration::from_secs(300))
+    })?;
+    let coredbs: Api<CoreDB> = Api::namespaced(ctx.client.clone(), &namespace);
+    let cluster_api: Api<Cluster> = Api::namespaced(ctx.client.clone(), &namespace);
+    let cluster_name = verification_cluster_name(&name);
+    let previous = cdb
+        .status
+        .as_ref()
+        .and_then(|s| s.backup_verification.clone());
+    let now = Utc::now();
+
+    let Some(verification) = cdb.spec.backup.verification.as_ref() else {
+        // Clean up a verification interrupted by disabling it
+        if let Some(mut status) = previous.filter(|s| s.phase == BackupVerificationPhase::Restoring)
+        {
+            delete_cluster(&cluster_api, &cluster_name).await?;
+            finish(
+                &mut status,
+                Err("Backup verification was disabled".to_string()),
+                now,
+            );
+            patch_verification_status(&coredbs, &name, &status).await?;
+        }
+        return Ok(());
+    };
+
+    match previous {
+        Some(mut status) if status.phase == BackupVerificationPhase::Restoring => {
+            let timed_out =
+                now - status.started_at > ChronoDuration::minutes(verification.timeout_minutes);
+            let result = match get_cluster_by_name(&cluster_api, &cluster_name).await? {
+                Some(cluster) if is_cluster_ready(&cluster) => {
+                    run_check(verification, &cluster_name, &namespace, ctx.clone()).await
+                }
+                _ if timed_out => Err(format!(
+                    "Restore did not complete within {} minutes",
+                    verification.timeout_minutes
+                )),
+                _ => {
+                    debug!("Waiting for verification cluster {}", cluster_name);
+                    return Ok(());
+                }
+            };
+            delete_cluster(&cluster_api, &cluster_name).await?;
+            match &result {
+                Ok(()) => info!("Verified backup {} of {}", status.backup, name),
+                Err(message) => warn!(
+                    "Verification of backup {} of {} failed: {}",
+                    status.backup, name, message
+                ),
+            }
+            finish(&mut status, result, now);
+            ctx.metrics.backup_verification.set_result(
+                cdb,
+                status.phase == BackupVerificationPhase::Succeeded,
+                status.last_verified_backup.map(|t| t.timestamp()),
+            );
+            patch_verification_status(&coredbs, &name, &status).await
+        }
+        previous => {
+            if !is_verification_due(&verification.schedule, previous.as_ref(), now) {
+                return Ok(());
+            }
+            let backups: Api<Backup> = Api::namespaced(ctx.client.clone(), &namespace);
+            let lp = ListParams::default().labels(&format!("cnpg.io/cluster={}", name));
+            let backup_list = backups.list(&lp).await.map_err(|e| {
+                error!("Error listing backups of {}: {}", name, e);
+                Action::requeue(Duration::from_secs(300))
+            })?;
+            let Some(backup) = latest_completed_backup(backup_list.items, verification.method)
+            else {
+                debug!("No completed backup of {} to verify yet", name);
+                return Ok(());
+            };
+
+            let requires_load =
+                extensions_that_require_load(ctx.client.clone(), &namespace).await?;
+            let cluster =
+                verification_cluster_from_cdb(cdb, &backup, verification.method, requires_load);
+            let pp = PatchParams::apply("cntrlr").force();
+            cluster_api
+                .patch(&cluster_name, &pp, &Patch::Apply(&cluster))
+                .await
+                .map_err(|e| {
+                    error!(
+                        "Error creating verification Cluster {}: {}",
+                        cluster_name, e
+                    );
+                    Action::requeue(Duration::from_secs(300))
+                })?;
+            info!(
+                "Restoring backup {} of {} into {}",
+                backup.name_any(),
+                name,
+                cluster_name
+            );
+
+            let status = BackupVerificationStatus {
+                phase: BackupVerificationPhase::Restoring,
+                backup: backup.name_any(),
+                backup_completed_at: backup_completed_at(&backup),
+                started_at: now,
+                finished_at: None,
+                message: None,
+                last_verified_backup: previous.and_then(|s| s.last_verified_backup),
+            };
+            patch_verification_status(&coredbs, &name, &status).await
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn backup(name: &str, method: &str, phase: &str, stopped_at: &str) -> Backup {
+        serde_json::from_value(json!({
+            "apiVersion": "postgresql.cnpg.io/v1",
+            "kind": "Backup",
+            "metadata": { "name": name, "namespace": "default" },
+            "spec": { "cluster": { "name": "test" }, "method": method },
+            "status": {
+                "phase": phase,
+                "method": method,
+                "stoppedAt": stopped_at,
+                "snapshotBackupStatus": {
+                    "elements": [
+                        { "name": format!("{name}-data"), "type": "PG_DATA" },
+                        { "name": format!("{name}-wal"), "type": "PG_WAL" }
+                    ]
+                }
+            }
+        }))
+        .expect("Failed to parse Backup")
+    }
+
+    fn test_cdb() -> CoreDB {
+        let cdb_yaml = r#"
+        apiVersion: coredb.io/v1alpha1
+        kind: CoreDB
+        metadata:
+          name: test
+          namespace: default
+          uid: 752d59ef-2671-4890-9feb-0097459b18c8
+        spec:
+          backup:
+            destinationPath: s3://tembo-backup/test
+            verification:
+              query: SELECT count(*) > 0 FROM pg_class
+          image: quay.io/tembo/standard-cnpg:15-bffd097
+          replicas: 2
+        "#;
+        serde_yaml::from_str(cdb_yaml).expect("Failed to parse YAML")
+    }
+
+    #[test]
+    fn test_latest_completed_backup() {
+        let backups = vec![
+            backup(
+                "old",
+                "barmanObjectStore",
+                "completed",
+                "2024-11-10T00:00:00Z",
+            ),
+            backup(
+                "new",
+                "barmanObjectStore",
+                "completed",
+                "2024-11-11T00:00:00Z",
+            ),
+            backup(
+                "running",
+                "barmanObjectStore",
+                "running",
+                "2024-11-12T00:00:00Z",
+            ),
+            backup(
+                "snap",
+                "volumeSnapshot",
+                "completed",
+                "2024-11-12T00:00:00Z",
+            ),
+        ];
+        let latest =
+            latest_completed_backup(backups.clone(), BackupVerificationMethod::ObjectStore)
+                .unwrap();
+        assert_eq!(latest.name_any(), "new");
+        let latest =
+            latest_completed_backup(backups, BackupVerificationMethod::VolumeSnapshot).unwrap();
+        assert_eq!(latest.name_any(), "snap");
+        assert!(latest_completed_backup(vec![], BackupVerificationMethod::ObjectStore).is_none());
+    }
+
+    #[test]
+    fn test_verification_cluster_from_cdb() {
+        let cdb = test_cdb();
+        let verification = cdb.spec.backup.verification.as_ref().unwrap();
+        assert_eq!(verification.schedule, "0 3 * * SUN");
+        assert_eq!(verification.method, BackupVerificationMethod::ObjectStore);
+
+        let object_store = backup(
+            "new",
+            "barmanObjectStore",
+            "completed",
+            "2024-11-11T00:00:00Z",
+        );
+        let cluster = verification_cluster_from_cdb(
+            &cdb,
+            &object_store,
+            BackupVerificationMethod::ObjectStore,
+            BTreeMap::new(),
+        );
+        assert_eq!(cluster.metadata.name.as_deref(), Some("test-verify"));
+        assert_eq!(cluster.spec.instances, 1);
+        assert!(cluster.spec.backup.is_none());
+        let recovery = cluster.spec.bootstrap.unwrap().recovery.unwrap();
+        assert_eq!(recovery.backup.unwrap().name, "new");
+        assert!(recovery.source.is_none());
+
+        let snapshot = backup(
+            "snap",
+            "volumeSnapshot",
+            "completed",
+            "2024-11-12T00:00:00Z",
+        );
+        let cluster = verification_cluster_from_cdb(
+            &cdb,
+            &snapshot,
+            BackupVerificationMethod::VolumeSnapshot,
+            BTreeMap::new(),
+        );
+        let snapshots = cluster
+            .spec
+            .bootstrap
+            .unwrap()
+            .recovery
+            .unwrap()
+            .volume_snapshots
+            .unwrap();
+        assert_eq!(snapshots.storage.name, "snap-data");
+        assert_eq!(snapshots.wal_storage.unwrap().name, "snap-wal");
+    }
+
+    #[test]
+    fn test_check_passed() {
+        assert!(check_passed(true, Some("1")));
+        assert!(check_passed(true, Some("t")));
+        assert!(!check_passed(true, Some("f")));
+        assert!(!check_passed(false, None));
+    }
+
+    #[test]
+    fn test_is_verification_due_and_finish() {
+        let now: DateTime<Utc> = "2024-11-12T12:00:00Z".parse().unwrap();
+        assert!(is_verification_due("0 3 * * *", None, now));
+
+        let mut status = BackupVerificationStatus {
+            phase: BackupVerificationPhase::Restoring,
+            backup: "new".to_string(),
+            backup_completed_at: Some("2024-11-11T00:00:00Z".parse().unwrap()),
+            started_at: "2024-11-12T03:00:00Z".parse().unwrap(),
+            finished_at: None,
+            message: None,
+            last_verified_backup: None,
+        };
+        assert!(!is_verification_due("0 3 * * *", Some(&status), now));
+        assert!(is_verification_due(
+            "0 3 * * *",
+            Some(&status),
+            "2024-11-13T03:01:00Z".parse().unwrap()
+        ));
+        assert!(!is_verification_due("invalid", None, now));
+
+        finish(&mut status, Err("failed".to_string()), now);
+        assert_eq!(status.phase, BackupVerificationPhase::Failed);
+        assert!(status.last_verified_backup.is_none());
+        finish(&mut status, Ok(()), now);
+        assert_eq!(status.phase, BackupVerificationPhase::Succeeded);
+        assert_eq!(status.last_verified_backup, status.backup_completed_at);
+        assert!(status.message.is_none());
+    }
+}
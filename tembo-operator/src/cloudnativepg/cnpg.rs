@@ -1,11 +1,16 @@
-use crate::extensions::install::find_trunk_installs_to_pod;
+use crate::extensions::{
+    dependencies::resolve_extension_dependencies, install::find_trunk_installs_to_pod,
+};
 use crate::ingress_route_crd::{
     IngressRoute, IngressRouteRoutes, IngressRouteRoutesKind, IngressRouteRoutesServices,
     IngressRouteRoutesServicesKind, IngressRouteSpec, IngressRouteTls,
 };
 use crate::{
     apis::{
-        coredb_types::{Backup as CoreDBBackup, CoreDB, Restore, S3Credentials},
+        coredb_types::{
+            Backup as CoreDBBackup, ClientCertMode, CoreDB, PgBouncerRoute, ReplicaOf, Restore,
+            S3Credentials,
+        },
         postgres_parameters::MergeError,
     },
     cloudnativepg::{
@@ -38,18 +43,21 @@ use crate::{
             ClusterManagedRolesPasswordSecret, ClusterMonitoring,
             ClusterMonitoringCustomQueriesConfigMap, ClusterNodeMaintenanceWindow,
             ClusterPostgresql, ClusterPostgresqlSyncReplicaElectionConstraint,
-            ClusterPrimaryUpdateMethod, ClusterPrimaryUpdateStrategy, ClusterReplicationSlots,
-            ClusterReplicationSlotsHighAvailability, ClusterResources,
+            ClusterPrimaryUpdateMethod, ClusterPrimaryUpdateStrategy, ClusterReplica,
+            ClusterReplicationSlots, ClusterReplicationSlotsHighAvailability, ClusterResources,
             ClusterServiceAccountTemplate, ClusterServiceAccountTemplateMetadata, ClusterSpec,
             ClusterStorage, ClusterSuperuserSecret,
         },
         cnpg_utils::{
             get_pooler_instances, is_image_updated, patch_cluster, restart_and_wait_for_restart,
         },
+        major_upgrade::{
+            apply_upgrade_to_cluster, is_upgrade_in_progress, reconcile_major_upgrade,
+        },
         placement::cnpg_placement::PlacementConfig,
         poolers::{
-            Pooler, PoolerCluster, PoolerPgbouncer, PoolerSpec, PoolerTemplate, PoolerTemplateSpec,
-            PoolerTemplateSpecContainers, PoolerType,
+            Pooler, PoolerCluster, PoolerMonitoring, PoolerPgbouncer, PoolerSpec, PoolerTemplate,
+            PoolerTemplateSpec, PoolerTemplateSpecContainers, PoolerType,
         },
         scheduledbackups::{
             ScheduledBackup, ScheduledBackupBackupOwnerReference, ScheduledBackupCluster,
@@ -62,7 +70,7 @@ use crate::{
     is_postgres_ready,
     postgres_exporter::EXPORTER_CONFIGMAP_PREFIX,
     psql::PsqlOutput,
-    trunk::extensions_that_require_load,
+    trunk::{extension_dependencies, extensions_that_require_load},
     Context,
 };
 use chrono::{DateTime, NaiveDateTime, Offset};
@@ -154,6 +162,29 @@ fn create_cluster_backup_barman_object_store(
 
 fn create_cluster_certificates(cdb: &CoreDB) -> Option<ClusterCertificates> {
     let name = cdb.metadata.name.clone().unwrap();
+    if let Some(tls) = cdb.spec.tls.as_ref() {
+        // The server secret provided, or issued by cert-manager, also holds the ca.crt
+        // clients use to verify the server
+        let server_secret = tls
+            .server_secret
+            .clone()
+            .or_else(|| tls.issuer.as_ref().map(|_| format!("{}-server1", name)));
+        let client_ca_secret = tls.client_auth.as_ref().map(|auth| auth.ca_secret.clone());
+        if server_secret.is_none() && client_ca_secret.is_none() {
+            return None;
+        }
+        debug!(
+            "Including certificates from spec.tls in CNPG spec: {}",
+            name
+        );
+        return Some(ClusterCertificates {
+            client_ca_secret,
+            server_ca_secret: server_secret.clone(),
+            server_tls_secret: server_secret,
+            ..ClusterCertificates::default()
+        });
+    }
+
     match std::env::var("USE_SHARED_CA") {
         Ok(_) => {
             debug!(
@@ -178,6 +209,38 @@ fn create_cluster_certificates(cdb: &CoreDB) -> Option<ClusterCertificates> {
     }
 }
 
+// cnpg_pg_hba returns the pg_hba rules of the cluster, which CNPG puts before its own
+// rules. Roles using client certificates are only allowed over TLS with a certificate,
+// then the valid rules of spec.hba follow in order.
+fn cnpg_pg_hba(cdb: &CoreDB) -> Option<Vec<String>> {
+    let mut rules = vec![];
+    if let Some(client_auth) = cdb.spec.tls.as_ref().and_then(|t| t.client_auth.as_ref()) {
+        if !client_auth.roles.is_empty() {
+            let roles = client_auth.roles.join(",");
+            let method = match client_auth.mode {
+                ClientCertMode::Cert => "cert".to_string(),
+                ClientCertMode::VerifyFull => "scram-sha-256 clientcert=verify-full".to_string(),
+            };
+            rules.push(format!("hostssl all {roles} all {method}"));
+            rules.push(format!("host all {roles} all reject"));
+        }
+    }
+
+    for rule in cdb.spec.hba.iter() {
+        match rule.validate() {
+            Ok(()) => rules.push(rule.to_hba_line()),
+            Err(e) => error!(
+                "Skipping invalid hba rule {:?} on instance {}: {}",
+                rule,
+                cdb.name_any(),
+                e
+            ),
+        }
+    }
+
+    (!rules.is_empty()).then_some(rules)
+}
+
 fn create_cluster_backup_volume_snapshot(cdb: &CoreDB) -> ClusterBackupVolumeSnapshot {
     let class_name = cdb
         .spec
@@ -210,18 +273,23 @@ fn create_cluster_backup(
     backup_path: &str,
     credentials: Option<BackupCredentials>,
 ) -> Option<ClusterBackup> {
-    let retention_days = match &cdb.spec.backup.retentionPolicy {
-        None => "30d".to_string(),
+    let mut retention_days = match &cdb.spec.backup.retentionPolicy {
+        None => 30,
         Some(retention_policy) => match retention_policy.parse::<i32>() {
-            Ok(days) => {
-                format!("{}d", days)
-            }
+            Ok(days) => days,
             Err(_) => {
                 warn!("Invalid retention policy because could not convert to i32, using default of 30 days");
-                "30d".to_string()
+                30
             }
         },
     };
+    if let Some(retention) = &cdb.spec.backup.retention {
+        // The object store keeps everything needed to restore the backups retained by GFS
+        if !retention.dry_run && !retention.is_empty() {
+            retention_days = retention_days.max(retention.window_days() as i32);
+        }
+    }
+    let retention_days = format!("{}d", retention_days);
 
     let volume_snapshot = cdb.spec.backup.volume_snapshot.as_ref().and_then(|vs| {
         if vs.enabled {
@@ -350,7 +418,11 @@ pub fn cnpg_cluster_bootstrap_from_cdb(
     Option<Vec<ClusterExternalClusters>>,
     Option<ClusterSuperuserSecret>,
 ) {
-    let cluster_bootstrap = if cdb.spec.restore.is_some() {
+    // A replica is bootstrapped like a restore from the backups of its source
+    let replica_restore = cdb.spec.replica_of.as_ref().map(ReplicaOf::as_restore);
+    let restore = replica_restore.as_ref().or(cdb.spec.restore.as_ref());
+
+    let cluster_bootstrap = if restore.is_some() {
         cnpg_cluster_bootstrap(cdb, true)
     } else {
         cnpg_cluster_bootstrap(cdb, false)
@@ -364,7 +436,7 @@ pub fn cnpg_cluster_bootstrap_from_cdb(
 
     let superuser_secret_name = format!("{}-connection", cluster_name);
 
-    let coredb_cluster = if let Some(restore) = &cdb.spec.restore {
+    let coredb_cluster = if let Some(restore) = restore {
         // This generates the default is s3_credentials was none
         let s3_credentials = generate_s3_restore_credentials(restore.s3_credentials.as_ref());
 
@@ -388,7 +460,7 @@ pub fn cnpg_cluster_bootstrap_from_cdb(
                 server_name: Some(restore.server_name.clone()),
                 ..ClusterExternalClustersBarmanObjectStore::default()
             }),
-            ..ClusterExternalClusters::default()
+            ..cnpg_replica_streaming(cdb)
         }
     } else {
         ClusterExternalClusters {
@@ -416,39 +488,169 @@ pub fn cnpg_cluster_bootstrap_from_cdb(
     )
 }
 
-fn cnpg_cluster_bootstrap(cdb: &CoreDB, restore: bool) -> ClusterBootstrap {
-    // parse_target_time returns the parsed target_time which is used for point-in-time-recovery
-    // todo: Somehow turn this into a requeue action, so that we can retry when the target_time is not in the correct format.
-    //      for now we just log the error and return None, which will disable point-in-time-recovery, but allow for a full recovery
-    let parsed_target_time = cdb.spec.restore.as_ref().and_then(|restore| {
-        restore.recovery_target_time.as_ref().and_then(|time_str| {
-            match parse_target_time(Some(time_str)) {
-                Ok(Some(parsed_time)) => Some(parsed_time),
-                Ok(None) => None,
-                Err(err) => {
-                    error!(
-                        "Failed to parse target_time for instance: {}, {}",
-                        cdb.name_any(),
-                        err
-                    );
-                    None
-                }
-            }
+// cnpg_replica_streaming returns the connection to the source of a replica, when it streams WAL
+fn cnpg_replica_streaming(cdb: &CoreDB) -> ClusterExternalClusters {
+    let Some(streaming) = cdb
+        .spec
+        .replica_of
+        .as_ref()
+        .and_then(|replica_of| replica_of.streaming.as_ref())
+    else {
+        return ClusterExternalClusters::default();
+    };
+
+    let connection_parameters = BTreeMap::from([
+        ("host".to_string(), streaming.host.clone()),
+        ("port".to_string(), streaming.port.to_string()),
+        ("user".to_string(), "postgres".to_string()),
+        ("dbname".to_string(), "postgres".to_string()),
+        ("sslmode".to_string(), "require".to_string()),
+    ]);
+    ClusterExternalClusters {
+        connection_parameters: Some(connection_parameters),
+        password: Some(ClusterExternalClustersPassword {
+            name: Some(streaming.password_secret.name.clone()),
+            key: streaming.password_secret.key.clone(),
+            ..ClusterExternalClustersPassword::default()
+        }),
+        ..ClusterExternalClusters::default()
+    }
+}
+
+// cnpg_replica returns the replica configuration of the cluster. Disabling it on a
+// replica cluster promotes it.
+fn cnpg_replica(cdb: &CoreDB) -> Option<ClusterReplica> {
+    cdb.spec
+        .replica_of
+        .as_ref()
+        .map(|replica_of| ClusterReplica {
+            enabled: !replica_of.promote,
+            source: "tembo-recovery".to_string(),
         })
-    });
+}
+
+// cnpg_recovery_target returns the point-in-time-recovery target of the restore, if any.
+// CNPG only accepts a single target: when several are set, the first one of LSN, xid,
+// name and time is used. The targets are expected to be valid, see recovery_target_error.
+fn cnpg_recovery_target(cdb: &CoreDB) -> Option<ClusterBootstrapRecoveryRecoveryTarget> {
+    // Replicas follow their source, they are never restored to a target
+    if cdb.spec.replica_of.is_some() {
+        return None;
+    }
+    let restore = cdb.spec.restore.as_ref()?;
+    let exclusive = restore
+        .recovery_target_inclusive
+        .map(|inclusive| !inclusive);
+
+    let targets = [
+        restore.recovery_target_lsn.is_some(),
+        restore.recovery_target_xid.is_some(),
+        restore.recovery_target_name.is_some(),
+        restore.recovery_target_time.is_some(),
+    ];
+    if targets.iter().filter(|set| **set).count() > 1 {
+        warn!(
+            "Multiple recovery targets are set for instance {}, only the first one of LSN, xid, name and time is used",
+            cdb.name_any()
+        );
+    }
 
+    if let Some(lsn) = &restore.recovery_target_lsn {
+        return Some(ClusterBootstrapRecoveryRecoveryTarget {
+            target_lsn: Some(lsn.clone()),
+            exclusive,
+            ..ClusterBootstrapRecoveryRecoveryTarget::default()
+        });
+    }
+
+    if let Some(xid) = &restore.recovery_target_xid {
+        return Some(ClusterBootstrapRecoveryRecoveryTarget {
+            target_xid: Some(xid.clone()),
+            exclusive,
+            ..ClusterBootstrapRecoveryRecoveryTarget::default()
+        });
+    }
+
+    if let Some(name) = &restore.recovery_target_name {
+        return Some(ClusterBootstrapRecoveryRecoveryTarget {
+            target_name: Some(name.clone()),
+            exclusive,
+            ..ClusterBootstrapRecoveryRecoveryTarget::default()
+        });
+    }
+
+    // An invalid target_time is refused by reconcile_cnpg before the cluster is created,
+    // see recovery_target_error
+    let time_str = restore.recovery_target_time.as_ref()?;
+    match parse_target_time(Some(time_str)) {
+        Ok(parsed_time) => parsed_time.map(|target_time| ClusterBootstrapRecoveryRecoveryTarget {
+            target_time: Some(target_time),
+            exclusive,
+            ..ClusterBootstrapRecoveryRecoveryTarget::default()
+        }),
+        Err(err) => {
+            error!(
+                "Failed to parse target_time for instance: {}, {}",
+                cdb.name_any(),
+                err
+            );
+            None
+        }
+    }
+}
+
+// recovery_target_error returns the field and the reason of an invalid point-in-time-recovery
+// target. Restoring without the target would recover to the end of the WAL archive, so the
+// cluster is not created until the target is fixed.
+pub(crate) fn recovery_target_error(restore: &Restore) -> Option<(&'static str, String)> {
+    if let Some(lsn) = &restore.recovery_target_lsn {
+        if !is_valid_lsn(lsn) {
+            return Some((
+                "recoveryTargetLsn",
+                format!("invalid LSN '{lsn}', expected e.g. 0/3000060"),
+            ));
+        }
+    }
+    if let Some(xid) = &restore.recovery_target_xid {
+        if xid.parse::<u64>().is_err() {
+            return Some((
+                "recoveryTargetXid",
+                format!("invalid transaction ID '{xid}'"),
+            ));
+        }
+    }
+    if let Some(time) = &restore.recovery_target_time {
+        if let Err(err) = parse_target_time(Some(time)) {
+            return Some((
+                "recoveryTargetTime",
+                format!("invalid time '{time}': {err}"),
+            ));
+        }
+    }
+    None
+}
+
+// An LSN is two hexadecimal numbers separated by a slash, e.g. 0/3000060
+fn is_valid_lsn(lsn: &str) -> bool {
+    match lsn.split_once('/') {
+        Some((high, low)) => {
+            !high.is_empty()
+                && !low.is_empty()
+                && u32::from_str_radix(high, 16).is_ok()
+                && u32::from_str_radix(low, 16).is_ok()
+        }
+        None => false,
+    }
+}
+
+fn cnpg_cluster_bootstrap(cdb: &CoreDB, restore: bool) -> ClusterBootstrap {
     if restore {
         ClusterBootstrap {
             recovery: Some(ClusterBootstrapRecovery {
                 source: Some("tembo-recovery".to_string()),
                 database: Some("app".to_string()),
                 owner: Some("app".to_string()),
-                recovery_target: parsed_target_time.map(|target_time| {
-                    ClusterBootstrapRecoveryRecoveryTarget {
-                        target_time: Some(target_time),
-                        ..ClusterBootstrapRecoveryRecoveryTarget::default()
-                    }
-                }),
+                recovery_target: cnpg_recovery_target(cdb),
                 // TODO: reenable this once we have a work around for snapshots
                 // volume_snapshots: cnpg_cluster_bootstrap_recovery_volume_snapshots(cdb),
                 ..ClusterBootstrapRecovery::default()
@@ -491,49 +693,48 @@ fn cnpg_postgres_config(
     cdb: &CoreDB,
     requires_load: BTreeMap<String, String>,
 ) -> Result<PostgresConfig, MergeError> {
-    match cdb.spec.get_pg_configs(requires_load) {
-        Ok(Some(pg_configs)) => {
-            let mut postgres_parameters: BTreeMap<String, String> = BTreeMap::new();
-            let mut shared_preload_libraries: Vec<String> = Vec::new();
-
-            for pg_config in pg_configs {
-                match &pg_config.name[..] {
-                    "shared_preload_libraries" => {
-                        shared_preload_libraries.push(pg_config.value.to_string());
-                    }
-                    _ => {
-                        postgres_parameters
-                            .insert(pg_config.name.clone(), pg_config.value.to_string());
-                    }
+    let mut postgres_parameters: BTreeMap<String, String> = BTreeMap::new();
+    let mut shared_preload_libraries: Vec<String> = Vec::new();
+
+    if let Some(pg_configs) = cdb.spec.get_pg_configs(requires_load)? {
+        for pg_config in pg_configs {
+            match &pg_config.name[..] {
+                "shared_preload_libraries" => {
+                    shared_preload_libraries.push(pg_config.value.to_string());
+                }
+                _ => {
+                    postgres_parameters.insert(pg_config.name.clone(), pg_config.value.to_string());
                 }
             }
+        }
+    }
 
-            let params = if postgres_parameters.is_empty() {
-                None
-            } else {
-                Some(postgres_parameters)
-            };
+    // wal_level is not configurable by users, publications need it to be logical
+    let has_publications = cdb
+        .spec
+        .logical_replication
+        .as_ref()
+        .is_some_and(|lr| !lr.publications.is_empty());
+    if has_publications {
+        postgres_parameters.insert("wal_level".to_string(), "logical".to_string());
+    }
 
-            let libs = if shared_preload_libraries.is_empty() {
-                None
-            } else {
-                Some(shared_preload_libraries)
-            };
+    let params = if postgres_parameters.is_empty() {
+        None
+    } else {
+        Some(postgres_parameters)
+    };
 
-            Ok(PostgresConfig {
-                postgres_parameters: params,
-                shared_preload_libraries: libs,
-            })
-        }
-        Ok(None) => {
-            // Return None, None when no pg_config is set
-            Ok(PostgresConfig {
-                postgres_parameters: None,
-                shared_preload_libraries: None,
-            })
-        }
-        Err(e) => Err(e),
-    }
+    let libs = if shared_preload_libraries.is_empty() {
+        None
+    } else {
+        Some(shared_preload_libraries)
+    };
+
+    Ok(PostgresConfig {
+        postgres_parameters: params,
+        shared_preload_libraries: libs,
+    })
 }
 
 fn cnpg_cluster_storage(cdb: &CoreDB) -> Option<ClusterStorage> {
@@ -705,13 +906,14 @@ pub fn cnpg_cluster_from_cdb(
                     },
                 ),
                 shared_preload_libraries,
-                pg_hba: None,
+                pg_hba: cnpg_pg_hba(cdb),
                 enable_alter_system: Some(true),
                 ..ClusterPostgresql::default()
             }),
             primary_update_method,
             primary_update_strategy: Some(ClusterPrimaryUpdateStrategy::Unsupervised),
             replication_slots: replication,
+            replica: cnpg_replica(cdb),
             resources: Some(ClusterResources {
                 claims: None,
                 limits: cdb.spec.resources.clone().limits,
@@ -951,8 +1153,21 @@ pub async fn reconcile_cnpg(cdb: &CoreDB, ctx: Arc<Context>) -> Result<(), Actio
         Action::requeue(tokio::time::Duration::from_secs(300))
     })?;
 
+    // Major version upgrades replace the cluster, so they are handled before anything else
+    reconcile_major_upgrade(cdb, ctx.clone()).await?;
+    let upgrade_in_progress = cdb
+        .status
+        .as_ref()
+        .and_then(|s| s.pg_upgrade.as_ref())
+        .map(is_upgrade_in_progress)
+        .unwrap_or(false);
+
     let pods_to_fence = pods_to_fence(cdb, ctx.clone()).await?;
     let requires_load = extensions_that_require_load(ctx.client.clone(), namespace).await?;
+    // The dependencies of the enabled extensions need their shared_preload_libraries too,
+    // so they are all configured with a single restart
+    let dependencies = extension_dependencies(ctx.client.clone(), namespace).await?;
+    let (resolved, _) = resolve_extension_dependencies(cdb, &dependencies);
 
     // TODO: reenable this once we have a work around for snapshots
     // If we are restoring and have volume snapshots enabled, make sure we setup
@@ -966,18 +1181,33 @@ pub async fn reconcile_cnpg(cdb: &CoreDB, ctx: Arc<Context>) -> Result<(), Actio
     // }
 
     debug!("Generating CNPG spec");
-    let mut cluster = cnpg_cluster_from_cdb(cdb, Some(pods_to_fence), requires_load);
+    let mut cluster = cnpg_cluster_from_cdb(&resolved, Some(pods_to_fence), requires_load);
+    apply_upgrade_to_cluster(cdb, &mut cluster);
 
     let cluster_api: Api<Cluster> = Api::namespaced(ctx.client.clone(), namespace.as_str());
     let maybe_cluster = cluster_api.get(&name).await;
 
+    // The bootstrap of a restore can not be changed once the cluster is created
+    if maybe_cluster.is_err() && cdb.spec.replica_of.is_none() {
+        if let Some((field, message)) = cdb.spec.restore.as_ref().and_then(recovery_target_error) {
+            error!(
+                "Not restoring instance {}, spec.restore.{}: {}",
+                &name, field, message
+            );
+            return Err(Action::requeue(Duration::from_secs(300)));
+        }
+    }
+
     // Check if we are updating the cluster to reboot/restart the instance, if so do that first before
     // updating the cluster spec.  Also check to see if the image is being updated.  If do
     // update the image first before updating the cluster spec.
     if let Ok(ref cluster) = maybe_cluster {
         warn!("Cluster exists, checking if restart is required");
         restart_and_wait_for_restart(cdb, ctx.clone(), Some(cluster)).await?;
-        is_image_updated(cdb, ctx.clone(), Some(cluster)).await?;
+        // The image is managed by the major version upgrade while it is in progress
+        if !upgrade_in_progress {
+            is_image_updated(cdb, ctx.clone(), Some(cluster)).await?;
+        }
     }
 
     // Check CoreDB status if status.running is false, return requeue
@@ -1239,122 +1469,259 @@ pub async fn reconcile_pooler(
     placement: Option<PlacementConfig>,
 ) -> Result<(), Action> {
     let client = ctx.client.clone();
-    let name = cdb.name_any() + "-pooler";
     let namespace = cdb.namespace().unwrap();
-    let owner_reference = cdb.controller_owner_ref(&()).unwrap();
     let pooler_api: Api<Pooler> = Api::namespaced(client.clone(), namespace.as_str());
-    let tolerations = placement
-        .as_ref()
-        .and_then(|config| config.convert_pooler_tolerations());
-    let topology_spread_constraints = placement
-        .as_ref()
-        .and_then(|p| p.convert_pooler_topology_spread_constraints());
-    let affinity = placement.as_ref().and_then(|p| p.convert_pooler_affinity());
-    let node_selector = placement.as_ref().and_then(|p| p.node_selector.clone());
-    let instances = get_pooler_instances(cdb);
 
     // If pooler is enabled, create or update
-    if cdb.spec.connectionPooler.enabled {
-        debug!("Configuraing pooler instance for {}", cdb.name_any());
-        let pooler = Pooler {
-            metadata: ObjectMeta {
-                name: Some(name.clone()),
-                namespace: Some(namespace.clone()),
-                owner_references: Some(vec![owner_reference]),
-                ..ObjectMeta::default()
-            },
-            spec: PoolerSpec {
-                cluster: PoolerCluster {
-                    name: cdb.name_any(),
-                },
-                deployment_strategy: None,
-                instances,
-                monitoring: None,
-                pgbouncer: PoolerPgbouncer {
-                    auth_query: None,
-                    auth_query_secret: None,
-                    parameters: cdb.spec.connectionPooler.pooler.parameters.clone(),
-                    paused: None,
-                    pg_hba: None,
-                    pool_mode: Some(cdb.spec.connectionPooler.pooler.poolMode.clone()),
-                },
-                template: Some(PoolerTemplate {
-                    metadata: None,
-                    spec: Some(PoolerTemplateSpec {
-                        containers: vec![PoolerTemplateSpecContainers {
-                            name: "pgbouncer".to_string(),
-                            resources: cdb.spec.connectionPooler.pooler.resources.clone(),
-                            ..Default::default()
-                        }],
-                        affinity,
-                        node_selector,
-                        tolerations,
-                        topology_spread_constraints,
-                        ..Default::default()
-                    }),
-                }),
-                r#type: Some(PoolerType::Rw),
-            },
-            status: None,
-        };
+    let poolers = if cdb.spec.connectionPooler.enabled {
+        generate_poolers(cdb, placement.as_ref())
+    } else {
+        vec![]
+    };
 
+    for pooler in poolers.iter() {
+        let name = pooler.name_any();
         debug!("Patching Pooler {name}");
         let ps = PatchParams::apply("cntrlr").force();
         let _o = pooler_api
-            .patch(&name, &ps, &Patch::Apply(&pooler))
+            .patch(&name, &ps, &Patch::Apply(pooler))
             .await
             .map_err(|e| {
                 error!("Error patching Pooler: {}", e);
                 Action::requeue(Duration::from_secs(300))
             })?;
+    }
 
-        // Check to see if the primary pod is ready, if it is the setup pgbouncer.  If the pod is
-        // not ready then just continue on and wait for the next reconcile.
-        let primary_pod = cdb.primary_pod_cnpg_ready_or_not(client.clone()).await?;
-        if !is_postgres_ready().matches_object(Some(&primary_pod)) {
-            debug!("Primary pod is not ready, skipping setup_pgbouncer");
-            return Ok(());
+    // Delete poolers of this instance that are no longer desired, including every
+    // pooler once the connection pooler is disabled
+    let existing = pooler_api.list(&ListParams::default()).await.map_err(|e| {
+        error!("Error listing Poolers: {}", e);
+        Action::requeue(Duration::from_secs(300))
+    })?;
+    for existing_pooler in existing.items.iter() {
+        let name = existing_pooler.name_any();
+        if !is_instance_pooler(cdb, existing_pooler) || poolers.iter().any(|p| p.name_any() == name)
+        {
+            continue;
         }
+        debug!("Pooler {name} is no longer desired. Deleting Pooler {name}");
+        let dp = DeleteParams::default();
+        pooler_api.delete(&name, &dp).await.map_err(|e| {
+            error!("Error deleting Pooler: {}", e);
+            Action::requeue(Duration::from_secs(300))
+        })?;
+    }
 
-        match setup_pgbouncer_function(cdb, ctx.clone()).await {
-            Ok(_) => debug!(
-                "Successfully created setup_pgbouncer function on instance {}",
-                cdb.name_any()
-            ),
-            Err(e) => {
-                warn!(
-                    "Did not create setup_pgbouncer function, will requeue: {:?}",
-                    e
-                );
-                return Err(Action::requeue(Duration::from_secs(30)));
-            }
-        }
-        // Run the setup_pgbouncer function
-        cdb.psql(
-            "SELECT setup_pgbouncer();".to_string(),
-            "postgres".to_string(),
-            ctx.clone(),
-        )
-        .await?;
-    } else {
-        // If pooler is disabled and exists, delete
-        let pooler = pooler_api.get(&name).await;
-        if pooler.is_err() {
-            debug!("Pooler {name} does not exist. Skipping deletion");
-            return Ok(());
-        } else {
-            debug!("Found pooler {name} and pooler is disabled. Deleting Pooler {name}");
-            let dp = DeleteParams::default();
-            pooler_api.delete(&name, &dp).await.map_err(|e| {
-                error!("Error deleting Pooler: {}", e);
-                Action::requeue(Duration::from_secs(300))
-            })?;
+    if poolers.is_empty() {
+        return Ok(());
+    }
+
+    // Check to see if the primary pod is ready, if it is the setup pgbouncer.  If the pod is
+    // not ready then just continue on and wait for the next reconcile.
+    let primary_pod = cdb.primary_pod_cnpg_ready_or_not(client.clone()).await?;
+    if !is_postgres_ready().matches_object(Some(&primary_pod)) {
+        debug!("Primary pod is not ready, skipping setup_pgbouncer");
+        return Ok(());
+    }
+
+    match setup_pgbouncer_function(cdb, ctx.clone()).await {
+        Ok(_) => debug!(
+            "Successfully created setup_pgbouncer function on instance {}",
+            cdb.name_any()
+        ),
+        Err(e) => {
+            warn!(
+                "Did not create setup_pgbouncer function, will requeue: {:?}",
+                e
+            );
+            return Err(Action::requeue(Duration::from_secs(30)));
         }
     }
+    // Run the setup_pgbouncer function
+    cdb.psql(
+        "SELECT setup_pgbouncer();".to_string(),
+        "postgres".to_string(),
+        ctx.clone(),
+    )
+    .await?;
 
     Ok(())
 }
 
+// is_instance_pooler returns true if the Pooler is one of the poolers generated for the
+// instance: the main pooler, the read-only pooler or a route pooler
+fn is_instance_pooler(cdb: &CoreDB, pooler: &Pooler) -> bool {
+    pooler.spec.cluster.name == cdb.name_any()
+        && pooler
+            .name_any()
+            .starts_with(&format!("{}-pooler", cdb.name_any()))
+}
+
+// generate_poolers returns the desired Poolers of an instance: the main read-write
+// pooler, an optional read-only pooler and one pooler per valid route.
+fn generate_poolers(cdb: &CoreDB, placement: Option<&PlacementConfig>) -> Vec<Pooler> {
+    let pgbouncer = &cdb.spec.connectionPooler.pooler;
+    let base = cdb.name_any() + "-pooler";
+    let mut poolers = vec![generate_pooler(
+        cdb,
+        placement,
+        base.clone(),
+        PoolerType::Rw,
+        None,
+    )];
+
+    if pgbouncer.read_only {
+        poolers.push(generate_pooler(
+            cdb,
+            placement,
+            format!("{base}-ro"),
+            PoolerType::Ro,
+            None,
+        ));
+    }
+
+    for route in pgbouncer.routes.iter() {
+        if !is_valid_pooler_route_name(&route.name) {
+            warn!(
+                "Ignoring invalid pooler route {:?} on instance {}",
+                route.name,
+                cdb.name_any()
+            );
+            continue;
+        }
+        poolers.push(generate_pooler(
+            cdb,
+            placement,
+            format!("{base}-{}", route.name),
+            PoolerType::Rw,
+            Some(route),
+        ));
+    }
+
+    poolers
+}
+
+fn generate_pooler(
+    cdb: &CoreDB,
+    placement: Option<&PlacementConfig>,
+    name: String,
+    pooler_type: PoolerType,
+    route: Option<&PgBouncerRoute>,
+) -> Pooler {
+    let pgbouncer = &cdb.spec.connectionPooler.pooler;
+    let tolerations = placement.and_then(|config| config.convert_pooler_tolerations());
+    let topology_spread_constraints =
+        placement.and_then(|p| p.convert_pooler_topology_spread_constraints());
+    let affinity = placement.and_then(|p| p.convert_pooler_affinity());
+    let node_selector = placement.and_then(|p| p.node_selector.clone());
+
+    let pool_mode = route
+        .and_then(|r| r.pool_mode.clone())
+        .unwrap_or_else(|| pgbouncer.poolMode.clone());
+
+    Pooler {
+        metadata: ObjectMeta {
+            name: Some(name),
+            namespace: cdb.namespace(),
+            owner_references: Some(vec![cdb.controller_owner_ref(&()).unwrap()]),
+            ..ObjectMeta::default()
+        },
+        spec: PoolerSpec {
+            cluster: PoolerCluster {
+                name: cdb.name_any(),
+            },
+            deployment_strategy: None,
+            instances: get_pooler_instances(cdb),
+            monitoring: Some(PoolerMonitoring {
+                enable_pod_monitor: Some(pgbouncer.monitoring && !cdb.spec.stop),
+                ..PoolerMonitoring::default()
+            }),
+            pgbouncer: PoolerPgbouncer {
+                auth_query: None,
+                auth_query_secret: None,
+                parameters: pooler_route_parameters(pgbouncer.parameters.as_ref(), route),
+                paused: None,
+                pg_hba: route.map(pooler_route_hba),
+                pool_mode: Some(pool_mode),
+            },
+            template: Some(PoolerTemplate {
+                metadata: None,
+                spec: Some(PoolerTemplateSpec {
+                    containers: vec![PoolerTemplateSpecContainers {
+                        name: "pgbouncer".to_string(),
+                        resources: pgbouncer.resources.clone(),
+                        ..Default::default()
+                    }],
+                    affinity,
+                    node_selector,
+                    tolerations,
+                    topology_spread_constraints,
+                    ..Default::default()
+                }),
+            }),
+            r#type: Some(pooler_type),
+        },
+        status: None,
+    }
+}
+
+// The route name becomes part of the pooler and service names, so it has to be a
+// DNS label, and `ro` is reserved for the read-only pooler.
+fn is_valid_pooler_route_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 40
+        && name != "ro"
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+        && !name.starts_with('-')
+        && !name.ends_with('-')
+}
+
+// pooler_route_parameters applies the pool size overrides of a route on top of the
+// instance-wide pgbouncer parameters.
+fn pooler_route_parameters(
+    parameters: Option<&BTreeMap<String, String>>,
+    route: Option<&PgBouncerRoute>,
+) -> Option<BTreeMap<String, String>> {
+    let Some(route) = route else {
+        return parameters.cloned();
+    };
+    let mut parameters = parameters.cloned().unwrap_or_default();
+    if let Some(pool_size) = route.pool_size {
+        parameters.insert("default_pool_size".to_string(), pool_size.to_string());
+    }
+    if let Some(reserve_pool_size) = route.reserve_pool_size {
+        parameters.insert(
+            "reserve_pool_size".to_string(),
+            reserve_pool_size.to_string(),
+        );
+    }
+    Some(parameters)
+}
+
+// pooler_route_hba only lets the databases and users of a route through the route
+// pooler. CNPG appends its own catch-all rules after these, so everything else is
+// rejected explicitly.
+fn pooler_route_hba(route: &PgBouncerRoute) -> Vec<String> {
+    let databases = if route.databases.is_empty() {
+        "all".to_string()
+    } else {
+        route.databases.join(",")
+    };
+    let users = if route.users.is_empty() {
+        "all".to_string()
+    } else {
+        route.users.join(",")
+    };
+    vec![
+        format!("host {databases} {users} 0.0.0.0/0 md5"),
+        format!("host {databases} {users} ::/0 md5"),
+        "host all all 0.0.0.0/0 reject".to_string(),
+        "host all all ::/0 reject".to_string(),
+    ]
+}
+
 // This function was created from the instructions that CNPG gives when you have to setup pgbouncer
 // manually.  You can read more here: https://cloudnative-pg.io/documentation/1.20/connection_pooling/#authentication
 const PGBOUNCER_SETUP_FUNCTION: &str = r#"
@@ -2207,33 +2574,28 @@ pub(crate) async fn get_scheduled_backups(cdb: &CoreDB, ctx: Arc<Context>) -> Ve
     }
 }
 
+// get_poolers returns every Pooler of the instance, see is_instance_pooler
 #[instrument(skip(cdb, ctx), fields(trace_id, instance_name = %cdb.name_any()))]
-pub(crate) async fn get_pooler(cdb: &CoreDB, ctx: Arc<Context>) -> Option<Pooler> {
-    let instance_name = cdb.name_any() + "-pooler";
+pub(crate) async fn get_poolers(cdb: &CoreDB, ctx: Arc<Context>) -> Vec<Pooler> {
+    let instance_name = cdb.name_any();
     let namespace = match cdb.namespace() {
         Some(ns) => ns,
         _ => {
             error!("Namespace is not set for CoreDB {}", instance_name);
-            return None;
+            return vec![];
         }
     };
 
     let pooler: Api<Pooler> = Api::namespaced(ctx.client.clone(), &namespace);
-    let p = pooler.get(&instance_name).await;
-
-    match p {
-        Ok(pooler) => {
-            debug!("Pooler {} exists", instance_name);
-            Some(pooler)
-        }
-        // return Ok(false) if the Pooler does not exist (404)
-        Err(kube::Error::Api(ae)) if ae.code == 404 => {
-            debug!("Pooler {} does not exist", instance_name);
-            None
-        }
-        Err(_e) => {
-            error!("Error getting Pooler: {}", instance_name);
-            None
+    match pooler.list(&ListParams::default()).await {
+        Ok(poolers) => poolers
+            .items
+            .into_iter()
+            .filter(|p| is_instance_pooler(cdb, p))
+            .collect(),
+        Err(e) => {
+            error!("Error listing Poolers of {}: {}", instance_name, e);
+            vec![]
         }
     }
 }
@@ -2242,8 +2604,8 @@ pub(crate) async fn get_pooler(cdb: &CoreDB, ctx: Arc<Context>) -> Option<Pooler
 mod tests {
     use super::*;
     use crate::{
-        apis::coredb_types::{Backup as CoreDBBackup, CoreDB},
-        cloudnativepg::clusters::Cluster,
+        apis::coredb_types::{Backup as CoreDBBackup, BackupRetention, CoreDB},
+        cloudnativepg::{clusters::Cluster, poolers::PoolerPgbouncerPoolMode},
     };
     use serde_json::json;
     use std::collections::BTreeMap;
@@ -2649,6 +3011,29 @@ mod tests {
             "45d".to_string()
         );
 
+        // The object store keeps the longest of the GFS window and the retention policy,
+        // unless the GFS retention is a dry run
+        let mut gfs_cdb = cdb.clone();
+        gfs_cdb.spec.backup.retention = Some(BackupRetention {
+            monthly: 2,
+            ..BackupRetention::default()
+        });
+        let (gfs_backup, _) = cnpg_backup_configuration(&gfs_cdb, &cfg);
+        assert_eq!(gfs_backup.unwrap().retention_policy.unwrap(), "62d");
+        gfs_cdb.spec.backup.retention = Some(BackupRetention {
+            daily: 7,
+            ..BackupRetention::default()
+        });
+        let (gfs_backup, _) = cnpg_backup_configuration(&gfs_cdb, &cfg);
+        assert_eq!(gfs_backup.unwrap().retention_policy.unwrap(), "45d");
+        gfs_cdb.spec.backup.retention = Some(BackupRetention {
+            monthly: 2,
+            dry_run: true,
+            ..BackupRetention::default()
+        });
+        let (gfs_backup, _) = cnpg_backup_configuration(&gfs_cdb, &cfg);
+        assert_eq!(gfs_backup.unwrap().retention_policy.unwrap(), "45d");
+
         assert_eq!(
             s3_backup.spec.method,
             Some(ScheduledBackupMethod::BarmanObjectStore)
@@ -3174,4 +3559,327 @@ mod tests {
             "stormy-capybara-snap"
         );
     }
+
+    #[test]
+    fn test_cnpg_cluster_replica_of() {
+        let cdb_yaml = r#"
+        apiVersion: coredb.io/v1alpha1
+        kind: CoreDB
+        metadata:
+          name: test-replica
+          namespace: default
+          uid: 752d59ef-2671-4890-9feb-0097459b18c8
+        spec:
+          backup:
+            destinationPath: s3://tembo-backup/sample-standard-backup
+            encryption: ""
+            retentionPolicy: "30"
+            schedule: 17 9 * * *
+            endpointURL: http://minio:9000
+          image: quay.io/tembo/standard-cnpg:15-bffd097
+          replicaOf:
+            serverName: test-source
+            backupsPath: s3://tembo-backup/v2/test-source
+            streaming:
+              host: test-source.example.com
+              passwordSecret:
+                name: test-source-superuser
+        "#;
+        let mut cdb: CoreDB = serde_yaml::from_str(cdb_yaml).expect("Failed to parse YAML");
+        assert!(cdb.spec.is_replica());
+
+        let cluster = cnpg_cluster_from_cdb(&cdb, None, BTreeMap::new());
+        let replica = cluster.spec.replica.expect("Expected a replica cluster");
+        assert!(replica.enabled);
+        assert_eq!(replica.source, "tembo-recovery");
+
+        let recovery = cluster.spec.bootstrap.unwrap().recovery.unwrap();
+        assert_eq!(recovery.source.as_deref(), Some("tembo-recovery"));
+        assert!(recovery.recovery_target.is_none());
+
+        let external = &cluster.spec.external_clusters.unwrap()[0];
+        assert_eq!(external.name, "tembo-recovery");
+        let object_store = external.barman_object_store.as_ref().unwrap();
+        assert_eq!(object_store.server_name.as_deref(), Some("test-source"));
+        assert_eq!(
+            external
+                .connection_parameters
+                .as_ref()
+                .and_then(|p| p.get("host"))
+                .map(String::as_str),
+            Some("test-source.example.com")
+        );
+        assert_eq!(
+            external.password.as_ref().and_then(|p| p.name.as_deref()),
+            Some("test-source-superuser")
+        );
+
+        // Promoting disables the replica configuration of the cluster
+        cdb.spec.replica_of.as_mut().unwrap().promote = true;
+        assert!(!cdb.spec.is_replica());
+        let cluster = cnpg_cluster_from_cdb(&cdb, None, BTreeMap::new());
+        assert!(!cluster.spec.replica.unwrap().enabled);
+    }
+
+    #[test]
+    fn test_cnpg_recovery_target() {
+        let cdb_yaml = r#"
+        apiVersion: coredb.io/v1alpha1
+        kind: CoreDB
+        metadata:
+          name: test-restore
+          namespace: default
+        spec:
+          image: quay.io/tembo/standard-cnpg:15-bffd097
+          restore:
+            serverName: test-source
+            recoveryTargetLsn: 0/3000060
+            recoveryTargetInclusive: false
+        "#;
+        let mut cdb: CoreDB = serde_yaml::from_str(cdb_yaml).expect("Failed to parse YAML");
+        let target = cnpg_recovery_target(&cdb).expect("Expected a recovery target");
+        assert_eq!(target.target_lsn.as_deref(), Some("0/3000060"));
+        assert_eq!(target.exclusive, Some(true));
+        assert!(target.target_time.is_none());
+
+        // The LSN takes precedence over the other targets
+        let restore = cdb.spec.restore.as_mut().unwrap();
+        restore.recovery_target_time = Some("2023-09-26T21:15:42Z".to_string());
+        let target = cnpg_recovery_target(&cdb).unwrap();
+        assert_eq!(target.target_lsn.as_deref(), Some("0/3000060"));
+        assert!(target.target_time.is_none());
+
+        let restore = cdb.spec.restore.as_mut().unwrap();
+        restore.recovery_target_lsn = None;
+        restore.recovery_target_time = None;
+        restore.recovery_target_inclusive = None;
+        restore.recovery_target_xid = Some("1234".to_string());
+        let target = cnpg_recovery_target(&cdb).unwrap();
+        assert_eq!(target.target_xid.as_deref(), Some("1234"));
+        assert!(target.exclusive.is_none());
+
+        let restore = cdb.spec.restore.as_mut().unwrap();
+        restore.recovery_target_xid = None;
+        restore.recovery_target_name = Some("before-migration".to_string());
+        let target = cnpg_recovery_target(&cdb).unwrap();
+        assert_eq!(target.target_name.as_deref(), Some("before-migration"));
+    }
+
+    #[test]
+    fn test_recovery_target_error() {
+        let mut restore = Restore {
+            server_name: "test-source".to_string(),
+            recovery_target_lsn: Some("0/3000060".to_string()),
+            recovery_target_xid: Some("1234".to_string()),
+            recovery_target_time: Some("2023-09-26T21:15:42Z".to_string()),
+            ..Restore::default()
+        };
+        assert_eq!(recovery_target_error(&restore), None);
+
+        restore.recovery_target_lsn = Some("3000060".to_string());
+        assert_eq!(
+            recovery_target_error(&restore).map(|(field, _)| field),
+            Some("recoveryTargetLsn")
+        );
+
+        restore.recovery_target_lsn = None;
+        restore.recovery_target_xid = Some("latest".to_string());
+        assert_eq!(
+            recovery_target_error(&restore).map(|(field, _)| field),
+            Some("recoveryTargetXid")
+        );
+
+        restore.recovery_target_xid = None;
+        restore.recovery_target_time = Some("yesterday".to_string());
+        assert_eq!(
+            recovery_target_error(&restore).map(|(field, _)| field),
+            Some("recoveryTargetTime")
+        );
+    }
+
+    #[test]
+    fn test_is_valid_lsn() {
+        assert!(is_valid_lsn("0/3000060"));
+        assert!(is_valid_lsn("16/B374D848"));
+        assert!(!is_valid_lsn("3000060"));
+        assert!(!is_valid_lsn("0/"));
+        assert!(!is_valid_lsn("0/XYZ"));
+    }
+
+    #[test]
+    fn test_generate_poolers_with_routes() {
+        let cdb_yaml = r#"
+        apiVersion: coredb.io/v1alpha1
+        kind: CoreDB
+        metadata:
+          name: test
+          namespace: default
+          uid: 3b2a8c1e-0f59-4a3b-9e6c-2b1b4b6f7a10
+        spec:
+          connectionPooler:
+            enabled: true
+            pooler:
+              poolMode: transaction
+              parameters:
+                default_pool_size: "50"
+              readOnly: true
+              routes:
+                - name: migrations
+                  users: ["migrator"]
+                  poolMode: session
+                  poolSize: 5
+                  reservePoolSize: 2
+                - name: ro
+                  databases: ["app"]
+                - name: Invalid_Name
+        "#;
+        let cdb: CoreDB = serde_yaml::from_str(cdb_yaml).expect("Failed to parse YAML");
+        let poolers = generate_poolers(&cdb, None);
+        let names: Vec<String> = poolers.iter().map(|p| p.name_any()).collect();
+        assert_eq!(
+            names,
+            vec!["test-pooler", "test-pooler-ro", "test-pooler-migrations"]
+        );
+        // Hibernation scales every one of them
+        assert!(poolers.iter().all(|p| is_instance_pooler(&cdb, p)));
+        let mut other = poolers[0].clone();
+        other.spec.cluster.name = "test-pooler".to_string();
+        assert!(!is_instance_pooler(&cdb, &other));
+
+        let main = &poolers[0];
+        assert!(matches!(main.spec.r#type, Some(PoolerType::Rw)));
+        assert!(main.spec.pgbouncer.pg_hba.is_none());
+        assert!(matches!(
+            main.spec.pgbouncer.pool_mode,
+            Some(PoolerPgbouncerPoolMode::Transaction)
+        ));
+        assert_eq!(
+            main.spec
+                .monitoring
+                .as_ref()
+                .and_then(|m| m.enable_pod_monitor),
+            Some(true)
+        );
+
+        assert!(matches!(poolers[1].spec.r#type, Some(PoolerType::Ro)));
+
+        let route = &poolers[2];
+        assert!(matches!(
+            route.spec.pgbouncer.pool_mode,
+            Some(PoolerPgbouncerPoolMode::Session)
+        ));
+        let parameters = route.spec.pgbouncer.parameters.as_ref().unwrap();
+        assert_eq!(parameters.get("default_pool_size").unwrap(), "5");
+        assert_eq!(parameters.get("reserve_pool_size").unwrap(), "2");
+        assert_eq!(
+            route.spec.pgbouncer.pg_hba.as_ref().unwrap(),
+            &vec![
+                "host all migrator 0.0.0.0/0 md5".to_string(),
+                "host all migrator ::/0 md5".to_string(),
+                "host all all 0.0.0.0/0 reject".to_string(),
+                "host all all ::/0 reject".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_cluster_certificates_and_pg_hba_from_tls() {
+        let cdb_yaml = r#"
+        apiVersion: coredb.io/v1alpha1
+        kind: CoreDB
+        metadata:
+          name: test
+          namespace: default
+        spec:
+          tls:
+            issuer:
+              name: customer-ca
+            clientAuth:
+              caSecret: customer-client-ca
+              roles: ["app", "reporting"]
+        "#;
+        let mut cdb: CoreDB = serde_yaml::from_str(cdb_yaml).expect("Failed to parse YAML");
+        let certificates = create_cluster_certificates(&cdb).unwrap();
+        assert_eq!(
+            certificates.server_tls_secret.as_deref(),
+            Some("test-server1")
+        );
+        assert_eq!(
+            certificates.server_ca_secret.as_deref(),
+            Some("test-server1")
+        );
+        assert_eq!(
+            certificates.client_ca_secret.as_deref(),
+            Some("customer-client-ca")
+        );
+        assert!(certificates.replication_tls_secret.is_none());
+        assert_eq!(
+            cnpg_pg_hba(&cdb).unwrap(),
+            vec![
+                "hostssl all app,reporting all scram-sha-256 clientcert=verify-full",
+                "host all app,reporting all reject",
+            ]
+        );
+
+        // A provided server secret takes precedence over the issuer
+        let tls = cdb.spec.tls.as_mut().unwrap();
+        tls.server_secret = Some("customer-server".to_string());
+        tls.client_auth.as_mut().unwrap().mode = ClientCertMode::Cert;
+        let certificates = create_cluster_certificates(&cdb).unwrap();
+        assert_eq!(
+            certificates.server_tls_secret.as_deref(),
+            Some("customer-server")
+        );
+        assert_eq!(
+            cnpg_pg_hba(&cdb).unwrap()[0],
+            "hostssl all app,reporting all cert"
+        );
+    }
+
+    #[test]
+    fn test_cnpg_pg_hba_from_spec() {
+        let cdb_yaml = r#"
+        apiVersion: coredb.io/v1alpha1
+        kind: CoreDB
+        metadata:
+          name: test
+          namespace: default
+        spec:
+          hba:
+            - type: host
+              user: readonly
+              address: 10.0.0.0/8
+              method: scram-sha-256
+            - type: host
+              address: 0.0.0.0/0
+              method: trust
+            - type: hostnossl
+              address: 0.0.0.0/0
+              method: reject
+            - type: local
+              database: postgres
+              user: postgres
+              method: trust
+        "#;
+        let cdb: CoreDB = serde_yaml::from_str(cdb_yaml).expect("Failed to parse YAML");
+        // trust from a non-local address is rejected
+        assert_eq!(
+            cnpg_pg_hba(&cdb).unwrap(),
+            vec![
+                "host all readonly 10.0.0.0/8 scram-sha-256",
+                "hostnossl all all 0.0.0.0/0 reject",
+                "local postgres postgres trust",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_is_valid_pooler_route_name() {
+        assert!(is_valid_pooler_route_name("migrations"));
+        assert!(is_valid_pooler_route_name("oltp-1"));
+        assert!(!is_valid_pooler_route_name("ro"));
+        assert!(!is_valid_pooler_route_name(""));
+        assert!(!is_valid_pooler_route_name("-oltp"));
+        assert!(!is_valid_pooler_route_name("Oltp"));
+    }
 }
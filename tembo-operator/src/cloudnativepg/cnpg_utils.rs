@@ -8,6 +8,7 @@ pub use crate::{
     extensions::database_queries::is_not_restarting,
     patch_cdb_status_merge, requeue_normal_with_jitter, Context, RESTARTED_AT,
 };
+use chrono::{DateTime, Utc};
 use k8s_openapi::apimachinery::pkg::apis::meta::v1::Time;
 use kube::{
     api::{DeleteParams, ListParams, Patch, PatchParams},
@@ -15,6 +16,7 @@ use kube::{
     Api, ResourceExt,
 };
 use serde_json::json;
+use std::str::FromStr;
 use std::sync::Arc;
 use tokio::time::Duration;
 use tracing::{debug, error, info, instrument, warn};
@@ -180,14 +182,14 @@ pub async fn patch_scheduled_backup_merge(
     Ok(())
 }
 
-// patch_pooler_merge takes a CoreDB, context and serde_json::Value and patch merges the Pooler with the new spec
+// patch_pooler_merge takes a CoreDB, context, the name of one of its Poolers and serde_json::Value and patch merges the Pooler with the new spec
 #[instrument(skip(cdb, ctx), fields(trace_id, instance_name = %cdb.name_any(), patch = %patch))]
 pub async fn patch_pooler_merge(
     cdb: &CoreDB,
     ctx: &Arc<Context>,
+    name: &str,
     patch: serde_json::Value,
 ) -> Result<(), Action> {
-    let name = cdb.name_any() + "-pooler";
     let namespace = cdb.metadata.namespace.as_ref().ok_or_else(|| {
         error!("Namespace is empty for instance: {}.", name);
         Action::requeue(Duration::from_secs(300))
@@ -195,13 +197,13 @@ pub async fn patch_pooler_merge(
 
     let pooler_api: Api<Pooler> = Api::namespaced(ctx.client.clone(), namespace);
     let pp = PatchParams::apply("patch_merge");
-    let _ = pooler_api
-        .patch(&name, &pp, &Patch::Merge(&patch))
+    pooler_api
+        .patch(name, &pp, &Patch::Merge(&patch))
         .await
         .map_err(|e| {
-            error!("Error patching cluster: {}", e);
+            error!("Error patching Pooler {}: {}", name, e);
             Action::requeue(Duration::from_secs(300))
-        });
+        })?;
 
     Ok(())
 }
@@ -378,3 +380,34 @@ pub(crate) async fn removed_stalled_backups(
 
     Ok(())
 }
+
+// last_cron_occurrence returns the most recent time the cron expression fired before `now`.
+// Five-term expressions are standard cron, the cron crate expects a leading seconds term.
+pub(crate) fn last_cron_occurrence(expression: &str, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    match cron_schedule(expression) {
+        Ok(schedule) => schedule.after(&now).next_back(),
+        Err(e) => {
+            warn!("Invalid cron expression '{}': {}", expression, e);
+            None
+        }
+    }
+}
+
+// cron_schedule parses a cron expression with or without the seconds specifier
+pub(crate) fn cron_schedule(expression: &str) -> Result<cron::Schedule, cron::error::Error> {
+    let expression = match expression.split_whitespace().count() {
+        5 => format!("0 {}", expression.trim()),
+        _ => expression.trim().to_string(),
+    };
+    cron::Schedule::from_str(&expression)
+}
+
+// backup_completed_at returns the time a Backup stopped, if it did
+pub(crate) fn backup_completed_at(backup: &Backup) -> Option<DateTime<Utc>> {
+    backup
+        .status
+        .as_ref()
+        .and_then(|s| s.stopped_at.as_deref())
+        .and_then(|stopped_at| DateTime::parse_from_rfc3339(stopped_at).ok())
+        .map(|stopped_at| stopped_at.with_timezone(&Utc))
+}
@@ -1,9 +1,12 @@
-use crate::apis::coredb_types::CoreDB;
+use crate::apis::coredb_types::{
+    CoreDB, HibernationReason, HibernationSchedule, HibernationStatus,
+};
 use crate::cloudnativepg::clusters::{ClusterStatusConditions, ClusterStatusConditionsStatus};
-use crate::cloudnativepg::cnpg::{get_cluster, get_pooler, get_scheduled_backups};
+use crate::cloudnativepg::cnpg::{get_cluster, get_poolers, get_scheduled_backups};
 use crate::cloudnativepg::poolers::Pooler;
 use crate::cloudnativepg::scheduledbackups::ScheduledBackup;
 use crate::ingress::{delete_ingress_route, delete_ingress_route_tcp};
+use crate::logical_replication::disable_subscriptions;
 use crate::prometheus::podmonitor_crd as podmon;
 use crate::Error;
 
@@ -18,10 +21,11 @@ use k8s_openapi::api::apps::v1::Deployment;
 use super::clusters::Cluster;
 use crate::app_service::manager::get_appservice_deployment_objects;
 use crate::cloudnativepg::cnpg_utils::{
-    get_pooler_instances, patch_cluster_merge, patch_pooler_merge, patch_scheduled_backup_merge,
-    removed_stalled_backups,
+    get_pooler_instances, last_cron_occurrence, patch_cluster_merge, patch_pooler_merge,
+    patch_scheduled_backup_merge, removed_stalled_backups,
 };
 use crate::ingress_route_crd::IngressRoute;
+use chrono::{DateTime, Utc};
 use std::sync::Arc;
 use std::time::Duration;
 use tracing::{debug, error, info, warn};
@@ -65,8 +69,8 @@ pub async fn reconcile_cluster_hibernation(cdb: &CoreDB, ctx: &Arc<Context>) ->
         );
     }
 
-    let pooler = get_pooler(cdb, ctx.clone()).await;
-    if pooler.is_none() {
+    let poolers = get_poolers(cdb, ctx.clone()).await;
+    if poolers.is_empty() {
         warn!(
             "Pooler {} does not exist or disabled. Proceeding without it...",
             name
@@ -221,7 +225,7 @@ pub async fn reconcile_cluster_hibernation(cdb: &CoreDB, ctx: &Arc<Context>) ->
     }
 
     // Patch the Pooler cluster resource to match the hibernation state
-    if let Err(action) = update_pooler_instances(&pooler, cdb, ctx).await {
+    if let Err(action) = update_pooler_instances(&poolers, cdb, ctx).await {
         warn!(
             "Error updating pooler instances for {}. Requeuing...",
             cdb.name_any()
@@ -229,6 +233,12 @@ pub async fn reconcile_cluster_hibernation(cdb: &CoreDB, ctx: &Arc<Context>) ->
         return Err(action);
     }
 
+    // Stop the logical replication apply workers cleanly before the instance goes down
+    let hibernating = cluster_annotations.get("cnpg.io/hibernation") != Some(&"on".to_string());
+    if cdb.spec.stop && hibernating {
+        disable_subscriptions(cdb, ctx.clone()).await;
+    }
+
     // If CNPG is already hibernated then there maybe a dangling PodMonitor still present
     // This will not get cleaned up if already hibernated.  We need to remove it manually
     cleanup_hibernated_podmonitor(ctx, namespace, name.clone(), cdb, &cluster).await?;
@@ -457,56 +467,48 @@ async fn cleanup_hibernated_podmonitor(
     Ok(())
 }
 
+// update_pooler_instances scales every Pooler of the instance, the read-only and route
+// poolers included, to match the hibernation state
 async fn update_pooler_instances(
-    pooler: &Option<Pooler>,
+    poolers: &[Pooler],
     cdb: &CoreDB,
     ctx: &Arc<Context>,
 ) -> Result<(), Action> {
-    let name = cdb.name_any();
+    let Some(desired) = get_pooler_instances(cdb) else {
+        warn!(
+            "Could not determine desired instances for the Poolers of {}. Skipping update.",
+            cdb.name_any()
+        );
+        return Ok(());
+    };
 
-    match pooler {
-        Some(p) => {
-            let current_instances = p.spec.instances.unwrap_or(1);
-            let desired_instances = get_pooler_instances(cdb);
-
-            if let Some(desired) = desired_instances {
-                if current_instances != desired {
-                    let patch_pooler_spec = json!({
-                        "spec": {
-                            "instances": desired,
-                        }
-                    });
-
-                    match patch_pooler_merge(cdb, ctx, patch_pooler_spec).await {
-                        Ok(_) => {
-                            info!(
-                                "Updated Pooler instances for {} from {} to {}",
-                                name, current_instances, desired
-                            );
-                        }
-                        Err(e) => {
-                            error!("Failed to update Pooler instances for {}: {:?}", name, e);
-                            return Err(requeue_normal_with_jitter());
-                        }
-                    }
-                } else {
-                    debug!(
-                        "Pooler instances for {} already set to {}. No update needed.",
-                        name, current_instances
-                    );
-                }
-            } else {
-                warn!(
-                    "Could not determine desired instances for Pooler {}. Skipping update.",
-                    name
+    for p in poolers {
+        let name = p.name_any();
+        let current_instances = p.spec.instances.unwrap_or(1);
+        if current_instances == desired {
+            debug!(
+                "Pooler instances for {} already set to {}. No update needed.",
+                name, current_instances
+            );
+            continue;
+        }
+
+        let patch_pooler_spec = json!({
+            "spec": {
+                "instances": desired,
+            }
+        });
+        match patch_pooler_merge(cdb, ctx, &name, patch_pooler_spec).await {
+            Ok(_) => {
+                info!(
+                    "Updated Pooler instances for {} from {} to {}",
+                    name, current_instances, desired
                 );
             }
-        }
-        None => {
-            info!(
-                "Skipping Pooler operations as it doesn't exist for {}",
-                name
-            );
+            Err(e) => {
+                error!("Failed to update Pooler instances for {}: {:?}", name, e);
+                return Err(requeue_normal_with_jitter());
+            }
         }
     }
 
@@ -577,6 +579,186 @@ async fn update_scheduled_backups(
     Ok(())
 }
 
+// Client connections to the instance, ignoring the operator, the metrics exporter,
+// the pooler authentication user and replication
+const CLIENT_CONNECTIONS_QUERY: &str = "SELECT count(*) FROM pg_stat_activity \
+    WHERE backend_type = 'client backend' AND pid <> pg_backend_pid() \
+    AND application_name <> 'tembo-system' \
+    AND usename NOT IN ('postgres_exporter', 'cnpg_pooler_pgbouncer', 'streaming_replica');";
+
+/// What the hibernation policy wants to do with the instance
+#[derive(Debug, PartialEq)]
+enum PolicyAction {
+    Sleep(HibernationReason),
+    Wake,
+}
+
+/// Applies `spec.hibernation` by setting `spec.stop` on the CoreDB.
+///
+/// The instance is stopped when a scheduled window starts, or when it had no client
+/// connections for `idleTimeoutMinutes`. An instance stopped by the policy is woken up
+/// when a window ends. Stops and starts made by the control plane are recorded as
+/// well, so that `status.hibernation.reason` always holds the reason of the last
+/// hibernation.
+///
+/// Returns a short requeue after changing `spec.stop`, so that the hibernation is
+/// reconciled from the updated spec.
+pub async fn reconcile_hibernation_policy(cdb: &CoreDB, ctx: &Arc<Context>) -> Result<(), Action> {
+    let Some(policy) = cdb.spec.hibernation.as_ref() else {
+        return Ok(());
+    };
+    let name = cdb.name_any();
+    let namespace = cdb.namespace().ok_or_else(|| {
+        error!("Namespace is not set for CoreDB instance {}", name);
+        Action::requeue(Duration::from_secs(300))
+    })?;
+    let coredbs: Api<CoreDB> = Api::namespaced(ctx.client.clone(), &namespace);
+
+    let now = Utc::now();
+    let previous = cdb
+        .status
+        .as_ref()
+        .and_then(|s| s.hibernation.clone())
+        .unwrap_or_default();
+    let mut status = previous.clone();
+
+    // The control plane stopped or started the instance
+    if cdb.spec.stop != status.hibernated {
+        status.hibernated = cdb.spec.stop;
+        if cdb.spec.stop {
+            status.reason = Some(HibernationReason::Manual);
+        }
+        status.last_transition_time = Some(now);
+        status.idle_since = None;
+    }
+
+    let mut action = scheduled_action(&policy.schedules, &status, now);
+
+    let running = cdb.status.as_ref().is_some_and(|s| s.running);
+    if let (None, false, true, Some(timeout)) =
+        (&action, cdb.spec.stop, running, policy.idle_timeout_minutes)
+    {
+        match client_connections(cdb, ctx).await {
+            Some(0) => {
+                let idle_since = *status.idle_since.get_or_insert(now);
+                if is_idle_timed_out(idle_since, timeout, now) {
+                    action = Some(PolicyAction::Sleep(HibernationReason::Idle));
+                }
+            }
+            Some(_) => status.idle_since = None,
+            None => warn!(
+                "Could not count client connections of {}, skipping idle check",
+                name
+            ),
+        }
+    }
+
+    if let Some(action) = &action {
+        let stop = matches!(action, PolicyAction::Sleep(_));
+        info!("Hibernation policy of {}: {:?}", name, action);
+        let patch = json!({ "spec": { "stop": stop } });
+        let pp = PatchParams {
+            field_manager: Some("cntrlr".to_string()),
+            ..PatchParams::default()
+        };
+        coredbs
+            .patch(&name, &pp, &Patch::Merge(&patch))
+            .await
+            .map_err(|e| {
+                error!("Error setting spec.stop of {}: {:?}", name, e);
+                Action::requeue(Duration::from_secs(10))
+            })?;
+        status.hibernated = stop;
+        if let PolicyAction::Sleep(reason) = action {
+            status.reason = Some(*reason);
+        }
+        status.last_transition_time = Some(now);
+        status.idle_since = None;
+    }
+
+    if status != previous {
+        let patch_status = json!({
+            "apiVersion": "coredb.io/v1alpha1",
+            "kind": "CoreDB",
+            "status": {
+                "hibernation": status
+            }
+        });
+        patch_cdb_status_merge(&coredbs, &name, patch_status).await?;
+    }
+
+    match action {
+        Some(_) => Err(Action::requeue(Duration::from_secs(5))),
+        None => Ok(()),
+    }
+}
+
+// Decides whether a window of the schedules has started or ended since the last
+// transition of the instance. Windows already active when the instance was woken up
+// manually are not applied again.
+fn scheduled_action(
+    schedules: &[HibernationSchedule],
+    status: &HibernationStatus,
+    now: DateTime<Utc>,
+) -> Option<PolicyAction> {
+    let last_sleep = last_occurrence(schedules.iter().map(|s| s.sleep.as_str()), now);
+    let last_wake = last_occurrence(schedules.iter().map(|s| s.wake.as_str()), now);
+    let after_transition = |time: DateTime<Utc>| {
+        status
+            .last_transition_time
+            .is_none_or(|transition| time > transition)
+    };
+
+    if !status.hibernated {
+        let sleep = last_sleep?;
+        let in_window = last_wake.is_none_or(|wake| sleep > wake);
+        (in_window && after_transition(sleep))
+            .then_some(PolicyAction::Sleep(HibernationReason::Schedule))
+    } else {
+        // Only instances stopped by the policy are woken up by the schedules
+        if status.reason == Some(HibernationReason::Manual) {
+            return None;
+        }
+        let wake = last_wake?;
+        let window_ended = last_sleep.is_none_or(|sleep| wake > sleep);
+        (window_ended && after_transition(wake)).then_some(PolicyAction::Wake)
+    }
+}
+
+// The most recent time any of the cron expressions fired before `now`
+fn last_occurrence<'a>(
+    expressions: impl Iterator<Item = &'a str>,
+    now: DateTime<Utc>,
+) -> Option<DateTime<Utc>> {
+    expressions
+        .filter_map(|expression| last_cron_occurrence(expression, now))
+        .max()
+}
+
+fn is_idle_timed_out(idle_since: DateTime<Utc>, timeout_minutes: i64, now: DateTime<Utc>) -> bool {
+    now - idle_since >= chrono::Duration::minutes(timeout_minutes)
+}
+
+// Counts the client connections in pg_stat_activity, None if Postgres can't be queried
+async fn client_connections(cdb: &CoreDB, ctx: &Arc<Context>) -> Option<i64> {
+    let output = cdb
+        .psql(
+            CLIENT_CONNECTIONS_QUERY.to_string(),
+            "postgres".to_string(),
+            ctx.clone(),
+        )
+        .await
+        .ok()?;
+    if !output.success {
+        return None;
+    }
+    output
+        .stdout?
+        .lines()
+        .nth(2)
+        .and_then(|line| line.trim().parse().ok())
+}
+
 fn is_cluster_hibernated(cluster: &Cluster) -> bool {
     fn get_hibernation_condition(cluster: &Cluster) -> Option<&ClusterStatusConditions> {
         cluster
@@ -600,13 +782,114 @@ fn is_cluster_hibernated(cluster: &Cluster) -> bool {
 mod tests {
     use kube::api::ObjectMeta;
 
+    use crate::apis::coredb_types::{HibernationReason, HibernationSchedule, HibernationStatus};
     use crate::cloudnativepg::{
         clusters::{
             Cluster, ClusterSpec, ClusterStatus, ClusterStatusConditions,
             ClusterStatusConditionsStatus,
         },
-        hibernate::is_cluster_hibernated,
+        hibernate::{is_cluster_hibernated, is_idle_timed_out, scheduled_action, PolicyAction},
     };
+    use chrono::{DateTime, Utc};
+
+    fn time(rfc3339: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(rfc3339).unwrap().into()
+    }
+
+    #[test]
+    fn test_scheduled_action() {
+        let schedules = vec![HibernationSchedule {
+            sleep: "0 20 * * *".to_string(),
+            wake: "0 7 * * *".to_string(),
+        }];
+        let awake = HibernationStatus::default();
+
+        // Inside the window: stop the instance
+        assert_eq!(
+            scheduled_action(&schedules, &awake, time("2024-11-12T22:00:00Z")),
+            Some(PolicyAction::Sleep(HibernationReason::Schedule))
+        );
+        // Outside the window: nothing to do
+        assert_eq!(
+            scheduled_action(&schedules, &awake, time("2024-11-12T12:00:00Z")),
+            None
+        );
+        // Woken up manually inside the window: do not stop it again
+        let woken_up = HibernationStatus {
+            last_transition_time: Some(time("2024-11-12T21:00:00Z")),
+            ..HibernationStatus::default()
+        };
+        assert_eq!(
+            scheduled_action(&schedules, &woken_up, time("2024-11-12T22:00:00Z")),
+            None
+        );
+        // The next window stops it again
+        assert_eq!(
+            scheduled_action(&schedules, &woken_up, time("2024-11-13T20:30:00Z")),
+            Some(PolicyAction::Sleep(HibernationReason::Schedule))
+        );
+
+        // The window ended: wake up the instance
+        let mut hibernated = HibernationStatus {
+            hibernated: true,
+            reason: Some(HibernationReason::Schedule),
+            last_transition_time: Some(time("2024-11-12T20:00:00Z")),
+            idle_since: None,
+        };
+        assert_eq!(
+            scheduled_action(&schedules, &hibernated, time("2024-11-12T23:00:00Z")),
+            None
+        );
+        assert_eq!(
+            scheduled_action(&schedules, &hibernated, time("2024-11-13T07:01:00Z")),
+            Some(PolicyAction::Wake)
+        );
+        // Instances stopped manually are not woken up by the schedule
+        hibernated.reason = Some(HibernationReason::Manual);
+        assert_eq!(
+            scheduled_action(&schedules, &hibernated, time("2024-11-13T07:01:00Z")),
+            None
+        );
+
+        // Weeknights and weekends: 2024-11-16 is a Saturday
+        let weekdays = vec![HibernationSchedule {
+            sleep: "0 20 * * MON-FRI".to_string(),
+            wake: "0 7 * * MON-FRI".to_string(),
+        }];
+        assert_eq!(
+            scheduled_action(&weekdays, &awake, time("2024-11-16T12:00:00Z")),
+            Some(PolicyAction::Sleep(HibernationReason::Schedule))
+        );
+        assert_eq!(
+            scheduled_action(&weekdays, &awake, time("2024-11-18T12:00:00Z")),
+            None
+        );
+
+        // Invalid expressions are ignored
+        let invalid = vec![HibernationSchedule {
+            sleep: "not a cron".to_string(),
+            wake: "0 7 * * *".to_string(),
+        }];
+        assert_eq!(
+            scheduled_action(&invalid, &awake, time("2024-11-12T22:00:00Z")),
+            None
+        );
+    }
+
+    #[test]
+    fn test_is_idle_timed_out() {
+        let idle_since = time("2024-11-12T10:00:00Z");
+        assert!(!is_idle_timed_out(
+            idle_since,
+            30,
+            time("2024-11-12T10:29:00Z")
+        ));
+        assert!(is_idle_timed_out(
+            idle_since,
+            30,
+            time("2024-11-12T10:30:00Z")
+        ));
+    }
 
     #[test]
     fn test_is_cluster_hibernated() {
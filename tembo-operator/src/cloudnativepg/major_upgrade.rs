@@ -0,0 +1,1000 @@
+use crate::{
+    apis::coredb_types::{pg_major_from_image, CoreDB, PgUpgradePhase, PgUpgradeStatus},
+    cloudnativepg::{
+        backups::{Backup, BackupCluster, BackupMethod, BackupSpec},
+        clusters::{
+            Cluster, ClusterBootstrap, ClusterBootstrapInitdb, ClusterBootstrapInitdbImport,
+            ClusterBootstrapInitdbImportSource, ClusterBootstrapInitdbImportType,
+            ClusterExternalClusters, ClusterExternalClustersPassword, ClusterManaged,
+            ClusterManagedRoles, ClusterManagedRolesEnsure, ClusterManagedRolesPasswordSecret,
+            ClusterMonitoring, ClusterSpec, ClusterStatusConditionsStatus,
+        },
+        cnpg::cnpg_cluster_from_cdb,
+    },
+    patch_cdb_status_merge,
+    secret::reconcile_postgres_role_secret,
+    trunk::extensions_that_require_load,
+    Context,
+};
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use kube::{
+    api::{DeleteParams, ObjectMeta, Patch, PatchParams},
+    runtime::controller::Action,
+    Api, ResourceExt,
+};
+use serde_json::json;
+use std::{collections::BTreeMap, sync::Arc};
+use tokio::time::Duration;
+use tracing::{debug, error, info, instrument, warn};
+
+// Name of the external cluster used as the source of the logical import
+const UPGRADE_SOURCE_NAME: &str = "tembo-upgrade-source";
+
+// Label of the final backup taken before the switchover, backup retention never deletes it
+pub(crate) const FINAL_BACKUP_LABEL: &str = "tembo.io/pg-upgrade-final-backup";
+
+// The instance only accepts reads from the start of the import until the switchover
+const READ_ONLY_PARAMETER: &str = "default_transaction_read_only";
+
+// The temporary superuser the staging cluster imports the data with. While the import is
+// running, it is the only role allowed to connect to the instance over the network.
+pub(crate) const UPGRADE_ROLE: &str = "tembo_upgrade";
+
+// upgrade_hba returns the pg_hba rules blocking the connections to the instance during
+// the import. CNPG puts its own rules for replication and the pooler before them, and
+// local connections are still accepted.
+fn upgrade_hba() -> Vec<String> {
+    vec![
+        format!("host all {UPGRADE_ROLE} all scram-sha-256"),
+        "host all all all reject".to_string(),
+    ]
+}
+
+// upgrade_secret_name returns the name of the secret holding the password of UPGRADE_ROLE
+fn upgrade_secret_name(name: &str) -> String {
+    format!("{}-upgrade", name)
+}
+
+// staging_cluster_name returns the name of the temporary cluster holding the
+// data on the new major version during an upgrade
+pub(crate) fn staging_cluster_name(name: &str) -> String {
+    format!("{}-upgrade", name)
+}
+
+#[derive(Debug, PartialEq)]
+enum UpgradeDecision {
+    NotRequired,
+    Start,
+    Refuse(String),
+}
+
+// decide_upgrade checks if moving from one major version to another is required and allowed
+fn decide_upgrade(from_version: u32, to_version: u32, enabled: bool) -> UpgradeDecision {
+    if from_version == to_version {
+        UpgradeDecision::NotRequired
+    } else if to_version < from_version {
+        UpgradeDecision::Refuse(format!(
+            "Downgrading Postgres from {} to {} is not supported",
+            from_version, to_version
+        ))
+    } else if !enabled {
+        UpgradeDecision::Refuse(format!(
+            "Upgrading Postgres from {} to {} requires spec.pgUpgrade.enabled",
+            from_version, to_version
+        ))
+    } else {
+        UpgradeDecision::Start
+    }
+}
+
+// final_backup_name returns the name of the backup of the previous version taken
+// before the original cluster is removed
+fn final_backup_name(name: &str, from_version: u32) -> String {
+    format!("{}-pg{}-final", name, from_version)
+}
+
+// has_backups returns true if the cluster archives its WAL and base backups, which
+// is required to take the final backup of an upgrade
+fn has_backups(cluster: &Cluster) -> bool {
+    cluster
+        .spec
+        .backup
+        .as_ref()
+        .is_some_and(|b| b.barman_object_store.is_some())
+}
+
+// is_upgrade_in_progress returns true while the upgrade requires the operator to
+// deviate from the cluster spec generated from the CoreDB
+pub(crate) fn is_upgrade_in_progress(upgrade: &PgUpgradeStatus) -> bool {
+    matches!(
+        upgrade.phase,
+        PgUpgradePhase::Blocked
+            | PgUpgradePhase::Importing
+            | PgUpgradePhase::SwitchingOver
+            | PgUpgradePhase::Restoring
+            | PgUpgradePhase::RestoreFailed
+    )
+}
+
+// upgrade_import_bootstrap generates a bootstrap that imports all databases and roles
+// from the cluster behind the given read-write service, as the given superuser
+fn upgrade_import_bootstrap(
+    source_service: &str,
+    superuser: &str,
+    superuser_secret: &str,
+) -> (ClusterBootstrap, Vec<ClusterExternalClusters>) {
+    let bootstrap = ClusterBootstrap {
+        initdb: Some(ClusterBootstrapInitdb {
+            import: Some(ClusterBootstrapInitdbImport {
+                databases: vec!["*".to_string()],
+                roles: Some(vec!["*".to_string()]),
+                source: ClusterBootstrapInitdbImportSource {
+                    external_cluster: UPGRADE_SOURCE_NAME.to_string(),
+                },
+                r#type: ClusterBootstrapInitdbImportType::Monolith,
+                post_import_application_sql: None,
+                schema_only: None,
+            }),
+            ..ClusterBootstrapInitdb::default()
+        }),
+        ..ClusterBootstrap::default()
+    };
+
+    let connection_parameters = BTreeMap::from([
+        ("host".to_string(), source_service.to_string()),
+        ("user".to_string(), superuser.to_string()),
+        ("dbname".to_string(), "postgres".to_string()),
+    ]);
+    let external_clusters = vec![ClusterExternalClusters {
+        name: UPGRADE_SOURCE_NAME.to_string(),
+        connection_parameters: Some(connection_parameters),
+        password: Some(ClusterExternalClustersPassword {
+            name: Some(superuser_secret.to_string()),
+            key: "password".to_string(),
+            ..ClusterExternalClustersPassword::default()
+        }),
+        ..ClusterExternalClusters::default()
+    }];
+
+    (bootstrap, external_clusters)
+}
+
+// staging_cluster_from_cdb generates the temporary cluster running the new major version,
+// importing its data from the current cluster of the instance
+fn staging_cluster_from_cdb(
+    cdb: &CoreDB,
+    upgrade: &PgUpgradeStatus,
+    requires_load: BTreeMap<String, String>,
+) -> Cluster {
+    let name = cdb.name_any();
+    let mut cluster = cnpg_cluster_from_cdb(cdb, None, requires_load);
+    let (bootstrap, external_clusters) = upgrade_import_bootstrap(
+        &format!("{}-rw", name),
+        UPGRADE_ROLE,
+        &upgrade_secret_name(&name),
+    );
+
+    cluster.metadata.name = Some(staging_cluster_name(&name));
+    if let Some(annotations) = cluster.metadata.annotations.as_mut() {
+        annotations.remove("cnpg.io/fencedInstances");
+    }
+    cluster.spec.image_name = Some(upgrade.to_image.clone());
+    cluster.spec.instances = 1;
+    cluster.spec.bootstrap = Some(bootstrap);
+    cluster.spec.external_clusters = Some(external_clusters);
+    // The staging cluster must never write into the backups of the instance
+    cluster.spec.backup = None;
+    cluster.spec.monitoring = Some(ClusterMonitoring {
+        enable_pod_monitor: Some(false),
+        ..ClusterMonitoring::default()
+    });
+    if let Some(postgresql) = cluster.spec.postgresql.as_mut() {
+        postgresql.shared_preload_libraries = None;
+    }
+    cluster
+}
+
+// block_writes makes the cluster refuse writes during the import, or lifts it again.
+// Connections over the network are rejected except for UPGRADE_ROLE, and local sessions
+// are read-only. UPGRADE_ROLE only exists while writes are blocked.
+fn block_writes(spec: &mut ClusterSpec, name: &str, blocked: bool) {
+    let postgresql = spec.postgresql.get_or_insert_with(Default::default);
+    let parameters = postgresql.parameters.get_or_insert_with(BTreeMap::new);
+    let mut pg_hba: Vec<String> = postgresql
+        .pg_hba
+        .take()
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|rule| !upgrade_hba().contains(rule))
+        .collect();
+    if blocked {
+        parameters.insert(READ_ONLY_PARAMETER.to_string(), "on".to_string());
+        pg_hba.splice(0..0, upgrade_hba());
+    } else {
+        parameters.remove(READ_ONLY_PARAMETER);
+    }
+    postgresql.pg_hba = (!pg_hba.is_empty()).then_some(pg_hba);
+
+    let roles = spec
+        .managed
+        .get_or_insert_with(ClusterManaged::default)
+        .roles
+        .get_or_insert_with(Vec::new);
+    roles.retain(|role| role.name != UPGRADE_ROLE);
+    roles.push(ClusterManagedRoles {
+        name: UPGRADE_ROLE.to_string(),
+        ensure: Some(if blocked {
+            ClusterManagedRolesEnsure::Present
+        } else {
+            ClusterManagedRolesEnsure::Absent
+        }),
+        login: Some(true),
+        superuser: Some(true),
+        password_secret: Some(ClusterManagedRolesPasswordSecret {
+            name: upgrade_secret_name(name),
+        }),
+        ..ClusterManagedRoles::default()
+    });
+}
+
+// apply_upgrade_to_cluster adjusts the cluster generated from the CoreDB to the current
+// phase of the major version upgrade
+pub(crate) fn apply_upgrade_to_cluster(cdb: &CoreDB, cluster: &mut Cluster) {
+    let Some(upgrade) = cdb.status.as_ref().and_then(|s| s.pg_upgrade.as_ref()) else {
+        return;
+    };
+    let name = cdb.name_any();
+
+    match upgrade.phase {
+        PgUpgradePhase::Blocked => {
+            cluster.spec.image_name = Some(upgrade.from_image.clone());
+        }
+        PgUpgradePhase::Importing | PgUpgradePhase::SwitchingOver => {
+            // Keep running the previous version until the cutover, without accepting
+            // writes that would not be part of the import
+            cluster.spec.image_name = Some(upgrade.from_image.clone());
+            block_writes(&mut cluster.spec, &name, true);
+        }
+        PgUpgradePhase::Restoring | PgUpgradePhase::RestoreFailed => {
+            let (bootstrap, external_clusters) = upgrade_import_bootstrap(
+                &format!("{}-rw", staging_cluster_name(&name)),
+                "postgres",
+                &format!("{}-connection", name),
+            );
+            cluster.spec.bootstrap = Some(bootstrap);
+            cluster.spec.external_clusters = Some(external_clusters);
+            // The role was imported from the staging cluster
+            block_writes(&mut cluster.spec, &name, false);
+        }
+        PgUpgradePhase::Completed | PgUpgradePhase::RolledBack => {
+            block_writes(&mut cluster.spec, &name, false);
+        }
+    }
+
+    // A recreated cluster is a new database system, so its WAL archive and base backups
+    // are kept apart from those of the previous major version
+    if matches!(
+        upgrade.phase,
+        PgUpgradePhase::Restoring | PgUpgradePhase::RestoreFailed | PgUpgradePhase::Completed
+    ) {
+        if let Some(object_store) = cluster
+            .spec
+            .backup
+            .as_mut()
+            .and_then(|b| b.barman_object_store.as_mut())
+        {
+            object_store.server_name = Some(format!("{}-pg{}", name, upgrade.to_version));
+        }
+    }
+}
+
+pub(crate) fn is_cluster_ready(cluster: &Cluster) -> bool {
+    cluster
+        .status
+        .as_ref()
+        .and_then(|s| s.conditions.as_ref())
+        .map(|conditions| {
+            conditions
+                .iter()
+                .any(|c| c.r#type == "Ready" && c.status == ClusterStatusConditionsStatus::True)
+        })
+        .unwrap_or(false)
+}
+
+pub(crate) async fn get_cluster_by_name(
+    api: &Api<Cluster>,
+    name: &str,
+) -> Result<Option<Cluster>, Action> {
+    match api.get(name).await {
+        Ok(cluster) => Ok(Some(cluster)),
+        Err(kube::Error::Api(ae)) if ae.code == 404 => Ok(None),
+        Err(e) => {
+            error!("Error getting Cluster {}: {}", name, e);
+            Err(Action::requeue(Duration::from_secs(300)))
+        }
+    }
+}
+
+pub(crate) async fn delete_cluster(api: &Api<Cluster>, name: &str) -> Result<(), Action> {
+    match api.delete(name, &DeleteParams::default()).await {
+        Ok(_) => Ok(()),
+        Err(kube::Error::Api(ae)) if ae.code == 404 => Ok(()),
+        Err(e) => {
+            error!("Error deleting Cluster {}: {}", name, e);
+            Err(Action::requeue(Duration::from_secs(300)))
+        }
+    }
+}
+
+async fn patch_upgrade_status(
+    coredbs: &Api<CoreDB>,
+    name: &str,
+    upgrade: Option<&PgUpgradeStatus>,
+) -> Result<(), Action> {
+    let patch_status = json!({
+        "apiVersion": "coredb.io/v1alpha1",
+        "kind": "CoreDB",
+        "status": {
+            "pg_upgrade": upgrade
+        }
+    });
+    patch_cdb_status_merge(coredbs, name, patch_status).await
+}
+
+// set_writes_blocked applies block_writes to the cluster on the previous version right
+// away, as the import only starts once writes are blocked and a rollback lifts it
+async fn set_writes_blocked(api: &Api<Cluster>, name: &str, blocked: bool) -> Result<(), Action> {
+    let Some(mut cluster) = get_cluster_by_name(api, name).await? else {
+        return Ok(());
+    };
+    block_writes(&mut cluster.spec, name, blocked);
+    let read_only = if blocked { json!("on") } else { json!(null) };
+    let patch = json!({
+        "spec": {
+            "postgresql": {
+                "parameters": { READ_ONLY_PARAMETER: read_only },
+                "pg_hba": cluster.spec.postgresql.and_then(|p| p.pg_hba),
+            },
+            "managed": cluster.spec.managed,
+        }
+    });
+    let pp = PatchParams {
+        field_manager: Some("cntrlr".to_string()),
+        ..PatchParams::default()
+    };
+    match api.patch(name, &pp, &Patch::Merge(&patch)).await {
+        Ok(_) => Ok(()),
+        Err(kube::Error::Api(ae)) if ae.code == 404 => Ok(()),
+        Err(e) => {
+            error!("Error blocking writes on Cluster {}: {}", name, e);
+            Err(Action::requeue(Duration::from_secs(10)))
+        }
+    }
+}
+
+// are_writes_blocked checks that the primary of the instance applies block_writes
+async fn are_writes_blocked(cdb: &CoreDB, ctx: Arc<Context>) -> Result<bool, Action> {
+    let result = cdb
+        .psql(
+            format!(
+                "SELECT current_setting('{READ_ONLY_PARAMETER}') = 'on' \
+                AND EXISTS (SELECT 1 FROM pg_hba_file_rules WHERE auth_method = 'reject') \
+                AND EXISTS (SELECT 1 FROM pg_roles WHERE rolname = '{UPGRADE_ROLE}');"
+            ),
+            "postgres".to_string(),
+            ctx,
+        )
+        .await?;
+    Ok(result
+        .stdout
+        .is_some_and(|stdout| stdout.lines().any(|line| line.trim() == "t")))
+}
+
+// terminate_connections closes the connections opened over the network before writes
+// were blocked, except those of the import
+async fn terminate_connections(cdb: &CoreDB, ctx: Arc<Context>) -> Result<(), Action> {
+    cdb.psql(
+        format!(
+            "SELECT count(pg_terminate_backend(pid)) FROM pg_stat_activity \
+            WHERE backend_type = 'client backend' AND client_addr IS NOT NULL \
+            AND usename <> '{UPGRADE_ROLE}';"
+        ),
+        "postgres".to_string(),
+        ctx,
+    )
+    .await?;
+    Ok(())
+}
+
+// final_backup generates an on-demand backup of the cluster on the previous version.
+// It is not owned by the cluster, so it outlives the cluster.
+fn final_backup(name: &str, namespace: &str, from_version: u32) -> Backup {
+    Backup {
+        metadata: ObjectMeta {
+            name: Some(final_backup_name(name, from_version)),
+            namespace: Some(namespace.to_string()),
+            labels: Some(BTreeMap::from([
+                ("cnpg.io/cluster".to_string(), name.to_string()),
+                (FINAL_BACKUP_LABEL.to_string(), name.to_string()),
+            ])),
+            ..ObjectMeta::default()
+        },
+        spec: BackupSpec {
+            cluster: BackupCluster {
+                name: name.to_string(),
+            },
+            method: Some(BackupMethod::BarmanObjectStore),
+            ..BackupSpec::default()
+        },
+        status: None,
+    }
+}
+
+// roll_back restores the previous version while its cluster is still running
+async fn roll_back(
+    coredbs: &Api<CoreDB>,
+    cluster_api: &Api<Cluster>,
+    name: &str,
+    upgrade: &mut PgUpgradeStatus,
+    message: String,
+) -> Result<(), Action> {
+    delete_cluster(cluster_api, &staging_cluster_name(name)).await?;
+    set_writes_blocked(cluster_api, name, false).await?;
+    let patch = json!({ "spec": { "image": upgrade.from_image } });
+    let pp = PatchParams {
+        field_manager: Some("cntrlr".to_string()),
+        ..PatchParams::default()
+    };
+    coredbs
+        .patch(name, &pp, &Patch::Merge(&patch))
+        .await
+        .map_err(|e| {
+            error!("Error restoring image of {}: {:?}", name, e);
+            Action::requeue(Duration::from_secs(10))
+        })?;
+    upgrade.phase = PgUpgradePhase::RolledBack;
+    upgrade.finished_at = Some(Utc::now());
+    upgrade.message = Some(message);
+    Ok(())
+}
+
+// restore_failed_message tells how to recover an instance which was not recreated on the
+// new version after its cluster on the previous version was removed
+fn restore_failed_message(name: &str, upgrade: &PgUpgradeStatus, timeout_minutes: i64) -> String {
+    format!(
+        "Cluster {} was not recreated on Postgres {} within {} minutes. The data is kept in the \
+        staging cluster {}. To roll back, restore the final backup {} into a new instance with \
+        spec.image {} and spec.restore.serverName {}",
+        name,
+        upgrade.to_version,
+        timeout_minutes,
+        staging_cluster_name(name),
+        upgrade.final_backup.as_deref().unwrap_or_default(),
+        upgrade.from_image,
+        upgrade.restore_server_name.as_deref().unwrap_or(name),
+    )
+}
+
+fn is_timed_out(started_at: Option<DateTime<Utc>>, timeout_minutes: i64) -> bool {
+    started_at
+        .map(|started| Utc::now() - started > ChronoDuration::minutes(timeout_minutes))
+        .unwrap_or(false)
+}
+
+/// Drives a Postgres major version upgrade of the instance when the major version
+/// of `spec.image` differs from the one the Cluster is running.
+///
+/// The upgrade is a state machine persisted in `status.pg_upgrade`:
+/// * `Importing`: writes to the instance are blocked, then a staging cluster on the new
+///   version imports all data from it
+/// * `SwitchingOver`: a final backup of the previous version is taken, then the Cluster of
+///   the instance on the previous version is deleted
+/// * `Restoring`: the Cluster is recreated on the new version from the staging cluster
+/// * `RestoreFailed`: the Cluster was not recreated in time, the status tells how to
+///   restore the final backup, and the upgrade completes if the Cluster becomes ready
+/// * `Completed`: the staging cluster is deleted
+///
+/// Returns a requeue while the upgrade is progressing.
+#[instrument(skip(cdb, ctx), fields(trace_id, instance_name = %cdb.name_any()))]
+pub async fn reconcile_major_upgrade(cdb: &CoreDB, ctx: Arc<Context>) -> Result<(), Action> {
+    let name = cdb.name_any();
+    let namespace = cdb.namespace().ok_or_else(|| {
+        error!("Namespace is empty for instance: {}.", name);
+        Action::requeue(Duration::from_secs(300))
+    })?;
+    let cluster_api: Api<Cluster> = Api::namespaced(ctx.client.clone(), &namespace);
+    let coredbs: Api<CoreDB> = Api::namespaced(ctx.client.clone(), &namespace);
+    let staging_name = staging_cluster_name(&name);
+    let current_upgrade = cdb.status.as_ref().and_then(|s| s.pg_upgrade.clone());
+    let upgrade_spec = cdb.spec.pg_upgrade.clone().unwrap_or_default();
+
+    let mut upgrade = match current_upgrade {
+        Some(upgrade)
+            if is_upgrade_in_progress(&upgrade) && upgrade.phase != PgUpgradePhase::Blocked =>
+        {
+            upgrade
+        }
+        // A blocked upgrade is decided again, as the request may be withdrawn or now allowed
+        previous_upgrade => {
+            // Nothing to do for instances that do not have a cluster yet
+            let Some(cluster) = get_cluster_by_name(&cluster_api, &name).await? else {
+                return Ok(());
+            };
+            let Some(from_image) = cluster.spec.image_name.clone() else {
+                return Ok(());
+            };
+            let from_version = pg_major_from_image(&from_image);
+            let to_version = cdb.spec.pg_major();
+            let decision = if cdb.spec.is_replica() && from_version != to_version {
+                // A replica must run the same major version as its source
+                UpgradeDecision::Refuse(
+                    "Replicas follow the major version of their source, upgrade the source instead"
+                        .to_string(),
+                )
+            } else {
+                match decide_upgrade(from_version, to_version, upgrade_spec.enabled) {
+                    // Without a final backup, the previous version could not be restored
+                    UpgradeDecision::Start if !has_backups(&cluster) => {
+                        UpgradeDecision::Refuse(format!(
+                            "Upgrading Postgres from {} to {} requires backups to be enabled",
+                            from_version, to_version
+                        ))
+                    }
+                    decision => decision,
+                }
+            };
+            let was_blocked = previous_upgrade
+                .as_ref()
+                .is_some_and(|u| u.phase == PgUpgradePhase::Blocked);
+            match decision {
+                UpgradeDecision::NotRequired => {
+                    if was_blocked {
+                        patch_upgrade_status(&coredbs, &name, None).await?;
+                    }
+                    return Ok(());
+                }
+                UpgradeDecision::Refuse(message) => {
+                    warn!("Not upgrading instance {}: {}", name, message);
+                    let blocked = PgUpgradeStatus {
+                        phase: PgUpgradePhase::Blocked,
+                        from_image,
+                        to_image: cdb.spec.image.clone(),
+                        from_version,
+                        to_version,
+                        started_at: None,
+                        finished_at: None,
+                        message: Some(message),
+                        final_backup: None,
+                        restore_server_name: None,
+                        switched_over_at: None,
+                    };
+                    if previous_upgrade.as_ref() != Some(&blocked) {
+                        patch_upgrade_status(&coredbs, &name, Some(&blocked)).await?;
+                    }
+                    return Ok(());
+                }
+                UpgradeDecision::Start => {
+                    info!(
+                        "Starting upgrade of instance {} from Postgres {} to {}",
+                        name, from_version, to_version
+                    );
+                    PgUpgradeStatus {
+                        phase: PgUpgradePhase::Importing,
+                        from_image,
+                        to_image: cdb.spec.image.clone(),
+                        from_version,
+                        to_version,
+                        started_at: Some(Utc::now()),
+                        finished_at: None,
+                        message: None,
+                        final_backup: None,
+                        restore_server_name: None,
+                        switched_over_at: None,
+                    }
+                }
+            }
+        }
+    };
+
+    match upgrade.phase {
+        PgUpgradePhase::Blocked => return Ok(()),
+        PgUpgradePhase::Importing => {
+            match get_cluster_by_name(&cluster_api, &staging_name).await? {
+                Some(staging) if is_cluster_ready(&staging) => {
+                    info!("Staging cluster {} is ready, switching over", staging_name);
+                    upgrade.phase = PgUpgradePhase::SwitchingOver;
+                }
+                Some(_) if is_timed_out(upgrade.started_at, upgrade_spec.timeout_minutes) => {
+                    warn!(
+                        "Upgrade of instance {} timed out, rolling back to {}",
+                        name, upgrade.from_image
+                    );
+                    let message = format!(
+                        "Import into Postgres {} did not complete within {} minutes",
+                        upgrade.to_version, upgrade_spec.timeout_minutes
+                    );
+                    roll_back(&coredbs, &cluster_api, &name, &mut upgrade, message).await?;
+                }
+                Some(_) => {
+                    debug!("Waiting for staging cluster {} to be ready", staging_name);
+                }
+                None => {
+                    // The import only starts once no more writes can be made to the instance
+                    reconcile_postgres_role_secret(
+                        cdb,
+                        ctx.clone(),
+                        UPGRADE_ROLE,
+                        &upgrade_secret_name(&name),
+                    )
+                    .await
+                    .map_err(|e| {
+                        error!("Error creating the secret of {}: {:?}", UPGRADE_ROLE, e);
+                        Action::requeue(Duration::from_secs(10))
+                    })?;
+                    set_writes_blocked(&cluster_api, &name, true).await?;
+                    if !are_writes_blocked(cdb, ctx.clone()).await? {
+                        info!("Waiting for instance {} to block writes", name);
+                        patch_upgrade_status(&coredbs, &name, Some(&upgrade)).await?;
+                        return Err(Action::requeue(Duration::from_secs(10)));
+                    }
+                    terminate_connections(cdb, ctx.clone()).await?;
+                    let requires_load =
+                        extensions_that_require_load(ctx.client.clone(), &namespace).await?;
+                    let staging = staging_cluster_from_cdb(cdb, &upgrade, requires_load);
+                    let pp = PatchParams::apply("cntrlr").force();
+                    cluster_api
+                        .patch(&staging_name, &pp, &Patch::Apply(&staging))
+                        .await
+                        .map_err(|e| {
+                            error!("Error creating staging Cluster {}: {}", staging_name, e);
+                            Action::requeue(Duration::from_secs(300))
+                        })?;
+                    info!("Created staging cluster {}", staging_name);
+                }
+            }
+        }
+        PgUpgradePhase::SwitchingOver => {
+            if let Some(cluster) = get_cluster_by_name(&cluster_api, &name).await? {
+                let backup_api: Api<Backup> = Api::namespaced(ctx.client.clone(), &namespace);
+                let backup_name = final_backup_name(&name, upgrade.from_version);
+                let backup = backup_api.get_opt(&backup_name).await.map_err(|e| {
+                    error!("Error getting Backup {}: {}", backup_name, e);
+                    Action::requeue(Duration::from_secs(300))
+                })?;
+                let phase = backup
+                    .as_ref()
+                    .and_then(|b| b.status.as_ref())
+                    .and_then(|s| s.phase.clone());
+                match (backup, phase.as_deref()) {
+                    (None, _) => {
+                        info!(
+                            "Taking final backup {} of Postgres {}",
+                            backup_name, upgrade.from_version
+                        );
+                        let backup = final_backup(&name, &namespace, upgrade.from_version);
+                        let pp = PatchParams::apply("cntrlr").force();
+                        backup_api
+                            .patch(&backup_name, &pp, &Patch::Apply(&backup))
+                            .await
+                            .map_err(|e| {
+                                error!("Error creating Backup {}: {}", backup_name, e);
+                                Action::requeue(Duration::from_secs(300))
+                            })?;
+                        upgrade.final_backup = Some(backup_name);
+                        upgrade.restore_server_name = Some(
+                            cluster
+                                .spec
+                                .backup
+                                .as_ref()
+                                .and_then(|b| b.barman_object_store.as_ref())
+                                .and_then(|s| s.server_name.clone())
+                                .unwrap_or_else(|| name.clone()),
+                        );
+                    }
+                    (Some(_), Some("completed")) => {
+                        info!(
+                            "Deleting Cluster {} running Postgres {}",
+                            name, upgrade.from_version
+                        );
+                        delete_cluster(&cluster_api, &name).await?;
+                    }
+                    (Some(_), Some("failed")) => {
+                        warn!(
+                            "Final backup {} failed, rolling back to {}",
+                            backup_name, upgrade.from_image
+                        );
+                        let message = format!(
+                            "The final backup of Postgres {} failed",
+                            upgrade.from_version
+                        );
+                        roll_back(&coredbs, &cluster_api, &name, &mut upgrade, message).await?;
+                    }
+                    (Some(_), _) => {
+                        debug!("Waiting for final backup {} to complete", backup_name);
+                    }
+                }
+            } else {
+                // Trunk installs and extensions are reconciled again on the new pods
+                let patch_status = json!({
+                    "apiVersion": "coredb.io/v1alpha1",
+                    "kind": "CoreDB",
+                    "status": {
+                        "trunk_installs": null,
+                        "extensions": null
+                    }
+                });
+                patch_cdb_status_merge(&coredbs, &name, patch_status).await?;
+                upgrade.phase = PgUpgradePhase::Restoring;
+                upgrade.switched_over_at = Some(Utc::now());
+            }
+        }
+        PgUpgradePhase::Restoring | PgUpgradePhase::RestoreFailed => {
+            match get_cluster_by_name(&cluster_api, &name).await? {
+                Some(cluster) if is_cluster_ready(&cluster) => {
+                    info!(
+                        "Cluster {} is running Postgres {}, removing staging cluster",
+                        name, upgrade.to_version
+                    );
+                    delete_cluster(&cluster_api, &staging_name).await?;
+                    upgrade.phase = PgUpgradePhase::Completed;
+                    upgrade.finished_at = Some(Utc::now());
+                    upgrade.message = None;
+                }
+                // The previous version can not be rolled back to in place anymore, the
+                // upgrade keeps waiting for the cluster and reports how to restore it
+                _ if upgrade.phase == PgUpgradePhase::Restoring
+                    && is_timed_out(upgrade.switched_over_at, upgrade_spec.timeout_minutes) =>
+                {
+                    warn!(
+                        "Cluster {} was not recreated on Postgres {} in time",
+                        name, upgrade.to_version
+                    );
+                    upgrade.phase = PgUpgradePhase::RestoreFailed;
+                    upgrade.message = Some(restore_failed_message(
+                        &name,
+                        &upgrade,
+                        upgrade_spec.timeout_minutes,
+                    ));
+                }
+                _ => {}
+            }
+            // The Cluster is (re)created by reconcile_cnpg during this phase
+            patch_upgrade_status(&coredbs, &name, Some(&upgrade)).await?;
+            return Ok(());
+        }
+        PgUpgradePhase::Completed | PgUpgradePhase::RolledBack => return Ok(()),
+    }
+
+    patch_upgrade_status(&coredbs, &name, Some(&upgrade)).await?;
+    Err(Action::requeue(Duration::from_secs(10)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cloudnativepg::clusters::{ClusterStatus, ClusterStatusConditions};
+
+    fn upgrade_status(phase: PgUpgradePhase) -> PgUpgradeStatus {
+        PgUpgradeStatus {
+            phase,
+            from_image: "quay.io/tembo/standard-cnpg:15-bffd097".to_string(),
+            to_image: "quay.io/tembo/standard-cnpg:16-bffd097".to_string(),
+            from_version: 15,
+            to_version: 16,
+            started_at: Some(Utc::now()),
+            finished_at: None,
+            message: None,
+            final_backup: None,
+            restore_server_name: None,
+            switched_over_at: None,
+        }
+    }
+
+    fn test_cdb(phase: Option<PgUpgradePhase>) -> CoreDB {
+        let cdb_yaml = r#"
+        apiVersion: coredb.io/v1alpha1
+        kind: CoreDB
+        metadata:
+          name: test
+          namespace: default
+          uid: 752d59ef-2671-4890-9feb-0097459b18c8
+        spec:
+          backup:
+            destinationPath: s3://tembo-backup/test
+          image: quay.io/tembo/standard-cnpg:16-bffd097
+          pgUpgrade:
+            enabled: true
+        "#;
+        let mut cdb: CoreDB = serde_yaml::from_str(cdb_yaml).expect("Failed to parse YAML");
+        cdb.status = Some(crate::apis::coredb_types::CoreDBStatus {
+            pg_upgrade: phase.map(upgrade_status),
+            ..Default::default()
+        });
+        cdb
+    }
+
+    #[test]
+    fn test_decide_upgrade() {
+        assert_eq!(decide_upgrade(15, 15, false), UpgradeDecision::NotRequired);
+        assert_eq!(decide_upgrade(15, 16, true), UpgradeDecision::Start);
+        assert!(matches!(
+            decide_upgrade(15, 16, false),
+            UpgradeDecision::Refuse(_)
+        ));
+        assert!(matches!(
+            decide_upgrade(16, 15, true),
+            UpgradeDecision::Refuse(_)
+        ));
+    }
+
+    #[test]
+    fn test_staging_cluster_from_cdb() {
+        let cdb = test_cdb(Some(PgUpgradePhase::Importing));
+        let upgrade = upgrade_status(PgUpgradePhase::Importing);
+        let cluster = staging_cluster_from_cdb(&cdb, &upgrade, BTreeMap::new());
+
+        assert_eq!(cluster.metadata.name.as_deref(), Some("test-upgrade"));
+        assert_eq!(
+            cluster.spec.image_name.as_deref(),
+            Some("quay.io/tembo/standard-cnpg:16-bffd097")
+        );
+        assert_eq!(cluster.spec.instances, 1);
+        assert!(cluster.spec.backup.is_none());
+
+        let import = cluster
+            .spec
+            .bootstrap
+            .and_then(|b| b.initdb)
+            .and_then(|i| i.import)
+            .expect("Expected an import bootstrap");
+        assert_eq!(import.databases, vec!["*".to_string()]);
+        assert_eq!(import.source.external_cluster, UPGRADE_SOURCE_NAME);
+
+        let external = &cluster.spec.external_clusters.unwrap()[0];
+        assert_eq!(
+            external
+                .connection_parameters
+                .as_ref()
+                .and_then(|p| p.get("host"))
+                .map(String::as_str),
+            Some("test-rw")
+        );
+        // The import connects with the role allowed past the blocked writes
+        assert_eq!(
+            external
+                .connection_parameters
+                .as_ref()
+                .and_then(|p| p.get("user"))
+                .map(String::as_str),
+            Some(UPGRADE_ROLE)
+        );
+        assert_eq!(
+            external.password.as_ref().and_then(|p| p.name.as_deref()),
+            Some("test-upgrade")
+        );
+    }
+
+    #[test]
+    fn test_apply_upgrade_to_cluster() {
+        // The previous image is kept while importing
+        let cdb = test_cdb(Some(PgUpgradePhase::Importing));
+        let mut cluster = cnpg_cluster_from_cdb(&cdb, None, BTreeMap::new());
+        apply_upgrade_to_cluster(&cdb, &mut cluster);
+        assert_eq!(
+            cluster.spec.image_name.as_deref(),
+            Some("quay.io/tembo/standard-cnpg:15-bffd097")
+        );
+        // and the previous version refuses writes
+        let read_only = |cluster: &Cluster| {
+            cluster
+                .spec
+                .postgresql
+                .as_ref()
+                .and_then(|p| p.parameters.as_ref())
+                .and_then(|p| p.get(READ_ONLY_PARAMETER))
+                .cloned()
+        };
+        assert_eq!(read_only(&cluster).as_deref(), Some("on"));
+        // only accepts connections of the import role
+        let pg_hba = cluster
+            .spec
+            .postgresql
+            .as_ref()
+            .and_then(|p| p.pg_hba.clone())
+            .unwrap_or_default();
+        assert_eq!(pg_hba[..2], upgrade_hba()[..]);
+        let upgrade_role = |cluster: &Cluster| {
+            cluster
+                .spec
+                .managed
+                .as_ref()
+                .and_then(|m| m.roles.as_ref())
+                .and_then(|r| r.iter().find(|r| r.name == UPGRADE_ROLE))
+                .and_then(|r| r.ensure.clone())
+        };
+        assert!(matches!(
+            upgrade_role(&cluster),
+            Some(ClusterManagedRolesEnsure::Present)
+        ));
+
+        // The recreated cluster imports from the staging cluster
+        let cdb = test_cdb(Some(PgUpgradePhase::Restoring));
+        let mut cluster = cnpg_cluster_from_cdb(&cdb, None, BTreeMap::new());
+        apply_upgrade_to_cluster(&cdb, &mut cluster);
+        assert_eq!(
+            cluster.spec.image_name.as_deref(),
+            Some("quay.io/tembo/standard-cnpg:16-bffd097")
+        );
+        assert_eq!(read_only(&cluster), None);
+        assert!(cluster
+            .spec
+            .postgresql
+            .as_ref()
+            .and_then(|p| p.pg_hba.as_ref())
+            .is_none_or(|rules| !rules.contains(&"host all all all reject".to_string())));
+        assert!(matches!(
+            upgrade_role(&cluster),
+            Some(ClusterManagedRolesEnsure::Absent)
+        ));
+        let external = &cluster.spec.external_clusters.unwrap()[0];
+        assert_eq!(
+            external
+                .connection_parameters
+                .as_ref()
+                .and_then(|p| p.get("host"))
+                .map(String::as_str),
+            Some("test-upgrade-rw")
+        );
+
+        // Without an upgrade the cluster is left untouched
+        let cdb = test_cdb(None);
+        let mut cluster = cnpg_cluster_from_cdb(&cdb, None, BTreeMap::new());
+        apply_upgrade_to_cluster(&cdb, &mut cluster);
+        assert_eq!(
+            cluster.spec.image_name.as_deref(),
+            Some("quay.io/tembo/standard-cnpg:16-bffd097")
+        );
+    }
+
+    #[test]
+    fn test_restore_failed_message() {
+        let mut upgrade = upgrade_status(PgUpgradePhase::RestoreFailed);
+        upgrade.final_backup = Some("test-pg15-final".to_string());
+        upgrade.restore_server_name = Some("test-v1".to_string());
+        let message = restore_failed_message("test", &upgrade, 60);
+        assert!(message.contains("staging cluster test-upgrade"));
+        assert!(message.contains("final backup test-pg15-final"));
+        assert!(message.contains("spec.image quay.io/tembo/standard-cnpg:15-bffd097"));
+        assert!(message.contains("spec.restore.serverName test-v1"));
+    }
+
+    #[test]
+    fn test_final_backup() {
+        let backup = final_backup("test", "default", 15);
+        assert_eq!(backup.metadata.name.as_deref(), Some("test-pg15-final"));
+        assert_eq!(backup.spec.cluster.name, "test");
+        // Backup retention skips it, and it is not deleted along with the cluster
+        let labels = backup.metadata.labels.unwrap();
+        assert_eq!(labels[FINAL_BACKUP_LABEL], "test");
+        assert!(backup.metadata.owner_references.is_none());
+    }
+
+    #[test]
+    fn test_is_cluster_ready() {
+        let mut cluster = Cluster::new("test", Default::default());
+        assert!(!is_cluster_ready(&cluster));
+
+        cluster.status = Some(ClusterStatus {
+            conditions: Some(vec![ClusterStatusConditions {
+                r#type: "Ready".to_string(),
+                status: ClusterStatusConditionsStatus::True,
+                last_transition_time: Utc::now().to_rfc3339(),
+                message: "Cluster is Ready".to_string(),
+                observed_generation: None,
+                reason: "ClusterIsReady".to_string(),
+            }]),
+            ..ClusterStatus::default()
+        });
+        assert!(is_cluster_ready(&cluster));
+    }
+}
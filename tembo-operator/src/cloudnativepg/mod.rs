@@ -1,3 +1,4 @@
+pub(crate) mod backup_verification;
 pub mod backups;
 pub mod clusters;
 pub(crate) mod cnpg;
@@ -5,6 +6,7 @@ pub(crate) mod cnpg;
 pub(crate) mod archive;
 pub mod cnpg_utils;
 pub mod hibernate;
+pub(crate) mod major_upgrade;
 pub(crate) mod placement;
 pub mod poolers;
 pub mod retention;
@@ -0,0 +1,279 @@
+use crate::{
+    apis::coredb_types::{BackupRetention, BackupRetentionStatus, CoreDB},
+    cloudnativepg::{
+        backups::{Backup, BackupMethod},
+        cnpg_utils::backup_completed_at,
+        major_upgrade::FINAL_BACKUP_LABEL,
+        retention::snapshots::delete_backup_and_snapshot,
+    },
+    patch_cdb_status_merge,
+    snapshots::volumesnapshots_crd::VolumeSnapshot,
+    Context,
+};
+use chrono::{DateTime, Utc};
+use kube::{
+    api::{Api, DeleteParams, ListParams},
+    runtime::controller::Action,
+    ResourceExt,
+};
+use serde_json::json;
+use std::{collections::BTreeSet, sync::Arc};
+use tracing::{error, info, instrument, warn};
+
+// The period a backup belongs to, for each granularity of the retention
+type Bucket = fn(&DateTime<Utc>) -> String;
+
+/// Returns the names of the backups retained by the grandfather-father-son policy.
+///
+/// For each granularity, the newest backup of each of the N most recent periods
+/// having a backup is retained. The newest backup is always retained.
+pub(crate) fn gfs_retained(
+    backups: &[(String, DateTime<Utc>)],
+    retention: &BackupRetention,
+) -> BTreeSet<String> {
+    if retention.is_empty() {
+        return backups.iter().map(|(name, _)| name.clone()).collect();
+    }
+
+    let mut sorted: Vec<&(String, DateTime<Utc>)> = backups.iter().collect();
+    sorted.sort_by_key(|(_, completed_at)| std::cmp::Reverse(*completed_at));
+
+    let mut retained = BTreeSet::new();
+    if let Some((name, _)) = sorted.first() {
+        retained.insert(name.clone());
+    }
+
+    let buckets: [(u32, Bucket); 4] = [
+        (retention.hourly, |t| t.format("%Y-%m-%d %H").to_string()),
+        (retention.daily, |t| t.format("%Y-%m-%d").to_string()),
+        (retention.weekly, |t| t.format("%G-W%V").to_string()),
+        (retention.monthly, |t| t.format("%Y-%m").to_string()),
+    ];
+    for (count, bucket) in buckets {
+        let mut periods = BTreeSet::new();
+        for (name, completed_at) in &sorted {
+            if periods.len() >= count as usize {
+                break;
+            }
+            if periods.insert(bucket(completed_at)) {
+                retained.insert(name.clone());
+            }
+        }
+    }
+    retained
+}
+
+async fn delete_backup(
+    backups_api: &Api<Backup>,
+    snapshots_api: &Api<VolumeSnapshot>,
+    backup: &Backup,
+    namespace: &str,
+) -> Result<(), Action> {
+    if matches!(backup.spec.method, Some(BackupMethod::VolumeSnapshot)) {
+        return delete_backup_and_snapshot(backups_api, snapshots_api, backup, namespace).await;
+    }
+    // Deleting a Backup does not delete it from the object store, where the
+    // retention policy of the Cluster applies
+    match backups_api
+        .delete(&backup.name_any(), &DeleteParams::default())
+        .await
+    {
+        Ok(_) => {
+            info!(
+                "Deleted Backup '{}' in namespace '{}'",
+                backup.name_any(),
+                namespace
+            );
+            Ok(())
+        }
+        Err(e) => {
+            warn!(
+                "Failed to delete Backup '{}' in namespace '{}': {}",
+                backup.name_any(),
+                namespace,
+                e
+            );
+            Err(Action::requeue(tokio::time::Duration::from_secs(300)))
+        }
+    }
+}
+
+/// Applies `spec.backup.retention` to the completed `Backup` objects of the instance
+/// and their volume snapshots, and records the retained set in `status.backup_retention`.
+///
+/// In dry-run mode nothing is deleted, and the backups which would be deleted are
+/// recorded instead.
+#[instrument(skip(cdb, ctx), fields(instance = %cdb.name_any()))]
+pub async fn reconcile_backup_retention(cdb: &CoreDB, ctx: Arc<Context>) -> Result<(), Action> {
+    let Some(retention) = cdb.spec.backup.retention.as_ref() else {
+        return Ok(());
+    };
+    let name = cdb.name_any();
+    let namespace = cdb.metadata.namespace.as_ref().ok_or_else(|| {
+        error!("Namespace is empty for instance: {}.", name);
+        Action::requeue(tokio::time::Duration::from_secs(300))
+    })?;
+    let backups_api: Api<Backup> = Api::namespaced(ctx.client.clone(), namespace);
+    let snapshots_api: Api<VolumeSnapshot> = Api::namespaced(ctx.client.clone(), namespace);
+
+    let lp = ListParams::default().labels(&format!("cnpg.io/cluster={}", name));
+    let mut backups: Vec<(Backup, DateTime<Utc>)> = backups_api
+        .list(&lp)
+        .await
+        .map_err(|e| {
+            error!("Failed to list backups of {}: {}", name, e);
+            Action::requeue(tokio::time::Duration::from_secs(300))
+        })?
+        .items
+        .into_iter()
+        .filter(|backup| {
+            backup
+                .status
+                .as_ref()
+                .is_some_and(|s| s.phase.as_deref() == Some("completed"))
+        })
+        // The final backup of a major version upgrade is kept to roll back the upgrade
+        .filter(|backup| !backup.labels().contains_key(FINAL_BACKUP_LABEL))
+        .filter_map(|backup| backup_completed_at(&backup).map(|at| (backup, at)))
+        .collect();
+    backups.sort_by_key(|(_, completed_at)| std::cmp::Reverse(*completed_at));
+
+    let completed: Vec<(String, DateTime<Utc>)> = backups
+        .iter()
+        .map(|(backup, at)| (backup.name_any(), *at))
+        .collect();
+    let retained = gfs_retained(&completed, retention);
+    let (kept, expired): (Vec<&Backup>, Vec<&Backup>) = backups
+        .iter()
+        .map(|(backup, _)| backup)
+        .partition(|backup| retained.contains(&backup.name_any()));
+
+    let previous = cdb
+        .status
+        .as_ref()
+        .and_then(|s| s.backup_retention.clone())
+        .unwrap_or_default();
+    let mut status = BackupRetentionStatus {
+        dry_run: retention.dry_run,
+        retained: kept.iter().map(|backup| backup.name_any()).collect(),
+        would_delete: Vec::new(),
+        deleted: previous.deleted.clone(),
+        applied_at: previous.applied_at,
+    };
+    let expired_names: Vec<String> = expired.iter().map(|backup| backup.name_any()).collect();
+    if retention.dry_run {
+        status.would_delete = expired_names;
+    } else if !expired.is_empty() {
+        for backup in expired {
+            delete_backup(&backups_api, &snapshots_api, backup, namespace).await?;
+        }
+        status.deleted = expired_names;
+    }
+
+    if status != previous {
+        status.applied_at = Some(Utc::now());
+        let coredbs: Api<CoreDB> = Api::namespaced(ctx.client.clone(), namespace);
+        let patch_status = json!({
+            "apiVersion": "coredb.io/v1alpha1",
+            "kind": "CoreDB",
+            "status": {
+                "backup_retention": status
+            }
+        });
+        patch_cdb_status_merge(&coredbs, &name, patch_status).await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(rfc3339: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(rfc3339).unwrap().into()
+    }
+
+    #[test]
+    fn test_gfs_retained() {
+        let backups: Vec<(String, DateTime<Utc>)> = [
+            ("b1", "2024-09-15T00:00:00Z"),
+            ("b2", "2024-10-20T00:00:00Z"),
+            ("b3", "2024-11-04T00:00:00Z"),
+            ("b4", "2024-11-10T00:00:00Z"),
+            ("b5", "2024-11-11T00:00:00Z"),
+            ("b6", "2024-11-12T00:00:00Z"),
+            ("b7", "2024-11-12T10:00:00Z"),
+            ("b8", "2024-11-12T11:00:00Z"),
+        ]
+        .into_iter()
+        .map(|(name, at)| (name.to_string(), time(at)))
+        .collect();
+        let names = |retained: BTreeSet<String>| retained.into_iter().collect::<Vec<String>>();
+
+        // Nothing configured: every backup is kept
+        let retention = BackupRetention::default();
+        assert_eq!(names(gfs_retained(&backups, &retention)).len(), 8);
+
+        // Only the newest backup of the current hour
+        let retention = BackupRetention {
+            hourly: 1,
+            ..BackupRetention::default()
+        };
+        assert_eq!(names(gfs_retained(&backups, &retention)), vec!["b8"]);
+
+        // b8 and b7 for the hours, b8 (Nov 12) and b5 (Nov 11) for the days
+        let retention = BackupRetention {
+            hourly: 2,
+            daily: 2,
+            ..BackupRetention::default()
+        };
+        assert_eq!(
+            names(gfs_retained(&backups, &retention)),
+            vec!["b5", "b7", "b8"]
+        );
+
+        // Weeks start on Monday: b8 (week 46), b4 (week 45), b2 (week 42)
+        let retention = BackupRetention {
+            weekly: 3,
+            ..BackupRetention::default()
+        };
+        assert_eq!(
+            names(gfs_retained(&backups, &retention)),
+            vec!["b2", "b4", "b8"]
+        );
+
+        // More months than backups: the newest backup of every month
+        let retention = BackupRetention {
+            monthly: 12,
+            ..BackupRetention::default()
+        };
+        assert_eq!(
+            names(gfs_retained(&backups, &retention)),
+            vec!["b1", "b2", "b8"]
+        );
+
+        assert!(gfs_retained(&[], &retention).is_empty());
+    }
+
+    #[test]
+    fn test_window_days() {
+        let retention = BackupRetention {
+            hourly: 48,
+            daily: 7,
+            ..BackupRetention::default()
+        };
+        assert_eq!(retention.window_days(), 7);
+        let retention = BackupRetention {
+            hourly: 49,
+            ..BackupRetention::default()
+        };
+        assert_eq!(retention.window_days(), 3);
+        let retention = BackupRetention {
+            weekly: 4,
+            monthly: 2,
+            ..BackupRetention::default()
+        };
+        assert_eq!(retention.window_days(), 62);
+        assert_eq!(BackupRetention::default().window_days(), 1);
+    }
+}
@@ -1 +1,2 @@
+pub mod gfs;
 pub mod snapshots;
@@ -127,7 +127,7 @@ fn should_delete_backup(backup: &Backup, cutoff_time: DateTime<Utc>) -> bool {
     backup_name = %backup.name_any(),
     namespace = %namespace
 ))]
-async fn delete_backup_and_snapshot(
+pub(crate) async fn delete_backup_and_snapshot(
     backups_api: &Api<Backup>,
     snapshots_api: &Api<VolumeSnapshot>,
     backup: &Backup,
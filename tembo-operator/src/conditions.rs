@@ -0,0 +1,242 @@
+use chrono::Utc;
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::{Condition, Time};
+
+// Condition types reported in `status.conditions` of a CoreDB. Each step of the
+// reconcile loop owns one condition, and `Ready` summarizes all of them so that
+// `kubectl wait --for=condition=Ready coredb/<name>` works.
+pub const CONDITION_READY: &str = "Ready";
+pub const CONDITION_NETWORK_POLICIES: &str = "NetworkPoliciesReady";
+pub const CONDITION_TRUNK_CONFIGMAP: &str = "TrunkConfigMapReady";
+pub const CONDITION_CERTIFICATES: &str = "CertificatesReady";
+pub const CONDITION_INGRESS: &str = "IngressReady";
+pub const CONDITION_SECRETS: &str = "SecretsReady";
+pub const CONDITION_APP_SERVICES: &str = "AppServicesReady";
+pub const CONDITION_CLUSTER: &str = "ClusterReady";
+pub const CONDITION_POOLER: &str = "PoolerReady";
+pub const CONDITION_DATABASES: &str = "DatabasesReady";
+pub const CONDITION_EXTENSIONS: &str = "ExtensionsReady";
+pub const CONDITION_LOGICAL_REPLICATION: &str = "LogicalReplicationReady";
+pub const CONDITION_HEARTBEAT: &str = "HeartbeatReady";
+pub const CONDITION_JOBS: &str = "JobsReady";
+
+// Reasons used for the conditions above
+pub const REASON_RECONCILED: &str = "Reconciled";
+pub const REASON_RECONCILING: &str = "Reconciling";
+pub const REASON_STOPPED: &str = "Stopped";
+pub const REASON_DRIFTED: &str = "Drifted";
+pub const REASON_UNSUPPORTED: &str = "Unsupported";
+pub const REASON_INVALID: &str = "Invalid";
+
+// pending_message describes, for the user, what a reconcile step that has not
+// completed yet is waiting for. The step is retried on the next reconcile.
+pub fn pending_message(type_: &str) -> String {
+    let step = match type_ {
+        CONDITION_NETWORK_POLICIES => "the network policies to be applied",
+        CONDITION_TRUNK_CONFIGMAP => "the Trunk metadata ConfigMap to be created",
+        CONDITION_CERTIFICATES => "the TLS certificates to be issued",
+        CONDITION_INGRESS => "the ingress routes to be applied",
+        CONDITION_SECRETS => "the connection Secrets to be created",
+        CONDITION_APP_SERVICES => "the app services to be applied",
+        CONDITION_CLUSTER => "the Postgres cluster to be ready",
+        CONDITION_POOLER => "the connection pooler to be ready",
+        CONDITION_DATABASES => "the databases and roles to be reconciled",
+        CONDITION_EXTENSIONS => "the extensions to be installed and enabled",
+        CONDITION_LOGICAL_REPLICATION => "the publications and subscriptions to be reconciled",
+        CONDITION_HEARTBEAT => "the heartbeat to be recorded",
+        CONDITION_JOBS => "the jobs to be applied",
+        _ => "this step to complete",
+    };
+    format!("Waiting for {step}, retrying")
+}
+
+const STATUS_TRUE: &str = "True";
+const STATUS_FALSE: &str = "False";
+
+// set_condition inserts or replaces the condition of the given type. The
+// lastTransitionTime is only moved forward when the status actually changes.
+pub fn set_condition(
+    conditions: &mut Vec<Condition>,
+    type_: &str,
+    status: bool,
+    reason: &str,
+    message: String,
+    observed_generation: Option<i64>,
+) {
+    let status = if status { STATUS_TRUE } else { STATUS_FALSE };
+    let last_transition_time = conditions
+        .iter()
+        .find(|c| c.type_ == type_ && c.status == status)
+        .map(|c| c.last_transition_time.clone())
+        .unwrap_or_else(|| Time(Utc::now()));
+
+    let condition = Condition {
+        type_: type_.to_string(),
+        status: status.to_string(),
+        reason: reason.to_string(),
+        message,
+        observed_generation,
+        last_transition_time,
+    };
+
+    match conditions.iter_mut().find(|c| c.type_ == type_) {
+        Some(existing) => *existing = condition,
+        None => conditions.push(condition),
+    }
+}
+
+// set_ready_condition derives the Ready condition from the step conditions. Ready is
+// False with the reason of the first step condition which is False, e.g. a database
+// which drifted or a job which is invalid, and True when every step is.
+pub fn set_ready_condition(conditions: &mut Vec<Condition>, observed_generation: Option<i64>) {
+    let not_ready = conditions
+        .iter()
+        .find(|c| c.type_ != CONDITION_READY && c.status == STATUS_FALSE)
+        .map(|c| (c.reason.clone(), format!("{}: {}", c.type_, c.message)));
+    match not_ready {
+        Some((reason, message)) => set_condition(
+            conditions,
+            CONDITION_READY,
+            false,
+            &reason,
+            message,
+            observed_generation,
+        ),
+        None => set_condition(
+            conditions,
+            CONDITION_READY,
+            true,
+            REASON_RECONCILED,
+            "Instance is fully reconciled".to_string(),
+            observed_generation,
+        ),
+    }
+}
+
+// is_condition_true returns true if the condition of the given type exists and has status "True"
+pub fn is_condition_true(conditions: &[Condition], type_: &str) -> bool {
+    conditions
+        .iter()
+        .any(|c| c.type_ == type_ && c.status == STATUS_TRUE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, Utc};
+
+    #[test]
+    fn test_set_condition_adds_new_condition() {
+        let mut conditions = vec![];
+        set_condition(
+            &mut conditions,
+            CONDITION_CLUSTER,
+            true,
+            REASON_RECONCILED,
+            "".to_string(),
+            Some(3),
+        );
+
+        assert_eq!(conditions.len(), 1);
+        assert_eq!(conditions[0].type_, CONDITION_CLUSTER);
+        assert_eq!(conditions[0].status, "True");
+        assert_eq!(conditions[0].reason, REASON_RECONCILED);
+        assert_eq!(conditions[0].observed_generation, Some(3));
+        assert!(is_condition_true(&conditions, CONDITION_CLUSTER));
+        assert!(!is_condition_true(&conditions, CONDITION_READY));
+    }
+
+    #[test]
+    fn test_set_condition_keeps_transition_time_when_status_unchanged() {
+        let then = Time(Utc::now() - Duration::hours(1));
+        let mut conditions = vec![Condition {
+            type_: CONDITION_POOLER.to_string(),
+            status: "True".to_string(),
+            reason: REASON_RECONCILED.to_string(),
+            message: "".to_string(),
+            observed_generation: Some(1),
+            last_transition_time: then.clone(),
+        }];
+
+        set_condition(
+            &mut conditions,
+            CONDITION_POOLER,
+            true,
+            REASON_RECONCILED,
+            "".to_string(),
+            Some(2),
+        );
+        assert_eq!(conditions.len(), 1);
+        assert_eq!(conditions[0].last_transition_time, then);
+        assert_eq!(conditions[0].observed_generation, Some(2));
+
+        set_condition(
+            &mut conditions,
+            CONDITION_POOLER,
+            false,
+            REASON_RECONCILING,
+            "Pooler has not been reconciled yet".to_string(),
+            Some(2),
+        );
+        assert_eq!(conditions.len(), 1);
+        assert_eq!(conditions[0].status, "False");
+        assert!(conditions[0].last_transition_time.0 > then.0);
+    }
+
+    #[test]
+    fn test_set_ready_condition() {
+        let mut conditions = vec![];
+        set_condition(
+            &mut conditions,
+            CONDITION_CLUSTER,
+            true,
+            REASON_RECONCILED,
+            "".to_string(),
+            Some(1),
+        );
+        set_ready_condition(&mut conditions, Some(1));
+        assert!(is_condition_true(&conditions, CONDITION_READY));
+
+        set_condition(
+            &mut conditions,
+            CONDITION_DATABASES,
+            false,
+            REASON_DRIFTED,
+            "Not in sync with the spec: app".to_string(),
+            Some(1),
+        );
+        set_ready_condition(&mut conditions, Some(1));
+        let ready = conditions
+            .iter()
+            .find(|c| c.type_ == CONDITION_READY)
+            .unwrap();
+        assert_eq!(ready.status, "False");
+        assert_eq!(ready.reason, REASON_DRIFTED);
+        assert_eq!(
+            ready.message,
+            "DatabasesReady: Not in sync with the spec: app"
+        );
+
+        set_condition(
+            &mut conditions,
+            CONDITION_DATABASES,
+            true,
+            REASON_RECONCILED,
+            "".to_string(),
+            Some(2),
+        );
+        set_ready_condition(&mut conditions, Some(2));
+        assert!(is_condition_true(&conditions, CONDITION_READY));
+    }
+
+    #[test]
+    fn test_pending_message() {
+        assert_eq!(
+            pending_message(CONDITION_CLUSTER),
+            "Waiting for the Postgres cluster to be ready, retrying"
+        );
+        assert_eq!(
+            pending_message("Unknown"),
+            "Waiting for this step to complete, retrying"
+        );
+    }
+}
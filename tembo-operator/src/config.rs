@@ -6,6 +6,32 @@ pub struct Config {
     pub enable_volume_snapshot: bool,
     pub volume_snapshot_retention_period_days: u64,
     pub reconcile_ttl: u64,
+    pub ingress_provider: IngressProvider,
+    pub gateway_name: String,
+    pub gateway_namespace: String,
+    pub gateway_traefik_middlewares: bool,
+}
+
+/// The implementation used to expose Postgres and app services outside the cluster
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum IngressProvider {
+    /// Traefik IngressRoute, IngressRouteTCP and Middleware objects
+    #[default]
+    Traefik,
+    /// Gateway API TLSRoute and HTTPRoute objects attached to a shared Gateway
+    GatewayApi,
+}
+
+impl std::str::FromStr for IngressProvider {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "traefik" => Ok(IngressProvider::Traefik),
+            "gateway-api" | "gatewayapi" => Ok(IngressProvider::GatewayApi),
+            other => Err(format!("unknown ingress provider: {other}")),
+        }
+    }
 }
 
 impl Default for Config {
@@ -23,6 +49,18 @@ impl Default for Config {
             .unwrap(),
             // The time to live for reconciling the entire instance
             reconcile_ttl: from_env_default("RECONCILE_TTL", "90").parse().unwrap(),
+            // Which ingress implementation to generate routes for: traefik or gateway-api
+            ingress_provider: from_env_default("INGRESS_PROVIDER", "traefik")
+                .parse()
+                .unwrap(),
+            // The Gateway that Gateway API routes attach to
+            gateway_name: from_env_default("GATEWAY_NAME", "tembo-gateway"),
+            gateway_namespace: from_env_default("GATEWAY_NAMESPACE", "tembo-system"),
+            // Whether the Gateway is implemented by Traefik, which applies Traefik
+            // Middlewares referenced by the ExtensionRef filters of HTTPRoutes
+            gateway_traefik_middlewares: from_env_default("GATEWAY_TRAEFIK_MIDDLEWARES", "false")
+                .parse()
+                .unwrap(),
         }
     }
 }
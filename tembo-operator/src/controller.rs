@@ -3,30 +3,46 @@ use futures::stream::StreamExt;
 
 use crate::{
     apis::coredb_types::{CoreDB, CoreDBStatus, VolumeSnapshot},
-    app_service::manager::reconcile_app_services,
+    app_service::{jobs::reconcile_app_jobs, manager::reconcile_app_services},
     cloudnativepg::{
-        archive::wal::reconcile_last_archive_status,
+        archive::{restore_points::reconcile_restore_points, wal::reconcile_last_archive_status},
+        backup_verification::reconcile_backup_verification,
         backups::Backup,
         cnpg::{
             cnpg_cluster_from_cdb, reconcile_cnpg, reconcile_cnpg_scheduled_backup,
             reconcile_pooler,
         },
         placement::cnpg_placement::PlacementConfig,
-        retention::snapshots::cleanup_old_volume_snapshots,
+        retention::{gfs::reconcile_backup_retention, snapshots::cleanup_old_volume_snapshots},
         VOLUME_SNAPSHOT_CLASS_NAME,
     },
-    config::Config,
+    conditions::{
+        pending_message, set_condition, set_ready_condition, CONDITION_APP_SERVICES,
+        CONDITION_CERTIFICATES, CONDITION_CLUSTER, CONDITION_DATABASES, CONDITION_EXTENSIONS,
+        CONDITION_HEARTBEAT, CONDITION_INGRESS, CONDITION_JOBS, CONDITION_LOGICAL_REPLICATION,
+        CONDITION_NETWORK_POLICIES, CONDITION_POOLER, CONDITION_READY, CONDITION_SECRETS,
+        CONDITION_TRUNK_CONFIGMAP, REASON_DRIFTED, REASON_INVALID, REASON_RECONCILED,
+        REASON_RECONCILING, REASON_STOPPED, REASON_UNSUPPORTED,
+    },
+    config::{Config, IngressProvider},
+    databases::reconcile_databases_and_roles,
     dedicated_networking::reconcile_dedicated_networking,
     exec::{ExecCommand, ExecOutput},
     extensions::database_queries::is_not_restarting,
+    gateway::reconcile_postgres_tls_routes,
     heartbeat::reconcile_heartbeat,
-    ingress::reconcile_postgres_ing_route_tcp,
-    postgres_certificates::reconcile_certificates,
+    ingress::{delete_postgres_traefik_objects, reconcile_postgres_ing_route_tcp},
+    logical_replication::reconcile_logical_replication,
+    postgres_certificates::{reconcile_certificates, reconcile_tls_status},
     psql::{PsqlCommand, PsqlOutput},
     secret::{reconcile_postgres_role_secret, reconcile_secret},
+    storage_autoscaling::reconcile_storage_autoscaling,
     telemetry, Error, Metrics, Result,
 };
-use k8s_openapi::{api::core::v1::Pod, apimachinery::pkg::util::intstr::IntOrString};
+use k8s_openapi::{
+    api::core::v1::Pod,
+    apimachinery::pkg::{apis::meta::v1::Condition as StatusCondition, util::intstr::IntOrString},
+};
 use kube::{
     api::{Api, ListParams, Patch, PatchParams, ResourceExt},
     client::Client,
@@ -40,13 +56,15 @@ use kube::{
     Resource,
 };
 
-use crate::cloudnativepg::hibernate::reconcile_cluster_hibernation;
+use crate::cloudnativepg::hibernate::{
+    reconcile_cluster_hibernation, reconcile_hibernation_policy,
+};
 use crate::{
     apis::postgres_parameters::PgConfig,
     configmap::reconcile_generic_metrics_configmap,
     extensions::{database_queries::list_config_params, reconcile_extensions},
     ingress::{reconcile_extra_postgres_ing_route_tcp, reconcile_ip_allowlist_middleware},
-    network_policies::reconcile_network_policies,
+    network_policies::{reconcile_egress_network_policies, reconcile_network_policies},
     postgres_exporter::reconcile_metrics_configmap,
     trunk::{extensions_that_require_load, reconcile_trunk_configmap},
 };
@@ -184,18 +202,318 @@ impl CoreDB {
         let name = self.name_any();
         let coredbs: Api<CoreDB> = Api::namespaced(client.clone(), &ns);
 
+        let mut conditions = self
+            .status
+            .as_ref()
+            .and_then(|s| s.conditions.clone())
+            .unwrap_or_default();
+
+        // Stop or start the instance according to spec.hibernation
+        reconcile_hibernation_policy(self, &ctx).await?;
+
         // If the cluster is stopped, apply hibernation and exit
-        reconcile_cluster_hibernation(self, &ctx).await?;
+        if let Err(action) = reconcile_cluster_hibernation(self, &ctx).await {
+            if self.spec.stop {
+                set_condition(
+                    &mut conditions,
+                    CONDITION_READY,
+                    false,
+                    REASON_STOPPED,
+                    "Instance is stopped".to_string(),
+                    self.metadata.generation,
+                );
+                patch_cdb_conditions(&coredbs, &name, &conditions).await?;
+            }
+            return Err(action);
+        }
 
         // Setup Node/Pod Placement Configuration for the Pooler and App Service deployments
         let placement_config = PlacementConfig::new(self);
 
-        reconcile_network_policies(ctx.client.clone(), &ns).await?;
+        let result = match reconcile_network_policies(self, ctx.client.clone(), &ns).await {
+            Ok(()) => reconcile_egress_network_policies(self, ctx.client.clone()).await,
+            Err(action) => Err(action),
+        };
+        self.track_condition(
+            &coredbs,
+            &mut conditions,
+            CONDITION_NETWORK_POLICIES,
+            result,
+        )
+        .await?;
 
         // Fetch any metadata we need from Trunk
-        reconcile_trunk_configmap(ctx.client.clone(), &ns).await?;
+        let result = reconcile_trunk_configmap(ctx.client.clone(), &ns).await;
+        self.track_condition(&coredbs, &mut conditions, CONDITION_TRUNK_CONFIGMAP, result)
+            .await?;
+
+        let result = reconcile_certificates(ctx.client.clone(), self, &ns).await;
+        self.track_condition(&coredbs, &mut conditions, CONDITION_CERTIFICATES, result)
+            .await?;
+
+        let result = self.reconcile_ingress(ctx.clone()).await;
+        let refused = self
+            .track_condition(&coredbs, &mut conditions, CONDITION_INGRESS, result)
+            .await?;
+        if let Some(message) = refused {
+            set_condition(
+                &mut conditions,
+                CONDITION_INGRESS,
+                false,
+                REASON_UNSUPPORTED,
+                message,
+                self.metadata.generation,
+            );
+        }
+
+        debug!("Reconciling secret");
+        // Superuser connection info
+        let result = match reconcile_secret(self, ctx.clone()).await {
+            Ok(()) => reconcile_postgres_role_secret(
+                self,
+                ctx.clone(),
+                "readonly",
+                &format!("{}-ro", name.clone()),
+            )
+            .await
+            .map(|_| ())
+            .map_err(|e| {
+                error!("Error reconciling postgres exporter secret: {:?}", e);
+                Action::requeue(Duration::from_secs(300))
+            }),
+            Err(action) => Err(action),
+        };
+        self.track_condition(&coredbs, &mut conditions, CONDITION_SECRETS, result)
+            .await?;
 
-        reconcile_certificates(ctx.client.clone(), self, &ns).await?;
+        let result = reconcile_app_services(self, ctx.clone(), placement_config.clone()).await;
+        self.track_condition(&coredbs, &mut conditions, CONDITION_APP_SERVICES, result)
+            .await?;
+
+        if self
+            .spec
+            .metrics
+            .as_ref()
+            .and_then(|m| m.queries.as_ref())
+            .is_some()
+        {
+            debug!("Reconciling prometheus configmap");
+            reconcile_metrics_configmap(self, client.clone(), &ns)
+                .await
+                .map_err(|e| {
+                    error!("Error reconciling prometheus configmap: {:?}", e);
+                    Action::requeue(Duration::from_secs(300))
+                })?;
+        }
+
+        reconcile_generic_metrics_configmap(self, ctx.clone()).await?;
+
+        // Before we reconcile CNPG, we need to make sure that spec.backup.volumeSnapshot is
+        // enabled in the CoreDB spec if cfg.enable_volume_snapshot = true.  If it's not
+        // then we should enable it, otherwise it should be a no-op.
+        self.enable_volume_snapshot(cfg, ctx.clone()).await?;
+
+        // A full data volume stops Postgres, so the volume is grown before any step that
+        // needs Postgres to be running
+        reconcile_storage_autoscaling(self, ctx.clone()).await?;
+
+        let result = reconcile_cnpg(self, ctx.clone()).await;
+        self.track_condition(&coredbs, &mut conditions, CONDITION_CLUSTER, result)
+            .await?;
+        if cfg.enable_backup {
+            reconcile_cnpg_scheduled_backup(self, ctx.clone()).await?;
+        }
+
+        // Cleanup old Postgres Exporter Deployments, Service, ServiceAccount, Role and RoleBinding
+        crate::deployment_postgres_exporter::cleanup_postgres_exporter(self, ctx.clone())
+            .await
+            .map_err(|e| {
+                error!("Error reconciling prometheus exporter deployment: {:?}", e);
+                Action::requeue(Duration::from_secs(300))
+            })?;
+
+        // Reconcile Pooler resource
+        let result = reconcile_pooler(self, ctx.clone(), placement_config.clone()).await;
+        self.track_condition(&coredbs, &mut conditions, CONDITION_POOLER, result)
+            .await?;
+
+        // Check if Postgres is already running
+        let pg_postmaster_start_time = is_not_restarting(self, ctx.clone(), "postgres").await?;
+
+        let patch_status = json!({
+            "apiVersion": "coredb.io/v1alpha1",
+            "kind": "CoreDB",
+            "status": {
+                "running": true,
+                "pg_postmaster_start_time": pg_postmaster_start_time,
+            }
+        });
+        patch_cdb_status_merge(&coredbs, &name, patch_status).await?;
+
+        // Databases and roles are reconciled before extensions, which can target them
+        let result = reconcile_databases_and_roles(self, ctx.clone()).await;
+        let (databases, roles) = self
+            .track_condition(&coredbs, &mut conditions, CONDITION_DATABASES, result)
+            .await?;
+        let unsynced: Vec<&str> = databases
+            .iter()
+            .chain(roles.iter())
+            .filter(|status| !status.synced)
+            .map(|status| status.name.as_str())
+            .collect();
+        if !unsynced.is_empty() {
+            set_condition(
+                &mut conditions,
+                CONDITION_DATABASES,
+                false,
+                REASON_DRIFTED,
+                format!("Not in sync with the spec: {}", unsynced.join(", ")),
+                self.metadata.generation,
+            );
+        }
+
+        let result = reconcile_extensions(self, ctx.clone(), &coredbs, &name).await;
+        let (trunk_installs, extensions) = self
+            .track_condition(&coredbs, &mut conditions, CONDITION_EXTENSIONS, result)
+            .await?;
+
+        // Subscriptions need the tables created by extensions to exist
+        let result = reconcile_logical_replication(self, ctx.clone()).await;
+        let logical_replication = self
+            .track_condition(
+                &coredbs,
+                &mut conditions,
+                CONDITION_LOGICAL_REPLICATION,
+                result,
+            )
+            .await?;
+        let unsynced: Vec<&str> = logical_replication
+            .iter()
+            .flat_map(|lr| lr.publications.iter().chain(lr.subscriptions.iter()))
+            .filter(|status| !status.synced)
+            .map(|status| status.name.as_str())
+            .collect();
+        if !unsynced.is_empty() {
+            set_condition(
+                &mut conditions,
+                CONDITION_LOGICAL_REPLICATION,
+                false,
+                REASON_DRIFTED,
+                format!("Not in sync with the spec: {}", unsynced.join(", ")),
+                self.metadata.generation,
+            );
+        }
+
+        // Jobs run after the AppServices, once the extensions and tables they set up exist
+        let result = reconcile_app_jobs(self, ctx.clone(), placement_config.clone()).await;
+        let invalid = self
+            .track_condition(&coredbs, &mut conditions, CONDITION_JOBS, result)
+            .await?;
+        if let Some(message) = invalid {
+            set_condition(
+                &mut conditions,
+                CONDITION_JOBS,
+                false,
+                REASON_INVALID,
+                message,
+                self.metadata.generation,
+            );
+        }
+
+        let recovery_time = self
+            .get_recovery_time(ctx.clone(), cfg.enable_volume_snapshot)
+            .await?;
+        let last_archiver_status = reconcile_last_archive_status(self, ctx.clone()).await?;
+        reconcile_restore_points(self, ctx.clone()).await?;
+        reconcile_backup_verification(self, ctx.clone()).await?;
+        reconcile_tls_status(self, ctx.clone()).await?;
+
+        let current_config_values = get_current_config_values(self, ctx.clone()).await?;
+
+        let result = reconcile_heartbeat(self, ctx.clone()).await;
+        self.track_condition(&coredbs, &mut conditions, CONDITION_HEARTBEAT, result)
+            .await?;
+
+        set_ready_condition(&mut conditions, self.metadata.generation);
+
+        #[allow(deprecated)]
+        let new_status = CoreDBStatus {
+            running: true,
+            extensionsUpdating: false,
+            storage: Some(self.spec.storage.clone()),
+            extensions: Some(extensions),
+            trunk_installs: Some(trunk_installs),
+            resources: Some(self.spec.resources.clone()),
+            runtime_config: Some(current_config_values),
+            first_recoverability_time: recovery_time,
+            last_fully_reconciled_at: None,
+            pg_postmaster_start_time,
+            last_archiver_status,
+            conditions: Some(conditions),
+            pg_upgrade: None,
+            databases: (!databases.is_empty()).then_some(databases),
+            roles: (!roles.is_empty()).then_some(roles),
+            logical_replication,
+            restore_points: None,
+            backup_verification: None,
+            backup_retention: None,
+            storage_autoscaling: None,
+            hibernation: None,
+            tls: None,
+            dedicated_networking: None,
+            jobs: None,
+            app_services: None,
+        };
+
+        debug!("Updating CoreDB status to {:?} for {name}", new_status);
+
+        let patch_status = json!({
+            "apiVersion": "coredb.io/v1alpha1",
+            "kind": "CoreDB",
+            "status": new_status
+        });
+
+        patch_cdb_status_merge(&coredbs, &name, patch_status).await?;
+
+        // Apply the GFS retention of spec.backup.retention when it is set, otherwise
+        // cleanup old volume snapshots that are older than the retention period
+        // set in cfg.volume_snapshot_retention_period
+        // if volumesnapshots is enabled
+        if self.spec.backup.retention.is_some() {
+            reconcile_backup_retention(self, ctx.clone()).await?;
+        } else if cfg.enable_volume_snapshot {
+            match cleanup_old_volume_snapshots(
+                self,
+                client,
+                cfg.volume_snapshot_retention_period_days,
+            )
+            .await
+            {
+                Ok(_) => {
+                    info!(
+                        "Successfully cleaned up old volume snapshots for instance: {}",
+                        self.name_any()
+                    );
+                }
+                Err(action) => {
+                    return Err(action);
+                }
+            }
+        }
+
+        info!("Fully reconciled {}", self.name_any());
+        Ok(requeue_normal_with_jitter())
+    }
+
+    // reconcile_ingress exposes the Postgres and pooler services through the configured
+    // ingress provider when DATA_PLANE_BASEDOMAIN is set, or removes the routes when
+    // ingress is not wanted. The routes of the other provider are removed, so that
+    // switching providers does not leave the instance exposed twice. Returns why the
+    // routes were refused, if they were.
+    #[instrument(skip(self, ctx))]
+    async fn reconcile_ingress(&self, ctx: Arc<Context>) -> Result<Option<String>, Action> {
+        let ns = self.namespace().unwrap();
+        let name = self.name_any();
 
         // Check if we need to delete the IngressRouteTCP and MiddlewareTCP resources
         let delete = self.spec.replicas < 1 || self.spec.stop || self.spec.disable_ingress;
@@ -203,6 +521,45 @@ impl CoreDB {
         // Ingress
         match std::env::var("DATA_PLANE_BASEDOMAIN") {
             Ok(basedomain) => {
+                let cfg = Config::default();
+                if cfg.ingress_provider == IngressProvider::GatewayApi {
+                    debug!(
+                        "DATA_PLANE_BASEDOMAIN is set to {}, reconciling Gateway API routes for {}",
+                        basedomain,
+                        name.clone()
+                    );
+                    let refused =
+                        reconcile_postgres_tls_routes(self, ctx.clone(), &cfg, &basedomain, delete)
+                            .await
+                            .map_err(|e| {
+                                error!("Error reconciling postgres TLSRoutes: {:?}", e);
+                                Action::requeue(Duration::from_secs(300))
+                            })?;
+                    if let Some(message) = &refused {
+                        warn!("Not exposing {}: {}", name, message);
+                    }
+                    delete_postgres_traefik_objects(self, ctx.clone())
+                        .await
+                        .map_err(|e| {
+                            error!("Error deleting postgres IngressRouteTCPs: {:?}", e);
+                            Action::requeue(Duration::from_secs(300))
+                        })?;
+                    reconcile_dedicated_networking(self, ctx.clone(), basedomain.as_str())
+                        .await
+                        .map_err(|e| {
+                            error!("Error reconciling dedicated networking: {:?}", e);
+                            Action::requeue(Duration::from_secs(300))
+                        })?;
+                    return Ok(refused);
+                }
+
+                reconcile_postgres_tls_routes(self, ctx.clone(), &cfg, &basedomain, true)
+                    .await
+                    .map_err(|e| {
+                        error!("Error deleting postgres TLSRoutes: {:?}", e);
+                        Action::requeue(Duration::from_secs(300))
+                    })?;
+
                 debug!(
                     "DATA_PLANE_BASEDOMAIN is set to {}, reconciling IngressRouteTCP and MiddlewareTCP for {}",
                     basedomain, name.clone()
@@ -317,136 +674,54 @@ impl CoreDB {
             }
         };
 
-        debug!("Reconciling secret");
-        // Superuser connection info
-        reconcile_secret(self, ctx.clone()).await?;
-        reconcile_app_services(self, ctx.clone(), placement_config.clone()).await?;
-
-        if self
-            .spec
-            .metrics
-            .as_ref()
-            .and_then(|m| m.queries.as_ref())
-            .is_some()
-        {
-            debug!("Reconciling prometheus configmap");
-            reconcile_metrics_configmap(self, client.clone(), &ns)
-                .await
-                .map_err(|e| {
-                    error!("Error reconciling prometheus configmap: {:?}", e);
-                    Action::requeue(Duration::from_secs(300))
-                })?;
-        }
-
-        let _ = reconcile_postgres_role_secret(
-            self,
-            ctx.clone(),
-            "readonly",
-            &format!("{}-ro", name.clone()),
-        )
-        .await
-        .map_err(|e| {
-            error!("Error reconciling postgres exporter secret: {:?}", e);
-            Action::requeue(Duration::from_secs(300))
-        })?;
-
-        reconcile_generic_metrics_configmap(self, ctx.clone()).await?;
-
-        // Before we reconcile CNPG, we need to make sure that spec.backup.volumeSnapshot is
-        // enabled in the CoreDB spec if cfg.enable_volume_snapshot = true.  If it's not
-        // then we should enable it, otherwise it should be a no-op.
-        self.enable_volume_snapshot(cfg, ctx.clone()).await?;
-
-        reconcile_cnpg(self, ctx.clone()).await?;
-        if cfg.enable_backup {
-            reconcile_cnpg_scheduled_backup(self, ctx.clone()).await?;
-        }
-
-        // Cleanup old Postgres Exporter Deployments, Service, ServiceAccount, Role and RoleBinding
-        crate::deployment_postgres_exporter::cleanup_postgres_exporter(self, ctx.clone())
-            .await
-            .map_err(|e| {
-                error!("Error reconciling prometheus exporter deployment: {:?}", e);
-                Action::requeue(Duration::from_secs(300))
-            })?;
-
-        // Reconcile Pooler resource
-        reconcile_pooler(self, ctx.clone(), placement_config.clone()).await?;
-
-        // Check if Postgres is already running
-        let pg_postmaster_start_time = is_not_restarting(self, ctx.clone(), "postgres").await?;
+        Ok(None)
+    }
 
-        let patch_status = json!({
-            "apiVersion": "coredb.io/v1alpha1",
-            "kind": "CoreDB",
-            "status": {
-                "running": true,
-                "pg_postmaster_start_time": pg_postmaster_start_time,
+    // track_condition records the outcome of a reconcile step in the given conditions.
+    // When the step did not complete, the step condition and the Ready condition are
+    // set to False and patched into the status before the requeue action is returned.
+    async fn track_condition<T>(
+        &self,
+        coredbs: &Api<CoreDB>,
+        conditions: &mut Vec<StatusCondition>,
+        condition_type: &str,
+        result: Result<T, Action>,
+    ) -> Result<T, Action> {
+        let generation = self.metadata.generation;
+        match result {
+            Ok(value) => {
+                set_condition(
+                    conditions,
+                    condition_type,
+                    true,
+                    REASON_RECONCILED,
+                    "".to_string(),
+                    generation,
+                );
+                Ok(value)
             }
-        });
-        patch_cdb_status_merge(&coredbs, &name, patch_status).await?;
-        let (trunk_installs, extensions) =
-            reconcile_extensions(self, ctx.clone(), &coredbs, &name).await?;
-
-        let recovery_time = self
-            .get_recovery_time(ctx.clone(), cfg.enable_volume_snapshot)
-            .await?;
-        let last_archiver_status = reconcile_last_archive_status(self, ctx.clone()).await?;
-
-        let current_config_values = get_current_config_values(self, ctx.clone()).await?;
-
-        #[allow(deprecated)]
-        let new_status = CoreDBStatus {
-            running: true,
-            extensionsUpdating: false,
-            storage: Some(self.spec.storage.clone()),
-            extensions: Some(extensions),
-            trunk_installs: Some(trunk_installs),
-            resources: Some(self.spec.resources.clone()),
-            runtime_config: Some(current_config_values),
-            first_recoverability_time: recovery_time,
-            last_fully_reconciled_at: None,
-            pg_postmaster_start_time,
-            last_archiver_status,
-        };
-
-        debug!("Updating CoreDB status to {:?} for {name}", new_status);
-
-        let patch_status = json!({
-            "apiVersion": "coredb.io/v1alpha1",
-            "kind": "CoreDB",
-            "status": new_status
-        });
-
-        patch_cdb_status_merge(&coredbs, &name, patch_status).await?;
-
-        reconcile_heartbeat(self, ctx.clone()).await?;
-
-        // Cleanup old volume snapshots that are older than the retention period
-        // set in cfg.volume_snapshot_retention_period
-        // if volumesnapshots is enabled
-        if cfg.enable_volume_snapshot {
-            match cleanup_old_volume_snapshots(
-                self,
-                client,
-                cfg.volume_snapshot_retention_period_days,
-            )
-            .await
-            {
-                Ok(_) => {
-                    info!(
-                        "Successfully cleaned up old volume snapshots for instance: {}",
-                        self.name_any()
-                    );
-                }
-                Err(action) => {
-                    return Err(action);
-                }
+            Err(action) => {
+                let message = pending_message(condition_type);
+                set_condition(
+                    conditions,
+                    condition_type,
+                    false,
+                    REASON_RECONCILING,
+                    message.clone(),
+                    generation,
+                );
+                set_condition(
+                    conditions,
+                    CONDITION_READY,
+                    false,
+                    REASON_RECONCILING,
+                    message,
+                    generation,
+                );
+                patch_cdb_conditions(coredbs, &self.name_any(), conditions).await?;
+                Err(action)
             }
         }
-
-        info!("Fully reconciled {}", self.name_any());
-        Ok(requeue_normal_with_jitter())
     }
 
     // enable_volume_snapshot makes sure that the CoreDB spec has the spec.backup.volumeSnapshot
@@ -748,7 +1023,8 @@ impl CoreDB {
         }
     }
 
-    #[instrument(skip(self, context))]
+    // The command is not recorded in the span, since it can contain passwords
+    #[instrument(skip(self, command, context))]
     pub async fn psql(
         &self,
         command: String,
@@ -899,6 +1175,22 @@ pub async fn get_current_config_values(
     Ok(cfg)
 }
 
+// patch_cdb_conditions replaces the conditions in the CoreDB status
+pub async fn patch_cdb_conditions(
+    cdb: &Api<CoreDB>,
+    name: &str,
+    conditions: &[StatusCondition],
+) -> Result<(), Action> {
+    let patch_status = json!({
+        "apiVersion": "coredb.io/v1alpha1",
+        "kind": "CoreDB",
+        "status": {
+            "conditions": conditions
+        }
+    });
+    patch_cdb_status_merge(cdb, name, patch_status).await
+}
+
 pub async fn patch_cdb_status_merge(
     cdb: &Api<CoreDB>,
     name: &str,
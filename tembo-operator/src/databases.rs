@@ -0,0 +1,872 @@
+use crate::{
+    apis::coredb_types::{
+        CoreDB, Database, ManagedObjectStatus, Role, SchemaGrant, SchemaPrivilege,
+    },
+    cloudnativepg::major_upgrade::UPGRADE_ROLE,
+    extensions::database_queries::check_input,
+    logical_replication::REPLICATION_ROLE,
+    psql::PsqlOutput,
+    secret::{
+        fetch_decoded_secret_key, generate_password, reconcile_role_connection_secret,
+        role_connection_secret_name,
+    },
+    Context,
+};
+use kube::{runtime::controller::Action, ResourceExt};
+use std::sync::Arc;
+use tracing::{debug, error, info, instrument, warn};
+
+// Roles managed by CloudNativePG or by the operator itself, `spec.roles` must not change them
+const RESERVED_ROLES: [&str; 6] = [
+    "postgres",
+    "streaming_replica",
+    "cnpg_pooler_pgbouncer",
+    "readonly",
+    REPLICATION_ROLE,
+    UPGRADE_ROLE,
+];
+
+// is_reserved_role returns true for the roles `spec.roles` must not manage, including
+// the predefined roles of Postgres
+pub(crate) fn is_reserved_role(name: &str) -> bool {
+    RESERVED_ROLES.contains(&name) || name.starts_with("pg_")
+}
+
+// The predefined roles of Postgres that only give access to statistics and settings
+const MONITORING_ROLES: [&str; 4] = [
+    "pg_monitor",
+    "pg_read_all_settings",
+    "pg_read_all_stats",
+    "pg_stat_scan_tables",
+];
+
+// is_reserved_membership returns true for the roles the roles of `spec.roles` must not be
+// granted. Memberships of the reserved roles would give superuser or server file access,
+// only the monitoring roles are allowed.
+pub(crate) fn is_reserved_membership(name: &str) -> bool {
+    is_reserved_role(name) && !MONITORING_ROLES.contains(&name)
+}
+
+// A role as it currently exists in Postgres
+#[derive(Debug, PartialEq)]
+struct ObservedRole {
+    name: String,
+    login: bool,
+    connection_limit: i32,
+    member_of: Vec<String>,
+}
+
+// A database as it currently exists in Postgres
+#[derive(Debug, PartialEq)]
+struct ObservedDatabase {
+    name: String,
+    owner: String,
+}
+
+pub(crate) fn quote_ident(ident: &str) -> String {
+    format!("\"{}\"", ident.replace('"', "\"\""))
+}
+
+pub(crate) fn quote_literal(literal: &str) -> String {
+    format!("'{}'", literal.replace('\'', "''"))
+}
+
+fn quoted_literal_list(names: &[&str]) -> String {
+    names
+        .iter()
+        .map(|name| quote_literal(name))
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+fn list_roles_query(names: &[&str]) -> String {
+    format!(
+        "SELECT r.rolname, r.rolcanlogin, r.rolconnlimit, \
+         COALESCE(string_agg(g.rolname, ',' ORDER BY g.rolname), '') AS member_of \
+         FROM pg_roles r \
+         LEFT JOIN pg_auth_members m ON m.member = r.oid \
+         LEFT JOIN pg_roles g ON g.oid = m.roleid \
+         WHERE r.rolname IN ({}) \
+         GROUP BY r.rolname, r.rolcanlogin, r.rolconnlimit;",
+        quoted_literal_list(names)
+    )
+}
+
+fn list_databases_query(names: &[&str]) -> String {
+    format!(
+        "SELECT datname, pg_get_userbyid(datdba) FROM pg_database WHERE datname IN ({});",
+        quoted_literal_list(names)
+    )
+}
+
+fn parse_roles(psql_str: &str) -> Vec<ObservedRole> {
+    let mut roles = vec![];
+    for line in psql_str.lines().skip(2) {
+        let fields: Vec<&str> = line.split('|').map(|s| s.trim()).collect();
+        if fields.len() < 4 {
+            continue;
+        }
+        roles.push(ObservedRole {
+            name: fields[0].to_owned(),
+            login: fields[1] == "t",
+            connection_limit: fields[2].parse().unwrap_or(-1),
+            member_of: fields[3]
+                .split(',')
+                .filter(|role| !role.is_empty())
+                .map(str::to_owned)
+                .collect(),
+        });
+    }
+    roles
+}
+
+fn parse_databases(psql_str: &str) -> Vec<ObservedDatabase> {
+    let mut databases = vec![];
+    for line in psql_str.lines().skip(2) {
+        let fields: Vec<&str> = line.split('|').map(|s| s.trim()).collect();
+        if fields.len() < 2 {
+            continue;
+        }
+        databases.push(ObservedDatabase {
+            name: fields[0].to_owned(),
+            owner: fields[1].to_owned(),
+        });
+    }
+    databases
+}
+
+// role_drift lists the differences between a role in the spec and the role in Postgres
+fn role_drift(role: &Role, observed: Option<&ObservedRole>) -> Vec<String> {
+    let Some(observed) = observed else {
+        return vec![format!("role {} does not exist", role.name)];
+    };
+    let mut drift = vec![];
+    if observed.login != role.login {
+        drift.push(format!(
+            "login is {} instead of {}",
+            observed.login, role.login
+        ));
+    }
+    if observed.connection_limit != role.connection_limit {
+        drift.push(format!(
+            "connection limit is {} instead of {}",
+            observed.connection_limit, role.connection_limit
+        ));
+    }
+    for member_of in &role.member_of {
+        if is_reserved_membership(member_of) {
+            drift.push(format!(
+                "membership of reserved role {} is not granted",
+                member_of
+            ));
+        } else if !observed.member_of.contains(member_of) {
+            drift.push(format!("not a member of {}", member_of));
+        }
+    }
+    drift
+}
+
+// role_statements generates the statements bringing the role in line with the spec.
+// The password is only set when it is passed in.
+fn role_statements(
+    role: &Role,
+    observed: Option<&ObservedRole>,
+    password: Option<&str>,
+) -> Vec<String> {
+    let mut options = vec![
+        if role.login { "LOGIN" } else { "NOLOGIN" }.to_string(),
+        format!("CONNECTION LIMIT {}", role.connection_limit),
+    ];
+    if let Some(password) = password {
+        options.push(format!("PASSWORD {}", quote_literal(password)));
+    }
+
+    let mut statements = vec![];
+    match observed {
+        None => statements.push(format!(
+            "CREATE ROLE {} WITH {};",
+            quote_ident(&role.name),
+            options.join(" ")
+        )),
+        Some(observed) => {
+            if observed.login != role.login
+                || observed.connection_limit != role.connection_limit
+                || password.is_some()
+            {
+                statements.push(format!(
+                    "ALTER ROLE {} WITH {};",
+                    quote_ident(&role.name),
+                    options.join(" ")
+                ));
+            }
+        }
+    }
+    for member_of in role
+        .member_of
+        .iter()
+        .filter(|member_of| !is_reserved_membership(member_of))
+    {
+        let is_member = observed
+            .map(|observed| observed.member_of.contains(member_of))
+            .unwrap_or(false);
+        if !is_member {
+            statements.push(format!(
+                "GRANT {} TO {};",
+                quote_ident(member_of),
+                quote_ident(&role.name)
+            ));
+        }
+    }
+    statements
+}
+
+// database_drift lists the differences between a database in the spec and the database in Postgres
+fn database_drift(database: &Database, observed: Option<&ObservedDatabase>) -> Vec<String> {
+    match observed {
+        None => vec![format!("database {} does not exist", database.name)],
+        Some(observed) if observed.owner != database.owner => vec![format!(
+            "owner is {} instead of {}",
+            observed.owner, database.owner
+        )],
+        Some(_) => vec![],
+    }
+}
+
+fn database_statements(database: &Database, observed: Option<&ObservedDatabase>) -> Vec<String> {
+    match observed {
+        None => vec![format!(
+            "CREATE DATABASE {} OWNER {};",
+            quote_ident(&database.name),
+            quote_ident(&database.owner)
+        )],
+        Some(observed) if observed.owner != database.owner => vec![format!(
+            "ALTER DATABASE {} OWNER TO {};",
+            quote_ident(&database.name),
+            quote_ident(&database.owner)
+        )],
+        Some(_) => vec![],
+    }
+}
+
+// grant_check_query returns a query that is true when the role already holds all privileges of the grant
+fn grant_check_query(role_name: &str, grant: &SchemaGrant) -> String {
+    let mut checks = vec![];
+    let (schema_privileges, table_privileges): (Vec<&SchemaPrivilege>, Vec<&SchemaPrivilege>) =
+        grant
+            .privileges
+            .iter()
+            .partition(|privilege| privilege.is_schema_privilege());
+    for privilege in schema_privileges {
+        checks.push(format!(
+            "has_schema_privilege({}, {}, {})",
+            quote_literal(role_name),
+            quote_literal(&grant.schema),
+            quote_literal(privilege.as_sql())
+        ));
+    }
+    for privilege in table_privileges {
+        checks.push(format!(
+            "NOT EXISTS (SELECT 1 FROM pg_tables WHERE schemaname = {} \
+             AND NOT has_table_privilege({}, format('%I.%I', schemaname, tablename), {}))",
+            quote_literal(&grant.schema),
+            quote_literal(role_name),
+            quote_literal(privilege.as_sql())
+        ));
+    }
+    if checks.is_empty() {
+        checks.push("true".to_string());
+    }
+    format!("SELECT {} AS granted;", checks.join(" AND "))
+}
+
+fn grant_statements(role_name: &str, grant: &SchemaGrant) -> Vec<String> {
+    let (schema_privileges, table_privileges): (Vec<&SchemaPrivilege>, Vec<&SchemaPrivilege>) =
+        grant
+            .privileges
+            .iter()
+            .partition(|privilege| privilege.is_schema_privilege());
+    let schema = quote_ident(&grant.schema);
+    let role = quote_ident(role_name);
+
+    let mut statements = vec![];
+    if !schema_privileges.is_empty() {
+        let privileges: Vec<&str> = schema_privileges.iter().map(|p| p.as_sql()).collect();
+        statements.push(format!(
+            "GRANT {} ON SCHEMA {} TO {};",
+            privileges.join(", "),
+            schema,
+            role
+        ));
+    }
+    if !table_privileges.is_empty() {
+        let privileges: Vec<&str> = table_privileges.iter().map(|p| p.as_sql()).collect();
+        statements.push(format!(
+            "GRANT {} ON ALL TABLES IN SCHEMA {} TO {};",
+            privileges.join(", "),
+            schema,
+            role
+        ));
+        statements.push(format!(
+            "ALTER DEFAULT PRIVILEGES IN SCHEMA {} GRANT {} ON TABLES TO {};",
+            schema,
+            privileges.join(", "),
+            role
+        ));
+    }
+    statements
+}
+
+pub(crate) fn psql_error(output: &PsqlOutput) -> String {
+    output
+        .stderr
+        .as_deref()
+        .map(str::trim)
+        .filter(|stderr| !stderr.is_empty())
+        .unwrap_or("unknown error")
+        .to_string()
+}
+
+// execute_statements runs each statement on its own, since CREATE DATABASE cannot
+// run in the implicit transaction of a multi-statement command. The errors are returned.
+pub(crate) async fn execute_statements(
+    cdb: &CoreDB,
+    ctx: Arc<Context>,
+    database: &str,
+    statements: Vec<String>,
+) -> Result<Vec<String>, Action> {
+    let mut errors = vec![];
+    for statement in statements {
+        let output = cdb
+            .psql(statement, database.to_owned(), ctx.clone())
+            .await?;
+        if !output.success {
+            errors.push(psql_error(&output));
+        }
+    }
+    Ok(errors)
+}
+
+// role_password returns the password the role should have, and whether it differs
+// from the password in the connection secret of the role
+async fn role_password(
+    cdb: &CoreDB,
+    ctx: Arc<Context>,
+    role: &Role,
+) -> Result<Result<(String, bool), String>, Action> {
+    let namespace = cdb.namespace().unwrap();
+    let current = fetch_decoded_secret_key(
+        ctx.clone(),
+        &namespace,
+        &role_connection_secret_name(cdb, &role.name),
+        "password",
+    )
+    .await?;
+
+    let desired = match &role.password_secret {
+        Some(secret) => {
+            match fetch_decoded_secret_key(ctx.clone(), &namespace, &secret.name, &secret.key)
+                .await?
+            {
+                Some(password) => password,
+                None => {
+                    return Ok(Err(format!(
+                        "password secret {} with key {} not found",
+                        secret.name, secret.key
+                    )))
+                }
+            }
+        }
+        None => current.clone().unwrap_or_else(generate_password),
+    };
+    let changed = current.as_ref() != Some(&desired);
+    Ok(Ok((desired, changed)))
+}
+
+#[instrument(skip(cdb, ctx), fields(instance_name = %cdb.name_any()))]
+async fn reconcile_roles(
+    cdb: &CoreDB,
+    ctx: Arc<Context>,
+) -> Result<Vec<ManagedObjectStatus>, Action> {
+    let roles = &cdb.spec.roles;
+    if roles.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let names: Vec<&str> = roles
+        .iter()
+        .map(|role| role.name.as_str())
+        .filter(|name| !is_reserved_role(name))
+        .collect();
+    // Nothing to observe when every role is reserved, `IN ()` is not valid SQL
+    let observed_roles = if names.is_empty() {
+        vec![]
+    } else {
+        let output = cdb
+            .psql(list_roles_query(&names), "postgres".to_owned(), ctx.clone())
+            .await?;
+        parse_roles(output.stdout.as_deref().unwrap_or_default())
+    };
+
+    let mut statuses = vec![];
+    for role in roles {
+        if is_reserved_role(&role.name) {
+            warn!("Skipping reserved role {}", role.name);
+            statuses.push(ManagedObjectStatus {
+                name: role.name.clone(),
+                database: None,
+                synced: false,
+                drift: vec![format!("role {} is reserved", role.name)],
+            });
+            continue;
+        }
+        let invalid: Vec<&String> = std::iter::once(&role.name)
+            .chain(role.member_of.iter())
+            .filter(|name| !check_input(name))
+            .collect();
+        if !invalid.is_empty() {
+            warn!(
+                "Skipping role {} with invalid names {:?}",
+                role.name, invalid
+            );
+            statuses.push(ManagedObjectStatus {
+                name: role.name.clone(),
+                database: None,
+                synced: false,
+                drift: vec![format!("invalid role names: {:?}", invalid)],
+            });
+            continue;
+        }
+
+        let observed = observed_roles.iter().find(|o| o.name == role.name);
+        let mut drift = role_drift(role, observed);
+        let reserved: Vec<&String> = role
+            .member_of
+            .iter()
+            .filter(|member_of| is_reserved_membership(member_of))
+            .collect();
+        if !reserved.is_empty() {
+            warn!(
+                "Not granting reserved roles {:?} to role {}",
+                reserved, role.name
+            );
+        }
+
+        let password = if role.login {
+            match role_password(cdb, ctx.clone(), role).await? {
+                Ok(password) => Some(password),
+                Err(message) => {
+                    warn!("Not reconciling role {}: {}", role.name, message);
+                    drift.push(message);
+                    statuses.push(ManagedObjectStatus {
+                        name: role.name.clone(),
+                        database: None,
+                        synced: false,
+                        drift,
+                    });
+                    continue;
+                }
+            }
+        } else {
+            None
+        };
+        // Only send the password when it changed, or when the role is created
+        let new_password = password
+            .as_ref()
+            .filter(|(_, changed)| *changed || observed.is_none())
+            .map(|(password, _)| password.as_str());
+
+        let statements = role_statements(role, observed, new_password);
+        if !statements.is_empty() {
+            info!(
+                "Applying {} statements to role {} of {}",
+                statements.len(),
+                role.name,
+                cdb.name_any()
+            );
+        }
+        let errors = execute_statements(cdb, ctx.clone(), "postgres", statements).await?;
+        if !errors.is_empty() {
+            error!("Error reconciling role {}: {:?}", role.name, errors);
+        } else if let Some((password, changed)) = password {
+            if changed {
+                reconcile_role_connection_secret(cdb, ctx.clone(), &role.name, password).await?;
+            }
+        }
+
+        // The drift is resolved once applied, except the refused memberships
+        if errors.is_empty() {
+            drift.retain(|message| message.starts_with("membership of reserved role"));
+        }
+        drift.extend(errors.iter().map(|e| format!("failed to apply: {}", e)));
+        statuses.push(ManagedObjectStatus {
+            name: role.name.clone(),
+            database: None,
+            synced: errors.is_empty() && reserved.is_empty(),
+            drift,
+        });
+    }
+    Ok(statuses)
+}
+
+#[instrument(skip(cdb, ctx), fields(instance_name = %cdb.name_any()))]
+async fn reconcile_databases(
+    cdb: &CoreDB,
+    ctx: Arc<Context>,
+) -> Result<Vec<ManagedObjectStatus>, Action> {
+    let databases = &cdb.spec.databases;
+    if databases.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let names: Vec<&str> = databases.iter().map(|db| db.name.as_str()).collect();
+    let output = cdb
+        .psql(
+            list_databases_query(&names),
+            "postgres".to_owned(),
+            ctx.clone(),
+        )
+        .await?;
+    let observed_databases = parse_databases(output.stdout.as_deref().unwrap_or_default());
+
+    let mut statuses = vec![];
+    for database in databases {
+        if !check_input(&database.name) || !check_input(&database.owner) {
+            warn!("Skipping database {} with invalid names", database.name);
+            statuses.push(ManagedObjectStatus {
+                name: database.name.clone(),
+                database: None,
+                synced: false,
+                drift: vec!["invalid database or owner name".to_string()],
+            });
+            continue;
+        }
+
+        let observed = observed_databases.iter().find(|o| o.name == database.name);
+        let mut drift = database_drift(database, observed);
+        let statements = database_statements(database, observed);
+        debug!(
+            "Statements for database {}: {:?}",
+            database.name, statements
+        );
+        let errors = execute_statements(cdb, ctx.clone(), "postgres", statements).await?;
+        if !errors.is_empty() {
+            error!("Error reconciling database {}: {:?}", database.name, errors);
+        }
+
+        // The drift is resolved once applied
+        if errors.is_empty() {
+            drift.clear();
+        }
+        drift.extend(errors.iter().map(|e| format!("failed to apply: {}", e)));
+        statuses.push(ManagedObjectStatus {
+            name: database.name.clone(),
+            database: None,
+            synced: errors.is_empty(),
+            drift,
+        });
+    }
+    Ok(statuses)
+}
+
+// reconcile_grants applies the schema grants of the roles, recording drift and errors
+// in the status of the role
+async fn reconcile_grants(
+    cdb: &CoreDB,
+    ctx: Arc<Context>,
+    role_statuses: &mut [ManagedObjectStatus],
+) -> Result<(), Action> {
+    for role in &cdb.spec.roles {
+        let Some(status) = role_statuses
+            .iter_mut()
+            .find(|status| status.name == role.name && status.synced)
+        else {
+            continue;
+        };
+
+        for grant in &role.grants {
+            if !check_input(&grant.database) || !check_input(&grant.schema) {
+                status.synced = false;
+                status.drift.push(format!(
+                    "invalid grant on {}.{}",
+                    grant.database, grant.schema
+                ));
+                continue;
+            }
+
+            let output = cdb
+                .psql(
+                    grant_check_query(&role.name, grant),
+                    grant.database.clone(),
+                    ctx.clone(),
+                )
+                .await?;
+            if !output.success {
+                status.synced = false;
+                status.drift.push(format!(
+                    "failed to check grants on {}.{}: {}",
+                    grant.database,
+                    grant.schema,
+                    psql_error(&output)
+                ));
+                continue;
+            }
+            if output.get_field(0).as_deref() == Some("t") {
+                continue;
+            }
+
+            let errors = execute_statements(
+                cdb,
+                ctx.clone(),
+                &grant.database,
+                grant_statements(&role.name, grant),
+            )
+            .await?;
+            if !errors.is_empty() {
+                error!("Error granting privileges to {}: {:?}", role.name, errors);
+                status.synced = false;
+                status.drift.push(format!(
+                    "missing privileges on {}.{}",
+                    grant.database, grant.schema
+                ));
+                status
+                    .drift
+                    .extend(errors.iter().map(|e| format!("failed to apply: {}", e)));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Reconciles `spec.roles` and `spec.databases`. Roles are reconciled first since
+/// they own the databases, and grants last since they need both.
+///
+/// Returns the status of the databases and the roles.
+#[instrument(skip(cdb, ctx), fields(instance_name = %cdb.name_any()))]
+pub async fn reconcile_databases_and_roles(
+    cdb: &CoreDB,
+    ctx: Arc<Context>,
+) -> Result<(Vec<ManagedObjectStatus>, Vec<ManagedObjectStatus>), Action> {
+    // Replicas are read-only, their databases and roles come from the source
+    if cdb.spec.is_replica() {
+        debug!("Skipping databases and roles of replica {}", cdb.name_any());
+        return Ok((vec![], vec![]));
+    }
+    let mut role_statuses = reconcile_roles(cdb, ctx.clone()).await?;
+    let database_statuses = reconcile_databases(cdb, ctx.clone()).await?;
+    reconcile_grants(cdb, ctx, &mut role_statuses).await?;
+    Ok((database_statuses, role_statuses))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn role(name: &str) -> Role {
+        Role {
+            name: name.to_string(),
+            login: true,
+            connection_limit: -1,
+            member_of: vec!["pg_read_all_stats".to_string()],
+            password_secret: None,
+            grants: vec![],
+        }
+    }
+
+    #[test]
+    fn test_is_reserved_role() {
+        assert!(is_reserved_role("postgres"));
+        assert!(is_reserved_role("streaming_replica"));
+        assert!(is_reserved_role("cnpg_pooler_pgbouncer"));
+        assert!(is_reserved_role("pg_read_all_data"));
+        assert!(!is_reserved_role("reporting"));
+    }
+
+    #[test]
+    fn test_parse_roles() {
+        let psql_str = " rolname | rolcanlogin | rolconnlimit |     member_of
+-----------+-------------+--------------+-------------------
+ reporting | t           |           10 | pg_read_all_stats
+ batch     | f           |           -1 |
+(2 rows)
+";
+        let roles = parse_roles(psql_str);
+        assert_eq!(
+            roles,
+            vec![
+                ObservedRole {
+                    name: "reporting".to_string(),
+                    login: true,
+                    connection_limit: 10,
+                    member_of: vec!["pg_read_all_stats".to_string()],
+                },
+                ObservedRole {
+                    name: "batch".to_string(),
+                    login: false,
+                    connection_limit: -1,
+                    member_of: vec![],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_role_statements_create() {
+        let role = role("reporting");
+        assert_eq!(
+            role_drift(&role, None),
+            vec!["role reporting does not exist".to_string()]
+        );
+        assert_eq!(
+            role_statements(&role, None, Some("it's secret")),
+            vec![
+                "CREATE ROLE \"reporting\" WITH LOGIN CONNECTION LIMIT -1 PASSWORD 'it''s secret';"
+                    .to_string(),
+                "GRANT \"pg_read_all_stats\" TO \"reporting\";".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_role_statements_drift() {
+        let role = role("reporting");
+        let observed = ObservedRole {
+            name: "reporting".to_string(),
+            login: true,
+            connection_limit: 5,
+            member_of: vec!["pg_read_all_stats".to_string()],
+        };
+        assert_eq!(
+            role_drift(&role, Some(&observed)),
+            vec!["connection limit is 5 instead of -1".to_string()]
+        );
+        assert_eq!(
+            role_statements(&role, Some(&observed), None),
+            vec!["ALTER ROLE \"reporting\" WITH LOGIN CONNECTION LIMIT -1;".to_string()]
+        );
+
+        // Nothing to do when the role matches the spec
+        let observed = ObservedRole {
+            connection_limit: -1,
+            ..observed
+        };
+        assert!(role_drift(&role, Some(&observed)).is_empty());
+        assert!(role_statements(&role, Some(&observed), None).is_empty());
+    }
+
+    #[test]
+    fn test_role_statements_reserved_member_of() {
+        let role = Role {
+            member_of: vec![
+                "postgres".to_string(),
+                "pg_execute_server_program".to_string(),
+                "pg_write_server_files".to_string(),
+                "pg_read_server_files".to_string(),
+                "pg_monitor".to_string(),
+            ],
+            ..role("reporting")
+        };
+        let observed = ObservedRole {
+            name: "reporting".to_string(),
+            login: true,
+            connection_limit: -1,
+            member_of: vec![],
+        };
+        // Only the monitoring role is granted
+        assert_eq!(
+            role_statements(&role, Some(&observed), None),
+            vec!["GRANT \"pg_monitor\" TO \"reporting\";".to_string()]
+        );
+        assert_eq!(
+            role_drift(&role, Some(&observed)),
+            vec![
+                "membership of reserved role postgres is not granted".to_string(),
+                "membership of reserved role pg_execute_server_program is not granted".to_string(),
+                "membership of reserved role pg_write_server_files is not granted".to_string(),
+                "membership of reserved role pg_read_server_files is not granted".to_string(),
+                "not a member of pg_monitor".to_string(),
+            ]
+        );
+        assert!(is_reserved_membership("readonly"));
+        assert!(!is_reserved_membership("reporting"));
+    }
+
+    #[test]
+    fn test_database_statements() {
+        let database = Database {
+            name: "analytics".to_string(),
+            owner: "reporting".to_string(),
+        };
+        assert_eq!(
+            database_statements(&database, None),
+            vec!["CREATE DATABASE \"analytics\" OWNER \"reporting\";".to_string()]
+        );
+
+        let observed = parse_databases(
+            " datname  | pg_get_userbyid
+-----------+-----------------
+ analytics | postgres
+(1 row)
+",
+        );
+        assert_eq!(
+            database_drift(&database, observed.first()),
+            vec!["owner is postgres instead of reporting".to_string()]
+        );
+        assert_eq!(
+            database_statements(&database, observed.first()),
+            vec!["ALTER DATABASE \"analytics\" OWNER TO \"reporting\";".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_grant_statements() {
+        let grant = SchemaGrant {
+            database: "analytics".to_string(),
+            schema: "public".to_string(),
+            privileges: vec![
+                SchemaPrivilege::Usage,
+                SchemaPrivilege::Select,
+                SchemaPrivilege::Insert,
+            ],
+        };
+        assert_eq!(
+            grant_statements("reporting", &grant),
+            vec![
+                "GRANT USAGE ON SCHEMA \"public\" TO \"reporting\";".to_string(),
+                "GRANT SELECT, INSERT ON ALL TABLES IN SCHEMA \"public\" TO \"reporting\";"
+                    .to_string(),
+                "ALTER DEFAULT PRIVILEGES IN SCHEMA \"public\" GRANT SELECT, INSERT ON TABLES TO \"reporting\";"
+                    .to_string(),
+            ]
+        );
+
+        let query = grant_check_query("reporting", &grant);
+        assert!(query.starts_with(
+            "SELECT has_schema_privilege('reporting', 'public', 'USAGE') AND NOT EXISTS"
+        ));
+        assert!(query.contains("has_table_privilege('reporting'"));
+    }
+
+    #[test]
+    fn test_deserialize_roles() {
+        let yaml = r#"
+        - name: reporting
+          passwordSecret:
+            name: reporting-password
+          grants:
+            - database: analytics
+              privileges:
+                - USAGE
+                - SELECT
+        "#;
+        let roles: Vec<Role> = serde_yaml::from_str(yaml).unwrap();
+        assert!(roles[0].login);
+        assert_eq!(roles[0].connection_limit, -1);
+        assert_eq!(roles[0].password_secret.as_ref().unwrap().key, "password");
+        assert_eq!(roles[0].grants[0].schema, "public");
+        assert_eq!(
+            roles[0].grants[0].privileges,
+            vec![SchemaPrivilege::Usage, SchemaPrivilege::Select]
+        );
+    }
+}
@@ -1,4 +1,7 @@
-use crate::{apis::coredb_types::CoreDB, Context};
+use crate::{
+    apis::coredb_types::{CoreDB, DedicatedNetworkingEndpoint, DedicatedNetworkingStatus},
+    patch_cdb_status_merge, Context,
+};
 use crate::{errors::OperatorError, network_policies::apply_network_policy};
 use k8s_openapi::api::core::v1::Service;
 use k8s_openapi::api::networking::v1::NetworkPolicy;
@@ -7,10 +10,10 @@ use kube::{
     api::{Api, Patch, PatchParams, ResourceExt},
     client::Client,
 };
-use serde_json::json;
+use serde_json::{json, Value};
 use std::env;
 use std::sync::Arc;
-use tracing::{debug, error, info};
+use tracing::{debug, error, info, warn};
 
 /// Reconcile dedicated networking resources for the CoreDB instance.
 ///
@@ -62,33 +65,29 @@ pub async fn reconcile_dedicated_networking(
                 "Handling primary service ingress for CoreDB instance: {}",
                 cdb.name_any()
             );
-            reconcile_dedicated_networking_service(
+            let mut endpoints = vec![reconcile_dedicated_networking_service(
                 cdb,
                 client.clone(),
                 &ns,
-                dedicated_networking.public,
                 false,
-                &dedicated_networking.serviceType,
                 basedomain,
             )
             .await
             .map_err(|e| {
                 error!("Failed to reconcile primary service ingress: {:?}", e);
                 e
-            })?;
+            })?];
 
             if dedicated_networking.include_standby {
                 debug!(
                     "Handling standby service ingress for CoreDB instance: {}",
                     cdb.name_any()
                 );
-                reconcile_dedicated_networking_service(
+                let standby_endpoint = reconcile_dedicated_networking_service(
                     cdb,
                     client.clone(),
                     &ns,
-                    dedicated_networking.public,
                     true,
-                    &dedicated_networking.serviceType,
                     basedomain,
                 )
                 .await
@@ -96,6 +95,7 @@ pub async fn reconcile_dedicated_networking(
                     error!("Failed to reconcile standby service ingress: {:?}", e);
                     e
                 })?;
+                endpoints.push(standby_endpoint);
             } else {
                 debug!(
                     "Standby service is not included. Deleting standby service for CoreDB instance: {}",
@@ -109,6 +109,13 @@ pub async fn reconcile_dedicated_networking(
                     })?;
             }
 
+            patch_dedicated_networking_status(
+                cdb,
+                client.clone(),
+                Some(DedicatedNetworkingStatus { endpoints }),
+            )
+            .await?;
+
             debug!(
                 "Completed reconciliation of dedicated networking for CoreDB instance: {} in namespace: {}",
                 cdb.name_any(),
@@ -141,6 +148,8 @@ pub async fn reconcile_dedicated_networking(
                     error!("Failed to delete standby service: {:?}", e);
                     e
                 })?;
+
+            patch_dedicated_networking_status(cdb, client.clone(), None).await?;
         }
     } else {
         debug!(
@@ -169,11 +178,49 @@ pub async fn reconcile_dedicated_networking(
                 error!("Failed to delete standby service: {:?}", e);
                 e
             })?;
+
+        patch_dedicated_networking_status(cdb, client.clone(), None).await?;
     }
 
     Ok(())
 }
 
+/// Write the external endpoints of dedicated networking into the CoreDB status.
+///
+/// The status is only patched when the endpoints changed, and is removed when
+/// dedicated networking is disabled.
+async fn patch_dedicated_networking_status(
+    cdb: &CoreDB,
+    client: Client,
+    status: Option<DedicatedNetworkingStatus>,
+) -> Result<(), OperatorError> {
+    let current = cdb
+        .status
+        .as_ref()
+        .and_then(|s| s.dedicated_networking.as_ref());
+    if current == status.as_ref() {
+        return Ok(());
+    }
+
+    let name = cdb.name_any();
+    let cdb_api: Api<CoreDB> = Api::namespaced(client, &cdb.namespace().unwrap());
+    let patch_status = json!({
+        "apiVersion": "coredb.io/v1alpha1",
+        "kind": "CoreDB",
+        "status": {
+            "dedicated_networking": status
+        }
+    });
+    patch_cdb_status_merge(&cdb_api, &name, patch_status)
+        .await
+        .map_err(|_| {
+            OperatorError::ServiceError(format!(
+                "Failed to update dedicated networking status of {}",
+                name
+            ))
+        })
+}
+
 /// Reconcile the network policies for dedicated networking.
 ///
 /// This function applies a specific network policy that allows traffic from a specified CIDR block
@@ -317,51 +364,167 @@ async fn delete_dedicated_networking_policies(
 /// Reconcile the Service resource for dedicated networking.
 ///
 /// This function creates or updates a Service resource for the primary or standby service
-/// based on the dedicated networking configuration.
+/// based on the dedicated networking configuration, and returns its external endpoint.
 ///
 /// # Parameters
 /// - `cdb`: The CoreDB custom resource instance.
 /// - `client`: The Kubernetes client.
 /// - `namespace`: The namespace in which to create or update the service.
-/// - `is_public`: Whether the service is public or private.
 /// - `is_standby`: Whether the service is for a standby (read-only) instance.
-/// - `service_type`: The type of the Kubernetes Service (e.g., "LoadBalancer", "ClusterIP").
 /// - `basedomain`: The base domain for the service.
 async fn reconcile_dedicated_networking_service(
     cdb: &CoreDB,
     client: Client,
     namespace: &str,
-    is_public: bool,
     is_standby: bool,
-    service_type: &str,
     basedomain: &str,
-) -> Result<(), OperatorError> {
+) -> Result<DedicatedNetworkingEndpoint, OperatorError> {
+    let service = generate_dedicated_networking_service(cdb, namespace, is_standby, basedomain);
+    let service_name = service["metadata"]["name"].as_str().unwrap().to_string();
+
+    debug!(
+        "Applying Service: {} in namespace: {} with type: {}",
+        service_name, namespace, service["spec"]["type"]
+    );
+
+    let svc_api: Api<Service> = Api::namespaced(client, namespace);
+    let patch_params = PatchParams::apply("cntrlr").force();
+    let patch = Patch::Apply(&service);
+
+    let applied = svc_api
+        .patch(&service_name, &patch_params, &patch)
+        .await
+        .map_err(|e| {
+            error!(
+                "Failed to apply service: {} in namespace: {}. Error: {}",
+                service_name, namespace, e
+            );
+            OperatorError::ServiceError(format!("Failed to apply service: {:?}", e))
+        })?;
+
+    debug!(
+        "Successfully applied service: {} in namespace: {}",
+        service_name, namespace
+    );
+
+    let addresses = applied
+        .status
+        .and_then(|status| status.load_balancer)
+        .and_then(|lb| lb.ingress)
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|ingress| ingress.hostname.or(ingress.ip))
+        .collect();
+
+    Ok(DedicatedNetworkingEndpoint {
+        service: service_name,
+        standby: is_standby,
+        hostname: service["metadata"]["annotations"]["external-dns.alpha.kubernetes.io/hostname"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string(),
+        port: service["spec"]["ports"][0]["port"].as_i64().unwrap_or(5432) as i32,
+        addresses,
+    })
+}
+
+/// Generate the Service resource for the primary or standby dedicated service.
+/// Whether a dedicated networking hostname is a single DNS label under the namespace of
+/// the instance, `<label>.<namespace>.<basedomain>`. Instances can not publish records
+/// for the hostnames of other instances or of other domains. The base domain is not
+/// checked when it is not known.
+pub(crate) fn is_allowed_hostname(
+    hostname: &str,
+    namespace: &str,
+    basedomain: Option<&str>,
+) -> bool {
+    let Some((label, domain)) = hostname.split_once('.') else {
+        return false;
+    };
+    let Some(domain) = domain
+        .strip_prefix(namespace)
+        .and_then(|rest| rest.strip_prefix('.'))
+    else {
+        return false;
+    };
+    is_dns_label(label)
+        && domain.split('.').all(is_dns_label)
+        && basedomain.is_none_or(|basedomain| domain == basedomain)
+}
+
+// A lowercase RFC 1123 label
+pub(crate) fn is_dns_label(label: &str) -> bool {
+    !label.is_empty()
+        && label.len() <= 63
+        && label
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+        && !label.starts_with('-')
+        && !label.ends_with('-')
+}
+
+fn generate_dedicated_networking_service(
+    cdb: &CoreDB,
+    namespace: &str,
+    is_standby: bool,
+    basedomain: &str,
+) -> Value {
     let cdb_name = &cdb.name_any();
     let owner_reference = cdb.controller_owner_ref(&()).unwrap();
+    let dedicated_networking = cdb.spec.dedicated_networking.clone().unwrap_or_default();
+    let service_type = dedicated_networking.serviceType.as_str();
     let service_name = if is_standby {
         format!("{}-dedicated-ro", cdb_name)
     } else {
         format!("{}-dedicated", cdb_name)
     };
 
-    let lb_scheme = if is_public {
+    let lb_scheme = if dedicated_networking.public {
         "internet-facing"
     } else {
         "internal"
     };
-    let lb_internal = if is_public { "false" } else { "true" };
-
-    debug!(
-        "Applying Service: {} in namespace: {} with type: {} and scheme: {}",
-        service_name, namespace, service_type, lb_scheme
-    );
+    let lb_internal = if dedicated_networking.public {
+        "false"
+    } else {
+        "true"
+    };
 
-    let external_dns_hostname = format!(
+    let (hostname, port) = if is_standby {
+        (
+            dedicated_networking.standby_hostname.clone(),
+            dedicated_networking.standby_port,
+        )
+    } else {
+        (
+            dedicated_networking.hostname.clone(),
+            dedicated_networking.port,
+        )
+    };
+    let default_hostname = format!(
         "dedicated{suffix}.{namespace}.{basedomain}",
         suffix = if is_standby { "-ro" } else { "" }
     );
+    let external_dns_hostname = match hostname {
+        Some(hostname) if is_allowed_hostname(&hostname, namespace, Some(basedomain)) => hostname,
+        Some(hostname) => {
+            error!(
+                "Ignoring hostname {} of instance {}, it is not a subdomain of {}.{}",
+                hostname, cdb_name, namespace, basedomain
+            );
+            default_hostname
+        }
+        None => default_hostname,
+    };
+    let port = port.unwrap_or(5432);
 
-    let mut annotations = serde_json::Map::new();
+    // The operator annotations are set last, so that user provided annotations can not
+    // change the published hostname or expose an internal load balancer
+    let mut annotations: serde_json::Map<String, Value> = dedicated_networking
+        .annotations
+        .iter()
+        .map(|(k, v)| (k.clone(), serde_json::Value::String(v.clone())))
+        .collect();
     annotations.insert(
         "external-dns.alpha.kubernetes.io/hostname".to_string(),
         serde_json::Value::String(external_dns_hostname),
@@ -405,7 +568,7 @@ async fn reconcile_dedicated_networking_service(
         "ports".to_string(),
         json!([{
             "name": "postgres",
-            "port": 5432,
+            "port": port,
             "protocol": "TCP",
             "targetPort": 5432
         }]),
@@ -419,28 +582,22 @@ async fn reconcile_dedicated_networking_service(
     );
     service_spec.insert("sessionAffinity".to_string(), json!("None"));
     service_spec.insert("type".to_string(), json!(service_type));
-    let ip_allow_list = cdb.spec.ip_allow_list.clone().unwrap_or_default();
 
-    // Allow ip_allow_list to allow all entries are in CIDR notation
-    let ip_allow_list_cidr: Vec<String> = ip_allow_list
-        .iter()
-        .map(|ip| {
-            if ip.contains('/') {
-                ip.clone()
-            } else {
-                format!("{}/32", ip)
-            }
-        })
-        .collect();
+    if service_type == "LoadBalancer" {
+        if let (false, Some(ip)) = (is_standby, &dedicated_networking.load_balancer_ip) {
+            service_spec.insert("loadBalancerIP".to_string(), json!(ip));
+        }
 
-    if service_type == "LoadBalancer" && !ip_allow_list_cidr.is_empty() {
-        service_spec.insert(
-            "loadBalancerSourceRanges".to_string(),
-            json!(ip_allow_list_cidr),
-        );
+        let ip_allow_list_cidr = load_balancer_source_ranges(cdb);
+        if !ip_allow_list_cidr.is_empty() {
+            service_spec.insert(
+                "loadBalancerSourceRanges".to_string(),
+                json!(ip_allow_list_cidr),
+            );
+        }
     }
 
-    let service = json!({
+    json!({
         "apiVersion": "v1",
         "kind": "Service",
         "metadata": {
@@ -451,29 +608,40 @@ async fn reconcile_dedicated_networking_service(
             "labels": labels
         },
         "spec": service_spec
-    });
-
-    let svc_api: Api<Service> = Api::namespaced(client, namespace);
-    let patch_params = PatchParams::apply("cntrlr").force();
-    let patch = Patch::Apply(&service);
-
-    svc_api
-        .patch(&service_name, &patch_params, &patch)
-        .await
-        .map_err(|e| {
-            error!(
-                "Failed to apply service: {} in namespace: {}. Error: {}",
-                service_name, namespace, e
-            );
-            OperatorError::ServiceError(format!("Failed to apply service: {:?}", e))
-        })?;
-
-    debug!(
-        "Successfully applied service: {} in namespace: {}",
-        service_name, namespace
-    );
+    })
+}
 
-    Ok(())
+/// Convert `spec.ip_allow_list` into CIDRs for `loadBalancerSourceRanges`. Single
+/// addresses become host routes, and invalid entries are skipped since a single one
+/// would make the Service invalid.
+fn load_balancer_source_ranges(cdb: &CoreDB) -> Vec<String> {
+    cdb.spec
+        .ip_allow_list
+        .clone()
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|ip| {
+            let (addr, prefix) = ip.split_once('/').unwrap_or((ip.as_str(), ""));
+            let max_prefix = match addr.parse::<std::net::IpAddr>() {
+                Ok(std::net::IpAddr::V4(_)) => 32,
+                Ok(std::net::IpAddr::V6(_)) => 128,
+                Err(_) => {
+                    warn!("Skipping invalid entry in ip_allow_list: {}", ip);
+                    return None;
+                }
+            };
+            if prefix.is_empty() {
+                return Some(format!("{}/{}", addr, max_prefix));
+            }
+            match prefix.parse::<u8>() {
+                Ok(p) if p <= max_prefix => Some(ip.clone()),
+                _ => {
+                    warn!("Skipping invalid entry in ip_allow_list: {}", ip);
+                    None
+                }
+            }
+        })
+        .collect()
 }
 
 /// Delete the Service resource for dedicated networking.
@@ -534,3 +702,130 @@ async fn delete_dedicated_networking_service(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::apis::coredb_types::{CoreDBSpec, DedicatedNetworking};
+    use std::collections::BTreeMap;
+
+    fn coredb(dedicated_networking: DedicatedNetworking, ip_allow_list: Vec<&str>) -> CoreDB {
+        let mut cdb = CoreDB::new(
+            "test",
+            CoreDBSpec {
+                dedicated_networking: Some(dedicated_networking),
+                ip_allow_list: Some(ip_allow_list.into_iter().map(String::from).collect()),
+                ..CoreDBSpec::default()
+            },
+        );
+        cdb.metadata.namespace = Some("org-test-inst-test".to_string());
+        cdb.metadata.uid = Some("752d59ef-2671-4890-9feb-0097459b18c8".to_string());
+        cdb
+    }
+
+    #[test]
+    fn test_generate_dedicated_networking_service() {
+        let cdb = coredb(
+            DedicatedNetworking {
+                enabled: true,
+                include_standby: true,
+                public: true,
+                serviceType: "LoadBalancer".to_string(),
+                annotations: BTreeMap::from([
+                    (
+                        "service.beta.kubernetes.io/aws-load-balancer-scheme".to_string(),
+                        "internal".to_string(),
+                    ),
+                    (
+                        "service.beta.kubernetes.io/aws-load-balancer-cross-zone-load-balancing-enabled"
+                            .to_string(),
+                        "true".to_string(),
+                    ),
+                ]),
+                load_balancer_ip: Some("203.0.113.10".to_string()),
+                hostname: Some("db.org-test-inst-test.tembo.io".to_string()),
+                standby_hostname: Some("db-ro.example.com".to_string()),
+                port: Some(6432),
+                ..DedicatedNetworking::default()
+            },
+            vec!["198.51.100.7", "10.0.0.0/8", "2001:db8::1", "not-an-ip"],
+        );
+
+        let primary =
+            generate_dedicated_networking_service(&cdb, "org-test-inst-test", false, "tembo.io");
+        let annotations = &primary["metadata"]["annotations"];
+        assert_eq!(
+            annotations["external-dns.alpha.kubernetes.io/hostname"],
+            "db.org-test-inst-test.tembo.io"
+        );
+        // The operator annotations can not be overridden
+        assert_eq!(
+            annotations["service.beta.kubernetes.io/aws-load-balancer-scheme"],
+            "internet-facing"
+        );
+        assert_eq!(
+            annotations
+                ["service.beta.kubernetes.io/aws-load-balancer-cross-zone-load-balancing-enabled"],
+            "true"
+        );
+        assert_eq!(primary["spec"]["ports"][0]["port"], 6432);
+        assert_eq!(primary["spec"]["ports"][0]["targetPort"], 5432);
+        assert_eq!(primary["spec"]["loadBalancerIP"], "203.0.113.10");
+        assert_eq!(
+            primary["spec"]["loadBalancerSourceRanges"],
+            json!(["198.51.100.7/32", "10.0.0.0/8", "2001:db8::1/128"])
+        );
+
+        let standby =
+            generate_dedicated_networking_service(&cdb, "org-test-inst-test", true, "tembo.io");
+        assert_eq!(standby["metadata"]["name"], "test-dedicated-ro");
+        // Hostnames outside of the namespace subdomain are ignored
+        assert_eq!(
+            standby["metadata"]["annotations"]["external-dns.alpha.kubernetes.io/hostname"],
+            "dedicated-ro.org-test-inst-test.tembo.io"
+        );
+        assert_eq!(standby["spec"]["ports"][0]["port"], 5432);
+        assert!(standby["spec"].get("loadBalancerIP").is_none());
+    }
+
+    #[test]
+    fn test_is_allowed_hostname() {
+        let ns = "org-test-inst-test";
+        assert!(is_allowed_hostname(
+            "db.org-test-inst-test.tembo.io",
+            ns,
+            Some("tembo.io")
+        ));
+        assert!(is_allowed_hostname(
+            "db.org-test-inst-test.tembo.io",
+            ns,
+            None
+        ));
+        assert!(!is_allowed_hostname(
+            "db.org-test-inst-test.example.com",
+            ns,
+            Some("tembo.io")
+        ));
+        assert!(!is_allowed_hostname(
+            "db.org-other.tembo.io",
+            ns,
+            Some("tembo.io")
+        ));
+        assert!(!is_allowed_hostname(
+            "a.b.org-test-inst-test.tembo.io",
+            ns,
+            Some("tembo.io")
+        ));
+        assert!(!is_allowed_hostname(
+            "org-test-inst-test.tembo.io",
+            ns,
+            Some("tembo.io")
+        ));
+        assert!(!is_allowed_hostname(
+            "DB.org-test-inst-test.tembo.io",
+            ns,
+            Some("tembo.io")
+        ));
+        assert!(!is_allowed_hostname("db.org-test-inst-test.", ns, None));
+    }
+}
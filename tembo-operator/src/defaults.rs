@@ -208,9 +208,16 @@ pub fn default_pgbouncer() -> PgBouncer {
         poolMode: default_pool_mode(),
         parameters: Some(default_pooler_parameters()),
         resources: Some(default_pooler_resources()),
+        routes: vec![],
+        read_only: false,
+        monitoring: default_pooler_monitoring(),
     }
 }
 
+pub fn default_pooler_monitoring() -> bool {
+    true
+}
+
 pub fn default_volume_snapshot() -> Option<VolumeSnapshot> {
     Some(VolumeSnapshot {
         enabled: false,
@@ -218,6 +225,10 @@ pub fn default_volume_snapshot() -> Option<VolumeSnapshot> {
     })
 }
 
+pub fn default_pg_upgrade_timeout_minutes() -> i64 {
+    120
+}
+
 pub fn default_affinity_configuration() -> Option<ClusterAffinity> {
     Some(ClusterAffinity {
         pod_anti_affinity_type: Some("preferred".to_string()),
@@ -225,3 +236,91 @@ pub fn default_affinity_configuration() -> Option<ClusterAffinity> {
         ..ClusterAffinity::default()
     })
 }
+
+pub fn default_database_owner() -> String {
+    "postgres".to_owned()
+}
+
+pub fn default_role_login() -> bool {
+    true
+}
+
+pub fn default_role_connection_limit() -> i32 {
+    -1
+}
+
+pub fn default_role_password_secret_key() -> String {
+    "password".to_owned()
+}
+
+pub fn default_grant_schema() -> String {
+    "public".to_owned()
+}
+
+pub fn default_replication_database() -> String {
+    "postgres".to_owned()
+}
+
+pub fn default_subscription_enabled() -> bool {
+    true
+}
+
+pub fn default_subscription_copy_data() -> bool {
+    true
+}
+
+pub fn default_subscription_connection_secret_key() -> String {
+    "uri".to_owned()
+}
+
+pub fn default_replica_streaming_port() -> i32 {
+    5432
+}
+
+pub fn default_backup_verification_schedule() -> String {
+    "0 3 * * SUN".to_owned()
+}
+
+pub fn default_backup_verification_query() -> String {
+    "SELECT 1".to_owned()
+}
+
+pub fn default_backup_verification_database() -> String {
+    "postgres".to_owned()
+}
+
+pub fn default_backup_verification_timeout_minutes() -> i64 {
+    120
+}
+
+pub fn default_storage_autoscaling_threshold_percent() -> u8 {
+    80
+}
+
+pub fn default_storage_autoscaling_step() -> Quantity {
+    Quantity("10Gi".to_string())
+}
+
+pub fn default_storage_autoscaling_cooldown_minutes() -> i64 {
+    360
+}
+
+pub fn default_tls_issuer_kind() -> String {
+    "Issuer".to_owned()
+}
+
+pub fn default_hba_all() -> String {
+    "all".to_owned()
+}
+
+pub fn default_allow_internet_egress() -> bool {
+    true
+}
+
+pub fn default_network_policy_apply_to_app_services() -> bool {
+    true
+}
+
+pub fn default_egress_protocol() -> String {
+    "TCP".to_owned()
+}
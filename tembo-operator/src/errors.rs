@@ -13,6 +13,9 @@ pub enum OperatorError {
     #[error("An IngressRouteTCP failed to Create, Update, or Delete")]
     IngressRouteTcpError,
 
+    #[error("Failed to create, update, or delete Gateway API route: {0}")]
+    GatewayRouteError(String),
+
     #[error("Failed to create, update, or delete NetworkPolicy: {0}")]
     NetworkPolicyError(String),
 
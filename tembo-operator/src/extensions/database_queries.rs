@@ -449,6 +449,8 @@ pub async fn get_all_extensions(
                 schema: Some(ext.schema),
                 error: None,
                 error_message: None,
+                previous_version: None,
+                requested_version: None,
             };
             ext_hashmap
                 .entry((ext.name, ext.description))
@@ -548,6 +550,68 @@ pub async fn toggle_extension(
     Ok(())
 }
 
+/// Handles updating an extension location to the requested version
+/// On failure, returns an error message
+#[instrument(skip(cdb, ctx), fields(cdb_name = %cdb.name_any(), ext_name, database_name, version))]
+pub async fn update_extension(
+    cdb: &CoreDB,
+    ext_name: &str,
+    database_name: &str,
+    version: &str,
+    ctx: Arc<Context>,
+) -> Result<(), ToggleError> {
+    let coredb_name = cdb.name_any();
+    if !check_input(ext_name) {
+        warn!(
+            "Extension is not formatted properly. Skipping operation. {}",
+            &coredb_name
+        );
+        return Err(ToggleError::WithDescription(
+            "Extension name is not formatted properly".into(),
+        ));
+    }
+    if !check_input(database_name) {
+        warn!(
+            "Database name is not formatted properly. Skipping operation. {}",
+            &coredb_name
+        );
+        return Err(ToggleError::WithDescription(
+            "Database name is not formatted properly".into(),
+        ));
+    }
+
+    let command = types::generate_extension_update_cmd(ext_name, version)
+        .map_err(ToggleError::WithDescription)?;
+
+    let psql_output = cdb
+        .psql(command, database_name.to_string(), ctx.clone())
+        .await
+        .map_err(|e| {
+            error!(
+                "Failed to update extension because of kube exec error: {:?}",
+                e
+            );
+            ToggleError::WithAction(e)
+        })?;
+
+    if !psql_output.success {
+        warn!(
+            "Failed to update extension {} to {} in database {}, instance {}",
+            ext_name, version, database_name, &coredb_name
+        );
+        return Err(ToggleError::WithDescription(
+            psql_output.stderr.unwrap_or_else(|| {
+                "Failed to update extension, and found no output. Please try again. If this issue persists, contact support.".to_string()
+            }),
+        ));
+    }
+    info!(
+        "Successfully updated extension {} to {} in database {}, instance {}",
+        ext_name, version, database_name, &coredb_name
+    );
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use crate::{
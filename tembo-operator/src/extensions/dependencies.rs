@@ -0,0 +1,358 @@
+use crate::{
+    apis::coredb_types::CoreDB,
+    extensions::types::{Extension, ExtensionInstallLocation, ExtensionStatus, TrunkInstall},
+    trunk::ExtensionDependencies,
+};
+use std::collections::{BTreeMap, BTreeSet};
+
+// Prefix of the error messages set on extension locations that can not be enabled because
+// of their dependencies. These errors are cleared once the dependencies are satisfied.
+pub const DEPENDENCY_ERROR_PREFIX: &str = "Dependency error: ";
+
+/// Dependency errors by extension name and database
+pub type DependencyErrors = BTreeMap<(String, String), String>;
+
+/// Add the dependencies of the enabled extensions to the spec of the CoreDB.
+///
+/// Every dependency missing from a database where a dependent extension is enabled is
+/// enabled there as well, and the Trunk projects providing the dependencies are added to
+/// the trunk installs. The extensions are sorted so that dependencies come before the
+/// extensions that require them, and the shared_preload_libraries of all of them are
+/// configured with a single restart.
+///
+/// Locations that can not be enabled, either because of a circular dependency or because
+/// a dependency is explicitly disabled, are returned as errors.
+pub fn resolve_extension_dependencies(
+    cdb: &CoreDB,
+    dependencies: &ExtensionDependencies,
+) -> (CoreDB, DependencyErrors) {
+    let mut resolved = cdb.clone();
+    let mut errors = DependencyErrors::new();
+
+    let mut order = vec![];
+    let mut cycles = vec![];
+    let mut visited = BTreeSet::new();
+    for extension in cdb.spec.extensions.iter() {
+        visit(
+            &extension.name,
+            dependencies,
+            &mut vec![],
+            &mut visited,
+            &mut order,
+            &mut cycles,
+        );
+    }
+    let mut cyclic: BTreeMap<&str, String> = BTreeMap::new();
+    for cycle in cycles.iter() {
+        for name in cycle.iter() {
+            cyclic
+                .entry(name.as_str())
+                .or_insert_with(|| format!("circular dependency {}", cycle.join(" -> ")));
+        }
+    }
+
+    let mut added_dependencies = BTreeSet::new();
+    for extension in cdb.spec.extensions.iter() {
+        for location in extension.locations.iter().filter(|l| l.enabled) {
+            let key = (extension.name.clone(), location.database.clone());
+            if let Some(message) = cyclic.get(extension.name.as_str()) {
+                errors.insert(key, format!("{DEPENDENCY_ERROR_PREFIX}{message}"));
+                continue;
+            }
+            for dependency in transitive_dependencies(&extension.name, dependencies) {
+                match location_spec(cdb, &dependency, &location.database) {
+                    Some(dependency_location) if !dependency_location.enabled => {
+                        errors.insert(
+                            key.clone(),
+                            format!(
+                                "{DEPENDENCY_ERROR_PREFIX}requires {}, which is disabled in database {}",
+                                dependency, location.database
+                            ),
+                        );
+                        errors
+                            .entry((dependency.clone(), location.database.clone()))
+                            .or_insert_with(|| {
+                                format!(
+                                    "{DEPENDENCY_ERROR_PREFIX}required by {}, which is enabled in database {}",
+                                    extension.name, location.database
+                                )
+                            });
+                    }
+                    Some(_) => {}
+                    None => {
+                        add_location(&mut resolved, &dependency, &location.database);
+                        added_dependencies.insert(dependency);
+                    }
+                }
+            }
+        }
+    }
+
+    for dependency in added_dependencies {
+        let Some(trunk_project) = dependencies.trunk_projects.get(&dependency) else {
+            // Not distributed through Trunk, so expected to ship with Postgres
+            continue;
+        };
+        if !resolved
+            .spec
+            .trunk_installs
+            .iter()
+            .any(|install| &install.name == trunk_project)
+        {
+            resolved.spec.trunk_installs.push(TrunkInstall {
+                name: trunk_project.clone(),
+                version: None,
+            });
+        }
+    }
+
+    resolved.spec.extensions.sort_by_key(|extension| {
+        order
+            .iter()
+            .position(|name| name == &extension.name)
+            .unwrap_or(usize::MAX)
+    });
+
+    (resolved, errors)
+}
+
+/// Clear the dependency errors of the locations whose dependencies are now satisfied, so
+/// enabling them is attempted again.
+pub fn clear_resolved_dependency_errors(
+    mut statuses: Vec<ExtensionStatus>,
+    errors: &DependencyErrors,
+) -> Vec<ExtensionStatus> {
+    for status in statuses.iter_mut() {
+        for location in status.locations.iter_mut() {
+            let is_dependency_error = location
+                .error_message
+                .as_deref()
+                .is_some_and(|message| message.starts_with(DEPENDENCY_ERROR_PREFIX));
+            if is_dependency_error
+                && !errors.contains_key(&(status.name.clone(), location.database.clone()))
+            {
+                location.error = Some(false);
+                location.error_message = None;
+            }
+        }
+    }
+    statuses
+}
+
+/// The dependencies of an extension, in the order they need to be enabled
+pub fn transitive_dependencies(
+    extension: &str,
+    dependencies: &ExtensionDependencies,
+) -> Vec<String> {
+    let mut order = vec![];
+    visit(
+        extension,
+        dependencies,
+        &mut vec![],
+        &mut BTreeSet::new(),
+        &mut order,
+        &mut vec![],
+    );
+    order.retain(|name| name != extension);
+    order
+}
+
+// Depth first traversal adding the extensions to `order` after their dependencies
+fn visit(
+    extension: &str,
+    dependencies: &ExtensionDependencies,
+    path: &mut Vec<String>,
+    visited: &mut BTreeSet<String>,
+    order: &mut Vec<String>,
+    cycles: &mut Vec<Vec<String>>,
+) {
+    if visited.contains(extension) {
+        return;
+    }
+    if let Some(start) = path.iter().position(|name| name == extension) {
+        let mut cycle = path[start..].to_vec();
+        cycle.push(extension.to_string());
+        cycles.push(cycle);
+        return;
+    }
+    path.push(extension.to_string());
+    for dependency in dependencies
+        .dependencies
+        .get(extension)
+        .into_iter()
+        .flatten()
+    {
+        visit(dependency, dependencies, path, visited, order, cycles);
+    }
+    path.pop();
+    visited.insert(extension.to_string());
+    order.push(extension.to_string());
+}
+
+fn location_spec<'a>(
+    cdb: &'a CoreDB,
+    extension: &str,
+    database: &str,
+) -> Option<&'a ExtensionInstallLocation> {
+    cdb.spec
+        .extensions
+        .iter()
+        .filter(|e| e.name == extension)
+        .flat_map(|e| e.locations.iter())
+        .find(|l| l.database == database)
+}
+
+fn add_location(cdb: &mut CoreDB, extension: &str, database: &str) {
+    let location = ExtensionInstallLocation {
+        enabled: true,
+        database: database.to_string(),
+        version: None,
+        schema: None,
+        allow_downgrade: false,
+    };
+    match cdb.spec.extensions.iter_mut().find(|e| e.name == extension) {
+        Some(existing) => {
+            if !existing.locations.iter().any(|l| l.database == database) {
+                existing.locations.push(location);
+            }
+        }
+        None => cdb.spec.extensions.push(Extension {
+            name: extension.to_string(),
+            description: None,
+            locations: vec![location],
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::apis::coredb_types::CoreDBSpec;
+
+    fn extension(name: &str, enabled: bool) -> Extension {
+        Extension {
+            name: name.to_string(),
+            description: None,
+            locations: vec![ExtensionInstallLocation {
+                enabled,
+                database: "postgres".to_string(),
+                version: None,
+                schema: None,
+                allow_downgrade: false,
+            }],
+        }
+    }
+
+    fn dependencies() -> ExtensionDependencies {
+        ExtensionDependencies {
+            dependencies: BTreeMap::from([
+                (
+                    "vectorize".to_string(),
+                    vec![
+                        "pgmq".to_string(),
+                        "pg_cron".to_string(),
+                        "vector".to_string(),
+                    ],
+                ),
+                ("pgmq".to_string(), vec!["pg_partman".to_string()]),
+                ("a".to_string(), vec!["b".to_string()]),
+                ("b".to_string(), vec!["a".to_string()]),
+            ]),
+            trunk_projects: BTreeMap::from([
+                ("vectorize".to_string(), "vectorize".to_string()),
+                ("pgmq".to_string(), "pgmq".to_string()),
+                ("pg_cron".to_string(), "pg_cron".to_string()),
+                ("vector".to_string(), "pgvector".to_string()),
+                ("pg_partman".to_string(), "pg_partman".to_string()),
+            ]),
+        }
+    }
+
+    fn coredb(extensions: Vec<Extension>) -> CoreDB {
+        CoreDB::new(
+            "test",
+            CoreDBSpec {
+                extensions,
+                trunk_installs: vec![TrunkInstall {
+                    name: "vectorize".to_string(),
+                    version: Some("0.10.0".to_string()),
+                }],
+                ..CoreDBSpec::default()
+            },
+        )
+    }
+
+    #[test]
+    fn test_resolve_extension_dependencies() {
+        let cdb = coredb(vec![
+            extension("vectorize", true),
+            extension("pg_cron", true),
+        ]);
+        let (resolved, errors) = resolve_extension_dependencies(&cdb, &dependencies());
+        assert!(errors.is_empty());
+
+        let order: Vec<&str> = resolved
+            .spec
+            .extensions
+            .iter()
+            .map(|e| e.name.as_str())
+            .collect();
+        assert_eq!(
+            order,
+            vec!["pg_partman", "pgmq", "pg_cron", "vector", "vectorize"]
+        );
+        assert!(resolved
+            .spec
+            .extensions
+            .iter()
+            .all(|e| e.locations[0].enabled && e.locations[0].database == "postgres"));
+
+        let installs: Vec<&str> = resolved
+            .spec
+            .trunk_installs
+            .iter()
+            .map(|i| i.name.as_str())
+            .collect();
+        assert_eq!(
+            installs,
+            vec!["vectorize", "pg_partman", "pgmq", "pgvector"]
+        );
+    }
+
+    #[test]
+    fn test_resolve_extension_dependencies_errors() {
+        let cdb = coredb(vec![
+            extension("vectorize", true),
+            extension("vector", false),
+            extension("a", true),
+        ]);
+        let (_, errors) = resolve_extension_dependencies(&cdb, &dependencies());
+        assert_eq!(
+            errors.get(&("vectorize".to_string(), "postgres".to_string())),
+            Some(
+                &"Dependency error: requires vector, which is disabled in database postgres"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            errors.get(&("vector".to_string(), "postgres".to_string())),
+            Some(
+                &"Dependency error: required by vectorize, which is enabled in database postgres"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            errors.get(&("a".to_string(), "postgres".to_string())),
+            Some(&"Dependency error: circular dependency a -> b -> a".to_string())
+        );
+    }
+
+    #[test]
+    fn test_transitive_dependencies() {
+        assert_eq!(
+            transitive_dependencies("vectorize", &dependencies()),
+            vec!["pg_partman", "pgmq", "pg_cron", "vector"]
+        );
+        assert!(transitive_dependencies("pg_cron", &dependencies()).is_empty());
+    }
+}
@@ -5,7 +5,7 @@ use crate::{
         kubernetes_queries::{add_trunk_install_to_status, remove_trunk_installs_from_status},
         types::{TrunkInstall, TrunkInstallStatus},
     },
-    trunk::get_latest_trunk_project_version,
+    trunk::{get_latest_trunk_project_version, trunk_registry_url},
     Context,
 };
 use k8s_openapi::{api::core::v1::Pod, apimachinery::pkg::apis::meta::v1::ObjectMeta};
@@ -145,10 +145,12 @@ pub fn find_trunk_installs_to_pod<'a>(cdb: &'a CoreDB, pod_name: &str) -> Vec<&'
         .and_then(|status| status.trunk_installs.as_deref())
         .unwrap_or_default();
 
-    // Get extensions in spec.trunk_install that are not in status.trunk_install
+    // Get extensions in spec.trunk_install that are not in status.trunk_install,
+    // or that are installed with another version than the one requested
     for ext in &cdb.spec.trunk_installs {
         if !trunk_install_statuses.iter().any(|ext_status| {
             ext.name == ext_status.name
+                && (ext.version.is_none() || ext.version == ext_status.version)
                 && !ext_status.error
                 && ext_status
                     .installed_to_pods
@@ -337,7 +339,7 @@ async fn execute_extension_install_command(
     let cmd = vec![
         "trunk".to_owned(),
         "install".to_owned(),
-        "-r https://registry.pgtrunk.io".to_owned(),
+        format!("-r {}", trunk_registry_url()),
         ext.name.clone(),
         "--version".to_owned(),
         version,
@@ -730,6 +732,15 @@ mod tests {
         assert_eq!(result[0].name, "install2");
         assert_eq!(result[1].name, "install3");
 
+        // A new version of an installed project is installed again
+        cdb.spec.trunk_installs[0].version = Some("1.1".to_string());
+        let result = find_trunk_installs_to_pod(&cdb, pod_name);
+        assert_eq!(result.len(), 3);
+        assert_eq!(result[0].name, "install1");
+        cdb.spec.trunk_installs[0].version = None;
+        let result = find_trunk_installs_to_pod(&cdb, pod_name);
+        assert_eq!(result.len(), 2);
+
         // Test with Postgres image.
         cdb.spec.image = "quay.io/tembo/postgres:17-noble".to_string();
         let result = find_trunk_installs_to_pod(&cdb, pod_name);
@@ -259,8 +259,12 @@ fn update_trunk_installs(
 
     for existing_status in &current_trunk_installs {
         if existing_status.name == new_trunk_install.name
-            && existing_status.version == new_trunk_install.version
+            && new_trunk_install.version.is_some()
+            && existing_status.version != new_trunk_install.version
         {
+            // Another version was installed, it replaces the previous one on every pod
+            updated_trunk_installs.push(new_trunk_install.clone());
+        } else if existing_status.name == new_trunk_install.name {
             // Update existing status
             let mut update_status = existing_status.clone();
             if update_status.installed_to_pods.is_none() {
@@ -329,6 +333,36 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_add_new_trunk_install_with_new_version() {
+        let initial_trunk_installs = vec![TrunkInstallStatus {
+            error: false,
+            installed_to_pods: Some(vec![
+                "test-coredb-24631-1".to_string(),
+                "test-coredb-24631-2".to_string(),
+            ]),
+            name: "pgmq".to_string(),
+            version: Some("1.1.0".to_string()),
+            loading: false,
+            error_message: None,
+        }];
+
+        let new_trunk_install = TrunkInstallStatus {
+            error: false,
+            installed_to_pods: Some(vec!["test-coredb-24631-2".to_string()]),
+            name: "pgmq".to_string(),
+            version: Some("1.2.0".to_string()),
+            loading: false,
+            error_message: None,
+        };
+
+        let updated_trunk_installs =
+            update_trunk_installs(initial_trunk_installs, &new_trunk_install);
+
+        // The new version is only installed to the new pod
+        assert_eq!(updated_trunk_installs, vec![new_trunk_install]);
+    }
+
     #[test]
     fn test_add_new_trunk_install_with_same_name_new_host() {
         let initial_trunk_installs = vec![TrunkInstallStatus {
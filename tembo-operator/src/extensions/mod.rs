@@ -1,13 +1,17 @@
 pub mod database_queries;
+pub mod dependencies;
 pub mod install;
 pub mod kubernetes_queries;
 pub mod toggle;
 pub mod types;
+pub mod update;
 
 use crate::{
     apis::coredb_types::CoreDB,
     extensions::types::{ExtensionStatus, TrunkInstallStatus},
-    is_postgres_ready, Context,
+    is_postgres_ready,
+    trunk::extension_dependencies,
+    Context,
 };
 use kube::{
     runtime::{controller::Action, wait::Condition},
@@ -25,8 +29,14 @@ pub async fn reconcile_extensions(
 ) -> Result<(Vec<TrunkInstallStatus>, Vec<ExtensionStatus>), Action> {
     // Trunk installs do not require postgres is ready
     let coredb_name = coredb.name_any();
+    let dependencies =
+        extension_dependencies(ctx.client.clone(), &coredb.namespace().unwrap()).await?;
+    // Dependencies of the enabled extensions are installed along with them
+    let (resolved, _) = dependencies::resolve_extension_dependencies(coredb, &dependencies);
+    // The packages of the requested extension versions are installed to all pods
+    let resolved = update::pin_trunk_installs_to_extension_versions(&resolved).await?;
     debug!("Reconciling trunk installs: {}", coredb_name);
-    let trunk_installs = install::reconcile_trunk_installs(coredb, ctx.clone()).await?;
+    let trunk_installs = install::reconcile_trunk_installs(&resolved, ctx.clone()).await?;
 
     let primary_pod_cnpg = coredb.primary_pod_cnpg(ctx.client.clone()).await?;
 
@@ -35,8 +45,20 @@ pub async fn reconcile_extensions(
         return Err(Action::requeue(Duration::from_secs(5)));
     }
 
+    // Replicas are read-only, extensions are enabled through the source
+    if coredb.spec.is_replica() {
+        debug!("Skipping extension toggles of replica {}", coredb_name);
+        let extension_statuses = coredb
+            .status
+            .as_ref()
+            .and_then(|s| s.extensions.clone())
+            .unwrap_or_default();
+        return Ok((trunk_installs, extension_statuses));
+    }
+
     // Toggles require postgres is ready
     debug!("Reconciling extension statuses: {}", coredb_name);
-    let extension_statuses = toggle::reconcile_extension_toggle_state(coredb, ctx.clone()).await?;
+    let extension_statuses =
+        toggle::reconcile_extension_toggle_state(coredb, ctx.clone(), &dependencies).await?;
     Ok((trunk_installs, extension_statuses))
 }
@@ -1,14 +1,20 @@
 use crate::{
     apis::coredb_types::CoreDB,
     extensions::{
-        database_queries, kubernetes_queries,
+        database_queries,
+        dependencies::{
+            clear_resolved_dependency_errors, resolve_extension_dependencies,
+            transitive_dependencies, DependencyErrors, DEPENDENCY_ERROR_PREFIX,
+        },
+        kubernetes_queries,
         types::{self, Extension, ExtensionInstallLocationStatus, ExtensionStatus},
+        update::{determine_extension_locations_to_update, is_pending_version_update_error},
     },
     get_current_coredb_resource,
-    trunk::{self, Version},
+    trunk::{self, ExtensionDependencies, Version},
     Context,
 };
-use kube::runtime::controller::Action;
+use kube::{runtime::controller::Action, ResourceExt};
 
 use crate::extensions::install::check_for_so_files;
 use crate::extensions::types::TrunkInstall;
@@ -26,7 +32,11 @@ use crate::{
     },
     trunk::extensions_that_require_load,
 };
-use std::{collections::BTreeMap, sync::Arc, time::Duration};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::Arc,
+    time::Duration,
+};
 use tracing::{error, info, warn};
 
 use super::database_queries::ToggleError;
@@ -34,7 +44,11 @@ use super::database_queries::ToggleError;
 pub async fn reconcile_extension_toggle_state(
     cdb: &CoreDB,
     ctx: Arc<Context>,
+    dependencies: &ExtensionDependencies,
 ) -> Result<Vec<ExtensionStatus>, Action> {
+    // The dependencies of the enabled extensions are enabled first
+    let (cdb, dependency_errors) = resolve_extension_dependencies(cdb, dependencies);
+    let cdb = &cdb;
     let mut all_actually_installed_extensions =
         database_queries::get_all_extensions(cdb, ctx.clone()).await?;
 
@@ -49,14 +63,109 @@ pub async fn reconcile_extension_toggle_state(
     .await?;
     all_actually_installed_extensions.append(&mut extensions_with_load);
 
-    let ext_status_updates =
-        determine_updated_extensions_status(cdb, all_actually_installed_extensions);
+    let ext_status_updates = clear_resolved_dependency_errors(
+        determine_updated_extensions_status(cdb, all_actually_installed_extensions),
+        &dependency_errors,
+    );
     kubernetes_queries::update_extensions_status(cdb, ext_status_updates.clone(), &ctx).await?;
     let cdb = get_current_coredb_resource(cdb, ctx.clone()).await?;
+    let (cdb, dependency_errors) = resolve_extension_dependencies(&cdb, dependencies);
     let toggle_these_extensions = determine_extension_locations_to_toggle(&cdb);
 
-    let ext_status_updates =
-        toggle_extensions(ctx, ext_status_updates, &cdb, toggle_these_extensions).await?;
+    let ext_status_updates = toggle_extensions(
+        ctx.clone(),
+        ext_status_updates,
+        &cdb,
+        toggle_these_extensions,
+        dependencies,
+        &dependency_errors,
+    )
+    .await?;
+    let ext_status_updates = update_extensions(ctx, ext_status_updates, &cdb).await?;
+    Ok(ext_status_updates)
+}
+
+// Run ALTER EXTENSION ... UPDATE on the enabled extensions requesting another version. The Trunk
+// packages of the requested versions were installed to all pods before the toggles.
+async fn update_extensions(
+    ctx: Arc<Context>,
+    ext_status_updates: Vec<ExtensionStatus>,
+    cdb: &CoreDB,
+) -> Result<Vec<ExtensionStatus>, Action> {
+    let mut ext_status_updates = ext_status_updates;
+    for update in determine_extension_locations_to_update(cdb) {
+        let Some(location_status) =
+            types::get_location_status(cdb, &update.extension, &update.location.database)
+        else {
+            continue;
+        };
+        if location_status.error == Some(true) {
+            continue;
+        }
+        if update.is_refused_downgrade() {
+            warn!(
+                "Not downgrading extension {} from {} to {} in database {} for {}",
+                update.extension,
+                update.current_version,
+                update.desired_version,
+                update.location.database,
+                cdb.name_any()
+            );
+            ext_status_updates = set_location_error(
+                cdb,
+                ctx.clone(),
+                &update.extension,
+                &update.location,
+                update.refused_downgrade_message(),
+            )
+            .await?;
+            continue;
+        }
+        match database_queries::update_extension(
+            cdb,
+            &update.extension,
+            &update.location.database,
+            &update.desired_version,
+            ctx.clone(),
+        )
+        .await
+        {
+            Ok(_) => {
+                let location_status = ExtensionInstallLocationStatus {
+                    version: Some(update.desired_version.clone()),
+                    previous_version: Some(update.current_version.clone()),
+                    requested_version: Some(update.desired_version.clone()),
+                    ..location_status
+                };
+                ext_status_updates = kubernetes_queries::update_extension_location_in_status(
+                    cdb,
+                    ctx.clone(),
+                    &update.extension,
+                    &location_status,
+                )
+                .await?;
+            }
+            Err(ToggleError::WithAction(action)) => {
+                return Err(action);
+            }
+            Err(ToggleError::WithDescription(error_message)) => {
+                // The failed update is not retried until another version is requested
+                let location_status = ExtensionInstallLocationStatus {
+                    error: Some(true),
+                    error_message: Some(update.failed_update_message(&error_message)),
+                    requested_version: Some(update.desired_version.clone()),
+                    ..location_status
+                };
+                ext_status_updates = kubernetes_queries::update_extension_location_in_status(
+                    cdb,
+                    ctx.clone(),
+                    &update.extension,
+                    &location_status,
+                )
+                .await?;
+            }
+        }
+    }
     Ok(ext_status_updates)
 }
 
@@ -65,15 +174,56 @@ async fn toggle_extensions(
     ext_status_updates: Vec<ExtensionStatus>,
     cdb: &CoreDB,
     toggle_these_extensions: Vec<Extension>,
+    dependencies: &ExtensionDependencies,
+    dependency_errors: &DependencyErrors,
 ) -> Result<Vec<ExtensionStatus>, Action> {
     let current_shared_preload_libraries = list_shared_preload_libraries(cdb, ctx.clone()).await?;
     let requires_load =
         extensions_that_require_load(ctx.client.clone(), &cdb.metadata.namespace.clone().unwrap())
             .await?;
     let mut ext_status_updates = ext_status_updates.clone();
+    // Locations that failed to toggle, so their dependents are not attempted
+    let mut failed_locations: BTreeSet<(String, String)> = BTreeSet::new();
 
     for extension_to_toggle in toggle_these_extensions {
         for location_to_toggle in &extension_to_toggle.locations {
+            let location_key = (
+                extension_to_toggle.name.clone(),
+                location_to_toggle.database.clone(),
+            );
+            let dependency_error = dependency_errors.get(&location_key).cloned().or_else(|| {
+                if !location_to_toggle.enabled {
+                    return None;
+                }
+                transitive_dependencies(&extension_to_toggle.name, dependencies)
+                    .into_iter()
+                    .find(|dependency| {
+                        failed_locations
+                            .contains(&(dependency.clone(), location_to_toggle.database.clone()))
+                    })
+                    .map(|dependency| {
+                        format!("{DEPENDENCY_ERROR_PREFIX}{dependency} failed to enable")
+                    })
+            });
+            if let Some(error_message) = dependency_error {
+                warn!(
+                    "Not toggling extension {} in database {} for {}: {}",
+                    extension_to_toggle.name,
+                    location_to_toggle.database,
+                    cdb.name_any(),
+                    error_message
+                );
+                failed_locations.insert(location_key);
+                ext_status_updates = set_location_error(
+                    cdb,
+                    ctx.clone(),
+                    &extension_to_toggle.name,
+                    location_to_toggle,
+                    error_message,
+                )
+                .await?;
+                continue;
+            }
             let expected_library_name = match requires_load.get(&extension_to_toggle.name) {
                 None => &extension_to_toggle.name,
                 Some(expected_library_name) => expected_library_name,
@@ -159,31 +309,13 @@ async fn toggle_extensions(
                     return Err(action);
                 }
                 Err(ToggleError::WithDescription(error_message)) => {
-                    let mut location_status = match types::get_location_status(
-                        cdb,
-                        &extension_to_toggle.name,
-                        &location_to_toggle.database,
-                    ) {
-                        None => {
-                            error!("There should always be an extension status for a location before attempting to toggle an extension for that location");
-                            ExtensionInstallLocationStatus {
-                                database: location_to_toggle.database.clone(),
-                                schema: None,
-                                version: None,
-                                enabled: None,
-                                error: Some(true),
-                                error_message: None,
-                            }
-                        }
-                        Some(location_status) => location_status,
-                    };
-                    location_status.error = Some(true);
-                    location_status.error_message = Some(error_message);
-                    ext_status_updates = kubernetes_queries::update_extension_location_in_status(
+                    failed_locations.insert(location_key);
+                    ext_status_updates = set_location_error(
                         cdb,
                         ctx.clone(),
                         &extension_to_toggle.name,
-                        &location_status,
+                        location_to_toggle,
+                        error_message,
                     )
                     .await?;
                 }
@@ -193,6 +325,45 @@ async fn toggle_extensions(
     Ok(ext_status_updates)
 }
 
+// Report an error for an extension location in the status
+async fn set_location_error(
+    cdb: &CoreDB,
+    ctx: Arc<Context>,
+    extension_name: &str,
+    location: &types::ExtensionInstallLocation,
+    error_message: String,
+) -> Result<Vec<ExtensionStatus>, Action> {
+    let mut location_status = match types::get_location_status(
+        cdb,
+        extension_name,
+        &location.database,
+    ) {
+        None => {
+            error!("There should always be an extension status for a location before attempting to toggle an extension for that location");
+            ExtensionInstallLocationStatus {
+                database: location.database.clone(),
+                schema: None,
+                version: None,
+                enabled: None,
+                error: Some(true),
+                error_message: None,
+                previous_version: None,
+                requested_version: None,
+            }
+        }
+        Some(location_status) => location_status,
+    };
+    location_status.error = Some(true);
+    location_status.error_message = Some(error_message);
+    kubernetes_queries::update_extension_location_in_status(
+        cdb,
+        ctx.clone(),
+        extension_name,
+        &location_status,
+    )
+    .await
+}
+
 // In this function, we check if we are awaiting restart on shared_preload_libraries
 fn requeue_if_expecting_shared_preload_library(
     cdb: &CoreDB,
@@ -262,26 +433,56 @@ pub fn determine_updated_extensions_status(
                 version: actual_location.version.clone(),
                 error: Some(false),
                 error_message: None,
+                previous_version: None,
+                requested_version: None,
             };
-            // If there is a current status, retain the error and error message if the schema has not changed
-            match types::get_location_status(
+            let current_status = types::get_location_status(
                 cdb,
                 &actual_extension.name.clone(),
                 &actual_location.database.clone(),
-            ) {
-                None => {}
-                Some(current_status) => {
-                    if current_status.schema == actual_location.schema {
-                        location_status.error = current_status.error;
-                        location_status.error_message = current_status.error_message;
-                    }
+            );
+            // If there is a current status, retain the version it was updated from,
+            // or record the current version if the enabled version changed
+            if let Some(current_status) = &current_status {
+                location_status.previous_version =
+                    match (current_status.enabled, actual_location.enabled) {
+                        (Some(true), Some(true))
+                            if current_status.version != actual_location.version =>
+                        {
+                            current_status.version.clone()
+                        }
+                        _ => current_status.previous_version.clone(),
+                    };
+                location_status.requested_version = current_status.requested_version.clone();
+            }
+            // If there is a current status, retain the error and error message if the schema has not changed
+            match current_status {
+                Some(current_status) if current_status.schema == actual_location.schema => {
+                    location_status.error = current_status.error;
+                    location_status.error_message = current_status.error_message;
                 }
+                _ => {}
             }
-            // If the desired state matches the actual state, unset the error and error message
+            // If the desired state matches the actual state, unset the error and error message,
+            // unless the error is from a version update that is still pending
             match types::get_location_spec(cdb, &actual_extension.name, &actual_location.database) {
                 None => {}
                 Some(desired_location) => {
-                    if actual_location.enabled == Some(desired_location.enabled) {
+                    // Record the requested version once it is enabled, and the version
+                    // requested before the status tracked it, which is not updated to
+                    if desired_location.version.is_some()
+                        && (desired_location.version == actual_location.version
+                            || location_status.requested_version.is_none())
+                    {
+                        location_status.requested_version = desired_location.version.clone();
+                    }
+                    if actual_location.enabled == Some(desired_location.enabled)
+                        && !is_pending_version_update_error(
+                            location_status.error_message.as_deref(),
+                            &desired_location,
+                            actual_location.version.as_deref(),
+                        )
+                    {
                         location_status.error = Some(false);
                         location_status.error_message = None;
                     }
@@ -326,6 +527,8 @@ pub fn determine_updated_extensions_status(
                     version: desired_location.version.clone(),
                     error: Some(true),
                     error_message: Some("Extension is not installed".to_string()),
+                    previous_version: None,
+                    requested_version: None,
                 };
                 ext_status_updates = merge_location_status_into_extension_status_list(
                     &desired_extension.name.clone(),
@@ -452,6 +655,8 @@ async fn check_for_extensions_enabled_with_load(
                                 version: desired_location.version.clone(),
                                 error: Some(false),
                                 error_message: None,
+                                previous_version: None,
+                                requested_version: None,
                             };
                             extension_status.locations.push(location_status);
                         }
@@ -467,6 +672,8 @@ async fn check_for_extensions_enabled_with_load(
                         version: None,
                         error: Some(false),
                         error_message: None,
+                        previous_version: None,
+                        requested_version: None,
                     };
                     extension_status.locations.push(location_status);
                 }
@@ -530,4 +737,87 @@ mod tests {
         let result = determine_updated_extensions_status(&cdb, all_actually_installed_extensions);
         assert_eq!(result, vec![]);
     }
+
+    #[test]
+    fn test_determine_updated_extensions_status_version_update() {
+        let location_status =
+            |version: &str, error_message: Option<&str>| ExtensionInstallLocationStatus {
+                database: "postgres".to_string(),
+                schema: Some("public".to_string()),
+                version: Some(version.to_string()),
+                enabled: Some(true),
+                error: Some(error_message.is_some()),
+                error_message: error_message.map(str::to_string),
+                previous_version: None,
+                requested_version: None,
+            };
+        let extension_status = |location: ExtensionInstallLocationStatus| ExtensionStatus {
+            name: "pgmq".to_string(),
+            description: None,
+            locations: vec![location],
+        };
+        let refused = "Version update error: refusing to downgrade to 1.0.0 from 1.1.0, set allowDowngrade to force it";
+        let mut cdb = CoreDB::new(
+            "test",
+            CoreDBSpec {
+                extensions: vec![Extension {
+                    name: "pgmq".to_string(),
+                    description: None,
+                    locations: vec![types::ExtensionInstallLocation {
+                        enabled: true,
+                        version: Some("1.0.0".to_string()),
+                        ..Default::default()
+                    }],
+                }],
+                ..Default::default()
+            },
+        );
+        cdb.status = Some(CoreDBStatus {
+            extensions: Some(vec![extension_status(ExtensionInstallLocationStatus {
+                requested_version: Some("1.1.0".to_string()),
+                ..location_status("1.1.0", Some(refused))
+            })]),
+            ..Default::default()
+        });
+
+        // The refused downgrade is kept while it is still requested
+        let actual = vec![extension_status(location_status("1.1.0", None))];
+        let result = determine_updated_extensions_status(&cdb, actual.clone());
+        let location = &result[0].locations[0];
+        assert_eq!(location.error, Some(true));
+        assert_eq!(location.error_message.as_deref(), Some(refused));
+        assert_eq!(location.previous_version, None);
+        assert_eq!(location.requested_version.as_deref(), Some("1.1.0"));
+
+        // Allowing the downgrade clears it
+        cdb.spec.extensions[0].locations[0].allow_downgrade = true;
+        let result = determine_updated_extensions_status(&cdb, actual);
+        assert_eq!(result[0].locations[0].error, Some(false));
+        assert_eq!(result[0].locations[0].error_message, None);
+
+        // The version it was updated from is recorded
+        let actual = vec![extension_status(location_status("1.0.0", None))];
+        let result = determine_updated_extensions_status(&cdb, actual);
+        assert_eq!(result[0].locations[0].version.as_deref(), Some("1.0.0"));
+        assert_eq!(
+            result[0].locations[0].previous_version.as_deref(),
+            Some("1.1.0")
+        );
+        assert_eq!(
+            result[0].locations[0].requested_version.as_deref(),
+            Some("1.0.0")
+        );
+
+        // A version requested before the status tracked it is recorded without updating to it
+        cdb.status = Some(CoreDBStatus {
+            extensions: Some(vec![extension_status(location_status("1.1.0", None))]),
+            ..Default::default()
+        });
+        let actual = vec![extension_status(location_status("1.1.0", None))];
+        let result = determine_updated_extensions_status(&cdb, actual);
+        assert_eq!(
+            result[0].locations[0].requested_version.as_deref(),
+            Some("1.0.0")
+        );
+    }
 }
@@ -1,5 +1,6 @@
 use crate::{apis::coredb_types::CoreDB, defaults, extensions::database_queries::check_input};
 use lazy_static::lazy_static;
+use regex::Regex;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
@@ -15,6 +16,8 @@ lazy_static! {
         );
         m
     };
+    static ref VALID_EXTENSION_VERSION: Regex =
+        Regex::new(r"^[a-zA-Z0-9][a-zA-Z0-9._+-]*$").unwrap();
 }
 
 /// TrunkInstall allows installation of extensions from the [pgtrunk](https://pgt.dev)
@@ -145,10 +148,22 @@ pub struct ExtensionInstallLocation {
     pub database: String,
 
     /// The extension version to install. If not specified, the latest version will be used.
+    /// Changing it on an enabled extension updates the extension to the requested version.
     pub version: Option<String>,
 
     /// The schema to enable the extension on. (eg: "public")
     pub schema: Option<String>,
+
+    /// Allow updating the extension to a version lower than the one currently enabled.
+    /// Downgrades are refused otherwise, since most extensions do not ship downgrade scripts.
+    ///
+    /// **Default**: false
+    #[serde(
+        default,
+        rename = "allowDowngrade",
+        skip_serializing_if = "std::ops::Not::not"
+    )]
+    pub allow_downgrade: bool,
 }
 
 impl Default for ExtensionInstallLocation {
@@ -158,6 +173,7 @@ impl Default for ExtensionInstallLocation {
             database: "postgres".to_string(),
             version: None,
             schema: None,
+            allow_downgrade: false,
         }
     }
 }
@@ -169,12 +185,14 @@ impl From<ExtensionInstallLocationStatus> for ExtensionInstallLocation {
             database: status.database,
             schema: status.schema,
             version: status.version,
+            allow_downgrade: false,
         }
     }
 }
 
 /// generates the CREATE or DROP EXTENSION command for a given extension
 /// handles schema specification in the command
+#[allow(clippy::unnecessary_unwrap)]
 pub fn generate_extension_enable_cmd(
     ext_name: &str,
     ext_loc: &ExtensionInstallLocation,
@@ -210,6 +228,21 @@ pub fn generate_extension_enable_cmd(
     Ok(command)
 }
 
+/// generates the ALTER EXTENSION command updating an extension to a given version
+pub fn generate_extension_update_cmd(ext_name: &str, version: &str) -> Result<String, String> {
+    if !VALID_EXTENSION_VERSION.is_match(version) {
+        warn!(
+            "Extension version is not formatted properly. Skipping operation. {}",
+            version
+        );
+        return Err("Extension version is not formatted properly".to_string());
+    }
+    Ok(format!(
+        "ALTER EXTENSION \"{}\" UPDATE TO '{}';",
+        ext_name, version
+    ))
+}
+
 #[derive(Clone, Debug, Deserialize, Eq, Hash, JsonSchema, Serialize, PartialEq, ToSchema)]
 pub struct ExtensionStatus {
     pub name: String,
@@ -229,6 +262,13 @@ pub struct ExtensionInstallLocationStatus {
     // Optional to handle upgrading existing resources
     pub error: Option<bool>,
     pub error_message: Option<String>,
+    // The version the extension was updated from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub previous_version: Option<String>,
+    // The version requested in the spec when the location was last updated. The extension
+    // is only updated when the requested version changes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub requested_version: Option<String>,
 }
 
 pub fn get_location_status(
@@ -300,6 +340,8 @@ mod tests {
             enabled: Some(true),
             error: Some(false),
             error_message: None,
+            previous_version: None,
+            requested_version: None,
         };
         let cdb = CoreDB {
             metadata: Default::default(),
@@ -329,6 +371,7 @@ mod tests {
             schema: None,
             database: location_database.to_owned(),
             version: Some("1.9".to_owned()),
+            allow_downgrade: false,
         };
         let cdb = CoreDB {
             metadata: Default::default(),
@@ -357,6 +400,7 @@ mod tests {
             schema: None,
             enabled: true,
             version: Some("1.0.0".to_string()),
+            allow_downgrade: false,
         };
         let cmd = generate_extension_enable_cmd("my_ext", &loc1);
         assert_eq!(
@@ -370,6 +414,7 @@ mod tests {
             schema: None,
             enabled: true,
             version: Some("1.0.0".to_string()),
+            allow_downgrade: false,
         };
         let cmd = generate_extension_enable_cmd("my_ext", &loc2);
         assert_eq!(
@@ -383,11 +428,21 @@ mod tests {
             schema: None,
             enabled: false,
             version: Some("1.0.0".to_string()),
+            allow_downgrade: false,
         };
         let cmd = generate_extension_enable_cmd("my_ext", &loc2);
         assert_eq!(cmd.unwrap(), "DROP EXTENSION IF EXISTS \"my_ext\" CASCADE;");
     }
 
+    #[test]
+    fn test_generate_extension_update_cmd() {
+        assert_eq!(
+            generate_extension_update_cmd("my_ext", "1.2.0").unwrap(),
+            "ALTER EXTENSION \"my_ext\" UPDATE TO '1.2.0';"
+        );
+        assert!(generate_extension_update_cmd("my_ext", "1.0'; DROP TABLE x; --").is_err());
+    }
+
     #[test]
     fn test_toggle_logic() {
         let desired_extensions = vec![
@@ -399,6 +454,7 @@ mod tests {
                     schema: None,
                     database: "db1".to_string(),
                     version: None,
+                    allow_downgrade: false,
                 }],
             },
             Extension {
@@ -411,6 +467,7 @@ mod tests {
                         schema: None,
                         database: "db_where_its_available_and_disabled".to_string(),
                         version: None,
+                        allow_downgrade: false,
                     },
                     // Requesting to disable a currently enabled extension
                     ExtensionInstallLocation {
@@ -418,6 +475,7 @@ mod tests {
                         schema: None,
                         database: "db_where_its_available_and_enabled".to_string(),
                         version: None,
+                        allow_downgrade: false,
                     },
                     // Requesting to enable a currently disabled extension that is not currently in status
                     ExtensionInstallLocation {
@@ -425,6 +483,7 @@ mod tests {
                         schema: None,
                         database: "db_where_its_available_and_disabled_missing_from_status".to_string(),
                         version: None,
+                        allow_downgrade: false,
                     },
                     // Requesting to disable a currently enabled extension that is not currently in status
                     ExtensionInstallLocation {
@@ -432,6 +491,7 @@ mod tests {
                         schema: None,
                         database: "db_where_its_available_and_enabled_missing_from_status".to_string(),
                         version: None,
+                        allow_downgrade: false,
                     },
                     // This situation is if we toggled an extension to True, but it failed to enable
                     // And now we toggle it back to false
@@ -440,6 +500,7 @@ mod tests {
                         schema: None,
                         database: "db_where_it_is_currently_in_error_having_tried_to_enable_and_failed".to_string(),
                         version: None,
+                        allow_downgrade: false,
                     },
                     // This situation is if we toggled an extension to True, but it failed to enable
                     // because it wasn't installed, now we toggle it back to false
@@ -448,6 +509,7 @@ mod tests {
                         schema: None,
                         database: "db_where_it_is_currently_in_error_having_tried_to_enable_and_failed_because_missing".to_string(),
                         version: None,
+                        allow_downgrade: false,
                     },
                     // Requesting to enable an extension that is not installed
                     ExtensionInstallLocation {
@@ -455,6 +517,7 @@ mod tests {
                         schema: None,
                         database: "db_where_its_not_available".to_string(),
                         version: None,
+                        allow_downgrade: false,
                     },
                     // Requesting to enable an extension that previously failed to enable
                     ExtensionInstallLocation {
@@ -462,6 +525,7 @@ mod tests {
                         schema: None,
                         database: "db_where_enable_failed".to_string(),
                         version: None,
+                        allow_downgrade: false,
                     }
                 ],
             },
@@ -473,6 +537,7 @@ mod tests {
                     schema: None,
                     database: "db1".to_string(),
                     version: None,
+                    allow_downgrade: false,
                 }],
             },
         ];
@@ -489,6 +554,8 @@ mod tests {
                     version: None,
                     error: Some(false),
                     error_message: None,
+                    previous_version: None,
+                    requested_version: None,
                 },
                 // Requesting to disable a currently enabled extension
                 ExtensionInstallLocationStatus {
@@ -498,6 +565,8 @@ mod tests {
                     version: None,
                     error: Some(false),
                     error_message: None,
+                    previous_version: None,
+                    requested_version: None,
                 },
                 ExtensionInstallLocationStatus {
                     enabled: Some(false),
@@ -507,6 +576,8 @@ mod tests {
                     version: None,
                     error: Some(true),
                     error_message: Some("Failed to enable extension".to_string()),
+                    previous_version: None,
+                    requested_version: None,
                 },
                 ExtensionInstallLocationStatus {
                     enabled: None,
@@ -517,6 +588,8 @@ mod tests {
                     version: None,
                     error: Some(true),
                     error_message: Some("Extension is not installed".to_string()),
+                    previous_version: None,
+                    requested_version: None,
                 },
                 ExtensionInstallLocationStatus {
                     enabled: Some(false),
@@ -525,6 +598,8 @@ mod tests {
                     version: None,
                     error: Some(true),
                     error_message: Some("Failed to enable extension".to_string()),
+                    previous_version: None,
+                    requested_version: None,
                 },
             ],
         }];
@@ -553,6 +628,8 @@ mod tests {
                         schema: Some("public".to_string()),
                         version: None,
                         error_message: None,
+                        previous_version: None,
+                        requested_version: None,
                     },
                     ExtensionInstallLocationStatus {
                         enabled: Some(true),
@@ -561,6 +638,8 @@ mod tests {
                         schema: Some("public".to_string()),
                         version: None,
                         error_message: None,
+                        previous_version: None,
+                        requested_version: None,
                     },
                     ExtensionInstallLocationStatus {
                         enabled: Some(false),
@@ -570,6 +649,8 @@ mod tests {
                         schema: Some("public".to_string()),
                         version: None,
                         error_message: None,
+                        previous_version: None,
+                        requested_version: None,
                     },
                     ExtensionInstallLocationStatus {
                         enabled: Some(true),
@@ -579,6 +660,8 @@ mod tests {
                         schema: Some("public".to_string()),
                         version: None,
                         error_message: None,
+                        previous_version: None,
+                        requested_version: None,
                     },
                     ExtensionInstallLocationStatus {
                         enabled: Some(false),
@@ -589,6 +672,8 @@ mod tests {
                         schema: Some("public".to_string()),
                         version: None,
                         error_message: None,
+                        previous_version: None,
+                        requested_version: None,
                     },
                     ExtensionInstallLocationStatus {
                         enabled: Some(false),
@@ -597,6 +682,8 @@ mod tests {
                         schema: Some("public".to_string()),
                         version: None,
                         error_message: None,
+                        previous_version: None,
+                        requested_version: None,
                     },
                 ],
             },
@@ -611,6 +698,8 @@ mod tests {
                         schema: Some("public".to_string()),
                         version: None,
                         error_message: None,
+                        previous_version: None,
+                        requested_version: None,
                     },
                     ExtensionInstallLocationStatus {
                         enabled: Some(true),
@@ -619,6 +708,8 @@ mod tests {
                         schema: Some("public".to_string()),
                         version: None,
                         error_message: None,
+                        previous_version: None,
+                        requested_version: None,
                     },
                 ],
             },
@@ -768,6 +859,8 @@ mod tests {
                 version: None,
                 error: Some(false),
                 error_message: None,
+                previous_version: None,
+                requested_version: None,
             }],
         }];
         let new_location_status = ExtensionInstallLocationStatus {
@@ -777,6 +870,8 @@ mod tests {
             version: None,
             error: Some(false),
             error_message: None,
+            previous_version: None,
+            requested_version: None,
         };
 
         // Try updating existing from disabled to enabled
@@ -803,6 +898,8 @@ mod tests {
                 version: None,
                 error: Some(false),
                 error_message: None,
+                previous_version: None,
+                requested_version: None,
             }],
         }];
         let new_location_status = ExtensionInstallLocationStatus {
@@ -812,6 +909,8 @@ mod tests {
             version: None,
             error: Some(false),
             error_message: None,
+            previous_version: None,
+            requested_version: None,
         };
 
         let result = merge_location_status_into_extension_status_list(
@@ -842,6 +941,8 @@ mod tests {
                 version: None,
                 error: Some(false),
                 error_message: None,
+                previous_version: None,
+                requested_version: None,
             }],
         }];
         let new_location_status = ExtensionInstallLocationStatus {
@@ -851,6 +952,8 @@ mod tests {
             version: None,
             error: Some(false),
             error_message: None,
+            previous_version: None,
+            requested_version: None,
         };
 
         let result = merge_location_status_into_extension_status_list(
@@ -883,6 +986,8 @@ mod tests {
                 enabled: Some(true),
                 error: Some(false),
                 error_message: None,
+                previous_version: None,
+                requested_version: None,
             }],
         };
         let extension: Extension = status.into();
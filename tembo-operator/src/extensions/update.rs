@@ -0,0 +1,340 @@
+use crate::{
+    apis::coredb_types::CoreDB,
+    extensions::types::{get_location_status, ExtensionInstallLocation, TrunkInstall},
+    trunk::{
+        convert_to_semver, get_trunk_project_for_extension, get_trunk_project_metadata_for_version,
+        Version,
+    },
+};
+use kube::runtime::controller::Action;
+use std::cmp::Ordering;
+use tracing::{debug, warn};
+
+// Prefix of the error messages set on extension locations whose version could not be updated.
+// These errors are kept until the requested version changes, so a failed update is not retried
+// on every reconcile.
+pub const VERSION_UPDATE_ERROR_PREFIX: &str = "Version update error: ";
+
+/// An enabled extension location whose requested version differs from the enabled version
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExtensionUpdate {
+    pub extension: String,
+    pub location: ExtensionInstallLocation,
+    pub current_version: String,
+    pub desired_version: String,
+}
+
+impl ExtensionUpdate {
+    /// Downgrades are refused unless explicitly allowed on the location. Versions that can not
+    /// be compared are left to Postgres, which fails the update if there is no update path.
+    pub fn is_refused_downgrade(&self) -> bool {
+        !self.location.allow_downgrade
+            && compare_extension_versions(&self.current_version, &self.desired_version)
+                == Some(Ordering::Greater)
+    }
+
+    pub fn refused_downgrade_message(&self) -> String {
+        format!(
+            "{VERSION_UPDATE_ERROR_PREFIX}refusing to downgrade to {} from {}, set allowDowngrade to force it",
+            self.desired_version, self.current_version
+        )
+    }
+
+    pub fn failed_update_message(&self, error: &str) -> String {
+        format!(
+            "{VERSION_UPDATE_ERROR_PREFIX}failed to update to {}: {}",
+            self.desired_version, error
+        )
+    }
+}
+
+/// Find the enabled extension locations that request a different version than the one enabled
+/// in the database, according to the status. Only a change of the requested version triggers an
+/// update, so a version changed in the database directly is not reverted on every reconcile.
+pub fn determine_extension_locations_to_update(cdb: &CoreDB) -> Vec<ExtensionUpdate> {
+    let mut updates = vec![];
+    for extension in &cdb.spec.extensions {
+        for location in extension.locations.iter().filter(|l| l.enabled) {
+            let Some(desired_version) = &location.version else {
+                continue;
+            };
+            let Some(status) = get_location_status(cdb, &extension.name, &location.database) else {
+                continue;
+            };
+            match (status.enabled, status.version) {
+                (Some(true), Some(current_version))
+                    if &current_version != desired_version
+                        && status
+                            .requested_version
+                            .as_ref()
+                            .is_some_and(|requested| requested != desired_version) =>
+                {
+                    updates.push(ExtensionUpdate {
+                        extension: extension.name.clone(),
+                        location: location.clone(),
+                        current_version,
+                        desired_version: desired_version.clone(),
+                    });
+                }
+                _ => {}
+            }
+        }
+    }
+    updates
+}
+
+/// Whether the error of a location belongs to a version update that is still pending for the
+/// requested version. Changing the requested version, or allowing a refused downgrade, clears it.
+pub fn is_pending_version_update_error(
+    error_message: Option<&str>,
+    desired_location: &ExtensionInstallLocation,
+    actual_version: Option<&str>,
+) -> bool {
+    let (Some(error_message), Some(desired_version)) =
+        (error_message, desired_location.version.as_deref())
+    else {
+        return false;
+    };
+    if actual_version == Some(desired_version) {
+        return false;
+    }
+    error_message.starts_with(&format!(
+        "{VERSION_UPDATE_ERROR_PREFIX}failed to update to {desired_version}: "
+    )) || (!desired_location.allow_downgrade
+        && error_message.starts_with(&format!(
+            "{VERSION_UPDATE_ERROR_PREFIX}refusing to downgrade to {desired_version} "
+        )))
+}
+
+/// Compare two extension versions, if both of them can be read as semantic versions
+pub fn compare_extension_versions(a: &str, b: &str) -> Option<Ordering> {
+    let a = semver::Version::parse(&convert_to_semver(a)).ok()?;
+    let b = semver::Version::parse(&convert_to_semver(b)).ok()?;
+    Some(a.cmp(&b))
+}
+
+/// Pin the Trunk installs of the extensions requesting a version to the Trunk project version
+/// providing it, so the package is installed to all pods before enabling the extension or running
+/// ALTER EXTENSION ... UPDATE. Refused downgrades keep the package of the enabled version.
+pub async fn pin_trunk_installs_to_extension_versions(cdb: &CoreDB) -> Result<CoreDB, Action> {
+    let refused: Vec<(String, String)> = determine_extension_locations_to_update(cdb)
+        .into_iter()
+        .filter(|update| update.is_refused_downgrade())
+        .map(|update| (update.extension, update.location.database))
+        .collect();
+    let mut pinned = cdb.clone();
+    for extension in &cdb.spec.extensions {
+        for location in extension.locations.iter().filter(|l| l.enabled) {
+            let Some(version) = &location.version else {
+                continue;
+            };
+            if refused.contains(&(extension.name.clone(), location.database.clone())) {
+                continue;
+            }
+            let Some(trunk_project) =
+                get_trunk_project_for_extension(extension.name.clone()).await?
+            else {
+                continue;
+            };
+            match get_trunk_project_metadata_for_version(
+                &trunk_project,
+                Version::Extension(version),
+            )
+            .await
+            {
+                Ok(metadata) => pin_trunk_install(&mut pinned, &trunk_project, &metadata.version),
+                Err(e) => {
+                    warn!(
+                        "Could not find a Trunk project version of {} providing {} {}: {}",
+                        trunk_project, extension.name, version, e
+                    );
+                }
+            }
+        }
+    }
+    Ok(pinned)
+}
+
+fn pin_trunk_install(cdb: &mut CoreDB, trunk_project: &str, version: &str) {
+    debug!("Pinning Trunk install {} to {}", trunk_project, version);
+    match cdb
+        .spec
+        .trunk_installs
+        .iter_mut()
+        .find(|install| install.name == trunk_project)
+    {
+        Some(install) => install.version = Some(version.to_string()),
+        None => cdb.spec.trunk_installs.push(TrunkInstall {
+            name: trunk_project.to_string(),
+            version: Some(version.to_string()),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        apis::coredb_types::{CoreDBSpec, CoreDBStatus},
+        extensions::types::{Extension, ExtensionInstallLocationStatus, ExtensionStatus},
+    };
+
+    fn coredb(desired_version: &str, allow_downgrade: bool, current_version: &str) -> CoreDB {
+        let mut cdb = CoreDB::new(
+            "test",
+            CoreDBSpec {
+                extensions: vec![Extension {
+                    name: "pgmq".to_string(),
+                    description: None,
+                    locations: vec![ExtensionInstallLocation {
+                        enabled: true,
+                        version: Some(desired_version.to_string()),
+                        allow_downgrade,
+                        ..ExtensionInstallLocation::default()
+                    }],
+                }],
+                ..CoreDBSpec::default()
+            },
+        );
+        cdb.status = Some(CoreDBStatus {
+            extensions: Some(vec![ExtensionStatus {
+                name: "pgmq".to_string(),
+                description: None,
+                locations: vec![ExtensionInstallLocationStatus {
+                    database: "postgres".to_string(),
+                    schema: Some("public".to_string()),
+                    version: Some(current_version.to_string()),
+                    enabled: Some(true),
+                    error: Some(false),
+                    error_message: None,
+                    previous_version: None,
+                    requested_version: Some(current_version.to_string()),
+                }],
+            }]),
+            ..CoreDBStatus::default()
+        });
+        cdb
+    }
+
+    #[test]
+    fn test_determine_extension_locations_to_update() {
+        assert!(
+            determine_extension_locations_to_update(&coredb("1.1.0", false, "1.1.0")).is_empty()
+        );
+
+        let updates = determine_extension_locations_to_update(&coredb("1.2.0", false, "1.1.0"));
+        assert_eq!(updates.len(), 1);
+        assert_eq!(updates[0].current_version, "1.1.0");
+        assert_eq!(updates[0].desired_version, "1.2.0");
+        assert!(!updates[0].is_refused_downgrade());
+
+        let updates = determine_extension_locations_to_update(&coredb("1.0", false, "1.1.0"));
+        assert!(updates[0].is_refused_downgrade());
+        assert_eq!(
+            updates[0].refused_downgrade_message(),
+            "Version update error: refusing to downgrade to 1.0 from 1.1.0, set allowDowngrade to force it"
+        );
+
+        let updates = determine_extension_locations_to_update(&coredb("1.0", true, "1.1.0"));
+        assert!(!updates[0].is_refused_downgrade());
+
+        // The version is not updated again until another version is requested
+        let mut cdb = coredb("1.2.0", false, "1.1.0");
+        let location =
+            &mut cdb.status.as_mut().unwrap().extensions.as_mut().unwrap()[0].locations[0];
+        location.requested_version = Some("1.2.0".to_string());
+        assert!(determine_extension_locations_to_update(&cdb).is_empty());
+        // Nor when the status does not track the requested version yet
+        let location =
+            &mut cdb.status.as_mut().unwrap().extensions.as_mut().unwrap()[0].locations[0];
+        location.requested_version = None;
+        assert!(determine_extension_locations_to_update(&cdb).is_empty());
+    }
+
+    #[test]
+    fn test_is_pending_version_update_error() {
+        let update = &determine_extension_locations_to_update(&coredb("1.0", false, "1.1.0"))[0];
+        let refused = update.refused_downgrade_message();
+        let failed = update.failed_update_message("no update path");
+
+        for message in [&refused, &failed] {
+            assert!(is_pending_version_update_error(
+                Some(message),
+                &update.location,
+                Some("1.1.0")
+            ));
+        }
+        // Requesting another version retries the update
+        let other_version = ExtensionInstallLocation {
+            version: Some("1.2.0".to_string()),
+            ..update.location.clone()
+        };
+        assert!(!is_pending_version_update_error(
+            Some(&failed),
+            &other_version,
+            Some("1.1.0")
+        ));
+        // Allowing the downgrade retries it
+        let allowed = ExtensionInstallLocation {
+            allow_downgrade: true,
+            ..update.location.clone()
+        };
+        assert!(!is_pending_version_update_error(
+            Some(&refused),
+            &allowed,
+            Some("1.1.0")
+        ));
+        assert!(is_pending_version_update_error(
+            Some(&failed),
+            &allowed,
+            Some("1.1.0")
+        ));
+        // Other errors are not kept
+        assert!(!is_pending_version_update_error(
+            Some("Extension is not installed"),
+            &update.location,
+            Some("1.1.0")
+        ));
+    }
+
+    #[test]
+    fn test_compare_extension_versions() {
+        assert_eq!(
+            compare_extension_versions("1.6", "1.10"),
+            Some(Ordering::Less)
+        );
+        assert_eq!(
+            compare_extension_versions("0.10.0", "0.9.1"),
+            Some(Ordering::Greater)
+        );
+        assert_eq!(
+            compare_extension_versions("1.0", "1.0.0"),
+            Some(Ordering::Equal)
+        );
+        assert_eq!(compare_extension_versions("unknown", "1.0.0"), None);
+    }
+
+    #[test]
+    fn test_pin_trunk_install() {
+        let mut cdb = coredb("1.2.0", false, "1.1.0");
+        cdb.spec.trunk_installs = vec![TrunkInstall {
+            name: "pgmq".to_string(),
+            version: Some("1.1.0".to_string()),
+        }];
+        pin_trunk_install(&mut cdb, "pgmq", "1.2.0");
+        pin_trunk_install(&mut cdb, "pg_partman", "4.7.3");
+        assert_eq!(
+            cdb.spec.trunk_installs,
+            vec![
+                TrunkInstall {
+                    name: "pgmq".to_string(),
+                    version: Some("1.2.0".to_string()),
+                },
+                TrunkInstall {
+                    name: "pg_partman".to_string(),
+                    version: Some("4.7.3".to_string()),
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,274 @@
+// Subset of the Gateway API HTTPRoute CRD, in the layout generated by kopium
+// kopium command: kopium -A --derive Default httproutes.gateway.networking.k8s.io
+// Only the fields used by the operator are kept.
+
+#[allow(unused_imports)]
+mod prelude {
+    pub use kube::CustomResource;
+    pub use schemars::JsonSchema;
+    pub use serde::{Deserialize, Serialize};
+}
+use self::prelude::*;
+
+/// Spec defines the desired state of HTTPRoute.
+#[derive(CustomResource, Serialize, Deserialize, Clone, Debug, Default, JsonSchema)]
+#[kube(
+    group = "gateway.networking.k8s.io",
+    version = "v1",
+    kind = "HTTPRoute",
+    plural = "httproutes"
+)]
+#[kube(namespaced)]
+#[kube(derive = "Default")]
+pub struct HTTPRouteSpec {
+    /// Hostnames defines a set of hostnames that should match against the HTTP Host
+    /// header to select a HTTPRoute used to process the request.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hostnames: Option<Vec<String>>,
+    /// ParentRefs references the resources (usually Gateways) that a Route wants
+    /// to be attached to.
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        rename = "parentRefs"
+    )]
+    pub parent_refs: Option<Vec<HTTPRouteParentRefs>>,
+    /// Rules are a list of HTTP matchers, filters and actions.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rules: Option<Vec<HTTPRouteRules>>,
+}
+
+/// ParentReference identifies an API object (usually a Gateway) that can be considered
+/// a parent of this resource (usually a route).
+#[derive(Serialize, Deserialize, Clone, Debug, Default, JsonSchema)]
+pub struct HTTPRouteParentRefs {
+    /// Group is the group of the referent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+    /// Kind is kind of the referent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kind: Option<String>,
+    /// Name is the name of the referent.
+    pub name: String,
+    /// Namespace is the namespace of the referent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+    /// Port is the network port this Route targets.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub port: Option<i32>,
+    /// SectionName is the name of a section within the target resource, the
+    /// listener name for Gateways.
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        rename = "sectionName"
+    )]
+    pub section_name: Option<String>,
+}
+
+/// HTTPRouteRule defines semantics for matching an HTTP request based on
+/// conditions (matches), processing it (filters), and forwarding the request to
+/// an API object (backendRefs).
+#[derive(Serialize, Deserialize, Clone, Debug, Default, JsonSchema)]
+pub struct HTTPRouteRules {
+    /// BackendRefs defines the backend(s) where matching requests should be sent.
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        rename = "backendRefs"
+    )]
+    pub backend_refs: Option<Vec<HTTPRouteRulesBackendRefs>>,
+    /// Filters define the filters that are applied to requests that match this rule.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filters: Option<Vec<HTTPRouteRulesFilters>>,
+    /// Matches define conditions used for matching the rule against incoming HTTP
+    /// requests.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub matches: Option<Vec<HTTPRouteRulesMatches>>,
+    /// Name is the name of the route rule.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+}
+
+/// HTTPBackendRef defines how a HTTPRoute forwards a HTTP request.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, JsonSchema)]
+pub struct HTTPRouteRulesBackendRefs {
+    /// Group is the group of the referent. Defaults to the core API group.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+    /// Kind is the Kubernetes resource kind of the referent. Defaults to "Service".
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kind: Option<String>,
+    /// Name is the name of the referent.
+    pub name: String,
+    /// Namespace is the namespace of the backend.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+    /// Port specifies the destination port number to use for this resource.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub port: Option<i32>,
+    /// Weight specifies the proportion of requests forwarded to the referenced backend.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub weight: Option<i32>,
+}
+
+/// HTTPRouteFilter defines processing steps that must be completed during the
+/// request or response lifecycle.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, JsonSchema)]
+pub struct HTTPRouteRulesFilters {
+    /// ExtensionRef is an optional, implementation-specific extension to the "filter"
+    /// behavior.
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        rename = "extensionRef"
+    )]
+    pub extension_ref: Option<HTTPRouteRulesFiltersExtensionRef>,
+    /// RequestHeaderModifier defines a schema for a filter that modifies request
+    /// headers.
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        rename = "requestHeaderModifier"
+    )]
+    pub request_header_modifier: Option<HTTPRouteRulesFiltersRequestHeaderModifier>,
+    /// Type identifies the type of filter to apply.
+    #[serde(rename = "type")]
+    pub r#type: HTTPRouteRulesFiltersType,
+    /// URLRewrite defines a schema for a filter that modifies a request during forwarding.
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        rename = "urlRewrite"
+    )]
+    pub url_rewrite: Option<HTTPRouteRulesFiltersUrlRewrite>,
+}
+
+/// ExtensionRef is an optional, implementation-specific extension to the "filter"
+/// behavior.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, JsonSchema)]
+pub struct HTTPRouteRulesFiltersExtensionRef {
+    /// Group is the group of the referent.
+    pub group: String,
+    /// Kind is kind of the referent.
+    pub kind: String,
+    /// Name is the name of the referent.
+    pub name: String,
+}
+
+/// RequestHeaderModifier defines a schema for a filter that modifies request headers.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, JsonSchema)]
+pub struct HTTPRouteRulesFiltersRequestHeaderModifier {
+    /// Add adds the given header(s) (name, value) to the request before the action.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub add: Option<Vec<HTTPRouteRulesFiltersRequestHeaderModifierAdd>>,
+    /// Remove the given header(s) from the HTTP request before the action.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub remove: Option<Vec<String>>,
+    /// Set overwrites the request with the given header (name, value) before the action.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub set: Option<Vec<HTTPRouteRulesFiltersRequestHeaderModifierSet>>,
+}
+
+/// HTTPHeader represents an HTTP Header name and value as defined by RFC 7230.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, JsonSchema)]
+pub struct HTTPRouteRulesFiltersRequestHeaderModifierAdd {
+    /// Name is the name of the HTTP Header to be matched.
+    pub name: String,
+    /// Value is the value of HTTP Header to be matched.
+    pub value: String,
+}
+
+/// HTTPHeader represents an HTTP Header name and value as defined by RFC 7230.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, JsonSchema)]
+pub struct HTTPRouteRulesFiltersRequestHeaderModifierSet {
+    /// Name is the name of the HTTP Header to be matched.
+    pub name: String,
+    /// Value is the value of HTTP Header to be matched.
+    pub value: String,
+}
+
+/// HTTPRouteFilter defines processing steps that must be completed during the
+/// request or response lifecycle.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, JsonSchema, PartialEq)]
+pub enum HTTPRouteRulesFiltersType {
+    #[default]
+    RequestHeaderModifier,
+    ResponseHeaderModifier,
+    RequestMirror,
+    RequestRedirect,
+    #[serde(rename = "URLRewrite")]
+    UrlRewrite,
+    ExtensionRef,
+}
+
+/// URLRewrite defines a schema for a filter that modifies a request during forwarding.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, JsonSchema)]
+pub struct HTTPRouteRulesFiltersUrlRewrite {
+    /// Hostname is the value to be used to replace the Host header value during
+    /// forwarding.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hostname: Option<String>,
+    /// Path defines a path rewrite.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<HTTPRouteRulesFiltersUrlRewritePath>,
+}
+
+/// Path defines a path rewrite.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, JsonSchema)]
+pub struct HTTPRouteRulesFiltersUrlRewritePath {
+    /// ReplaceFullPath specifies the value with which to replace the full path
+    /// of a request during a rewrite or redirect.
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        rename = "replaceFullPath"
+    )]
+    pub replace_full_path: Option<String>,
+    /// ReplacePrefixMatch specifies the value with which to replace the prefix
+    /// match of a request during a rewrite or redirect.
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        rename = "replacePrefixMatch"
+    )]
+    pub replace_prefix_match: Option<String>,
+    /// Type defines the type of path modifier.
+    #[serde(rename = "type")]
+    pub r#type: HTTPRouteRulesFiltersUrlRewritePathType,
+}
+
+/// Path defines a path rewrite.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, JsonSchema, PartialEq)]
+pub enum HTTPRouteRulesFiltersUrlRewritePathType {
+    ReplaceFullPath,
+    #[default]
+    ReplacePrefixMatch,
+}
+
+/// HTTPRouteMatch defines the predicate used to match requests to a given action.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, JsonSchema)]
+pub struct HTTPRouteRulesMatches {
+    /// Path specifies a HTTP request path matcher.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<HTTPRouteRulesMatchesPath>,
+}
+
+/// Path specifies a HTTP request path matcher.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, JsonSchema)]
+pub struct HTTPRouteRulesMatchesPath {
+    /// Type specifies how to match against the path Value.
+    #[serde(default, skip_serializing_if = "Option::is_none", rename = "type")]
+    pub r#type: Option<HTTPRouteRulesMatchesPathType>,
+    /// Value of the HTTP path to match against.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value: Option<String>,
+}
+
+/// Path specifies a HTTP request path matcher.
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema, PartialEq)]
+pub enum HTTPRouteRulesMatchesPathType {
+    Exact,
+    PathPrefix,
+    RegularExpression,
+}
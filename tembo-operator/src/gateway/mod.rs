@@ -0,0 +1,814 @@
+// Gateway API implementation of instance and app service exposure, selected with
+// INGRESS_PROVIDER=gateway-api. Every route attaches to the shared Gateway named by
+// GATEWAY_NAME/GATEWAY_NAMESPACE, using the Traefik entry point names as listener
+// (section) names so the same app service routing works for both providers.
+//
+// Postgres and TCP app services are exposed with TLS passthrough routed on SNI, which
+// is what TLSRoute provides; plain TCPRoutes cannot share a listener between instances.
+pub mod http_route_crd;
+pub mod tls_route_crd;
+
+use crate::{
+    apis::coredb_types::CoreDB,
+    app_service::types::{AppService, IngressType, Middleware, COMPONENT_NAME},
+    config::Config,
+    errors::OperatorError,
+    Context,
+};
+use http_route_crd::{
+    HTTPRoute, HTTPRouteParentRefs, HTTPRouteRules, HTTPRouteRulesBackendRefs,
+    HTTPRouteRulesFilters, HTTPRouteRulesFiltersExtensionRef,
+    HTTPRouteRulesFiltersRequestHeaderModifier, HTTPRouteRulesFiltersRequestHeaderModifierSet,
+    HTTPRouteRulesFiltersType, HTTPRouteRulesFiltersUrlRewrite,
+    HTTPRouteRulesFiltersUrlRewritePath, HTTPRouteRulesFiltersUrlRewritePathType,
+    HTTPRouteRulesMatches, HTTPRouteRulesMatchesPath, HTTPRouteRulesMatchesPathType, HTTPRouteSpec,
+};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::{ObjectMeta, OwnerReference};
+use kube::{
+    api::{DeleteParams, ListParams, Patch, PatchParams},
+    Api, Client, Resource, ResourceExt,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use std::{collections::BTreeMap, fmt::Debug, sync::Arc};
+use tls_route_crd::{
+    TLSRoute, TLSRouteParentRefs, TLSRouteRules, TLSRouteRulesBackendRefs, TLSRouteSpec,
+};
+use tracing::{debug, error, warn};
+
+/// The Gateway listener that Postgres TLSRoutes attach to
+pub const POSTGRES_LISTENER: &str = "postgresql";
+
+const POSTGRES_COMPONENT: &str = "postgres";
+
+fn route_labels(coredb_name: &str, component: &str) -> BTreeMap<String, String> {
+    BTreeMap::from([
+        ("component".to_owned(), component.to_owned()),
+        ("coredb.io/name".to_owned(), coredb_name.to_owned()),
+    ])
+}
+
+fn tls_parent_refs(cfg: &Config, listeners: &[String]) -> Vec<TLSRouteParentRefs> {
+    listeners
+        .iter()
+        .map(|listener| TLSRouteParentRefs {
+            name: cfg.gateway_name.clone(),
+            namespace: Some(cfg.gateway_namespace.clone()),
+            section_name: Some(listener.clone()),
+            ..TLSRouteParentRefs::default()
+        })
+        .collect()
+}
+
+fn generate_tls_route(
+    cfg: &Config,
+    name: &str,
+    namespace: &str,
+    oref: OwnerReference,
+    labels: BTreeMap<String, String>,
+    hostnames: Vec<String>,
+    listeners: &[String],
+    service_name: &str,
+    port: i32,
+) -> TLSRoute {
+    TLSRoute {
+        metadata: ObjectMeta {
+            name: Some(name.to_owned()),
+            namespace: Some(namespace.to_owned()),
+            owner_references: Some(vec![oref]),
+            labels: Some(labels),
+            ..ObjectMeta::default()
+        },
+        spec: TLSRouteSpec {
+            hostnames: Some(hostnames),
+            parent_refs: Some(tls_parent_refs(cfg, listeners)),
+            rules: vec![TLSRouteRules {
+                backend_refs: Some(vec![TLSRouteRulesBackendRefs {
+                    name: service_name.to_owned(),
+                    port: Some(port),
+                    ..TLSRouteRulesBackendRefs::default()
+                }]),
+                name: None,
+            }],
+        },
+    }
+}
+
+// generate_postgres_tls_routes returns the TLSRoutes for the read-write, read-only
+// and pooler services of an instance. The extra domains are served by the
+// read-write route.
+pub(crate) fn generate_postgres_tls_routes(
+    cdb: &CoreDB,
+    cfg: &Config,
+    basedomain: &str,
+) -> Vec<TLSRoute> {
+    let name = cdb.name_any();
+    let namespace = cdb.namespace().unwrap_or_default();
+    let oref = cdb.controller_owner_ref(&()).unwrap();
+    let listeners = vec![POSTGRES_LISTENER.to_string()];
+    let labels = route_labels(&name, POSTGRES_COMPONENT);
+
+    let mut rw_hostnames = vec![format!("{name}.{basedomain}")];
+    let mut extra_domains = cdb.spec.extra_domains_rw.clone().unwrap_or_default();
+    // Ensure always same order
+    extra_domains.sort();
+    rw_hostnames.extend(extra_domains);
+
+    let mut services = vec![
+        (format!("{name}-rw"), rw_hostnames),
+        (
+            format!("{name}-ro"),
+            vec![format!("{name}-ro.{basedomain}")],
+        ),
+    ];
+    if cdb.spec.connectionPooler.enabled {
+        services.push((
+            format!("{name}-pooler"),
+            vec![format!("{name}-pooler.{basedomain}")],
+        ));
+    }
+
+    services
+        .into_iter()
+        .map(|(service_name, hostnames)| {
+            generate_tls_route(
+                cfg,
+                &service_name,
+                &namespace,
+                oref.clone(),
+                labels.clone(),
+                hostnames,
+                &listeners,
+                &service_name,
+                5432,
+            )
+        })
+        .collect()
+}
+
+// unenforced_ip_allow_list returns why the Postgres routes of an instance are refused
+// when its IP allow list restricts access. Gateway API has no standard client address
+// filter and TLS passthrough hides the client address from Postgres, so the instance
+// is not exposed at all rather than exposed to every address.
+pub(crate) fn unenforced_ip_allow_list(cdb: &CoreDB) -> Option<String> {
+    let restricted = cdb
+        .spec
+        .ip_allow_list
+        .iter()
+        .flatten()
+        .any(|entry| entry != "0.0.0.0/0");
+    restricted.then(|| {
+        "ipAllowList can not be enforced by the gateway-api ingress provider, Postgres is not exposed"
+            .to_string()
+    })
+}
+
+// reconcile_postgres_tls_routes is the Gateway API counterpart of the Postgres
+// IngressRouteTCPs. Routes that are no longer desired are removed, and all of them
+// are removed when `delete` is set. Returns why the routes were refused, if they were.
+pub async fn reconcile_postgres_tls_routes(
+    cdb: &CoreDB,
+    ctx: Arc<Context>,
+    cfg: &Config,
+    basedomain: &str,
+    delete: bool,
+) -> Result<Option<String>, OperatorError> {
+    let namespace = cdb.namespace().unwrap();
+    let api: Api<TLSRoute> = Api::namespaced(ctx.client.clone(), &namespace);
+    let refused = unenforced_ip_allow_list(cdb).filter(|_| !delete);
+    let desired = if delete || refused.is_some() {
+        vec![]
+    } else {
+        generate_postgres_tls_routes(cdb, cfg, basedomain)
+    };
+    let selector = format!(
+        "component={POSTGRES_COMPONENT},coredb.io/name={}",
+        cdb.name_any()
+    );
+    reconcile_routes(&api, &namespace, &selector, desired).await?;
+    Ok(refused)
+}
+
+// Resolves a Traefik style middleware into the equivalent HTTPRoute filter. Only
+// header changes and stripping the route prefix have a Gateway API equivalent, the
+// other middlewares return why the route can not be created, unless the Gateway is
+// implemented by Traefik, see traefik_middleware_filter.
+fn middleware_filter(path: &str, middleware: &Middleware) -> Result<HTTPRouteRulesFilters, String> {
+    match middleware {
+        Middleware::CustomRequestHeaders(mw) => {
+            let set: Vec<HTTPRouteRulesFiltersRequestHeaderModifierSet> = mw
+                .config
+                .iter()
+                .filter(|(_, value)| !value.is_empty())
+                .map(
+                    |(name, value)| HTTPRouteRulesFiltersRequestHeaderModifierSet {
+                        name: name.clone(),
+                        value: value.clone(),
+                    },
+                )
+                .collect();
+            // An empty value removes the header, same as in Traefik
+            let remove: Vec<String> = mw
+                .config
+                .iter()
+                .filter(|(_, value)| value.is_empty())
+                .map(|(name, _)| name.clone())
+                .collect();
+            Ok(HTTPRouteRulesFilters {
+                extension_ref: None,
+                r#type: HTTPRouteRulesFiltersType::RequestHeaderModifier,
+                request_header_modifier: Some(HTTPRouteRulesFiltersRequestHeaderModifier {
+                    add: None,
+                    remove: (!remove.is_empty()).then_some(remove),
+                    set: (!set.is_empty()).then_some(set),
+                }),
+                url_rewrite: None,
+            })
+        }
+        Middleware::StripPrefix(mw) => {
+            let route_prefix = path.trim_end_matches('/');
+            if !mw
+                .config
+                .iter()
+                .any(|prefix| prefix.trim_end_matches('/') == route_prefix)
+            {
+                return Err(format!(
+                    "middleware {} does not strip the route prefix, which is the only prefix Gateway API can strip",
+                    mw.name
+                ));
+            }
+            Ok(HTTPRouteRulesFilters {
+                extension_ref: None,
+                r#type: HTTPRouteRulesFiltersType::UrlRewrite,
+                request_header_modifier: None,
+                url_rewrite: Some(HTTPRouteRulesFiltersUrlRewrite {
+                    hostname: None,
+                    path: Some(HTTPRouteRulesFiltersUrlRewritePath {
+                        r#type: HTTPRouteRulesFiltersUrlRewritePathType::ReplacePrefixMatch,
+                        replace_prefix_match: Some("/".to_string()),
+                        replace_full_path: None,
+                    }),
+                }),
+            })
+        }
+        Middleware::ReplacePathRegex(mw) => Err(format!(
+            "middleware {} uses replacePathRegex, which Gateway API does not support",
+            mw.name
+        )),
+        // Rate limiting, authentication and allow listing are implementation specific
+        // policies, not HTTPRoute filters. The route is refused rather than exposed
+        // without the access control.
+        Middleware::BasicAuth(_)
+        | Middleware::ForwardAuth(_)
+        | Middleware::IpAllowList(_)
+        | Middleware::RateLimit(_) => Err(format!(
+            "middleware {} controls access, which Gateway API can not enforce",
+            middleware.name()
+        )),
+        // CORS and body limits are not HTTPRoute filters either
+        _ => Err(format!(
+            "middleware {} has no Gateway API equivalent",
+            middleware.name()
+        )),
+    }
+}
+
+// traefik_middleware_filter references the Traefik Middleware generated for a middleware
+// with an ExtensionRef filter, which Traefik applies when it implements the Gateway. The
+// Middlewares are named `<coredb-name>-<middleware-name>` like for the Traefik provider.
+// forwardAuth middlewares with an address outside of the namespace are not generated.
+fn traefik_middleware_filter(
+    coredb_name: &str,
+    namespace: &str,
+    middleware: &Middleware,
+) -> Result<HTTPRouteRulesFilters, String> {
+    if let Middleware::ForwardAuth(mw) = middleware {
+        if !mw.config.is_namespace_address(namespace) {
+            return Err(format!(
+                "middleware {} forwards to an address outside of the namespace",
+                mw.name
+            ));
+        }
+    }
+    Ok(HTTPRouteRulesFilters {
+        extension_ref: Some(HTTPRouteRulesFiltersExtensionRef {
+            group: "traefik.io".to_string(),
+            kind: "Middleware".to_string(),
+            name: format!("{}-{}", coredb_name, middleware.name()),
+        }),
+        r#type: HTTPRouteRulesFiltersType::ExtensionRef,
+        request_header_modifier: None,
+        url_rewrite: None,
+    })
+}
+
+// generate_app_http_route returns one HTTPRoute per instance, named after the
+// instance like the Traefik IngressRoute, holding a rule for every http routing entry
+// of its app services. Routing entries with a middleware that can not be applied are
+// left out instead of being exposed without it, and returned as errors.
+pub(crate) fn generate_app_http_route(
+    coredb_name: &str,
+    namespace: &str,
+    oref: OwnerReference,
+    cfg: &Config,
+    domain: &str,
+    appsvcs: &[AppService],
+) -> (Option<HTTPRoute>, Vec<String>) {
+    let middlewares: Vec<Middleware> = appsvcs
+        .iter()
+        .filter_map(|appsvc| appsvc.middlewares.clone())
+        .flatten()
+        .collect();
+
+    let mut listeners: Vec<String> = vec![];
+    let mut rules: Vec<HTTPRouteRules> = vec![];
+    let mut refused: Vec<String> = vec![];
+    for appsvc in appsvcs {
+        let resource_name = format!("{}-{}", coredb_name, appsvc.name);
+        for route in appsvc.routing.clone().unwrap_or_default() {
+            if route.ingress_type != Some(IngressType::http) {
+                continue;
+            }
+            let Some(path) = route.ingress_path.clone() else {
+                continue;
+            };
+            let filters: Result<Vec<HTTPRouteRulesFilters>, String> = route
+                .middlewares
+                .clone()
+                .unwrap_or_default()
+                .iter()
+                .map(|name| match middlewares.iter().find(|m| m.name() == name) {
+                    Some(middleware) => middleware_filter(&path, middleware).or_else(|e| {
+                        if cfg.gateway_traefik_middlewares {
+                            traefik_middleware_filter(coredb_name, namespace, middleware)
+                        } else {
+                            Err(e)
+                        }
+                    }),
+                    None => Err(format!("middleware {name} is not defined")),
+                })
+                .collect();
+            let filters = match filters {
+                Ok(filters) => filters,
+                Err(e) => {
+                    warn!(
+                        "Not routing {} to {} of {}: {}",
+                        path, appsvc.name, coredb_name, e
+                    );
+                    refused.push(format!("{} route {}: {}", appsvc.name, path, e));
+                    continue;
+                }
+            };
+            for entry_point in route.entry_points.clone().unwrap_or_default() {
+                if !listeners.contains(&entry_point) {
+                    listeners.push(entry_point);
+                }
+            }
+            rules.push(HTTPRouteRules {
+                backend_refs: Some(vec![HTTPRouteRulesBackendRefs {
+                    name: resource_name.clone(),
+                    port: Some(route.port as i32),
+                    ..HTTPRouteRulesBackendRefs::default()
+                }]),
+                filters: (!filters.is_empty()).then_some(filters),
+                matches: Some(vec![HTTPRouteRulesMatches {
+                    path: Some(HTTPRouteRulesMatchesPath {
+                        r#type: Some(HTTPRouteRulesMatchesPathType::PathPrefix),
+                        value: Some(path),
+                    }),
+                }]),
+                name: None,
+            });
+        }
+    }
+
+    if rules.is_empty() {
+        return (None, refused);
+    }
+
+    let route = HTTPRoute {
+        metadata: ObjectMeta {
+            // using coredb name, since we'll have 1x HTTPRoute per coredb
+            name: Some(coredb_name.to_owned()),
+            namespace: Some(namespace.to_owned()),
+            owner_references: Some(vec![oref]),
+            labels: Some(route_labels(coredb_name, COMPONENT_NAME)),
+            ..ObjectMeta::default()
+        },
+        spec: HTTPRouteSpec {
+            hostnames: Some(vec![format!("{coredb_name}.{domain}")]),
+            parent_refs: Some(
+                listeners
+                    .iter()
+                    .map(|listener| HTTPRouteParentRefs {
+                        name: cfg.gateway_name.clone(),
+                        namespace: Some(cfg.gateway_namespace.clone()),
+                        section_name: Some(listener.clone()),
+                        ..HTTPRouteParentRefs::default()
+                    })
+                    .collect(),
+            ),
+            rules: Some(rules),
+        },
+    };
+    (Some(route), refused)
+}
+
+// generate_app_tls_routes returns a TLSRoute per app service with tcp routing,
+// named `<coredb>-<app>` like the Traefik IngressRouteTCP.
+pub(crate) fn generate_app_tls_routes(
+    coredb_name: &str,
+    namespace: &str,
+    oref: OwnerReference,
+    cfg: &Config,
+    domain: &str,
+    appsvcs: &[AppService],
+) -> Vec<TLSRoute> {
+    let mut routes = vec![];
+    for appsvc in appsvcs {
+        let resource_name = format!("{}-{}", coredb_name, appsvc.name);
+        let tcp_routes: Vec<_> = appsvc
+            .routing
+            .clone()
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|r| r.ingress_type == Some(IngressType::tcp) && r.ingress_path.is_some())
+            .collect();
+        // A TLSRoute can only route a hostname to a single backend port
+        let Some(route) = tcp_routes.first() else {
+            continue;
+        };
+        let listeners = route.entry_points.clone().unwrap_or_default();
+        routes.push(generate_tls_route(
+            cfg,
+            &resource_name,
+            namespace,
+            oref.clone(),
+            route_labels(coredb_name, COMPONENT_NAME),
+            vec![format!("{coredb_name}.{domain}")],
+            &listeners,
+            &resource_name,
+            route.port as i32,
+        ));
+    }
+    routes
+}
+
+// reconcile_app_routes applies the HTTPRoute and TLSRoutes of the app services of an
+// instance and removes the ones that are no longer desired. The routing entries that
+// were refused are returned as an error once the other routes are applied.
+pub async fn reconcile_app_routes(
+    client: Client,
+    cdb: &CoreDB,
+    cfg: &Config,
+    domain: &str,
+    appsvcs: &[AppService],
+) -> Result<(), OperatorError> {
+    let coredb_name = cdb.name_any();
+    let namespace = cdb.namespace().unwrap();
+    let oref = cdb.controller_owner_ref(&()).unwrap();
+    let selector = format!("component={COMPONENT_NAME},coredb.io/name={coredb_name}");
+
+    let (http_routes, tls_routes, refused) = if cdb.spec.disable_ingress {
+        (vec![], vec![], vec![])
+    } else {
+        let (http_route, refused) =
+            generate_app_http_route(&coredb_name, &namespace, oref.clone(), cfg, domain, appsvcs);
+        (
+            http_route.into_iter().collect(),
+            generate_app_tls_routes(&coredb_name, &namespace, oref, cfg, domain, appsvcs),
+            refused,
+        )
+    };
+
+    let http_api: Api<HTTPRoute> = Api::namespaced(client.clone(), &namespace);
+    reconcile_routes(&http_api, &namespace, &selector, http_routes).await?;
+    let tls_api: Api<TLSRoute> = Api::namespaced(client, &namespace);
+    reconcile_routes(&tls_api, &namespace, &selector, tls_routes).await?;
+
+    if refused.is_empty() {
+        Ok(())
+    } else {
+        Err(OperatorError::GatewayRouteError(refused.join("; ")))
+    }
+}
+
+// delete_app_routes removes the Gateway API routes of the app services of an instance,
+// used when the instance is exposed through Traefik
+pub async fn delete_app_routes(client: Client, cdb: &CoreDB) -> Result<(), OperatorError> {
+    let namespace = cdb.namespace().unwrap();
+    let selector = format!(
+        "component={COMPONENT_NAME},coredb.io/name={}",
+        cdb.name_any()
+    );
+    let http_api: Api<HTTPRoute> = Api::namespaced(client.clone(), &namespace);
+    reconcile_routes(&http_api, &namespace, &selector, vec![]).await?;
+    let tls_api: Api<TLSRoute> = Api::namespaced(client, &namespace);
+    reconcile_routes(&tls_api, &namespace, &selector, vec![]).await
+}
+
+// Applies the desired routes and deletes the routes matching the label selector that
+// are not desired anymore.
+async fn reconcile_routes<K>(
+    api: &Api<K>,
+    namespace: &str,
+    selector: &str,
+    desired: Vec<K>,
+) -> Result<(), OperatorError>
+where
+    K: Resource + Clone + Debug + Serialize + DeserializeOwned,
+    <K as Resource>::DynamicType: Default,
+{
+    let kind = K::kind(&Default::default()).to_string();
+    let desired_names: Vec<String> = desired.iter().map(|r| r.name_any()).collect();
+    for route in desired.iter() {
+        let name = route.name_any();
+        let ps = PatchParams::apply("cntrlr").force();
+        api.patch(&name, &ps, &Patch::Apply(route))
+            .await
+            .map_err(|e| {
+                error!("Failed to apply {} {}.{}: {}", kind, name, namespace, e);
+                OperatorError::GatewayRouteError(format!("{kind} {name}: {e}"))
+            })?;
+        debug!("Applied {} {}.{}", kind, name, namespace);
+    }
+
+    let actual = match api.list(&ListParams::default().labels(selector)).await {
+        Ok(actual) => actual.items,
+        // Nothing to clean up when the Gateway API CRDs are not installed
+        Err(kube::Error::Api(ae)) if ae.code == 404 && desired.is_empty() => vec![],
+        Err(e) => {
+            return Err(OperatorError::GatewayRouteError(format!(
+                "listing {kind}: {e}"
+            )))
+        }
+    };
+    for route in actual {
+        let name = route.name_any();
+        if desired_names.contains(&name) {
+            continue;
+        }
+        api.delete(&name, &DeleteParams::default())
+            .await
+            .map_err(|e| {
+                error!("Failed to delete {} {}.{}: {}", kind, name, namespace, e);
+                OperatorError::GatewayRouteError(format!("{kind} {name}: {e}"))
+            })?;
+        debug!("Deleted {} {}.{}", kind, name, namespace);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cdb_from_yaml(yaml: &str) -> CoreDB {
+        serde_yaml::from_str(yaml).expect("Failed to parse YAML")
+    }
+
+    #[test]
+    fn test_generate_postgres_tls_routes() {
+        let cdb = cdb_from_yaml(
+            r#"
+        apiVersion: coredb.io/v1alpha1
+        kind: CoreDB
+        metadata:
+          name: test
+          namespace: org-test
+          uid: 8f2b6a0e-1c57-4d2e-9b1f-4e3c2a1d0b9c
+        spec:
+          extra_domains_rw: ["db.example.com"]
+          ipAllowList: ["10.0.0.0/8", "not-an-ip"]
+          connectionPooler:
+            enabled: true
+        "#,
+        );
+        let cfg = Config::default();
+        let routes = generate_postgres_tls_routes(&cdb, &cfg, "data.example.dev");
+        let names: Vec<String> = routes.iter().map(|r| r.name_any()).collect();
+        assert_eq!(names, vec!["test-rw", "test-ro", "test-pooler"]);
+
+        let rw = &routes[0];
+        assert_eq!(
+            rw.spec.hostnames.clone().unwrap(),
+            vec!["test.data.example.dev", "db.example.com"]
+        );
+        let parent = &rw.spec.parent_refs.as_ref().unwrap()[0];
+        assert_eq!(parent.name, cfg.gateway_name);
+        assert_eq!(parent.section_name.as_deref(), Some(POSTGRES_LISTENER));
+        let backend = &rw.spec.rules[0].backend_refs.as_ref().unwrap()[0];
+        assert_eq!(backend.name, "test-rw");
+        assert_eq!(backend.port, Some(5432));
+
+        assert_eq!(
+            routes[1].spec.hostnames.clone().unwrap(),
+            vec!["test-ro.data.example.dev"]
+        );
+    }
+
+    #[test]
+    fn test_unenforced_ip_allow_list() {
+        let mut cdb = cdb_from_yaml(
+            r#"
+        apiVersion: coredb.io/v1alpha1
+        kind: CoreDB
+        metadata:
+          name: test
+          namespace: org-test
+        spec:
+          ipAllowList: ["10.0.0.0/8", "not-an-ip"]
+        "#,
+        );
+        assert!(unenforced_ip_allow_list(&cdb).is_some());
+
+        // Invalid entries do not open the instance to every address either
+        cdb.spec.ip_allow_list = Some(vec!["not-an-ip".to_string()]);
+        assert!(unenforced_ip_allow_list(&cdb).is_some());
+
+        cdb.spec.ip_allow_list = Some(vec!["0.0.0.0/0".to_string()]);
+        assert!(unenforced_ip_allow_list(&cdb).is_none());
+        cdb.spec.ip_allow_list = None;
+        assert!(unenforced_ip_allow_list(&cdb).is_none());
+    }
+
+    #[test]
+    fn test_access_control_middlewares_are_refused() {
+        let middlewares: Vec<Middleware> = serde_json::from_value(serde_json::json!([
+            {"basicAuth": {"name": "basic", "config": {"secret": "users"}}},
+            {"forwardAuth": {"name": "forward", "config": {"address": "http://auth.org-test.svc/verify"}}},
+            {"ipAllowList": {"name": "allow-list"}},
+            {"rateLimit": {"name": "limit", "config": {"average": 100}}},
+        ]))
+        .unwrap();
+        for middleware in &middlewares {
+            assert_eq!(
+                middleware_filter("/rest/v1", middleware).unwrap_err(),
+                format!(
+                    "middleware {} controls access, which Gateway API can not enforce",
+                    middleware.name()
+                )
+            );
+        }
+    }
+
+    #[test]
+    fn test_traefik_middleware_filter() {
+        let middlewares: Vec<Middleware> = serde_json::from_value(serde_json::json!([
+            {"ipAllowList": {"name": "allow-list"}},
+            {"forwardAuth": {"name": "forward", "config": {"address": "http://auth.org-other.svc/verify"}}},
+        ]))
+        .unwrap();
+        let filter = traefik_middleware_filter("test", "org-test", &middlewares[0]).unwrap();
+        assert_eq!(filter.extension_ref.unwrap().name, "test-allow-list");
+        // The Middleware is not generated, the route is refused rather than left open
+        assert_eq!(
+            traefik_middleware_filter("test", "org-test", &middlewares[1]).unwrap_err(),
+            "middleware forward forwards to an address outside of the namespace"
+        );
+    }
+
+    #[test]
+    fn test_generate_app_routes() {
+        // Middlewares are externally tagged enums, which serde_yaml can not read untagged
+        let cdb: CoreDB = serde_json::from_value(serde_json::json!({
+            "apiVersion": "coredb.io/v1alpha1",
+            "kind": "CoreDB",
+            "metadata": {
+                "name": "test",
+                "namespace": "org-test",
+                "uid": "8f2b6a0e-1c57-4d2e-9b1f-4e3c2a1d0b9c"
+            },
+            "spec": {
+                "appServices": [
+                    {
+                        "name": "postgrest",
+                        "image": "postgrest/postgrest:v12.0.0",
+                        "routing": [
+                            {
+                                "port": 3000,
+                                "ingressPath": "/rest/v1",
+                                "middlewares": ["strip-path", "headers"]
+                            },
+                            {
+                                "port": 3000,
+                                "ingressPath": "/rest/v2",
+                                "middlewares": ["regex"]
+                            },
+                            {
+                                "port": 3000,
+                                "ingressPath": "/rest/v3",
+                                "middlewares": ["undefined"]
+                            }
+                        ],
+                        "middlewares": [
+                            {"stripPrefix": {"name": "strip-path", "config": ["/rest/v1"]}},
+                            {"customRequestHeaders": {
+                                "name": "headers",
+                                "config": {"Authorization": "", "X-Tembo": "yes"}
+                            }},
+                            {"replacePathRegex": {
+                                "name": "regex",
+                                "config": {"regex": "^/rest/(.*)", "replacement": "/$1"}
+                            }}
+                        ]
+                    },
+                    {
+                        "name": "ferretdb",
+                        "image": "ghcr.io/ferretdb/ferretdb",
+                        "routing": [{
+                            "port": 27018,
+                            "ingressPath": "/ferretdb/v1",
+                            "entryPoints": ["ferretdb"],
+                            "ingressType": "tcp"
+                        }]
+                    }
+                ]
+            }
+        }))
+        .expect("Failed to parse CoreDB");
+        let cfg = Config::default();
+        let appsvcs = cdb.spec.app_services.clone().unwrap();
+        let oref = cdb.controller_owner_ref(&()).unwrap();
+
+        let (http, refused) = generate_app_http_route(
+            "test",
+            "org-test",
+            oref.clone(),
+            &cfg,
+            "data.example.dev",
+            &appsvcs,
+        );
+        let http = http.unwrap();
+        assert_eq!(http.name_any(), "test");
+        assert_eq!(
+            http.spec.hostnames.clone().unwrap(),
+            vec!["test.data.example.dev"]
+        );
+        let parents = http.spec.parent_refs.clone().unwrap();
+        assert_eq!(parents.len(), 1);
+        assert_eq!(parents[0].section_name.as_deref(), Some("websecure"));
+        let rules = http.spec.rules.clone().unwrap();
+        assert_eq!(rules.len(), 1);
+        assert_eq!(
+            rules[0].backend_refs.as_ref().unwrap()[0].name,
+            "test-postgrest"
+        );
+        // The routes with a middleware that can not be applied are refused
+        assert_eq!(
+            refused,
+            vec![
+                "postgrest route /rest/v2: middleware regex uses replacePathRegex, which Gateway API does not support",
+                "postgrest route /rest/v3: middleware undefined is not defined",
+            ]
+        );
+        let filters = rules[0].filters.clone().unwrap();
+        assert_eq!(filters.len(), 2);
+        assert_eq!(filters[0].r#type, HTTPRouteRulesFiltersType::UrlRewrite);
+        let headers = filters[1].request_header_modifier.clone().unwrap();
+        assert_eq!(headers.remove.unwrap(), vec!["Authorization"]);
+        assert_eq!(headers.set.unwrap()[0].name, "X-Tembo");
+
+        // A Gateway implemented by Traefik applies the Traefik Middleware instead
+        let traefik_cfg = Config {
+            gateway_traefik_middlewares: true,
+            ..cfg.clone()
+        };
+        let (http, refused) = generate_app_http_route(
+            "test",
+            "org-test",
+            oref.clone(),
+            &traefik_cfg,
+            "data.example.dev",
+            &appsvcs,
+        );
+        assert_eq!(
+            refused,
+            vec!["postgrest route /rest/v3: middleware undefined is not defined"]
+        );
+        let rules = http.unwrap().spec.rules.unwrap();
+        assert_eq!(rules.len(), 2);
+        let filter = &rules[1].filters.as_ref().unwrap()[0];
+        assert_eq!(filter.r#type, HTTPRouteRulesFiltersType::ExtensionRef);
+        let extension_ref = filter.extension_ref.as_ref().unwrap();
+        assert_eq!(
+            (extension_ref.group.as_str(), extension_ref.kind.as_str()),
+            ("traefik.io", "Middleware")
+        );
+        assert_eq!(extension_ref.name, "test-regex");
+
+        let tls =
+            generate_app_tls_routes("test", "org-test", oref, &cfg, "data.example.dev", &appsvcs);
+        assert_eq!(tls.len(), 1);
+        assert_eq!(tls[0].name_any(), "test-ferretdb");
+        assert_eq!(
+            tls[0].spec.parent_refs.as_ref().unwrap()[0]
+                .section_name
+                .as_deref(),
+            Some("ferretdb")
+        );
+        assert_eq!(
+            tls[0].spec.rules[0].backend_refs.as_ref().unwrap()[0].port,
+            Some(27018)
+        );
+    }
+}
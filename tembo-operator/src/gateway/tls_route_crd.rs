@@ -0,0 +1,103 @@
+// Subset of the Gateway API TLSRoute CRD, in the layout generated by kopium
+// kopium command: kopium -A --derive Default tlsroutes.gateway.networking.k8s.io
+// Only the fields used by the operator are kept.
+
+#[allow(unused_imports)]
+mod prelude {
+    pub use kube::CustomResource;
+    pub use schemars::JsonSchema;
+    pub use serde::{Deserialize, Serialize};
+}
+use self::prelude::*;
+
+/// Spec defines the desired state of TLSRoute.
+#[derive(CustomResource, Serialize, Deserialize, Clone, Debug, Default, JsonSchema)]
+#[kube(
+    group = "gateway.networking.k8s.io",
+    version = "v1alpha2",
+    kind = "TLSRoute",
+    plural = "tlsroutes"
+)]
+#[kube(namespaced)]
+#[kube(derive = "Default")]
+pub struct TLSRouteSpec {
+    /// Hostnames defines a set of SNI names that should match against the
+    /// SNI attribute of TLS ClientHello message in TLS handshake.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hostnames: Option<Vec<String>>,
+    /// ParentRefs references the resources (usually Gateways) that a Route wants
+    /// to be attached to.
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        rename = "parentRefs"
+    )]
+    pub parent_refs: Option<Vec<TLSRouteParentRefs>>,
+    /// Rules are a list of TLS matchers and actions.
+    pub rules: Vec<TLSRouteRules>,
+}
+
+/// ParentReference identifies an API object (usually a Gateway) that can be considered
+/// a parent of this resource (usually a route).
+#[derive(Serialize, Deserialize, Clone, Debug, Default, JsonSchema)]
+pub struct TLSRouteParentRefs {
+    /// Group is the group of the referent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+    /// Kind is kind of the referent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kind: Option<String>,
+    /// Name is the name of the referent.
+    pub name: String,
+    /// Namespace is the namespace of the referent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+    /// Port is the network port this Route targets.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub port: Option<i32>,
+    /// SectionName is the name of a section within the target resource, the
+    /// listener name for Gateways.
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        rename = "sectionName"
+    )]
+    pub section_name: Option<String>,
+}
+
+/// TLSRouteRule is the configuration for a given rule.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, JsonSchema)]
+pub struct TLSRouteRules {
+    /// BackendRefs defines the backend(s) where matching requests should be sent.
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        rename = "backendRefs"
+    )]
+    pub backend_refs: Option<Vec<TLSRouteRulesBackendRefs>>,
+    /// Name is the name of the route rule.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+}
+
+/// BackendRef defines how a Route should forward a request to a Kubernetes resource.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, JsonSchema)]
+pub struct TLSRouteRulesBackendRefs {
+    /// Group is the group of the referent. Defaults to the core API group.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+    /// Kind is the Kubernetes resource kind of the referent. Defaults to "Service".
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kind: Option<String>,
+    /// Name is the name of the referent.
+    pub name: String,
+    /// Namespace is the namespace of the backend.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+    /// Port specifies the destination port number to use for this resource.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub port: Option<i32>,
+    /// Weight specifies the proportion of requests forwarded to the referenced backend.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub weight: Option<i32>,
+}
@@ -53,6 +53,12 @@ $$;
 // reconcile_heartbeat is a function to run the setup_heartbeat function on the database instance
 // and then run the run_heartbeat function to insert a timestamp into the heartbeat_table.
 pub async fn reconcile_heartbeat(coredb: &CoreDB, ctx: Arc<Context>) -> Result<(), Action> {
+    // Replicas are read-only, the heartbeat of the source is replicated to them
+    if coredb.spec.is_replica() {
+        debug!("Skipping heartbeat of replica {}", coredb.name_any());
+        return Ok(());
+    }
+
     // Match to make sure the HEARTBEAT_FUNCTION is installed on the database instance, requeue if
     // it fails for some reason.
     match setup_heartbeat(coredb, ctx.clone()).await {
@@ -279,6 +279,47 @@ pub async fn delete_ingress_route_tcp(
     Ok(())
 }
 
+// delete_postgres_traefik_objects removes the Postgres IngressRouteTCPs and the IP allow
+// list MiddlewareTCP of an instance, used when the instance is exposed through Gateway API
+pub async fn delete_postgres_traefik_objects(
+    cdb: &CoreDB,
+    ctx: Arc<Context>,
+) -> Result<(), OperatorError> {
+    let name = cdb.name_any();
+    let namespace = cdb.namespace().unwrap();
+    let ingress_route_tcp_api: Api<IngressRouteTCP> =
+        Api::namespaced(ctx.client.clone(), &namespace);
+    for ingress_route_tcp_name in [
+        format!("{name}-rw-0"),
+        format!("{name}-ro-0"),
+        format!("{name}-pooler-0"),
+        format!("extra-{name}-rw"),
+    ] {
+        delete_ingress_route_tcp(
+            ingress_route_tcp_api.clone(),
+            &namespace,
+            &ingress_route_tcp_name,
+        )
+        .await?;
+    }
+
+    let middleware_api: Api<MiddlewareTCP> = Api::namespaced(ctx.client.clone(), &namespace);
+    if middleware_api.get(&name).await.is_ok() {
+        middleware_api
+            .delete(&name, &DeleteParams::default())
+            .await
+            .map_err(|e| {
+                error!(
+                    "Failed to delete MiddlewareTCP {}.{}: {}",
+                    name, namespace, e
+                );
+                OperatorError::IngressRouteTcpError
+            })?;
+        info!("Deleted MiddlewareTCP {}.{}", name, namespace);
+    }
+    Ok(())
+}
+
 #[allow(clippy::too_many_arguments)]
 pub async fn reconcile_postgres_ing_route_tcp(
     cdb: &CoreDB,
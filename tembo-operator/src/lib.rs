@@ -4,7 +4,9 @@ pub use crate::controller::*;
 pub mod apis;
 
 pub mod app_service;
+pub mod conditions;
 pub mod configmap;
+pub mod databases;
 pub mod dedicated_networking;
 pub mod extensions;
 pub mod postgres_exporter;
@@ -15,9 +17,10 @@ mod exec;
 /// Metrics
 mod metrics;
 pub use metrics::Metrics;
-mod config;
+pub mod config;
 pub mod defaults;
 pub mod errors;
+pub mod gateway;
 pub mod prometheus;
 
 pub mod cloudnativepg;
@@ -26,6 +29,7 @@ mod deployment_postgres_exporter;
 pub mod fixtures;
 pub mod heartbeat;
 pub mod ingress;
+pub mod logical_replication;
 pub mod traefik;
 pub use traefik::ingress_route_crd;
 mod certmanager;
@@ -36,7 +40,9 @@ mod rbac;
 mod secret;
 mod service;
 pub mod snapshots;
+pub mod storage_autoscaling;
 mod trunk;
+pub mod validation;
 
 pub const RESTARTED_AT: &str = "kubectl.kubernetes.io/restartedAt";
 
@@ -0,0 +1,1014 @@
+use crate::{
+    apis::coredb_types::{
+        CoreDB, LogicalReplicationStatus, ManagedObjectStatus, Publication, ReplicationSlotStatus,
+        Subscription,
+    },
+    databases::{execute_statements, psql_error, quote_ident, quote_literal},
+    extensions::database_queries::check_input,
+    network_policies::reconcile_logical_replication_network_policies,
+    secret::{fetch_decoded_secret_key, reconcile_postgres_role_secret},
+    Context,
+};
+use kube::{runtime::controller::Action, Api, ResourceExt};
+use std::sync::Arc;
+use tracing::{debug, error, info, instrument, warn};
+
+const LIST_LOGICAL_SLOTS_QUERY: &str = "SELECT slot_name, database, active, \
+    COALESCE(pg_wal_lsn_diff(pg_current_wal_lsn(), confirmed_flush_lsn), 0)::bigint AS lag_bytes \
+    FROM pg_replication_slots WHERE slot_type = 'logical' ORDER BY slot_name;";
+
+const LIST_PUBLICATIONS_QUERY: &str = "SELECT p.pubname, p.puballtables, \
+    COALESCE(string_agg(t.schemaname || '.' || t.tablename, ',' ORDER BY t.schemaname, t.tablename) \
+    FILTER (WHERE NOT p.puballtables), '') AS tables \
+    FROM pg_publication p LEFT JOIN pg_publication_tables t ON t.pubname = p.pubname \
+    GROUP BY p.pubname, p.puballtables;";
+
+// The role subscribers connect to the publications of the instance with. It can
+// replicate and read the published tables, but nothing else.
+pub(crate) const REPLICATION_ROLE: &str = "tembo_replication";
+
+// replication_secret_name returns the name of the secret holding the password of
+// the replication role of an instance
+fn replication_secret_name(name: &str) -> String {
+    format!("{}-replication", name)
+}
+
+// A publication as it currently exists in Postgres
+#[derive(Debug, PartialEq)]
+struct ObservedPublication {
+    name: String,
+    all_tables: bool,
+    tables: Vec<String>,
+}
+
+// A subscription as it currently exists in Postgres
+#[derive(Debug, PartialEq)]
+struct ObservedSubscription {
+    enabled: bool,
+    publications: Vec<String>,
+    connection_matches: bool,
+}
+
+// normalize_table returns the schema qualified name of a table, defaulting to the public schema.
+// Returns None for names that are not valid identifiers.
+fn normalize_table(table: &str) -> Option<(String, String)> {
+    let (schema, name) = match table.split_once('.') {
+        Some((schema, name)) => (schema, name),
+        None => ("public", table),
+    };
+    if check_input(schema) && check_input(name) {
+        Some((schema.to_string(), name.to_string()))
+    } else {
+        None
+    }
+}
+
+fn desired_tables(publication: &Publication) -> Result<Vec<String>, String> {
+    let mut tables = publication
+        .tables
+        .iter()
+        .map(|table| {
+            normalize_table(table)
+                .map(|(schema, name)| format!("{}.{}", schema, name))
+                .ok_or_else(|| format!("invalid table name {}", table))
+        })
+        .collect::<Result<Vec<String>, String>>()?;
+    tables.sort();
+    tables.dedup();
+    Ok(tables)
+}
+
+fn quoted_tables(tables: &[String]) -> String {
+    tables
+        .iter()
+        .filter_map(|table| table.split_once('.'))
+        .map(|(schema, name)| format!("{}.{}", quote_ident(schema), quote_ident(name)))
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+fn create_publication_statement(publication: &Publication, tables: &[String]) -> String {
+    let name = quote_ident(&publication.name);
+    if publication.all_tables {
+        format!("CREATE PUBLICATION {} FOR ALL TABLES;", name)
+    } else if tables.is_empty() {
+        format!("CREATE PUBLICATION {};", name)
+    } else {
+        format!(
+            "CREATE PUBLICATION {} FOR TABLE {};",
+            name,
+            quoted_tables(tables)
+        )
+    }
+}
+
+fn publication_drift(
+    publication: &Publication,
+    tables: &[String],
+    observed: Option<&ObservedPublication>,
+) -> Vec<String> {
+    let Some(observed) = observed else {
+        return vec![format!("publication {} does not exist", publication.name)];
+    };
+    if observed.all_tables != publication.all_tables {
+        return vec![format!(
+            "allTables is {} instead of {}",
+            observed.all_tables, publication.all_tables
+        )];
+    }
+    if !publication.all_tables && observed.tables != tables {
+        return vec![format!(
+            "tables are [{}] instead of [{}]",
+            observed.tables.join(", "),
+            tables.join(", ")
+        )];
+    }
+    vec![]
+}
+
+fn publication_statements(
+    publication: &Publication,
+    tables: &[String],
+    observed: Option<&ObservedPublication>,
+) -> Vec<String> {
+    let name = quote_ident(&publication.name);
+    match observed {
+        None => vec![create_publication_statement(publication, tables)],
+        // FOR ALL TABLES cannot be altered, the publication is recreated
+        Some(observed) if observed.all_tables != publication.all_tables => vec![
+            format!("DROP PUBLICATION {};", name),
+            create_publication_statement(publication, tables),
+        ],
+        Some(observed) if !publication.all_tables && observed.tables != tables => {
+            if tables.is_empty() {
+                vec![format!(
+                    "ALTER PUBLICATION {} DROP TABLE {};",
+                    name,
+                    quoted_tables(&observed.tables)
+                )]
+            } else {
+                vec![format!(
+                    "ALTER PUBLICATION {} SET TABLE {};",
+                    name,
+                    quoted_tables(tables)
+                )]
+            }
+        }
+        Some(_) => vec![],
+    }
+}
+
+// replication_grant_statements gives the replication role read access to the tables
+// of a publication, which the initial copy of a subscription needs
+fn replication_grant_statements(publication: &Publication, tables: &[String]) -> Vec<String> {
+    let role = quote_ident(REPLICATION_ROLE);
+    if publication.all_tables {
+        return vec![format!("GRANT pg_read_all_data TO {};", role)];
+    }
+    let mut schemas: Vec<&str> = tables
+        .iter()
+        .filter_map(|table| table.split_once('.'))
+        .map(|(schema, _)| schema)
+        .collect();
+    schemas.sort();
+    schemas.dedup();
+    let mut statements: Vec<String> = schemas
+        .iter()
+        .map(|schema| format!("GRANT USAGE ON SCHEMA {} TO {};", quote_ident(schema), role))
+        .collect();
+    if !tables.is_empty() {
+        statements.push(format!(
+            "GRANT SELECT ON {} TO {};",
+            quoted_tables(tables),
+            role
+        ));
+    }
+    statements
+}
+
+fn parse_publications(psql_str: &str) -> Vec<ObservedPublication> {
+    let mut publications = vec![];
+    for line in psql_str.lines().skip(2) {
+        let fields: Vec<&str> = line.split('|').map(|s| s.trim()).collect();
+        if fields.len() < 3 {
+            continue;
+        }
+        publications.push(ObservedPublication {
+            name: fields[0].to_owned(),
+            all_tables: fields[1] == "t",
+            tables: fields[2]
+                .split(',')
+                .filter(|table| !table.is_empty())
+                .map(str::to_owned)
+                .collect(),
+        });
+    }
+    publications
+}
+
+fn parse_slots(psql_str: &str) -> Vec<ReplicationSlotStatus> {
+    let mut slots = vec![];
+    for line in psql_str.lines().skip(2) {
+        let fields: Vec<&str> = line.split('|').map(|s| s.trim()).collect();
+        if fields.len() < 4 {
+            continue;
+        }
+        slots.push(ReplicationSlotStatus {
+            name: fields[0].to_owned(),
+            database: fields[1].to_owned(),
+            active: fields[2] == "t",
+            lag_bytes: fields[3].parse().unwrap_or(0),
+        });
+    }
+    slots
+}
+
+fn subscription_query(subscription: &Subscription, connection: &str) -> String {
+    format!(
+        "SELECT subenabled, array_to_string(subpublications, ','), subconninfo = {} \
+         FROM pg_subscription WHERE subname = {};",
+        quote_literal(connection),
+        quote_literal(&subscription.name)
+    )
+}
+
+fn parse_subscription(psql_str: &str) -> Option<ObservedSubscription> {
+    let line = psql_str.lines().nth(2)?;
+    let fields: Vec<&str> = line.split('|').map(|s| s.trim()).collect();
+    if fields.len() < 3 {
+        return None;
+    }
+    let mut publications: Vec<String> = fields[1]
+        .split(',')
+        .filter(|publication| !publication.is_empty())
+        .map(str::to_owned)
+        .collect();
+    publications.sort();
+    Some(ObservedSubscription {
+        enabled: fields[0] == "t",
+        publications,
+        connection_matches: fields[2] == "t",
+    })
+}
+
+fn sorted_publications(subscription: &Subscription) -> Vec<String> {
+    let mut publications = subscription.publications.clone();
+    publications.sort();
+    publications.dedup();
+    publications
+}
+
+fn subscription_drift(
+    subscription: &Subscription,
+    observed: Option<&ObservedSubscription>,
+) -> Vec<String> {
+    let Some(observed) = observed else {
+        return vec![format!("subscription {} does not exist", subscription.name)];
+    };
+    let mut drift = vec![];
+    if !observed.connection_matches {
+        drift.push("connection changed".to_string());
+    }
+    let publications = sorted_publications(subscription);
+    if observed.publications != publications {
+        drift.push(format!(
+            "publications are [{}] instead of [{}]",
+            observed.publications.join(", "),
+            publications.join(", ")
+        ));
+    }
+    if observed.enabled != subscription.enabled {
+        drift.push(format!(
+            "enabled is {} instead of {}",
+            observed.enabled, subscription.enabled
+        ));
+    }
+    drift
+}
+
+fn subscription_statements(
+    subscription: &Subscription,
+    connection: &str,
+    observed: Option<&ObservedSubscription>,
+) -> Vec<String> {
+    let name = quote_ident(&subscription.name);
+    let publications = sorted_publications(subscription);
+    let quoted_publications = publications
+        .iter()
+        .map(|publication| quote_ident(publication))
+        .collect::<Vec<String>>()
+        .join(", ");
+
+    let Some(observed) = observed else {
+        return vec![format!(
+            "CREATE SUBSCRIPTION {} CONNECTION {} PUBLICATION {} WITH (enabled = {}, copy_data = {});",
+            name,
+            quote_literal(connection),
+            quoted_publications,
+            subscription.enabled,
+            subscription.copy_data
+        )];
+    };
+
+    let mut statements = vec![];
+    if !observed.connection_matches {
+        statements.push(format!(
+            "ALTER SUBSCRIPTION {} CONNECTION {};",
+            name,
+            quote_literal(connection)
+        ));
+    }
+    // Disable before changing publications, and enable after, since refreshing
+    // the publications requires an enabled subscription
+    if observed.enabled && !subscription.enabled {
+        statements.push(format!("ALTER SUBSCRIPTION {} DISABLE;", name));
+    }
+    if observed.publications != publications {
+        statements.push(format!(
+            "ALTER SUBSCRIPTION {} SET PUBLICATION {} WITH (refresh = {});",
+            name, quoted_publications, subscription.enabled
+        ));
+    }
+    if !observed.enabled && subscription.enabled {
+        statements.push(format!("ALTER SUBSCRIPTION {} ENABLE;", name));
+    }
+    statements
+}
+
+// subscription_connection returns the connection string of the publisher of a subscription
+async fn subscription_connection(
+    cdb: &CoreDB,
+    ctx: Arc<Context>,
+    subscription: &Subscription,
+) -> Result<Result<String, String>, Action> {
+    let source = &subscription.source;
+    match (&source.core_db, &source.connection_secret) {
+        (Some(core_db), None) => {
+            if !check_input(&core_db.database) {
+                return Ok(Err(format!("invalid database name {}", core_db.database)));
+            }
+            // Only the namespaces the publisher allows can subscribe to it
+            let coredbs: Api<CoreDB> = Api::namespaced(ctx.client.clone(), &core_db.namespace);
+            let source = coredbs.get_opt(&core_db.name).await.map_err(|e| {
+                error!(
+                    "Error getting CoreDB {} in namespace {}: {}",
+                    core_db.name, core_db.namespace, e
+                );
+                Action::requeue(std::time::Duration::from_secs(300))
+            })?;
+            let Some(source) = source else {
+                return Ok(Err(format!(
+                    "CoreDB {} in namespace {} not found",
+                    core_db.name, core_db.namespace
+                )));
+            };
+            let namespace = cdb.namespace().unwrap();
+            let allowed = source
+                .spec
+                .logical_replication
+                .as_ref()
+                .is_some_and(|lr| lr.allowed_subscriber_namespaces.contains(&namespace));
+            if !allowed {
+                return Ok(Err(format!(
+                    "namespace {} is not in the allowedSubscriberNamespaces of CoreDB {} in namespace {}",
+                    namespace, core_db.name, core_db.namespace
+                )));
+            }
+            let secret_name = replication_secret_name(&core_db.name);
+            let password =
+                fetch_decoded_secret_key(ctx, &core_db.namespace, &secret_name, "password").await?;
+            Ok(password
+                .map(|password| {
+                    format!(
+                        "host={}-rw.{}.svc.cluster.local port=5432 dbname={} user={} password={}",
+                        core_db.name,
+                        core_db.namespace,
+                        core_db.database,
+                        REPLICATION_ROLE,
+                        password
+                    )
+                })
+                .ok_or_else(|| {
+                    format!(
+                        "replication secret of CoreDB {} in namespace {} not found",
+                        core_db.name, core_db.namespace
+                    )
+                }))
+        }
+        (None, Some(secret)) => {
+            let namespace = cdb.namespace().unwrap();
+            let connection =
+                fetch_decoded_secret_key(ctx, &namespace, &secret.name, &secret.key).await?;
+            Ok(connection.ok_or_else(|| {
+                format!(
+                    "connection secret {} with key {} not found",
+                    secret.name, secret.key
+                )
+            }))
+        }
+        _ => Ok(Err(
+            "exactly one of source.coreDB and source.connectionSecret must be set".to_string(),
+        )),
+    }
+}
+
+fn status(
+    name: &str,
+    database: &str,
+    drift: Vec<String>,
+    errors: &[String],
+) -> ManagedObjectStatus {
+    let mut drift = drift;
+    drift.extend(errors.iter().map(|e| format!("failed to apply: {}", e)));
+    ManagedObjectStatus {
+        name: name.to_string(),
+        database: Some(database.to_string()),
+        synced: errors.is_empty(),
+        drift,
+    }
+}
+
+fn invalid(name: &str, database: &str, message: String) -> ManagedObjectStatus {
+    ManagedObjectStatus {
+        name: name.to_string(),
+        database: Some(database.to_string()),
+        synced: false,
+        drift: vec![message],
+    }
+}
+
+// removed_objects returns the objects of the previous status that are no longer in the spec
+fn removed_objects<'a>(
+    previous: &'a [ManagedObjectStatus],
+    current: &[(&str, &str)],
+) -> Vec<&'a ManagedObjectStatus> {
+    previous
+        .iter()
+        .filter(|status| {
+            let database = status.database.as_deref().unwrap_or("postgres");
+            !current.contains(&(status.name.as_str(), database))
+        })
+        .collect()
+}
+
+// reconcile_replication_role creates the role subscribers connect with, and the secret
+// holding its password. Returns true if the role was created.
+async fn reconcile_replication_role(cdb: &CoreDB, ctx: Arc<Context>) -> Result<bool, Action> {
+    let secret_name = replication_secret_name(&cdb.name_any());
+    reconcile_postgres_role_secret(cdb, ctx.clone(), REPLICATION_ROLE, &secret_name)
+        .await
+        .map_err(|e| {
+            error!("Error reconciling secret {}: {:?}", secret_name, e);
+            Action::requeue(std::time::Duration::from_secs(10))
+        })?;
+    let namespace = cdb.namespace().unwrap();
+    let Some(password) =
+        fetch_decoded_secret_key(ctx.clone(), &namespace, &secret_name, "password").await?
+    else {
+        return Err(Action::requeue(std::time::Duration::from_secs(10)));
+    };
+
+    let output = cdb
+        .psql(
+            format!(
+                "SELECT 1 FROM pg_roles WHERE rolname = {};",
+                quote_literal(REPLICATION_ROLE)
+            ),
+            "postgres".to_owned(),
+            ctx.clone(),
+        )
+        .await?;
+    if output.get_field(0).as_deref() == Some("1") {
+        return Ok(false);
+    }
+    info!("Creating role {} on {}", REPLICATION_ROLE, cdb.name_any());
+    let statement = format!(
+        "CREATE ROLE {} WITH LOGIN REPLICATION PASSWORD {};",
+        quote_ident(REPLICATION_ROLE),
+        quote_literal(&password)
+    );
+    let errors = execute_statements(cdb, ctx, "postgres", vec![statement]).await?;
+    if !errors.is_empty() {
+        error!("Error creating role {}: {:?}", REPLICATION_ROLE, errors);
+        return Err(Action::requeue(std::time::Duration::from_secs(10)));
+    }
+    Ok(true)
+}
+
+#[instrument(skip(cdb, ctx, previous), fields(instance_name = %cdb.name_any()))]
+async fn reconcile_publications(
+    cdb: &CoreDB,
+    ctx: Arc<Context>,
+    publications: &[Publication],
+    previous: &[ManagedObjectStatus],
+) -> Result<Vec<ManagedObjectStatus>, Action> {
+    let mut statuses = vec![];
+    let role_created = if publications.is_empty() {
+        false
+    } else {
+        reconcile_replication_role(cdb, ctx.clone()).await?
+    };
+
+    let mut databases: Vec<&str> = publications.iter().map(|p| p.database.as_str()).collect();
+    databases.sort();
+    databases.dedup();
+
+    for database in databases {
+        if !check_input(database) {
+            for publication in publications.iter().filter(|p| p.database == database) {
+                statuses.push(invalid(
+                    &publication.name,
+                    database,
+                    format!("invalid database name {}", database),
+                ));
+            }
+            continue;
+        }
+        let output = cdb
+            .psql(
+                LIST_PUBLICATIONS_QUERY.to_owned(),
+                database.to_owned(),
+                ctx.clone(),
+            )
+            .await?;
+        if !output.success {
+            for publication in publications.iter().filter(|p| p.database == database) {
+                statuses.push(invalid(
+                    &publication.name,
+                    database,
+                    format!("failed to list publications: {}", psql_error(&output)),
+                ));
+            }
+            continue;
+        }
+        let observed_publications = parse_publications(output.stdout.as_deref().unwrap_or(""));
+
+        for publication in publications.iter().filter(|p| p.database == database) {
+            if !check_input(&publication.name) {
+                statuses.push(invalid(
+                    &publication.name,
+                    database,
+                    "invalid publication name".to_string(),
+                ));
+                continue;
+            }
+            let tables = match desired_tables(publication) {
+                Ok(tables) => tables,
+                Err(message) => {
+                    statuses.push(invalid(&publication.name, database, message));
+                    continue;
+                }
+            };
+            let observed = observed_publications
+                .iter()
+                .find(|o| o.name == publication.name);
+            let drift = publication_drift(publication, &tables, observed);
+            let mut statements = publication_statements(publication, &tables, observed);
+            if role_created || !statements.is_empty() {
+                statements.extend(replication_grant_statements(publication, &tables));
+            }
+            debug!(
+                "Statements for publication {}: {:?}",
+                publication.name, statements
+            );
+            let errors = execute_statements(cdb, ctx.clone(), database, statements).await?;
+            if !errors.is_empty() {
+                error!(
+                    "Error reconciling publication {}: {:?}",
+                    publication.name, errors
+                );
+            }
+            statuses.push(status(&publication.name, database, drift, &errors));
+        }
+    }
+
+    let current: Vec<(&str, &str)> = publications
+        .iter()
+        .map(|p| (p.name.as_str(), p.database.as_str()))
+        .collect();
+    for removed in removed_objects(previous, &current) {
+        let database = removed.database.clone().unwrap_or("postgres".to_string());
+        info!(
+            "Dropping publication {} removed from the spec of {}",
+            removed.name,
+            cdb.name_any()
+        );
+        let statement = format!("DROP PUBLICATION IF EXISTS {};", quote_ident(&removed.name));
+        let errors = execute_statements(cdb, ctx.clone(), &database, vec![statement]).await?;
+        if !errors.is_empty() {
+            // Keep the publication in the status so that dropping it is retried
+            error!("Error dropping publication {}: {:?}", removed.name, errors);
+            statuses.push(status(
+                &removed.name,
+                &database,
+                vec!["removed from the spec".to_string()],
+                &errors,
+            ));
+        }
+    }
+    Ok(statuses)
+}
+
+#[instrument(skip(cdb, ctx, previous), fields(instance_name = %cdb.name_any()))]
+async fn reconcile_subscriptions(
+    cdb: &CoreDB,
+    ctx: Arc<Context>,
+    subscriptions: &[Subscription],
+    previous: &[ManagedObjectStatus],
+) -> Result<Vec<ManagedObjectStatus>, Action> {
+    let mut statuses = vec![];
+
+    for subscription in subscriptions {
+        let database = subscription.database.as_str();
+        let invalid_names: Vec<&String> = [&subscription.name, &subscription.database]
+            .into_iter()
+            .chain(subscription.publications.iter())
+            .filter(|name| !check_input(name))
+            .collect();
+        if !invalid_names.is_empty() {
+            statuses.push(invalid(
+                &subscription.name,
+                database,
+                format!("invalid names: {:?}", invalid_names),
+            ));
+            continue;
+        }
+        let connection = match subscription_connection(cdb, ctx.clone(), subscription).await? {
+            Ok(connection) => connection,
+            Err(message) => {
+                warn!(
+                    "Not reconciling subscription {}: {}",
+                    subscription.name, message
+                );
+                statuses.push(invalid(&subscription.name, database, message));
+                continue;
+            }
+        };
+
+        let output = cdb
+            .psql(
+                subscription_query(subscription, &connection),
+                database.to_owned(),
+                ctx.clone(),
+            )
+            .await?;
+        if !output.success {
+            statuses.push(invalid(
+                &subscription.name,
+                database,
+                format!("failed to get subscription: {}", psql_error(&output)),
+            ));
+            continue;
+        }
+        let observed = parse_subscription(output.stdout.as_deref().unwrap_or(""));
+        let drift = subscription_drift(subscription, observed.as_ref());
+        // The statements contain the password of the publisher, they are not logged
+        let statements = subscription_statements(subscription, &connection, observed.as_ref());
+        let errors = execute_statements(cdb, ctx.clone(), database, statements).await?;
+        if !errors.is_empty() {
+            error!(
+                "Error reconciling subscription {}: {:?}",
+                subscription.name, errors
+            );
+        }
+        statuses.push(status(&subscription.name, database, drift, &errors));
+    }
+
+    let current: Vec<(&str, &str)> = subscriptions
+        .iter()
+        .map(|s| (s.name.as_str(), s.database.as_str()))
+        .collect();
+    for removed in removed_objects(previous, &current) {
+        let database = removed.database.clone().unwrap_or("postgres".to_string());
+        info!(
+            "Dropping subscription {} removed from the spec of {}",
+            removed.name,
+            cdb.name_any()
+        );
+        // Dropping the subscription also drops its replication slot on the publisher
+        let statement = format!(
+            "DROP SUBSCRIPTION IF EXISTS {};",
+            quote_ident(&removed.name)
+        );
+        let errors = execute_statements(cdb, ctx.clone(), &database, vec![statement]).await?;
+        if !errors.is_empty() {
+            error!("Error dropping subscription {}: {:?}", removed.name, errors);
+            statuses.push(status(
+                &removed.name,
+                &database,
+                vec!["removed from the spec".to_string()],
+                &errors,
+            ));
+        }
+    }
+    Ok(statuses)
+}
+
+/// Reconciles `spec.logicalReplication`: the network policies towards publishers and
+/// subscribers, the publications, the subscriptions and the lag of the replication slots.
+///
+/// Returns None when the instance does not use logical replication.
+#[instrument(skip(cdb, ctx), fields(instance_name = %cdb.name_any()))]
+pub async fn reconcile_logical_replication(
+    cdb: &CoreDB,
+    ctx: Arc<Context>,
+) -> Result<Option<LogicalReplicationStatus>, Action> {
+    let spec = cdb.spec.logical_replication.clone().unwrap_or_default();
+    let previous_status = cdb
+        .status
+        .as_ref()
+        .and_then(|s| s.logical_replication.clone());
+    // Replicas are read-only, publications and subscriptions are reconciled after promotion
+    if cdb.spec.is_replica() {
+        debug!("Skipping logical replication of replica {}", cdb.name_any());
+        return Ok(previous_status);
+    }
+    let previous = previous_status.unwrap_or_default();
+
+    let mut source_namespaces: Vec<String> = spec
+        .subscriptions
+        .iter()
+        .filter_map(|s| s.source.core_db.as_ref())
+        .map(|core_db| core_db.namespace.clone())
+        .collect();
+    source_namespaces.sort();
+    source_namespaces.dedup();
+    reconcile_logical_replication_network_policies(
+        cdb,
+        ctx.client.clone(),
+        &spec.allowed_subscriber_namespaces,
+        &source_namespaces,
+    )
+    .await?;
+
+    if spec.publications.is_empty()
+        && spec.subscriptions.is_empty()
+        && previous.publications.is_empty()
+        && previous.subscriptions.is_empty()
+    {
+        return Ok(None);
+    }
+
+    let publications =
+        reconcile_publications(cdb, ctx.clone(), &spec.publications, &previous.publications)
+            .await?;
+    let subscriptions = reconcile_subscriptions(
+        cdb,
+        ctx.clone(),
+        &spec.subscriptions,
+        &previous.subscriptions,
+    )
+    .await?;
+
+    let slots = if spec.publications.is_empty() {
+        vec![]
+    } else {
+        let output = cdb
+            .psql(
+                LIST_LOGICAL_SLOTS_QUERY.to_owned(),
+                "postgres".to_owned(),
+                ctx.clone(),
+            )
+            .await?;
+        let slots = parse_slots(output.stdout.as_deref().unwrap_or(""));
+        for slot in slots.iter().filter(|slot| !slot.active) {
+            warn!(
+                "Logical replication slot {} of {} is inactive and retains {} bytes of WAL",
+                slot.name,
+                cdb.name_any(),
+                slot.lag_bytes
+            );
+        }
+        slots
+    };
+
+    Ok(Some(LogicalReplicationStatus {
+        publications,
+        subscriptions,
+        slots,
+    }))
+}
+
+/// Disables the subscriptions of the instance before it is hibernated, so that the
+/// apply workers stop cleanly instead of being killed with the instance. They are
+/// enabled again by `reconcile_logical_replication` when the instance is resumed.
+///
+/// This is best effort, errors are logged but do not prevent the hibernation.
+pub async fn disable_subscriptions(cdb: &CoreDB, ctx: Arc<Context>) {
+    let Some(spec) = cdb.spec.logical_replication.as_ref() else {
+        return;
+    };
+    for subscription in spec.subscriptions.iter().filter(|s| s.enabled) {
+        if !check_input(&subscription.name) || !check_input(&subscription.database) {
+            continue;
+        }
+        let statement = format!(
+            "ALTER SUBSCRIPTION {} DISABLE;",
+            quote_ident(&subscription.name)
+        );
+        match cdb
+            .psql(statement, subscription.database.clone(), ctx.clone())
+            .await
+        {
+            Ok(output) if output.success => {
+                info!(
+                    "Disabled subscription {} of {} before hibernation",
+                    subscription.name,
+                    cdb.name_any()
+                );
+            }
+            Ok(output) => warn!(
+                "Failed to disable subscription {} of {}: {}",
+                subscription.name,
+                cdb.name_any(),
+                psql_error(&output)
+            ),
+            Err(_) => warn!(
+                "Failed to disable subscription {} of {}, the instance is not reachable",
+                subscription.name,
+                cdb.name_any()
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::apis::coredb_types::{SubscriptionCoreDBSource, SubscriptionSource};
+
+    fn publication(tables: Vec<&str>) -> Publication {
+        Publication {
+            name: "orders_pub".to_string(),
+            database: "app".to_string(),
+            all_tables: false,
+            tables: tables.into_iter().map(str::to_string).collect(),
+        }
+    }
+
+    fn subscription(enabled: bool) -> Subscription {
+        Subscription {
+            name: "orders_sub".to_string(),
+            database: "app".to_string(),
+            publications: vec!["orders_pub".to_string()],
+            source: SubscriptionSource {
+                core_db: Some(SubscriptionCoreDBSource {
+                    name: "source".to_string(),
+                    namespace: "source".to_string(),
+                    database: "app".to_string(),
+                }),
+                connection_secret: None,
+            },
+            enabled,
+            copy_data: true,
+        }
+    }
+
+    #[test]
+    fn test_publication_statements() {
+        let publication = publication(vec!["orders", "sales.invoices"]);
+        let tables = desired_tables(&publication).unwrap();
+        assert_eq!(tables, vec!["public.orders", "sales.invoices"]);
+        assert_eq!(
+            publication_statements(&publication, &tables, None),
+            vec![
+                "CREATE PUBLICATION \"orders_pub\" FOR TABLE \"public\".\"orders\", \"sales\".\"invoices\";"
+                    .to_string()
+            ]
+        );
+
+        let observed = parse_publications(
+            " pubname    | puballtables | tables
+------------+--------------+---------------
+ orders_pub | f            | public.orders
+(1 row)
+",
+        );
+        assert_eq!(
+            publication_drift(&publication, &tables, observed.first()),
+            vec![
+                "tables are [public.orders] instead of [public.orders, sales.invoices]".to_string()
+            ]
+        );
+        assert_eq!(
+            publication_statements(&publication, &tables, observed.first()),
+            vec![
+                "ALTER PUBLICATION \"orders_pub\" SET TABLE \"public\".\"orders\", \"sales\".\"invoices\";"
+                    .to_string()
+            ]
+        );
+
+        let all_tables = Publication {
+            all_tables: true,
+            ..publication
+        };
+        assert_eq!(
+            publication_statements(&all_tables, &tables, observed.first()),
+            vec![
+                "DROP PUBLICATION \"orders_pub\";".to_string(),
+                "CREATE PUBLICATION \"orders_pub\" FOR ALL TABLES;".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_replication_grant_statements() {
+        let publication = publication(vec!["orders", "sales.invoices"]);
+        let tables = desired_tables(&publication).unwrap();
+        assert_eq!(
+            replication_grant_statements(&publication, &tables),
+            vec![
+                "GRANT USAGE ON SCHEMA \"public\" TO \"tembo_replication\";".to_string(),
+                "GRANT USAGE ON SCHEMA \"sales\" TO \"tembo_replication\";".to_string(),
+                "GRANT SELECT ON \"public\".\"orders\", \"sales\".\"invoices\" TO \"tembo_replication\";"
+                    .to_string(),
+            ]
+        );
+
+        let all_tables = Publication {
+            all_tables: true,
+            ..publication
+        };
+        assert_eq!(
+            replication_grant_statements(&all_tables, &[]),
+            vec!["GRANT pg_read_all_data TO \"tembo_replication\";".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_invalid_table_name() {
+        let publication = publication(vec!["public.orders; DROP TABLE x"]);
+        assert!(desired_tables(&publication).is_err());
+    }
+
+    #[test]
+    fn test_subscription_statements() {
+        let subscription = subscription(true);
+        let connection = "host=source-rw.source.svc.cluster.local password=it's";
+        assert_eq!(
+            subscription_statements(&subscription, connection, None),
+            vec![
+                "CREATE SUBSCRIPTION \"orders_sub\" CONNECTION 'host=source-rw.source.svc.cluster.local password=it''s' PUBLICATION \"orders_pub\" WITH (enabled = true, copy_data = true);"
+                    .to_string()
+            ]
+        );
+
+        // A subscription disabled for hibernation is enabled again
+        let observed = parse_subscription(
+            " subenabled | array_to_string | ?column?
+------------+-----------------+----------
+ f          | orders_pub      | t
+(1 row)
+",
+        )
+        .unwrap();
+        assert_eq!(
+            subscription_drift(&subscription, Some(&observed)),
+            vec!["enabled is false instead of true".to_string()]
+        );
+        assert_eq!(
+            subscription_statements(&subscription, connection, Some(&observed)),
+            vec!["ALTER SUBSCRIPTION \"orders_sub\" ENABLE;".to_string()]
+        );
+
+        // No rows means the subscription does not exist
+        assert!(parse_subscription(
+            " subenabled | array_to_string | ?column?
+------------+-----------------+----------
+(0 rows)
+"
+        )
+        .is_none());
+    }
+
+    #[test]
+    fn test_parse_slots() {
+        let slots = parse_slots(
+            " slot_name  | database | active | lag_bytes
+------------+----------+--------+-----------
+ orders_sub | app      | f      |   1048576
+(1 row)
+",
+        );
+        assert_eq!(
+            slots,
+            vec![ReplicationSlotStatus {
+                name: "orders_sub".to_string(),
+                database: "app".to_string(),
+                active: false,
+                lag_bytes: 1048576,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_removed_objects() {
+        let previous = vec![
+            invalid("orders_sub", "app", "".to_string()),
+            invalid("old_sub", "app", "".to_string()),
+        ];
+        let removed = removed_objects(&previous, &[("orders_sub", "app")]);
+        assert_eq!(removed.len(), 1);
+        assert_eq!(removed[0].name, "old_sub");
+    }
+}
@@ -3,7 +3,7 @@ use kube::ResourceExt;
 use opentelemetry::trace::TraceId;
 use prometheus_client::{
     encoding::EncodeLabelSet,
-    metrics::{counter::Counter, exemplar::HistogramWithExemplars, family::Family},
+    metrics::{counter::Counter, exemplar::HistogramWithExemplars, family::Family, gauge::Gauge},
     registry::{Registry, Unit},
 };
 use std::sync::Arc;
@@ -12,6 +12,7 @@ use tokio::time::Instant;
 #[derive(Clone)]
 pub struct Metrics {
     pub reconcile: ReconcileMetrics,
+    pub backup_verification: BackupVerificationMetrics,
     pub registry: Arc<Registry>,
 }
 
@@ -19,9 +20,11 @@ impl Default for Metrics {
     fn default() -> Self {
         let mut registry = Registry::with_prefix("doc_ctrl_reconcile");
         let reconcile = ReconcileMetrics::default().register(&mut registry);
+        let backup_verification = BackupVerificationMetrics::default().register(&mut registry);
         Self {
             registry: Arc::new(registry),
             reconcile,
+            backup_verification,
         }
     }
 }
@@ -101,6 +104,58 @@ impl ReconcileMetrics {
     }
 }
 
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct InstanceLabels {
+    pub instance: String,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct VerificationResultLabels {
+    pub instance: String,
+    pub result: String,
+}
+
+#[derive(Clone, Default)]
+pub struct BackupVerificationMetrics {
+    pub verifications: Family<VerificationResultLabels, Counter>,
+    pub last_success: Family<InstanceLabels, Gauge>,
+}
+
+impl BackupVerificationMetrics {
+    /// Register backup verification metrics to start tracking them.
+    pub fn register(self, r: &mut Registry) -> Self {
+        r.register(
+            "backup_verifications",
+            "backup verifications by result",
+            self.verifications.clone(),
+        );
+        r.register_with_unit(
+            "backup_verification_last_success",
+            "completion time of the last backup verified successfully",
+            Unit::Seconds,
+            self.last_success.clone(),
+        );
+        self
+    }
+
+    pub fn set_result(&self, cdb: &CoreDB, succeeded: bool, verified_backup: Option<i64>) {
+        let result = if succeeded { "succeeded" } else { "failed" };
+        self.verifications
+            .get_or_create(&VerificationResultLabels {
+                instance: cdb.name_any(),
+                result: result.to_string(),
+            })
+            .inc();
+        if let (true, Some(timestamp)) = (succeeded, verified_backup) {
+            self.last_success
+                .get_or_create(&InstanceLabels {
+                    instance: cdb.name_any(),
+                })
+                .set(timestamp);
+        }
+    }
+}
+
 /// Smart function duration measurer
 ///
 /// Relies on Drop to calculate duration and register the observation in the histogram
@@ -1,17 +1,25 @@
+use crate::{
+    apis::coredb_types::{CoreDB, EgressRule, EgressService},
+    app_service::types::COMPONENT_NAME,
+};
 use k8s_openapi::api::{
     core::v1::{Endpoints, Service},
     networking::v1::NetworkPolicy,
 };
 use kube::{
-    api::{Patch, PatchParams},
+    api::{DeleteParams, Patch, PatchParams},
     runtime::controller::Action,
-    Api, Client,
+    Api, Client, ResourceExt,
 };
 use serde_json::Value;
-use std::time::Duration;
-use tracing::{debug, error};
+use std::{collections::BTreeMap, time::Duration};
+use tracing::{debug, error, warn};
 
-pub async fn reconcile_network_policies(client: Client, namespace: &str) -> Result<(), Action> {
+pub async fn reconcile_network_policies(
+    cdb: &CoreDB,
+    client: Client,
+    namespace: &str,
+) -> Result<(), Action> {
     let kubernetes_api_ip_addresses = lookup_kubernetes_api_ips(&client).await?;
 
     let np_api: Api<NetworkPolicy> = Api::namespaced(client, namespace);
@@ -256,7 +264,16 @@ pub async fn reconcile_network_policies(client: Client, namespace: &str) -> Resu
           ]
         }
     });
-    apply_network_policy(namespace, &np_api, allow_public_internet).await?;
+    let allow_internet_egress = cdb
+        .spec
+        .network_policy
+        .as_ref()
+        .is_none_or(|np| np.allow_internet_egress);
+    if allow_internet_egress {
+        apply_network_policy(namespace, &np_api, allow_public_internet).await?;
+    } else {
+        delete_network_policy(namespace, &np_api, "allow-egress-to-internet").await?;
+    }
 
     let allow_within_namespace = serde_json::json!({
         "apiVersion": "networking.k8s.io/v1",
@@ -398,6 +415,274 @@ pub async fn reconcile_network_policies(client: Client, namespace: &str) -> Resu
     Ok(())
 }
 
+// Allow subscribers of the publications of the instance to reach Postgres, and the
+// instance to reach the publishers of its subscriptions. Policies that are no longer
+// needed are removed.
+pub async fn reconcile_logical_replication_network_policies(
+    cdb: &CoreDB,
+    client: Client,
+    subscriber_namespaces: &[String],
+    source_namespaces: &[String],
+) -> Result<(), Action> {
+    let namespace = cdb.namespace().unwrap();
+    let np_api: Api<NetworkPolicy> = Api::namespaced(client, &namespace);
+
+    let namespace_selectors = |namespaces: &[String]| -> Vec<Value> {
+        namespaces
+            .iter()
+            .map(|ns| {
+                serde_json::json!({
+                    "namespaceSelector": {
+                        "matchLabels": {
+                            "kubernetes.io/metadata.name": ns
+                        }
+                    }
+                })
+            })
+            .collect()
+    };
+
+    let ingress_name = "allow-logical-replication-subscribers";
+    if subscriber_namespaces.is_empty() {
+        delete_network_policy(&namespace, &np_api, ingress_name).await?;
+    } else {
+        let allow_subscribers = serde_json::json!({
+            "apiVersion": "networking.k8s.io/v1",
+            "kind": "NetworkPolicy",
+            "metadata": {
+                "name": ingress_name,
+                "namespace": namespace,
+            },
+            "spec": {
+                "podSelector": {
+                    "matchLabels": {
+                        "cnpg.io/cluster": cdb.name_any()
+                    }
+                },
+                "policyTypes": ["Ingress"],
+                "ingress": [
+                    {
+                        "ports": [
+                            {
+                                "port": 5432,
+                                "protocol": "TCP"
+                            }
+                        ],
+                        "from": namespace_selectors(subscriber_namespaces)
+                    }
+                ]
+            }
+        });
+        apply_network_policy(&namespace, &np_api, allow_subscribers).await?;
+    }
+
+    let egress_name = "allow-logical-replication-publishers";
+    if source_namespaces.is_empty() {
+        delete_network_policy(&namespace, &np_api, egress_name).await?;
+    } else {
+        let allow_publishers = serde_json::json!({
+            "apiVersion": "networking.k8s.io/v1",
+            "kind": "NetworkPolicy",
+            "metadata": {
+                "name": egress_name,
+                "namespace": namespace,
+            },
+            "spec": {
+                "podSelector": {
+                    "matchLabels": {
+                        "cnpg.io/cluster": cdb.name_any()
+                    }
+                },
+                "policyTypes": ["Egress"],
+                "egress": [
+                    {
+                        "ports": [
+                            {
+                                "port": 5432,
+                                "protocol": "TCP"
+                            }
+                        ],
+                        "to": namespace_selectors(source_namespaces)
+                    }
+                ]
+            }
+        });
+        apply_network_policy(&namespace, &np_api, allow_publishers).await?;
+    }
+    Ok(())
+}
+
+// Allow the egress declared in spec.networkPolicy from the instance pods, and from the
+// app service pods unless disabled. Services are resolved to the pods they select, since
+// network policies can not target services.
+pub async fn reconcile_egress_network_policies(cdb: &CoreDB, client: Client) -> Result<(), Action> {
+    let name = cdb.name_any();
+    let namespace = cdb.namespace().unwrap();
+    let np_api: Api<NetworkPolicy> = Api::namespaced(client.clone(), &namespace);
+    let instance_policy_name = format!("{name}-egress");
+    let app_services_policy_name = format!("{name}-app-services-egress");
+
+    let Some(network_policy) = cdb
+        .spec
+        .network_policy
+        .as_ref()
+        .filter(|np| !np.egress.is_empty())
+    else {
+        delete_network_policy(&namespace, &np_api, &instance_policy_name).await?;
+        delete_network_policy(&namespace, &np_api, &app_services_policy_name).await?;
+        return Ok(());
+    };
+
+    let mut service_selectors = BTreeMap::new();
+    for service in network_policy.egress.iter().flat_map(|r| r.services.iter()) {
+        let service_api: Api<Service> = Api::namespaced(client.clone(), &service.namespace);
+        match service_api.get_opt(&service.name).await {
+            Ok(Some(svc)) => match svc.spec.and_then(|spec| spec.selector) {
+                Some(selector) => {
+                    service_selectors.insert(service.clone(), selector);
+                }
+                None => warn!(
+                    "Service {}.{} has no selector, skipping it in the egress of {}",
+                    service.name, service.namespace, name
+                ),
+            },
+            Ok(None) => warn!(
+                "Service {}.{} not found, skipping it in the egress of {}",
+                service.name, service.namespace, name
+            ),
+            Err(e) => {
+                error!(
+                    "Failed to get Service {}.{}: {}",
+                    service.name, service.namespace, e
+                );
+                return Err(Action::requeue(Duration::from_secs(300)));
+            }
+        }
+    }
+    let egress = egress_rules(&network_policy.egress, &service_selectors);
+
+    let instance_pods = BTreeMap::from([("cnpg.io/cluster".to_string(), name.clone())]);
+    let instance_policy =
+        egress_network_policy(&instance_policy_name, &namespace, instance_pods, &egress);
+    apply_network_policy(&namespace, &np_api, instance_policy).await?;
+
+    if network_policy.apply_to_app_services {
+        let app_pods = BTreeMap::from([
+            ("component".to_string(), COMPONENT_NAME.to_string()),
+            ("coredb.io/name".to_string(), name.clone()),
+        ]);
+        let app_services_policy =
+            egress_network_policy(&app_services_policy_name, &namespace, app_pods, &egress);
+        apply_network_policy(&namespace, &np_api, app_services_policy).await?;
+    } else {
+        delete_network_policy(&namespace, &np_api, &app_services_policy_name).await?;
+    }
+    Ok(())
+}
+
+fn egress_network_policy(
+    name: &str,
+    namespace: &str,
+    pod_labels: BTreeMap<String, String>,
+    egress: &[Value],
+) -> Value {
+    serde_json::json!({
+        "apiVersion": "networking.k8s.io/v1",
+        "kind": "NetworkPolicy",
+        "metadata": {
+            "name": name,
+            "namespace": namespace,
+        },
+        "spec": {
+            "podSelector": {
+                "matchLabels": pod_labels
+            },
+            "policyTypes": ["Egress"],
+            "egress": egress
+        }
+    })
+}
+
+// Renders the egress rules, skipping invalid CIDRs and services that could not be
+// resolved. Rules left without a destination are dropped, since an empty `to` would
+// allow every destination.
+fn egress_rules(
+    rules: &[EgressRule],
+    service_selectors: &BTreeMap<EgressService, BTreeMap<String, String>>,
+) -> Vec<Value> {
+    let mut egress = vec![];
+    for rule in rules {
+        let mut to: Vec<Value> = vec![];
+        for cidr in rule.cidrs.iter() {
+            if is_valid_cidr(cidr) {
+                to.push(serde_json::json!({ "ipBlock": { "cidr": cidr } }));
+            } else {
+                warn!("Skipping invalid egress CIDR {}", cidr);
+            }
+        }
+        for service in rule.services.iter() {
+            if let Some(selector) = service_selectors.get(service) {
+                to.push(serde_json::json!({
+                    "namespaceSelector": {
+                        "matchLabels": {
+                            "kubernetes.io/metadata.name": service.namespace
+                        }
+                    },
+                    "podSelector": {
+                        "matchLabels": selector
+                    }
+                }));
+            }
+        }
+        if to.is_empty() {
+            continue;
+        }
+        let ports: Vec<Value> = rule
+            .ports
+            .iter()
+            .map(|p| serde_json::json!({ "port": p.port, "protocol": p.protocol }))
+            .collect();
+        if ports.is_empty() {
+            egress.push(serde_json::json!({ "to": to }));
+        } else {
+            egress.push(serde_json::json!({ "to": to, "ports": ports }));
+        }
+    }
+    egress
+}
+
+pub(crate) fn is_valid_cidr(cidr: &str) -> bool {
+    let Some((ip, prefix)) = cidr.split_once('/') else {
+        return false;
+    };
+    match ip.parse::<std::net::IpAddr>() {
+        Ok(std::net::IpAddr::V4(_)) => prefix.parse::<u8>().is_ok_and(|p| p <= 32),
+        Ok(std::net::IpAddr::V6(_)) => prefix.parse::<u8>().is_ok_and(|p| p <= 128),
+        Err(_) => false,
+    }
+}
+
+async fn delete_network_policy(
+    namespace: &str,
+    np_api: &Api<NetworkPolicy>,
+    name: &str,
+) -> Result<(), Action> {
+    match np_api.delete(name, &DeleteParams::default()).await {
+        Ok(_) => {
+            debug!("Deleted Network Policy {} in namespace {}", name, namespace);
+            Ok(())
+        }
+        Err(kube::Error::Api(ae)) if ae.code == 404 => Ok(()),
+        Err(e) => {
+            error!(
+                "Failed to delete Network Policy {} in namespace {}: {}",
+                name, namespace, e
+            );
+            Err(Action::requeue(Duration::from_secs(300)))
+        }
+    }
+}
+
 // This function essentially does
 // kubectl get svc -n default kubernetes
 // kubectl get endpoints -n default kubernetes
@@ -507,3 +792,69 @@ pub async fn apply_network_policy(
     };
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::apis::coredb_types::EgressPort;
+
+    #[test]
+    fn test_egress_rules() {
+        let warehouse = EgressService {
+            name: "warehouse-rw".to_string(),
+            namespace: "org-acme-inst-warehouse".to_string(),
+        };
+        let missing = EgressService {
+            name: "missing".to_string(),
+            namespace: "default".to_string(),
+        };
+        let rules = vec![
+            EgressRule {
+                cidrs: vec!["52.216.0.0/15".to_string(), "not-a-cidr".to_string()],
+                services: vec![],
+                ports: vec![EgressPort {
+                    port: 443,
+                    protocol: "TCP".to_string(),
+                }],
+            },
+            EgressRule {
+                cidrs: vec![],
+                services: vec![warehouse.clone()],
+                ports: vec![],
+            },
+            // Would allow everything if rendered with an empty `to`
+            EgressRule {
+                cidrs: vec![],
+                services: vec![missing],
+                ports: vec![],
+            },
+        ];
+        let selectors = BTreeMap::from([(
+            warehouse,
+            BTreeMap::from([("cnpg.io/cluster".to_string(), "warehouse".to_string())]),
+        )]);
+
+        let egress = egress_rules(&rules, &selectors);
+        assert_eq!(
+            egress,
+            vec![
+                serde_json::json!({
+                    "to": [{ "ipBlock": { "cidr": "52.216.0.0/15" } }],
+                    "ports": [{ "port": 443, "protocol": "TCP" }]
+                }),
+                serde_json::json!({
+                    "to": [{
+                        "namespaceSelector": {
+                            "matchLabels": {
+                                "kubernetes.io/metadata.name": "org-acme-inst-warehouse"
+                            }
+                        },
+                        "podSelector": {
+                            "matchLabels": { "cnpg.io/cluster": "warehouse" }
+                        }
+                    }]
+                }),
+            ]
+        );
+    }
+}
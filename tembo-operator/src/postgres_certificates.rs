@@ -1,17 +1,21 @@
 use crate::{
-    apis::coredb_types::CoreDB,
+    apis::coredb_types::{CoreDB, TlsStatus},
     certmanager::certificates::Certificate,
+    cloudnativepg::{clusters::Cluster, cnpg::get_cluster},
+    patch_cdb_status_merge,
     secret::{b64_encode, fetch_all_decoded_data_from_secret},
+    Context,
 };
+use chrono::{DateTime, Utc};
 use k8s_openapi::api::core::v1::Secret;
 use kube::{
     api::{Api, Patch, PatchParams},
     runtime::controller::Action,
-    Client,
+    Client, ResourceExt,
 };
 use serde_json::{json, Value};
-use std::time::Duration;
-use tracing::{debug, error};
+use std::{collections::BTreeMap, sync::Arc, time::Duration};
+use tracing::{debug, error, warn};
 
 const POSTGRES_CA_SECRET_NAME: &str = "postgres-ca-secret";
 const POSTGRES_CA_SECRET_CERT_KEY_NAME: &str = "ca.crt";
@@ -22,6 +26,25 @@ pub async fn reconcile_certificates(
     coredb: &CoreDB,
     namespace: &str,
 ) -> Result<(), Action> {
+    // A server certificate requested in spec.tls replaces the shared CA certificates
+    if let Some(tls) = coredb.spec.tls.as_ref() {
+        let coredb_name = coredb.name_any();
+        if let Some(secret) = tls.server_secret.as_ref() {
+            debug!("Using server certificate {secret} provided for {coredb_name}");
+            return Ok(());
+        }
+        if let Some(issuer) = tls.issuer.as_ref() {
+            let certificates_api: Api<Certificate> = Api::namespaced(client, namespace);
+            let issuer_ref = json!({
+                "name": issuer.name,
+                "kind": issuer.kind,
+                "group": "cert-manager.io"
+            });
+            let server_certificate = server_certificate(&coredb_name, namespace, issuer_ref);
+            return apply_certificate(namespace, &certificates_api, server_certificate).await;
+        }
+    }
+
     match std::env::var("USE_SHARED_CA") {
         Ok(_) => {}
         Err(_) => {
@@ -90,59 +113,16 @@ pub async fn reconcile_certificates(
         }
     };
 
-    let common_name = format!("{}-rw", coredb_name);
-    let mut dns_names = vec![
-        format!("{}-rw", coredb_name),
-        format!("{}-rw.{}", coredb_name, namespace),
-        format!("{}-rw.{}.svc", coredb_name, namespace),
-        format!("{}-rw.{}.svc.cluster.local", coredb_name, namespace),
-        format!("{}-r", coredb_name),
-        format!("{}-r.{}", coredb_name, namespace),
-        format!("{}-r.{}.svc", coredb_name, namespace),
-        format!("{}-r.{}.svc.cluster.local", coredb_name, namespace),
-        format!("{}-ro", coredb_name),
-        format!("{}-ro.{}", coredb_name, namespace),
-        format!("{}-ro.{}.svc", coredb_name, namespace),
-        format!("{}-ro.{}.svc.cluster.local", coredb_name, namespace),
-        format!("{}-pooler", coredb_name),
-        format!("{}-pooler.{}", coredb_name, namespace),
-        format!("{}-pooler.{}.svc", coredb_name, namespace),
-        format!("{}-pooler.{}.svc.cluster.local", coredb_name, namespace),
-    ];
-    match std::env::var("DATA_PLANE_BASEDOMAIN") {
-        Ok(basedomain) => {
-            let extra_domain_name = format!("{}.{}", coredb_name, basedomain);
-            let extra_pooler_domain_name = format!("{}-pooler.{}", coredb_name, basedomain);
-            let extra_ro_domain_name = format!("{}-ro.{}", coredb_name, basedomain);
-            dns_names.push(extra_domain_name);
-            dns_names.push(extra_pooler_domain_name);
-            dns_names.push(extra_ro_domain_name);
-        }
-        Err(_) => {
-            debug!("DATA_PLANE_BASEDOMAIN not set, not adding custom DNS name");
-        }
-    };
-
     // Create the first Certificate
-    let server_certificate = json!({
-        "apiVersion": "cert-manager.io/v1",
-        "kind": "Certificate",
-        "metadata": {
-            "name": format!("{}-server1", coredb_name),
-            "namespace": namespace,
-        },
-        "spec": {
-            "secretName": format!("{}-server1", coredb_name),
-            "usages": ["server auth"],
-            "dnsNames": dns_names,
-            "commonName": common_name,
-            "issuerRef": {
-                "name": POSTGRES_CERTIFICATE_ISSUER_NAME,
-                "kind": "ClusterIssuer",
-                "group": "cert-manager.io"
-            }
-        }
-    });
+    let server_certificate = server_certificate(
+        coredb_name,
+        namespace,
+        json!({
+            "name": POSTGRES_CERTIFICATE_ISSUER_NAME,
+            "kind": "ClusterIssuer",
+            "group": "cert-manager.io"
+        }),
+    );
 
     apply_certificate(namespace, &certificates_api, server_certificate).await?;
 
@@ -194,6 +174,59 @@ pub async fn reconcile_certificates(
     Ok(())
 }
 
+// server_certificate returns the Certificate `<name>-server1` for the service names of the
+// instance, issued by `issuer_ref`
+fn server_certificate(coredb_name: &str, namespace: &str, issuer_ref: Value) -> Value {
+    let common_name = format!("{}-rw", coredb_name);
+    let mut dns_names = vec![
+        format!("{}-rw", coredb_name),
+        format!("{}-rw.{}", coredb_name, namespace),
+        format!("{}-rw.{}.svc", coredb_name, namespace),
+        format!("{}-rw.{}.svc.cluster.local", coredb_name, namespace),
+        format!("{}-r", coredb_name),
+        format!("{}-r.{}", coredb_name, namespace),
+        format!("{}-r.{}.svc", coredb_name, namespace),
+        format!("{}-r.{}.svc.cluster.local", coredb_name, namespace),
+        format!("{}-ro", coredb_name),
+        format!("{}-ro.{}", coredb_name, namespace),
+        format!("{}-ro.{}.svc", coredb_name, namespace),
+        format!("{}-ro.{}.svc.cluster.local", coredb_name, namespace),
+        format!("{}-pooler", coredb_name),
+        format!("{}-pooler.{}", coredb_name, namespace),
+        format!("{}-pooler.{}.svc", coredb_name, namespace),
+        format!("{}-pooler.{}.svc.cluster.local", coredb_name, namespace),
+    ];
+    match std::env::var("DATA_PLANE_BASEDOMAIN") {
+        Ok(basedomain) => {
+            let extra_domain_name = format!("{}.{}", coredb_name, basedomain);
+            let extra_pooler_domain_name = format!("{}-pooler.{}", coredb_name, basedomain);
+            let extra_ro_domain_name = format!("{}-ro.{}", coredb_name, basedomain);
+            dns_names.push(extra_domain_name);
+            dns_names.push(extra_pooler_domain_name);
+            dns_names.push(extra_ro_domain_name);
+        }
+        Err(_) => {
+            debug!("DATA_PLANE_BASEDOMAIN not set, not adding custom DNS name");
+        }
+    };
+
+    json!({
+        "apiVersion": "cert-manager.io/v1",
+        "kind": "Certificate",
+        "metadata": {
+            "name": format!("{}-server1", coredb_name),
+            "namespace": namespace,
+        },
+        "spec": {
+            "secretName": format!("{}-server1", coredb_name),
+            "usages": ["server auth"],
+            "dnsNames": dns_names,
+            "commonName": common_name,
+            "issuerRef": issuer_ref
+        }
+    })
+}
+
 async fn apply_certificate(
     namespace: &str,
     cert_api: &Api<Certificate>,
@@ -233,3 +266,114 @@ async fn apply_certificate(
     };
     Ok(())
 }
+
+// reconcile_tls_status reports the expiration of the certificates in use, as found by
+// CNPG in the cluster status, in `status.tls`
+pub async fn reconcile_tls_status(cdb: &CoreDB, ctx: Arc<Context>) -> Result<(), Action> {
+    let Some(cluster) = get_cluster(cdb, ctx.clone()).await else {
+        return Ok(());
+    };
+    let Some(tls_status) = tls_status_from_cluster(&cluster) else {
+        return Ok(());
+    };
+    if cdb.status.as_ref().and_then(|s| s.tls.as_ref()) == Some(&tls_status) {
+        return Ok(());
+    }
+
+    let name = cdb.name_any();
+    let coredbs: Api<CoreDB> = Api::namespaced(ctx.client.clone(), &cdb.namespace().unwrap());
+    let patch_status = json!({
+        "apiVersion": "coredb.io/v1alpha1",
+        "kind": "CoreDB",
+        "status": {
+            "tls": tls_status
+        }
+    });
+    patch_cdb_status_merge(&coredbs, &name, patch_status).await
+}
+
+fn tls_status_from_cluster(cluster: &Cluster) -> Option<TlsStatus> {
+    let certificates = cluster.status.as_ref()?.certificates.as_ref()?;
+    let mut expirations = BTreeMap::new();
+    for (secret, expiration) in certificates.expirations.clone().unwrap_or_default() {
+        match parse_cnpg_expiration(&expiration) {
+            Some(expires_at) => {
+                expirations.insert(secret, expires_at);
+            }
+            None => warn!("Could not parse expiration {expiration} of certificate {secret}"),
+        }
+    }
+    let server_secret = certificates.server_tls_secret.clone();
+    let server_certificate_expiration = server_secret
+        .as_ref()
+        .and_then(|secret| expirations.get(secret).cloned());
+    Some(TlsStatus {
+        server_secret,
+        server_certificate_expiration,
+        expirations,
+    })
+}
+
+// CNPG reports expirations with the default Go time format, for example
+// `2025-01-15 10:00:00 +0000 UTC`
+fn parse_cnpg_expiration(expiration: &str) -> Option<DateTime<Utc>> {
+    let without_zone_name = expiration
+        .split_whitespace()
+        .take(3)
+        .collect::<Vec<_>>()
+        .join(" ");
+    DateTime::parse_from_str(&without_zone_name, "%Y-%m-%d %H:%M:%S %z")
+        .or_else(|_| DateTime::parse_from_rfc3339(expiration))
+        .ok()
+        .map(|t| t.with_timezone(&Utc))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_cnpg_expiration() {
+        let expected = "2025-01-15T10:00:00Z".parse::<DateTime<Utc>>().unwrap();
+        assert_eq!(
+            parse_cnpg_expiration("2025-01-15 10:00:00 +0000 UTC"),
+            Some(expected)
+        );
+        assert_eq!(
+            parse_cnpg_expiration("2025-01-15 12:00:00 +0200 CEST"),
+            Some(expected)
+        );
+        assert_eq!(
+            parse_cnpg_expiration("2025-01-15T10:00:00Z"),
+            Some(expected)
+        );
+        assert_eq!(parse_cnpg_expiration("soon"), None);
+    }
+
+    #[test]
+    fn test_tls_status_from_cluster() {
+        let cluster: Cluster = serde_json::from_value(json!({
+            "apiVersion": "postgresql.cnpg.io/v1",
+            "kind": "Cluster",
+            "metadata": {"name": "test"},
+            "spec": {"instances": 1},
+            "status": {
+                "certificates": {
+                    "serverTLSSecret": "customer-server",
+                    "expirations": {
+                        "customer-server": "2025-01-15 10:00:00 +0000 UTC",
+                        "test-replication": "2025-04-15 10:00:00 +0000 UTC"
+                    }
+                }
+            }
+        }))
+        .unwrap();
+        let status = tls_status_from_cluster(&cluster).unwrap();
+        assert_eq!(status.server_secret.as_deref(), Some("customer-server"));
+        assert_eq!(
+            status.server_certificate_expiration,
+            Some("2025-01-15T10:00:00Z".parse::<DateTime<Utc>>().unwrap())
+        );
+        assert_eq!(status.expirations.len(), 2);
+    }
+}
@@ -64,7 +64,7 @@ pub async fn reconcile_secret(cdb: &CoreDB, ctx: Arc<Context>) -> Result<(), Act
         }
     };
 
-    let data = secret_data(cdb, &ns, password);
+    let data = secret_data(cdb, &ns, "postgres", password);
 
     let secret: Secret = Secret {
         metadata: ObjectMeta {
@@ -92,12 +92,16 @@ pub async fn reconcile_secret(cdb: &CoreDB, ctx: Arc<Context>) -> Result<(), Act
     }
 }
 
-fn secret_data(cdb: &CoreDB, ns: &str, password: String) -> BTreeMap<String, ByteString> {
+fn secret_data(
+    cdb: &CoreDB,
+    ns: &str,
+    user: &str,
+    password: String,
+) -> BTreeMap<String, ByteString> {
     let mut data = BTreeMap::new();
 
     // encode and insert user into secret data
-    let user = "postgres".to_owned();
-    let b64_user = b64_encode(&user);
+    let b64_user = b64_encode(user);
     // Add as both 'user' and 'username'
     data.insert("user".to_owned(), b64_user.clone());
     data.insert("username".to_owned(), b64_user);
@@ -158,6 +162,76 @@ fn secret_data(cdb: &CoreDB, ns: &str, password: String) -> BTreeMap<String, Byt
     data
 }
 
+// role_connection_secret_name returns the name of the connection secret of a role in spec.roles
+pub fn role_connection_secret_name(cdb: &CoreDB, role_name: &str) -> String {
+    format!("{}-{}-connection", cdb.name_any(), role_name)
+}
+
+// Set the connection secret of a role in spec.roles, with the same keys as the superuser
+// connection secret
+pub async fn reconcile_role_connection_secret(
+    cdb: &CoreDB,
+    ctx: Arc<Context>,
+    role_name: &str,
+    password: String,
+) -> Result<(), Action> {
+    let ns = cdb.namespace().unwrap();
+    let name = role_connection_secret_name(cdb, role_name);
+    let secret_api: Api<Secret> = Api::namespaced(ctx.client.clone(), &ns);
+    let oref = cdb.controller_owner_ref(&()).unwrap();
+    let mut labels: BTreeMap<String, String> = BTreeMap::new();
+    labels.insert("role".to_owned(), role_name.to_string());
+    labels.insert("tembo.io/name".to_owned(), cdb.name_any());
+
+    let secret: Secret = Secret {
+        metadata: ObjectMeta {
+            name: Some(name.to_owned()),
+            namespace: Some(ns.to_owned()),
+            labels: Some(labels),
+            owner_references: Some(vec![oref]),
+            ..ObjectMeta::default()
+        },
+        data: Some(secret_data(cdb, &ns, role_name, password)),
+        ..Secret::default()
+    };
+
+    let ps = PatchParams::apply("cntrlr").force();
+    match secret_api.patch(&name, &ps, &Patch::Apply(&secret)).await {
+        Ok(_) => {
+            debug!(
+                "Successfully updated secret {} for role {}",
+                name, role_name
+            );
+            Ok(())
+        }
+        Err(e) => {
+            error!(
+                "Error updating secret {} for role {}: {:?}",
+                name, role_name, e
+            );
+            Err(Action::requeue(Duration::from_secs(10)))
+        }
+    }
+}
+
+// Lookup a single key of a secret, returns None if the secret or the key does not exist
+pub async fn fetch_decoded_secret_key(
+    ctx: Arc<Context>,
+    namespace: &str,
+    secret_name: &str,
+    key: &str,
+) -> Result<Option<String>, Action> {
+    let secret_api: Api<Secret> = Api::namespaced(ctx.client.clone(), namespace);
+    let secret = secret_api.get_opt(secret_name).await.map_err(|e| {
+        error!("Error getting secret {}: {:?}", secret_name, e);
+        Action::requeue(Duration::from_secs(10))
+    })?;
+    Ok(secret
+        .and_then(|secret| secret.data)
+        .and_then(|data| data.get(key).cloned())
+        .and_then(|value| String::from_utf8(value.0).ok()))
+}
+
 // Set postgres-exporter secret
 pub async fn reconcile_postgres_role_secret(
     cdb: &CoreDB,
@@ -276,7 +350,7 @@ pub fn b64_encode(string: &str) -> ByteString {
     ByteString(bytes_vec)
 }
 
-fn generate_password() -> String {
+pub fn generate_password() -> String {
     let pg = PasswordGenerator {
         length: 16,
         numbers: true,
@@ -0,0 +1,348 @@
+use crate::{
+    apis::coredb_types::{CoreDB, StorageAutoscaling, StorageAutoscalingStatus},
+    patch_cdb_status_merge, Context,
+};
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use k8s_openapi::apimachinery::pkg::api::resource::Quantity;
+use kube::{
+    api::{Patch, PatchParams},
+    runtime::{
+        controller::Action,
+        events::{Event, EventType},
+    },
+    Api, Resource, ResourceExt,
+};
+use serde_json::json;
+use std::sync::Arc;
+use tokio::time::Duration;
+use tracing::{debug, error, info, instrument, warn};
+
+// The mount point of the data volume in the CNPG instance pods
+const DATA_VOLUME_PATH: &str = "/var/lib/postgresql/data";
+
+const GIBIBYTE: u64 = 1024 * 1024 * 1024;
+
+/// What the autoscaler decided for the current usage of the data volume
+#[derive(Debug, PartialEq)]
+enum ScalingDecision {
+    /// The usage is below the threshold, or a resize happened recently
+    Keep,
+    /// Grow `spec.storage` to the given size
+    Grow(Quantity),
+    /// The usage is above the threshold, but `spec.storage` is already at the max size
+    MaxSizeReached,
+}
+
+/// Parses a Kubernetes quantity into bytes, e.g. `10Gi` or `500M`
+pub(crate) fn quantity_to_bytes(quantity: &Quantity) -> Option<u64> {
+    let value = quantity.0.trim();
+    let split = value
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(value.len());
+    let (number, suffix) = value.split_at(split);
+    let number: f64 = number.parse().ok()?;
+    let multiplier: u64 = match suffix {
+        "" => 1,
+        "Ki" => 1 << 10,
+        "Mi" => 1 << 20,
+        "Gi" => 1 << 30,
+        "Ti" => 1 << 40,
+        "Pi" => 1 << 50,
+        "k" => 1_000,
+        "M" => 1_000_000,
+        "G" => 1_000_000_000,
+        "T" => 1_000_000_000_000,
+        "P" => 1_000_000_000_000_000,
+        _ => return None,
+    };
+    Some((number * multiplier as f64).ceil() as u64)
+}
+
+// Rounds up to whole gibibytes, the granularity of cloud volumes
+fn bytes_to_quantity(bytes: u64) -> Quantity {
+    Quantity(format!("{}Gi", bytes.div_ceil(GIBIBYTE)))
+}
+
+// Parses the POSIX output of `df -P -k`: the second line holds the size and the
+// used space in kibibytes. Returns the usage of the volume in percent.
+fn parse_df_used_percent(output: &str) -> Option<u8> {
+    let fields: Vec<&str> = output.lines().nth(1)?.split_whitespace().collect();
+    let size: u64 = fields.get(1)?.parse().ok()?;
+    let used: u64 = fields.get(2)?.parse().ok()?;
+    if size == 0 {
+        return None;
+    }
+    Some(((used * 100).div_ceil(size)).min(100) as u8)
+}
+
+fn decide_scaling(
+    policy: &StorageAutoscaling,
+    storage: &Quantity,
+    used_percent: u8,
+    last_scaled_at: Option<DateTime<Utc>>,
+    now: DateTime<Utc>,
+) -> ScalingDecision {
+    if used_percent < policy.threshold_percent {
+        return ScalingDecision::Keep;
+    }
+    let in_cooldown = last_scaled_at
+        .is_some_and(|at| now - at < ChronoDuration::minutes(policy.cooldown_minutes));
+    if in_cooldown {
+        return ScalingDecision::Keep;
+    }
+    let (Some(current), Some(step), Some(max)) = (
+        quantity_to_bytes(storage),
+        quantity_to_bytes(&policy.step),
+        quantity_to_bytes(&policy.max_size),
+    ) else {
+        warn!("Invalid storage quantities in storageAutoscaling, not growing the volume");
+        return ScalingDecision::Keep;
+    };
+    if current >= max {
+        return ScalingDecision::MaxSizeReached;
+    }
+    let grown = bytes_to_quantity(current + step);
+    match quantity_to_bytes(&grown) {
+        Some(bytes) if bytes < max => ScalingDecision::Grow(grown),
+        _ => ScalingDecision::Grow(policy.max_size.clone()),
+    }
+}
+
+// Returns the usage of the data volume of the primary, None when it can't be checked
+async fn data_volume_used_percent(cdb: &CoreDB, ctx: &Arc<Context>) -> Option<u8> {
+    // The primary is not ready when its data volume is full
+    let pod = cdb
+        .primary_pod_cnpg_ready_or_not(ctx.client.clone())
+        .await
+        .ok()?;
+    let command = vec![
+        "df".to_string(),
+        "-P".to_string(),
+        "-k".to_string(),
+        DATA_VOLUME_PATH.to_string(),
+    ];
+    match cdb.exec(pod.name_any(), ctx.client.clone(), &command).await {
+        Ok(output) if output.success => output.stdout.as_deref().and_then(parse_df_used_percent),
+        Ok(output) => {
+            warn!(
+                "Error checking the data volume usage of {}: {:?}",
+                cdb.name_any(),
+                output.stderr
+            );
+            None
+        }
+        Err(e) => {
+            warn!(
+                "Error checking the data volume usage of {}: {:?}",
+                cdb.name_any(),
+                e
+            );
+            None
+        }
+    }
+}
+
+async fn publish_event(
+    cdb: &CoreDB,
+    ctx: &Arc<Context>,
+    type_: EventType,
+    reason: &str,
+    note: String,
+) {
+    let event = Event {
+        type_,
+        reason: reason.into(),
+        note: Some(note),
+        action: "StorageAutoscaling".into(),
+        secondary: None,
+    };
+    if let Err(e) = ctx.recorder.publish(&event, &cdb.object_ref(&())).await {
+        warn!("Error publishing event for {}: {:?}", cdb.name_any(), e);
+    }
+}
+
+/// Grows `spec.storage` according to `spec.storageAutoscaling` when the data volume of
+/// the primary is fuller than the threshold. Resizes and reaching the max size are
+/// reported with Kubernetes events, and the usage in `status.storage_autoscaling`.
+///
+/// Returns a short requeue after growing the volume, so that the Cluster is updated
+/// from the new spec.
+#[instrument(skip(cdb, ctx), fields(trace_id, instance_name = %cdb.name_any()))]
+pub async fn reconcile_storage_autoscaling(cdb: &CoreDB, ctx: Arc<Context>) -> Result<(), Action> {
+    let Some(policy) = cdb.spec.storage_autoscaling.as_ref() else {
+        return Ok(());
+    };
+    let name = cdb.name_any();
+    let Some(used_percent) = data_volume_used_percent(cdb, &ctx).await else {
+        return Ok(());
+    };
+    debug!("Data volume of {} is {}% full", name, used_percent);
+
+    let namespace = cdb.namespace().ok_or_else(|| {
+        error!("Namespace is not set for CoreDB instance {}", name);
+        Action::requeue(Duration::from_secs(300))
+    })?;
+    let coredbs: Api<CoreDB> = Api::namespaced(ctx.client.clone(), &namespace);
+    let previous = cdb
+        .status
+        .as_ref()
+        .and_then(|s| s.storage_autoscaling.clone())
+        .unwrap_or_default();
+    let mut status = StorageAutoscalingStatus {
+        used_percent: Some(used_percent),
+        max_size_reached: false,
+        ..previous.clone()
+    };
+
+    let now = Utc::now();
+    let decision = decide_scaling(
+        policy,
+        &cdb.spec.storage,
+        used_percent,
+        previous.last_scaled_at,
+        now,
+    );
+    match &decision {
+        ScalingDecision::Keep => {}
+        ScalingDecision::MaxSizeReached => {
+            status.max_size_reached = true;
+            if !previous.max_size_reached {
+                warn!(
+                    "Data volume of {} is {}% full and at its max size {}",
+                    name, used_percent, policy.max_size.0
+                );
+                publish_event(
+                    cdb,
+                    &ctx,
+                    EventType::Warning,
+                    "StorageMaxSizeReached",
+                    format!(
+                        "Data volume is {}% full, refusing to grow past maxSize {}",
+                        used_percent, policy.max_size.0
+                    ),
+                )
+                .await;
+            }
+        }
+        ScalingDecision::Grow(size) => {
+            let patch = json!({ "spec": { "storage": size } });
+            let pp = PatchParams {
+                field_manager: Some("cntrlr".to_string()),
+                ..PatchParams::default()
+            };
+            coredbs
+                .patch(&name, &pp, &Patch::Merge(&patch))
+                .await
+                .map_err(|e| {
+                    error!("Error growing storage of {}: {:?}", name, e);
+                    Action::requeue(Duration::from_secs(10))
+                })?;
+            info!(
+                "Growing storage of {} from {} to {}",
+                name, cdb.spec.storage.0, size.0
+            );
+            publish_event(
+                cdb,
+                &ctx,
+                EventType::Normal,
+                "StorageAutoscaled",
+                format!(
+                    "Data volume is {}% full, growing storage from {} to {}",
+                    used_percent, cdb.spec.storage.0, size.0
+                ),
+            )
+            .await;
+            status.last_scaled_at = Some(now);
+            status.last_scaled_to = Some(size.clone());
+        }
+    }
+
+    if status != previous {
+        let patch_status = json!({
+            "apiVersion": "coredb.io/v1alpha1",
+            "kind": "CoreDB",
+            "status": {
+                "storage_autoscaling": status
+            }
+        });
+        patch_cdb_status_merge(&coredbs, &name, patch_status).await?;
+    }
+
+    match decision {
+        ScalingDecision::Grow(_) => Err(Action::requeue(Duration::from_secs(5))),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> StorageAutoscaling {
+        StorageAutoscaling {
+            threshold_percent: 80,
+            step: Quantity("10Gi".to_string()),
+            max_size: Quantity("25Gi".to_string()),
+            cooldown_minutes: 360,
+        }
+    }
+
+    #[test]
+    fn test_quantity_to_bytes() {
+        assert_eq!(
+            quantity_to_bytes(&Quantity("8Gi".to_string())),
+            Some(8 * GIBIBYTE)
+        );
+        assert_eq!(
+            quantity_to_bytes(&Quantity("1.5Gi".to_string())),
+            Some(3 * GIBIBYTE / 2)
+        );
+        assert_eq!(
+            quantity_to_bytes(&Quantity("500M".to_string())),
+            Some(500_000_000)
+        );
+        assert_eq!(quantity_to_bytes(&Quantity("1024".to_string())), Some(1024));
+        assert_eq!(quantity_to_bytes(&Quantity("10Xi".to_string())), None);
+        assert_eq!(bytes_to_quantity(8 * GIBIBYTE + 1).0, "9Gi");
+    }
+
+    #[test]
+    fn test_parse_df_used_percent() {
+        let output = "Filesystem     1024-blocks    Used Available Capacity Mounted on\n\
+                      /dev/nvme1n1       8154588 6523670   1630918      80% /var/lib/postgresql/data\n";
+        assert_eq!(parse_df_used_percent(output), Some(80));
+        assert_eq!(parse_df_used_percent("garbage"), None);
+    }
+
+    #[test]
+    fn test_decide_scaling() {
+        let now: DateTime<Utc> = "2024-11-12T12:00:00Z".parse().unwrap();
+        let storage = Quantity("10Gi".to_string());
+
+        assert_eq!(
+            decide_scaling(&policy(), &storage, 79, None, now),
+            ScalingDecision::Keep
+        );
+        assert_eq!(
+            decide_scaling(&policy(), &storage, 80, None, now),
+            ScalingDecision::Grow(Quantity("20Gi".to_string()))
+        );
+        // Within the cooldown
+        let recently = Some(now - ChronoDuration::minutes(30));
+        assert_eq!(
+            decide_scaling(&policy(), &storage, 95, recently, now),
+            ScalingDecision::Keep
+        );
+        // Capped at the max size
+        let storage = Quantity("20Gi".to_string());
+        assert_eq!(
+            decide_scaling(&policy(), &storage, 90, None, now),
+            ScalingDecision::Grow(Quantity("25Gi".to_string()))
+        );
+        let storage = Quantity("25Gi".to_string());
+        assert_eq!(
+            decide_scaling(&policy(), &storage, 90, None, now),
+            ScalingDecision::MaxSizeReached
+        );
+    }
+}
@@ -6,13 +6,24 @@ use serde::{Deserialize, Serialize};
 use std::borrow::Cow;
 use std::fmt::Display;
 use std::ops::Not;
-use std::{collections::BTreeMap, env, time::Duration};
+use std::{
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    env,
+    sync::{Arc, RwLock},
+    time::{Duration, Instant, SystemTime},
+};
 
 use crate::configmap::apply_configmap;
 use tracing::error;
 use utoipa::ToSchema;
 
-const DEFAULT_TRUNK_REGISTRY_DOMAIN: &str = "registry.pgtrunk.io";
+const DEFAULT_TRUNK_REGISTRY_URL: &str = "https://registry.pgtrunk.io";
+
+// How long the cached Trunk metadata is used before it is refreshed from the registry
+const TRUNK_METADATA_TTL: Duration = Duration::from_secs(600);
+
+// How many older project versions are cached for pinned extension versions
+const TRUNK_VERSIONS_CACHE_SIZE: usize = 256;
 
 // One configmap per namespace
 // multiple DBs in the same namespace can share the same configmap
@@ -85,6 +96,38 @@ pub struct TrunkControlFileMetadata {
     pub content: Option<String>,
 }
 
+/// Dependencies between extensions, as published in the Trunk metadata
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+pub struct ExtensionDependencies {
+    /// The extensions each extension depends on
+    pub dependencies: BTreeMap<String, Vec<String>>,
+    /// The Trunk project providing each extension
+    pub trunk_projects: BTreeMap<String, String>,
+}
+
+impl ExtensionDependencies {
+    pub fn from_trunk_projects(projects: &[TrunkProjectMetadata]) -> Self {
+        let mut extension_dependencies = ExtensionDependencies::default();
+        for project in projects {
+            for extension in project.extensions.iter() {
+                extension_dependencies
+                    .trunk_projects
+                    .insert(extension.extension_name.clone(), project.name.clone());
+                let dependencies = extension
+                    .dependencies_extension_names
+                    .clone()
+                    .unwrap_or_default();
+                if !dependencies.is_empty() {
+                    extension_dependencies
+                        .dependencies
+                        .insert(extension.extension_name.clone(), dependencies);
+                }
+            }
+        }
+        extension_dependencies
+    }
+}
+
 // This is a place to configure specific exceptions before
 // Trunk handles everything.
 // In terms of extensions that require load, we need to know
@@ -150,18 +193,88 @@ pub async fn extensions_that_require_load(
     }
 }
 
-pub async fn reconcile_trunk_configmap(client: Client, namespace: &str) -> Result<(), Action> {
-    let libraries = match requires_load_list_from_trunk().await {
-        Ok(libraries) => libraries,
-        Err(e) => {
+// Get the dependencies between extensions from the trunk metadata configmap
+pub async fn extension_dependencies(
+    client: Client,
+    namespace: &str,
+) -> Result<ExtensionDependencies, Action> {
+    let cm_api: Api<ConfigMap> = Api::namespaced(client, namespace);
+
+    let cm = match cm_api.get(TRUNK_CONFIGMAP_NAME).await {
+        Ok(configmap) => configmap,
+        Err(_) => {
+            error!("Failed to get trunk configmap in namespace {}", namespace);
+            return Err(Action::requeue(Duration::from_secs(300)));
+        }
+    };
+    // Configmaps written by older versions of the operator have no dependencies
+    match cm.data.as_ref().and_then(|data| data.get("dependencies")) {
+        None => Ok(ExtensionDependencies::default()),
+        Some(dependencies) => serde_json::from_str(dependencies).map_err(|e| {
             error!(
-                "Failed to update extensions libraries list from trunk: {:?}",
-                e
+                "Invalid dependencies in trunk metadata configmap in namespace {}: {}",
+                namespace, e
             );
+            Action::requeue(Duration::from_secs(300))
+        }),
+    }
+}
+
+// Get the names of the extensions known to Trunk from the trunk metadata configmap, or None
+// if the configmap or the cached projects are not available yet
+pub async fn known_extension_names(client: Client, namespace: &str) -> Option<BTreeSet<String>> {
+    let cm_api: Api<ConfigMap> = Api::namespaced(client, namespace);
+    let cm = cm_api.get_opt(TRUNK_CONFIGMAP_NAME).await.ok()??;
+    let projects: Vec<TrunkProjectMetadata> = cm
+        .data
+        .as_ref()
+        .and_then(|data| data.get("projects"))
+        .and_then(|projects| serde_json::from_str(projects).ok())?;
+    let mut names: BTreeSet<String> = projects
+        .iter()
+        .flat_map(|project| {
+            std::iter::once(project.name.clone()).chain(
+                project
+                    .extensions
+                    .iter()
+                    .map(|extension| extension.extension_name.clone()),
+            )
+        })
+        .collect();
+    names.extend(
+        EXTRA_EXTENSIONS_REQUIRE_LOAD
+            .iter()
+            .map(|extension| extension.name.clone()),
+    );
+    Some(names)
+}
+
+pub async fn reconcile_trunk_configmap(client: Client, namespace: &str) -> Result<(), Action> {
+    let projects = match cached_trunk_projects(true) {
+        Some(projects) => Ok(projects),
+        None => fetch_trunk_projects().await.inspect(|projects| {
+            cache_trunk_projects(projects.clone());
+        }),
+    };
+    let projects = match projects {
+        Ok(projects) => projects,
+        Err(e) => {
+            error!("Failed to update extensions metadata from trunk: {:?}", e);
             let cm_api: Api<ConfigMap> = Api::namespaced(client.clone(), namespace);
             match cm_api.get(TRUNK_CONFIGMAP_NAME).await {
-                Ok(_) => {
+                Ok(cm) => {
                     // If the configmap is already present, we can just log the error and continue
+                    // with the metadata cached in it
+                    if cached_trunk_projects(false).is_none() {
+                        if let Some(projects) = cm
+                            .data
+                            .as_ref()
+                            .and_then(|data| data.get("projects"))
+                            .and_then(|projects| serde_json::from_str(projects).ok())
+                        {
+                            cache_trunk_projects(projects);
+                        }
+                    }
                     return Ok(());
                 }
                 Err(_e) => {
@@ -173,8 +286,33 @@ pub async fn reconcile_trunk_configmap(client: Client, namespace: &str) -> Resul
         }
     };
 
+    let libraries = requires_load_list(&projects);
+    let dependencies = ExtensionDependencies::from_trunk_projects(&projects);
+
     let mut data = BTreeMap::new();
     data.insert("libraries".to_string(), libraries.join(","));
+    data.insert(
+        "dependencies".to_string(),
+        serde_json::to_string(&dependencies).unwrap(),
+    );
+    // Cache the metadata so it survives restarts of the operator while the registry is
+    // unreachable. Control files and download links are not needed for reconciliation and
+    // are dropped to keep the configmap small.
+    let cached_projects: Vec<TrunkProjectMetadata> = projects
+        .iter()
+        .cloned()
+        .map(|mut project| {
+            project.downloads = None;
+            for extension in project.extensions.iter_mut() {
+                extension.control_file.content = None;
+            }
+            project
+        })
+        .collect();
+    data.insert(
+        "projects".to_string(),
+        serde_json::to_string(&cached_projects).unwrap(),
+    );
 
     match apply_configmap(client, namespace, TRUNK_CONFIGMAP_NAME, data).await {
         Ok(_) => Ok(()),
@@ -186,52 +324,220 @@ pub async fn reconcile_trunk_configmap(client: Client, namespace: &str) -> Resul
 }
 
 /// List of extensions that require load
-async fn requires_load_list_from_trunk() -> Result<Vec<String>, TrunkError> {
-    let projects = get_trunk_projects().await?;
-    let libraries = projects
-        .into_iter()
-        .flat_map(|project| project.extensions)
-        .filter_map(|extension| extension.loadable_libraries)
-        .flat_map(|libraries| libraries.into_iter().map(|library| library.library_name))
-        .collect();
+fn requires_load_list(projects: &[TrunkProjectMetadata]) -> Vec<String> {
+    projects
+        .iter()
+        .flat_map(|project| project.extensions.iter())
+        .filter_map(|extension| extension.loadable_libraries.as_ref())
+        .flat_map(|libraries| libraries.iter().map(|library| library.library_name.clone()))
+        .collect()
+}
 
-    Ok(libraries)
+// The registry Trunk metadata and packages are fetched from. TRUNK_REGISTRY_URL can point
+// to an internal mirror for clusters without internet access.
+pub fn trunk_registry_url() -> String {
+    let url = match (
+        env::var("TRUNK_REGISTRY_URL"),
+        env::var("TRUNK_REGISTRY_DOMAIN"),
+    ) {
+        (Ok(url), _) if !url.is_empty() => url,
+        (_, Ok(domain)) if !domain.is_empty() => format!("https://{domain}"),
+        _ => DEFAULT_TRUNK_REGISTRY_URL.to_string(),
+    };
+    url.trim_end_matches('/').to_string()
 }
 
-// Get all trunk projects
-pub async fn get_trunk_projects() -> Result<Vec<TrunkProjectMetadata>, TrunkError> {
-    let domain = env::var("TRUNK_REGISTRY_DOMAIN")
-        .unwrap_or_else(|_| DEFAULT_TRUNK_REGISTRY_DOMAIN.to_string());
-    let url = format!("https://{}/api/v1/trunk-projects", domain);
+// When TRUNK_REGISTRY_INDEX is set, the metadata is read from this file instead of the
+// registry API. The index is a JSON list in the format of /api/v1/trunk-projects, with an
+// entry for every version of every project, and is usually mounted from a ConfigMap or PVC.
+fn trunk_registry_index() -> Option<String> {
+    env::var("TRUNK_REGISTRY_INDEX")
+        .ok()
+        .filter(|path| !path.is_empty())
+}
 
-    let response = reqwest::get(&url).await?;
+// The parsed index is cached until the file is modified, e.g. when the ConfigMap it is
+// mounted from is updated
+async fn read_trunk_registry_index(
+    path: &str,
+) -> Result<Arc<Vec<TrunkProjectMetadata>>, TrunkError> {
+    let read_error =
+        |e: std::io::Error| TrunkError::IndexError(format!("failed to read {path}: {e}"));
+    let modified = tokio::fs::metadata(path)
+        .await
+        .and_then(|metadata| metadata.modified())
+        .map_err(read_error)?;
+    if let Some(index) = cached_trunk_registry_index(path, modified) {
+        return Ok(index);
+    }
 
-    if response.status().is_success() {
-        let project_metadata: Vec<TrunkProjectMetadata> = response.json().await?;
-        Ok(project_metadata)
-    } else {
-        error!("Failed to fetch all trunk projects: {}", response.status());
-        Err(TrunkError::NetworkFailure(
-            response.error_for_status().unwrap_err(),
-        ))
+    let index = tokio::fs::read_to_string(path).await.map_err(read_error)?;
+    let index: Arc<Vec<TrunkProjectMetadata>> = Arc::new(
+        serde_json::from_str(&index)
+            .map_err(|e| TrunkError::IndexError(format!("failed to parse {path}: {e}")))?,
+    );
+    let mut cache = TRUNK_PROJECTS_CACHE.write().unwrap();
+    cache.index = Some((path.to_string(), modified, index.clone()));
+    Ok(index)
+}
+
+// Keep the latest version of each project, in the order the projects first appear
+fn latest_trunk_projects(index: &[TrunkProjectMetadata]) -> Vec<TrunkProjectMetadata> {
+    let mut latest: Vec<TrunkProjectMetadata> = vec![];
+    for project in index.iter().cloned() {
+        match latest.iter_mut().find(|p| p.name == project.name) {
+            Some(existing) => {
+                let newer = match (
+                    semver::Version::parse(&convert_to_semver(&project.version)),
+                    semver::Version::parse(&convert_to_semver(&existing.version)),
+                ) {
+                    (Ok(new), Ok(current)) => new > current,
+                    _ => false,
+                };
+                if newer {
+                    *existing = project;
+                }
+            }
+            None => latest.push(project),
+        }
     }
+    latest
 }
 
-// Get all trunk project names
-pub async fn get_trunk_project_names() -> Result<Vec<String>, TrunkError> {
-    let domain = env::var("TRUNK_REGISTRY_DOMAIN")
-        .unwrap_or_else(|_| DEFAULT_TRUNK_REGISTRY_DOMAIN.to_string());
-    let url = format!("https://{}/api/v1/trunk-projects", domain);
+// Whether the metadata is the given version of a project
+fn is_trunk_project_version(
+    metadata: &TrunkProjectMetadata,
+    trunk_project_name: &str,
+    version: Version<'_>,
+) -> bool {
+    metadata.name == trunk_project_name
+        && match version {
+            Version::TrunkProject(project_version) => {
+                convert_to_semver(&metadata.version) == convert_to_semver(project_version)
+            }
+            Version::Extension(extension_version) => metadata
+                .extensions
+                .iter()
+                .any(|ext| ext.version == extension_version),
+        }
+}
+
+// Find the metadata of a project version in a list of projects
+fn find_trunk_project_version(
+    projects: &[TrunkProjectMetadata],
+    trunk_project_name: &str,
+    version: Version<'_>,
+) -> Option<TrunkProjectMetadata> {
+    projects
+        .iter()
+        .find(|metadata| is_trunk_project_version(metadata, trunk_project_name, version))
+        .cloned()
+}
+
+lazy_static! {
+    // The latest metadata of all Trunk projects, so reconciliation does not need to reach
+    // the registry. It is refreshed by reconcile_trunk_configmap once it is older than
+    // TRUNK_METADATA_TTL, and restored from the trunk configmap when the registry is
+    // unreachable after a restart of the operator. The older project versions looked up for
+    // pinned extension versions, and the parsed index file, are kept as well.
+    static ref TRUNK_PROJECTS_CACHE: RwLock<TrunkProjectsCache> =
+        RwLock::new(TrunkProjectsCache::default());
+}
+
+#[derive(Default)]
+struct TrunkProjectsCache {
+    latest: Option<(Instant, Vec<TrunkProjectMetadata>)>,
+    // Older project versions fetched from the registry, which do not change once published.
+    // The oldest entries are evicted beyond TRUNK_VERSIONS_CACHE_SIZE.
+    versions: VecDeque<TrunkProjectMetadata>,
+    // The path, modification time and projects of the index file
+    index: Option<(String, SystemTime, Arc<Vec<TrunkProjectMetadata>>)>,
+}
+
+impl TrunkProjectsCache {
+    fn insert_version(&mut self, project: &TrunkProjectMetadata) {
+        if self
+            .versions
+            .iter()
+            .any(|p| p.name == project.name && p.version == project.version)
+        {
+            return;
+        }
+        if self.versions.len() >= TRUNK_VERSIONS_CACHE_SIZE {
+            self.versions.pop_front();
+        }
+        self.versions.push_back(project.clone());
+    }
+}
+
+fn cached_trunk_projects(fresh: bool) -> Option<Vec<TrunkProjectMetadata>> {
+    let cache = TRUNK_PROJECTS_CACHE.read().unwrap();
+    cache
+        .latest
+        .as_ref()
+        .filter(|(fetched_at, _)| !fresh || fetched_at.elapsed() < TRUNK_METADATA_TTL)
+        .map(|(_, projects)| projects.clone())
+}
+
+fn cache_trunk_projects(projects: Vec<TrunkProjectMetadata>) {
+    let mut cache = TRUNK_PROJECTS_CACHE.write().unwrap();
+    cache.latest = Some((Instant::now(), projects));
+}
+
+fn cached_trunk_project_version(
+    trunk_project_name: &str,
+    version: Version<'_>,
+) -> Option<TrunkProjectMetadata> {
+    let cache = TRUNK_PROJECTS_CACHE.read().unwrap();
+    cache
+        .latest
+        .as_ref()
+        .and_then(|(_, projects)| find_trunk_project_version(projects, trunk_project_name, version))
+        .or_else(|| {
+            cache
+                .versions
+                .iter()
+                .find(|metadata| is_trunk_project_version(metadata, trunk_project_name, version))
+                .cloned()
+        })
+}
+
+fn cache_trunk_project_version(project: &TrunkProjectMetadata) {
+    TRUNK_PROJECTS_CACHE
+        .write()
+        .unwrap()
+        .insert_version(project);
+}
+
+fn cached_trunk_registry_index(
+    path: &str,
+    modified: SystemTime,
+) -> Option<Arc<Vec<TrunkProjectMetadata>>> {
+    let cache = TRUNK_PROJECTS_CACHE.read().unwrap();
+    cache
+        .index
+        .as_ref()
+        .filter(|(cached_path, cached_modified, _)| {
+            cached_path == path && *cached_modified == modified
+        })
+        .map(|(_, _, index)| index.clone())
+}
+
+// Fetch the latest metadata of all trunk projects from the index file or the registry
+async fn fetch_trunk_projects() -> Result<Vec<TrunkProjectMetadata>, TrunkError> {
+    if let Some(index) = trunk_registry_index() {
+        return Ok(latest_trunk_projects(
+            &read_trunk_registry_index(&index).await?,
+        ));
+    }
+
+    let url = format!("{}/api/v1/trunk-projects", trunk_registry_url());
 
     let response = reqwest::get(&url).await?;
 
     if response.status().is_success() {
         let project_metadata: Vec<TrunkProjectMetadata> = response.json().await?;
-        let project_names = project_metadata
-            .into_iter()
-            .map(|project_metadata| project_metadata.name)
-            .collect();
-        Ok(project_names)
+        Ok(project_metadata)
     } else {
         error!("Failed to fetch all trunk projects: {}", response.status());
         Err(TrunkError::NetworkFailure(
@@ -240,33 +546,40 @@ pub async fn get_trunk_project_names() -> Result<Vec<String>, TrunkError> {
     }
 }
 
+// Get all trunk projects
+pub async fn get_trunk_projects() -> Result<Vec<TrunkProjectMetadata>, TrunkError> {
+    if let Some(projects) = cached_trunk_projects(true) {
+        return Ok(projects);
+    }
+    match fetch_trunk_projects().await {
+        Ok(projects) => {
+            cache_trunk_projects(projects.clone());
+            Ok(projects)
+        }
+        // Stale metadata is preferred over failing when the registry is unreachable
+        Err(e) => cached_trunk_projects(false).ok_or(e),
+    }
+}
+
+// Get all trunk project names
+pub async fn get_trunk_project_names() -> Result<Vec<String>, TrunkError> {
+    let project_names = get_trunk_projects()
+        .await?
+        .into_iter()
+        .map(|project_metadata| project_metadata.name)
+        .collect();
+    Ok(project_names)
+}
+
 // Get the latest metadata entries for a given Trunk project
 async fn get_latest_trunk_project_metadata(
     trunk_project: &str,
 ) -> Result<TrunkProjectMetadata, TrunkError> {
-    let domain = env::var("TRUNK_REGISTRY_DOMAIN")
-        .unwrap_or_else(|_| DEFAULT_TRUNK_REGISTRY_DOMAIN.to_string());
-    let url = format!("https://{}/api/v1/trunk-projects", domain);
-
-    let response = reqwest::get(&url).await?;
-
-    if response.status().is_success() {
-        let projects: Vec<TrunkProjectMetadata> = response.json().await?;
-
-        projects
-            .into_iter()
-            .find(|project| project.name == trunk_project)
-            .ok_or_else(|| TrunkError::ProjectNotFound(trunk_project.to_owned()))
-    } else {
-        error!(
-            "Failed to fetch metadata for trunk project {}: {}",
-            trunk_project,
-            response.status()
-        );
-        Err(TrunkError::NetworkFailure(
-            response.error_for_status().unwrap_err(),
-        ))
-    }
+    get_trunk_projects()
+        .await?
+        .into_iter()
+        .find(|project| project.name == trunk_project)
+        .ok_or_else(|| TrunkError::ProjectNotFound(trunk_project.to_owned()))
 }
 
 // Get trunk project metadata for a specific version
@@ -274,9 +587,6 @@ pub async fn get_trunk_project_metadata_for_version(
     trunk_project_name: &str,
     version: Version<'_>,
 ) -> Result<TrunkProjectMetadata, TrunkError> {
-    let domain = env::var("TRUNK_REGISTRY_DOMAIN")
-        .unwrap_or_else(|_| DEFAULT_TRUNK_REGISTRY_DOMAIN.to_string());
-
     let converted_semver;
 
     let version = match version {
@@ -287,12 +597,31 @@ pub async fn get_trunk_project_metadata_for_version(
         extension => extension,
     };
 
+    // The index file has every version of every project
+    if let Some(index) = trunk_registry_index() {
+        let projects = read_trunk_registry_index(&index).await?;
+        return find_trunk_project_version(&projects, trunk_project_name, version).ok_or_else(
+            || match version {
+                Version::TrunkProject(_) => {
+                    TrunkError::TrunkProjectVersionNotFound(version.to_string())
+                }
+                Version::Extension(_) => TrunkError::ExtensionVersionNotFound(version.to_string()),
+            },
+        );
+    }
+
+    // The latest versions, and the versions fetched before, are served from the cache
+    if let Some(project) = cached_trunk_project_version(trunk_project_name, version) {
+        return Ok(project);
+    }
+
+    let registry = trunk_registry_url();
     let url = match version {
         Version::TrunkProject(trunk_project_version) => format!(
-            "https://{domain}/api/v1/trunk-projects/{trunk_project_name}/version/{trunk_project_version}"
+            "{registry}/api/v1/trunk-projects/{trunk_project_name}/version/{trunk_project_version}"
         ),
         Version::Extension(_extension_version) => {
-            format!("https://{domain}/api/v1/trunk-projects/{trunk_project_name}")
+            format!("{registry}/api/v1/trunk-projects/{trunk_project_name}")
         }
     };
 
@@ -324,16 +653,8 @@ pub async fn get_trunk_project_metadata_for_version(
                 }
             }
         }
-        Version::Extension(extension_version) => {
-            let trunk_project = project_metadata.into_iter().find(|metadata| {
-                metadata.name == trunk_project_name
-                    && metadata
-                        .extensions
-                        .iter()
-                        .any(|ext| ext.version == extension_version)
-            });
-
-            match trunk_project {
+        Version::Extension(_) => {
+            match find_trunk_project_version(&project_metadata, trunk_project_name, version) {
                 Some(project) => project,
                 None => {
                     error!(
@@ -345,6 +666,7 @@ pub async fn get_trunk_project_metadata_for_version(
         }
     };
 
+    cache_trunk_project_version(&trunk_project);
     Ok(trunk_project)
 }
 
@@ -520,12 +842,79 @@ pub enum TrunkError {
     ExtensionVersionNotFound(String),
     #[error("Trunk project with version '{0}' not found")]
     TrunkProjectVersionNotFound(String),
+    #[error("Invalid Trunk registry index: {0}")]
+    IndexError(String),
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn project(name: &str, version: &str, extension_version: &str) -> TrunkProjectMetadata {
+        TrunkProjectMetadata {
+            name: name.to_string(),
+            description: None,
+            documentation_link: None,
+            repository_link: None,
+            version: version.to_string(),
+            postgres_versions: None,
+            extensions: vec![TrunkExtensionMetadata {
+                extension_name: name.to_string(),
+                version: extension_version.to_string(),
+                trunk_project_name: name.to_string(),
+                dependencies_extension_names: None,
+                loadable_libraries: None,
+                configurations: None,
+                control_file: TrunkControlFileMetadata {
+                    absent: false,
+                    content: None,
+                },
+            }],
+            downloads: None,
+        }
+    }
+
+    #[test]
+    fn test_trunk_registry_index_lookups() {
+        let index = vec![
+            project("pgmq", "1.1.1", "1.1.1"),
+            project("pg_cron", "1.6.2", "1.6"),
+            project("pgmq", "1.4.4", "1.4.4"),
+            project("pgmq", "1.3.0", "1.3.0"),
+        ];
+
+        let found = find_trunk_project_version(&index, "pgmq", Version::TrunkProject("1.3"));
+        assert_eq!(found.unwrap().version, "1.3.0");
+        let found = find_trunk_project_version(&index, "pg_cron", Version::Extension("1.6"));
+        assert_eq!(found.unwrap().version, "1.6.2");
+        assert!(
+            find_trunk_project_version(&index, "pgmq", Version::TrunkProject("2.0.0")).is_none()
+        );
+
+        let latest = latest_trunk_projects(&index);
+        let latest: Vec<(&str, &str)> = latest
+            .iter()
+            .map(|p| (p.name.as_str(), p.version.as_str()))
+            .collect();
+        assert_eq!(latest, vec![("pgmq", "1.4.4"), ("pg_cron", "1.6.2")]);
+    }
+
+    #[test]
+    fn test_trunk_versions_cache_is_bounded() {
+        let mut cache = TrunkProjectsCache::default();
+        for i in 0..TRUNK_VERSIONS_CACHE_SIZE + 2 {
+            cache.insert_version(&project("pgmq", &format!("1.{i}.0"), &format!("1.{i}.0")));
+        }
+        cache.insert_version(&project("pgmq", "1.3.0", "1.3.0"));
+        assert_eq!(cache.versions.len(), TRUNK_VERSIONS_CACHE_SIZE);
+        // The oldest versions are evicted first
+        assert_eq!(cache.versions[0].version, "1.2.0");
+        assert_eq!(
+            cache.versions.back().unwrap().version,
+            format!("1.{}.0", TRUNK_VERSIONS_CACHE_SIZE + 1)
+        );
+    }
+
     #[tokio::test]
     async fn test_get_trunk_projects() {
         let result = get_trunk_projects().await;
@@ -701,13 +1090,14 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn test_requires_load_list_from_trunk() {
+    async fn test_requires_load_list() {
         // To ensure backwards compatibility with the older endpoint, let's ensure
         // that all of the results in that list are contained in the response from the newer v1 endpoint
-        let libraries_from_legacy_endpoint = requires_load_list_from_trunk().await.unwrap();
+        let libraries_from_legacy_endpoint =
+            requires_load_list(&get_trunk_projects().await.unwrap());
         assert!(libraries_from_legacy_endpoint.is_empty().not());
 
-        let libraries_from_v1_endpoint = requires_load_list_from_trunk().await.unwrap();
+        let libraries_from_v1_endpoint = requires_load_list(&get_trunk_projects().await.unwrap());
 
         for expected_library in libraries_from_legacy_endpoint {
             assert!(
@@ -0,0 +1,608 @@
+// Validation of CoreDB specs, shared by the admission webhook, conductor and the CLI.
+// The operator tolerates most of these mistakes by logging and skipping the invalid
+// values, validating up front reports them to the user instead.
+use crate::{
+    apis::{
+        coredb_types::{BackupRetention, CoreDB, CoreDBSpec},
+        postgres_parameters::{PgConfig, DISALLOWED_CONFIGS},
+    },
+    app_service::{jobs::app_job_errors, types::Middleware},
+    cloudnativepg::{cnpg::recovery_target_error, cnpg_utils::cron_schedule},
+    databases::{is_reserved_membership, is_reserved_role},
+    dedicated_networking::is_allowed_hostname,
+    extensions::database_queries::check_input,
+    ingress::VALID_IPV4_CIDR_BLOCK,
+    network_policies::is_valid_cidr,
+};
+use k8s_openapi::{
+    api::core::v1::ResourceRequirements, apimachinery::pkg::api::resource::Quantity,
+};
+use kube::Client;
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::{collections::BTreeSet, fmt};
+
+pub use crate::trunk::known_extension_names;
+
+lazy_static! {
+    static ref VALID_QUANTITY: Regex =
+        Regex::new(r"^[+-]?([0-9]+(\.[0-9]*)?|\.[0-9]+)([KMGTPE]i|[numkMGTPE]|[eE][+-]?[0-9]+)?$")
+            .unwrap();
+    static ref VALID_IPV4_CIDR: Regex = Regex::new(VALID_IPV4_CIDR_BLOCK).unwrap();
+}
+
+// Extensions shipped with Postgres that are not distributed through Trunk
+const BUILTIN_EXTENSIONS: [&str; 1] = ["plpgsql"];
+
+/// A field of the spec with an invalid value
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ValidationError {
+    /// Path of the field, e.g. `spec.backup.schedule`
+    pub field: String,
+    pub message: String,
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.field, self.message)
+    }
+}
+
+/// Validate the spec of a CoreDB, returning every invalid field.
+///
+/// The length of the resource names derived from the name of the instance, such as the
+/// names of the jobs, is only checked when `name` is provided. The fields that depend on the
+/// namespace of the instance, such as the dedicated networking hostnames, are only checked
+/// when `namespace` is provided. Extension names are checked
+/// against `known_extensions` when it is provided, along with the Trunk projects installed by
+/// the spec, see `known_extension_names`.
+pub fn validate_coredb_spec(
+    spec: &CoreDBSpec,
+    name: Option<&str>,
+    namespace: Option<&str>,
+    known_extensions: Option<&BTreeSet<String>>,
+) -> Result<(), Vec<ValidationError>> {
+    let mut errors = vec![];
+    let mut error =
+        |field: String, message: String| errors.push(ValidationError { field, message });
+
+    for (name, configs) in [
+        ("runtime_config", &spec.runtime_config),
+        ("override_configs", &spec.override_configs),
+    ] {
+        for (i, config) in configs.iter().flatten().enumerate() {
+            if let Some(message) = disallowed_config(config) {
+                error(format!("spec.{name}[{i}]"), message);
+            }
+        }
+    }
+
+    for (name, quantity) in [
+        ("storage", &spec.storage),
+        ("sharedirStorage", &spec.sharedirStorage),
+        ("pkglibdirStorage", &spec.pkglibdirStorage),
+    ] {
+        if !is_valid_quantity(quantity) {
+            error(format!("spec.{name}"), invalid_quantity(quantity));
+        }
+    }
+    for (field, quantity) in resource_quantities("spec.resources", &spec.resources) {
+        if !is_valid_quantity(quantity) {
+            error(field, invalid_quantity(quantity));
+        }
+    }
+    if let Some(autoscaling) = &spec.storage_autoscaling {
+        for (name, quantity) in [
+            ("step", &autoscaling.step),
+            ("maxSize", &autoscaling.max_size),
+        ] {
+            if !is_valid_quantity(quantity) {
+                error(
+                    format!("spec.storageAutoscaling.{name}"),
+                    invalid_quantity(quantity),
+                );
+            }
+        }
+    }
+
+    for (i, extension) in spec.extensions.iter().enumerate() {
+        let field = format!("spec.extensions[{i}]");
+        if !check_input(&extension.name) {
+            error(
+                field,
+                format!("invalid extension name '{}'", extension.name),
+            );
+            continue;
+        }
+        let is_known = |name: &str| {
+            BUILTIN_EXTENSIONS.contains(&name)
+                || spec
+                    .trunk_installs
+                    .iter()
+                    .any(|install| install.name == name)
+                || known_extensions.is_none_or(|known| known.contains(name))
+        };
+        if !is_known(&extension.name) {
+            error(field, format!("unknown extension '{}'", extension.name));
+        }
+    }
+    for (i, install) in spec.trunk_installs.iter().enumerate() {
+        if !check_input(&install.name) {
+            error(
+                format!("spec.trunk_installs[{i}]"),
+                format!("invalid Trunk project name '{}'", install.name),
+            );
+        }
+    }
+
+    if let Some(schedule) = &spec.backup.schedule {
+        if let Err(e) = cron_schedule(schedule) {
+            error(
+                "spec.backup.schedule".to_string(),
+                format!("invalid cron schedule '{schedule}': {}", cron_error(&e)),
+            );
+        }
+    }
+    if spec
+        .backup
+        .retention
+        .as_ref()
+        .is_some_and(BackupRetention::is_empty)
+    {
+        error(
+            "spec.backup.retention".to_string(),
+            "at least one of hourly, daily, weekly and monthly must be set".to_string(),
+        );
+    }
+    if let Some(verification) = &spec.backup.verification {
+        if let Err(e) = cron_schedule(&verification.schedule) {
+            error(
+                "spec.backup.verification.schedule".to_string(),
+                format!(
+                    "invalid cron schedule '{}': {}",
+                    verification.schedule,
+                    cron_error(&e)
+                ),
+            );
+        }
+    }
+
+    if let Some((field, message)) = spec.restore.as_ref().and_then(recovery_target_error) {
+        error(format!("spec.restore.{field}"), message);
+    }
+
+    for (i, entry) in spec.ip_allow_list.iter().flatten().enumerate() {
+        if !is_valid_allow_list_entry(entry) {
+            error(
+                format!("spec.ipAllowList[{i}]"),
+                format!("invalid IP address or CIDR block '{entry}'"),
+            );
+        }
+    }
+    if let Some(network_policy) = &spec.network_policy {
+        for (i, rule) in network_policy.egress.iter().enumerate() {
+            for (j, cidr) in rule.cidrs.iter().enumerate() {
+                if !is_valid_cidr(cidr) {
+                    error(
+                        format!("spec.networkPolicy.egress[{i}].cidrs[{j}]"),
+                        format!("invalid CIDR block '{cidr}'"),
+                    );
+                }
+            }
+        }
+    }
+    if let (Some(dedicated), Some(namespace)) = (&spec.dedicated_networking, namespace) {
+        for (name, hostname) in [
+            ("hostname", &dedicated.hostname),
+            ("standbyHostname", &dedicated.standby_hostname),
+        ] {
+            match hostname {
+                Some(hostname) if !is_allowed_hostname(hostname, namespace, None) => error(
+                    format!("spec.dedicatedNetworking.{name}"),
+                    format!("'{hostname}' must be a single label under '{namespace}.<basedomain>'"),
+                ),
+                _ => {}
+            }
+        }
+    }
+    for (i, role) in spec.roles.iter().enumerate() {
+        if is_reserved_role(&role.name) {
+            error(
+                format!("spec.roles[{i}]"),
+                format!("role '{}' is reserved", role.name),
+            );
+        }
+        for (j, member_of) in role.member_of.iter().enumerate() {
+            if is_reserved_membership(member_of) {
+                error(
+                    format!("spec.roles[{i}].memberOf[{j}]"),
+                    format!("role '{member_of}' is reserved"),
+                );
+            }
+        }
+    }
+    for (i, rule) in spec.hba.iter().enumerate() {
+        if let Err(message) = rule.validate() {
+            error(format!("spec.hba[{i}]"), message);
+        }
+    }
+
+    for (i, appsvc) in spec.app_services.iter().flatten().enumerate() {
+        let field = format!("spec.appServices[{i}]");
+        for (j, env) in appsvc.env.iter().flatten().enumerate() {
+            if !env.has_single_source() {
+                error(
+                    format!("{field}.env[{j}]"),
+                    "exactly one of value, valueFromPlatform, valueFromSecret and valueFromConfigMap must be set".to_string(),
+                );
+            }
+        }
+        if appsvc.replicas.is_some_and(|replicas| replicas < 0) {
+            error(
+                format!("{field}.replicas"),
+                "must not be negative".to_string(),
+            );
+        }
+        if let Some(autoscaling) = &appsvc.autoscaling {
+            if autoscaling.min_replicas < 1 || autoscaling.min_replicas > autoscaling.max_replicas {
+                error(
+                    format!("{field}.autoscaling"),
+                    format!(
+                        "minReplicas must be between 1 and maxReplicas ({})",
+                        autoscaling.max_replicas
+                    ),
+                );
+            }
+        }
+        for (j, middleware) in appsvc.middlewares.iter().flatten().enumerate() {
+            let field = format!("{field}.middlewares[{j}]");
+            match middleware {
+                Middleware::RateLimit(mw)
+                    if mw.config.average < 1 || mw.config.burst.is_some_and(|burst| burst < 1) =>
+                {
+                    error(
+                        format!("{field}.rateLimit"),
+                        "average and burst must be at least 1".to_string(),
+                    )
+                }
+                Middleware::IpAllowList(mw) => {
+                    for entry in mw.config.source_range.iter().flatten() {
+                        if !VALID_IPV4_CIDR.is_match(entry) {
+                            error(
+                                format!("{field}.ipAllowList"),
+                                format!("invalid IPv4 address or CIDR block '{entry}'"),
+                            );
+                        }
+                    }
+                }
+                Middleware::ForwardAuth(mw)
+                    if namespace.is_some_and(|namespace| !mw.config.is_namespace_address(namespace)) =>
+                {
+                    error(
+                        format!("{field}.forwardAuth"),
+                        format!(
+                            "address '{}' must be a service in the namespace, e.g. 'http://<service>.{}.svc:<port>/<path>'",
+                            mw.config.address,
+                            namespace.unwrap_or_default()
+                        ),
+                    )
+                }
+                Middleware::Cors(mw) if mw.config.allow_origins.is_empty() => error(
+                    format!("{field}.cors"),
+                    "allowOrigins must not be empty".to_string(),
+                ),
+                Middleware::RequestBodyLimit(mw) if mw.config.max_request_body_bytes < 1 => error(
+                    format!("{field}.requestBodyLimit"),
+                    "maxRequestBodyBytes must be at least 1".to_string(),
+                ),
+                _ => {}
+            }
+        }
+        if let Some(pdb) = &appsvc.pod_disruption_budget {
+            if pdb.min_available.is_some() && pdb.max_unavailable.is_some() {
+                error(
+                    format!("{field}.podDisruptionBudget"),
+                    "only one of minAvailable and maxUnavailable can be set".to_string(),
+                );
+            }
+        }
+    }
+
+    for i in 0..spec.jobs.len() {
+        for (field, message) in app_job_errors(&spec.jobs, i, name) {
+            error(format!("spec.jobs[{i}]{field}"), message);
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+/// Validate a CoreDB, looking up the known extensions in the trunk metadata configmap of its
+/// namespace. The extension names are not checked if the configmap is not available.
+pub async fn validate_coredb(cdb: &CoreDB, client: Client) -> Result<(), Vec<ValidationError>> {
+    let known_extensions = match cdb.metadata.namespace.as_deref() {
+        Some(namespace) => known_extension_names(client, namespace).await,
+        None => None,
+    };
+    validate_coredb_spec(
+        &cdb.spec,
+        cdb.metadata.name.as_deref(),
+        cdb.metadata.namespace.as_deref(),
+        known_extensions.as_ref(),
+    )
+}
+
+fn disallowed_config(config: &PgConfig) -> Option<String> {
+    DISALLOWED_CONFIGS
+        .contains(&config.name.as_str())
+        .then(|| format!("{} can not be configured", config.name))
+}
+
+// The errors of the cron crate point at the invalid term over several lines, the last one
+// has the reason
+pub(crate) fn cron_error(error: &cron::error::Error) -> String {
+    let error = error.to_string();
+    error.lines().last().unwrap_or_default().to_string()
+}
+
+fn resource_quantities<'a>(
+    field: &str,
+    resources: &'a ResourceRequirements,
+) -> Vec<(String, &'a Quantity)> {
+    [
+        ("requests", &resources.requests),
+        ("limits", &resources.limits),
+    ]
+    .into_iter()
+    .flat_map(|(name, quantities)| {
+        quantities
+            .iter()
+            .flatten()
+            .map(move |(resource, quantity)| (format!("{field}.{name}.{resource}"), quantity))
+    })
+    .collect()
+}
+
+fn is_valid_quantity(quantity: &Quantity) -> bool {
+    VALID_QUANTITY.is_match(&quantity.0)
+}
+
+fn invalid_quantity(quantity: &Quantity) -> String {
+    format!("invalid quantity '{}'", quantity.0)
+}
+
+// The ingress routes only accept IPv4 addresses and CIDR blocks, IPv6 is only supported by
+// dedicated networking
+fn is_valid_allow_list_entry(entry: &str) -> bool {
+    VALID_IPV4_CIDR.is_match(entry)
+        || entry.parse::<std::net::Ipv6Addr>().is_ok()
+        || (entry.contains(':') && is_valid_cidr(entry))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        apis::{
+            coredb_types::{HbaConnectionType, HbaMethod, HbaRule},
+            postgres_parameters::ConfigValue,
+        },
+        extensions::types::{Extension, TrunkInstall},
+    };
+    use std::collections::BTreeMap;
+
+    // A spec with the defaults applied when deserializing
+    fn default_spec() -> CoreDBSpec {
+        serde_json::from_str("{}").unwrap()
+    }
+
+    #[test]
+    fn test_validate_default_spec() {
+        assert_eq!(
+            validate_coredb_spec(&default_spec(), Some("test"), Some("default"), None),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn test_validate_coredb_spec() {
+        let mut spec = CoreDBSpec {
+            storage: Quantity("10 Gb".to_string()),
+            runtime_config: Some(vec![
+                PgConfig {
+                    name: "work_mem".to_string(),
+                    value: ConfigValue::Single("64MB".to_string()),
+                },
+                PgConfig {
+                    name: "data_directory".to_string(),
+                    value: ConfigValue::Single("/tmp".to_string()),
+                },
+            ]),
+            extensions: vec![
+                Extension {
+                    name: "pgmq".to_string(),
+                    description: None,
+                    locations: vec![],
+                },
+                Extension {
+                    name: "not_an_extension".to_string(),
+                    description: None,
+                    locations: vec![],
+                },
+                Extension {
+                    name: "custom".to_string(),
+                    description: None,
+                    locations: vec![],
+                },
+            ],
+            trunk_installs: vec![TrunkInstall {
+                name: "custom".to_string(),
+                version: None,
+            }],
+            ip_allow_list: Some(vec![
+                "10.0.0.0/8".to_string(),
+                "2001:db8::/32".to_string(),
+                "10.0.0.256".to_string(),
+            ]),
+            hba: vec![HbaRule {
+                r#type: HbaConnectionType::Host,
+                database: "all".to_string(),
+                user: "all".to_string(),
+                address: Some("0.0.0.0/0".to_string()),
+                method: HbaMethod::Trust,
+                options: None,
+            }],
+            ..default_spec()
+        };
+        spec.resources.limits = Some(BTreeMap::from([(
+            "memory".to_string(),
+            Quantity("1GB".to_string()),
+        )]));
+        spec.backup.schedule = Some("every day".to_string());
+        spec.backup.retention = Some(BackupRetention::default());
+        spec.roles = serde_json::from_value(serde_json::json!([
+            {"name": "postgres"},
+            {"name": "reporting", "memberOf": ["pg_read_all_stats", "pg_write_server_files"]},
+        ]))
+        .unwrap();
+        spec.dedicated_networking = Some(
+            serde_json::from_value(serde_json::json!({
+                "enabled": true,
+                "hostname": "db.org-test.tembo.io",
+                "standbyHostname": "db.org-other.tembo.io",
+            }))
+            .unwrap(),
+        );
+        spec.restore = Some(
+            serde_json::from_value(serde_json::json!({
+                "serverName": "test-source",
+                "recoveryTargetXid": "latest",
+            }))
+            .unwrap(),
+        );
+        spec.jobs = serde_json::from_value(serde_json::json!([
+            {
+                "name": "vacuum",
+                "image": "postgres:16",
+                "run": "Schedule",
+                "schedule": "0 0 3 * * *",
+            },
+            {
+                "name": "Migrate_DB",
+                "image": "quay.io/myorg/migrations:v2",
+                "env": [{"name": "DATABASE_URL"}],
+            },
+            {
+                "name": "refresh-the-materialized-views-of-the-monthly-reports",
+                "image": "postgres:16",
+                "run": "Schedule",
+                "schedule": "0 * * * *",
+            },
+        ]))
+        .unwrap();
+        spec.app_services = Some(vec![serde_json::from_value(serde_json::json!({
+            "name": "postgrest",
+            "image": "postgrest/postgrest:v12.2.8",
+            "autoscaling": {"minReplicas": 3, "maxReplicas": 2},
+            "env": [
+                {"name": "API_KEY", "valueFromSecret": {"name": "openai", "key": "api_key"}},
+                {"name": "MISSING"},
+            ],
+            "middlewares": [
+                {"rateLimit": {"name": "limit", "config": {"average": 0}}},
+                {"ipAllowList": {"name": "allow", "config": {"sourceRange": ["10.0.0.0/33"]}}},
+                {"forwardAuth": {"name": "auth", "config": {"address": "http://auth.org-other.svc/verify"}}},
+            ],
+        }))
+        .unwrap()]);
+
+        let known = BTreeSet::from(["pgmq".to_string()]);
+        let errors: Vec<String> =
+            validate_coredb_spec(&spec, Some("test"), Some("org-test"), Some(&known))
+                .unwrap_err()
+                .iter()
+                .map(ToString::to_string)
+                .collect();
+        assert_eq!(errors.len(), 21, "{errors:?}");
+        assert!(errors
+            .contains(&"spec.runtime_config[1]: data_directory can not be configured".to_string()));
+        assert!(errors.contains(&"spec.storage: invalid quantity '10 Gb'".to_string()));
+        assert!(
+            errors.contains(&"spec.resources.limits.memory: invalid quantity '1GB'".to_string())
+        );
+        assert!(errors
+            .contains(&"spec.extensions[1]: unknown extension 'not_an_extension'".to_string()));
+        assert!(errors.iter().any(|e| e
+            .starts_with("spec.backup.schedule: invalid cron schedule 'every day': ")
+            && !e.contains('\n')));
+        assert!(errors.contains(
+            &"spec.ipAllowList[2]: invalid IP address or CIDR block '10.0.0.256'".to_string()
+        ));
+        assert!(errors
+            .contains(&"spec.hba[0]: trust is only allowed for local connections".to_string()));
+        assert!(errors.contains(
+            &"spec.appServices[0].autoscaling: minReplicas must be between 1 and maxReplicas (2)"
+                .to_string()
+        ));
+        assert!(errors.contains(
+            &"spec.jobs[0].schedule: invalid cron schedule '0 0 3 * * *': expected 5 fields"
+                .to_string()
+        ));
+        assert!(errors.contains(
+            &"spec.jobs[1].name: 'Migrate_DB' must consist of lowercase alphanumeric characters or '-', and start and end with an alphanumeric character"
+                .to_string()
+        ));
+        assert!(errors.contains(
+            &"spec.jobs[1].env[0]: exactly one of value, valueFromPlatform, valueFromSecret and valueFromConfigMap must be set"
+                .to_string()
+        ));
+        assert!(errors.contains(
+            &"spec.jobs[2].name: 'test-refresh-the-materialized-views-of-the-monthly-reports' must be at most 52 characters"
+                .to_string()
+        ));
+        assert!(errors.contains(
+            &"spec.appServices[0].env[1]: exactly one of value, valueFromPlatform, valueFromSecret and valueFromConfigMap must be set"
+                .to_string()
+        ));
+        assert!(errors.contains(
+            &"spec.appServices[0].middlewares[0].rateLimit: average and burst must be at least 1"
+                .to_string()
+        ));
+        assert!(errors.contains(
+            &"spec.appServices[0].middlewares[1].ipAllowList: invalid IPv4 address or CIDR block '10.0.0.0/33'"
+                .to_string()
+        ));
+
+        assert!(errors.contains(
+            &"spec.appServices[0].middlewares[2].forwardAuth: address 'http://auth.org-other.svc/verify' must be a service in the namespace, e.g. 'http://<service>.org-test.svc:<port>/<path>'"
+                .to_string()
+        ));
+
+        assert!(errors.contains(&"spec.roles[0]: role 'postgres' is reserved".to_string()));
+        assert!(errors.contains(
+            &"spec.roles[1].memberOf[1]: role 'pg_write_server_files' is reserved".to_string()
+        ));
+        assert!(errors.contains(
+            &"spec.dedicatedNetworking.standbyHostname: 'db.org-other.tembo.io' must be a single label under 'org-test.<basedomain>'"
+                .to_string()
+        ));
+        assert!(errors.contains(
+            &"spec.backup.retention: at least one of hourly, daily, weekly and monthly must be set"
+                .to_string()
+        ));
+        assert!(errors.contains(
+            &"spec.restore.recoveryTargetXid: invalid transaction ID 'latest'".to_string()
+        ));
+
+        // Extensions are not checked without the known extensions
+        let errors = validate_coredb_spec(&spec, Some("test"), Some("org-test"), None).unwrap_err();
+        assert_eq!(errors.len(), 20);
+        // Nor the length of the job names without the name of the instance
+        let errors = validate_coredb_spec(&spec, None, Some("org-test"), None).unwrap_err();
+        assert_eq!(errors.len(), 19);
+    }
+}
@@ -315,27 +315,23 @@ mod test {
             .unwrap();
         println!("Sending request to '{}'", url);
         for i in 1..retries {
-            let response = httpclient.get(url).send().await;
-            if response.is_err() {
-                tokio::time::sleep(Duration::from_secs(delay as u64)).await;
-                println!(
-                    "Retry {}/{} request -- error: {}",
-                    i,
-                    retries,
-                    response.err().unwrap()
-                );
-            } else {
-                let resp = response.unwrap();
-                if resp.status() == 200 {
-                    return Ok(resp);
-                } else {
+            match httpclient.get(url).send().await {
+                Err(e) => {
                     tokio::time::sleep(Duration::from_secs(delay as u64)).await;
-                    println!(
-                        "Retry {}/{} request -- status: {}",
-                        i,
-                        retries,
-                        resp.status()
-                    );
+                    println!("Retry {}/{} request -- error: {}", i, retries, e);
+                }
+                Ok(resp) => {
+                    if resp.status() == 200 {
+                        return Ok(resp);
+                    } else {
+                        tokio::time::sleep(Duration::from_secs(delay as u64)).await;
+                        println!(
+                            "Retry {}/{} request -- status: {}",
+                            i,
+                            retries,
+                            resp.status()
+                        );
+                    }
                 }
             }
         }
@@ -637,8 +633,7 @@ mod test {
 
         match result {
             Ok(_ok) => Ok(()),
-            Err(_) => Err(kube::Error::ReadEvents(std::io::Error::new(
-                std::io::ErrorKind::Other,
+            Err(_) => Err(kube::Error::ReadEvents(std::io::Error::other(
                 "Timed out waiting for status.running to become false",
             ))),
         }
@@ -862,8 +857,7 @@ mod test {
                 name, extension, max_retries
             );
         }
-        Err(kube::Error::ReadEvents(std::io::Error::new(
-            std::io::ErrorKind::Other,
+        Err(kube::Error::ReadEvents(std::io::Error::other(
             "Timed out waiting for extension to be enabled",
         )))
     }
@@ -4646,8 +4640,8 @@ CREATE EVENT TRIGGER pgrst_watch
 
             while Utc::now().signed_duration_since(started_waiting) <= max_wait_time {
                 let coredb = coredbs.get(name).await.expect("spec not found");
-                if coredb.status.is_some() {
-                    return coredb.status.unwrap().runtime_config.clone();
+                if let Some(status) = coredb.status {
+                    return status.runtime_config;
                 }
                 tokio::time::sleep(Duration::from_secs(2)).await;
             }
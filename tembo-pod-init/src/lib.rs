@@ -4,4 +4,5 @@ pub mod health;
 pub mod metrics;
 pub mod mutate;
 pub mod telemetry;
+pub mod validate;
 pub mod watcher;
@@ -5,7 +5,8 @@ use opentelemetry::global;
 use parking_lot::Mutex;
 use std::sync::Arc;
 use tembo_pod_init::{
-    config::Config, health::*, metrics, mutate::mutate, telemetry, watcher::NamespaceWatcher,
+    config::Config, health::*, metrics, mutate::mutate, telemetry, validate::validate,
+    watcher::NamespaceWatcher,
 };
 use tracing::*;
 use tracing_actix_web::{DefaultRootSpanBuilder, TracingLogger};
@@ -81,6 +82,7 @@ async fn main() -> std::io::Result<()> {
                     .service(liveness)
                     .service(readiness)
                     .service(mutate)
+                    .service(validate)
                     .service(metrics::metrics)
             }
         }
@@ -0,0 +1,194 @@
+use crate::metrics;
+use actix_web::{post, web, HttpResponse, Responder};
+use controller::{
+    apis::coredb_types::CoreDB,
+    validation::{known_extension_names, validate_coredb_spec, ValidationError},
+};
+use kube::core::{
+    admission::{AdmissionRequest, AdmissionResponse, AdmissionReview, Operation},
+    TypeMeta,
+};
+use kube::Client;
+use std::collections::{BTreeSet, HashSet};
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::RwLock;
+use tracing::*;
+
+#[instrument(skip(client, body), fields(trace_id))]
+#[post("/validate")]
+async fn validate(
+    body: web::Json<AdmissionReview<CoreDB>>,
+    namespaces: web::Data<Arc<RwLock<HashSet<String>>>>,
+    client: web::Data<Arc<Client>>,
+    trace_id: web::Data<String>,
+) -> impl Responder {
+    let start_time = Instant::now();
+
+    // Set trace_id for logging
+    Span::current().record("trace_id", field::display(&trace_id.as_ref()));
+
+    let resource = "CoreDB";
+    let admission_request: AdmissionRequest<CoreDB> = match body.into_inner().request {
+        Some(request) => request,
+        None => {
+            metrics::increment_error_counter("unknown", "missing_request");
+            return HttpResponse::BadRequest().body("expected AdmissionRequest");
+        }
+    };
+    let namespace = admission_request
+        .namespace
+        .clone()
+        .unwrap_or_else(|| "unknown".to_string());
+    let operation_str = format!("{:?}", admission_request.operation);
+
+    let cdb = match &admission_request.object {
+        Some(cdb)
+            if admission_request.operation != Operation::Delete
+                && cdb.metadata.deletion_timestamp.is_none()
+                && namespaces.read().await.contains(&namespace) =>
+        {
+            cdb
+        }
+        _ => {
+            metrics::increment_request_counter(&namespace, &operation_str, resource, "skipped");
+            return admission_review(
+                &admission_request,
+                AdmissionResponse::from(&admission_request),
+            );
+        }
+    };
+
+    let known_extensions =
+        known_extension_names(client.as_ref().as_ref().clone(), &namespace).await;
+    let errors = new_validation_errors(
+        cdb,
+        admission_request.old_object.as_ref(),
+        &namespace,
+        known_extensions.as_ref(),
+    );
+
+    let response = if errors.is_empty() {
+        metrics::increment_request_counter(&namespace, &operation_str, resource, "allowed");
+        AdmissionResponse::from(&admission_request)
+    } else {
+        let message = errors
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join("; ");
+        info!(
+            "Denying CoreDB {} in namespace {}: {}",
+            admission_request.name, namespace, message
+        );
+        metrics::increment_request_counter(&namespace, &operation_str, resource, "denied");
+        AdmissionResponse::from(&admission_request).deny(format!("invalid CoreDB spec: {message}"))
+    };
+
+    let duration = start_time.elapsed().as_secs_f64();
+    metrics::observe_request_duration(&namespace, &operation_str, resource, duration);
+    admission_review(&admission_request, response)
+}
+
+// The validation errors of a CoreDB that its previous version did not have. Objects admitted
+// before the webhook was deployed can still be updated, e.g. to remove their finalizers.
+fn new_validation_errors(
+    cdb: &CoreDB,
+    old_cdb: Option<&CoreDB>,
+    namespace: &str,
+    known_extensions: Option<&BTreeSet<String>>,
+) -> Vec<ValidationError> {
+    let errors = validate_coredb_spec(
+        &cdb.spec,
+        cdb.metadata.name.as_deref(),
+        Some(namespace),
+        known_extensions,
+    )
+    .err()
+    .unwrap_or_default();
+    let old_errors = old_cdb
+        .and_then(|old_cdb| {
+            validate_coredb_spec(
+                &old_cdb.spec,
+                old_cdb.metadata.name.as_deref(),
+                Some(namespace),
+                known_extensions,
+            )
+            .err()
+        })
+        .unwrap_or_default();
+    errors
+        .into_iter()
+        .filter(|error| !old_errors.contains(error))
+        .collect()
+}
+
+fn admission_review(
+    admission_request: &AdmissionRequest<CoreDB>,
+    response: AdmissionResponse,
+) -> HttpResponse {
+    debug!("AdmissionResponse: {:?}", response);
+    HttpResponse::Ok().json(AdmissionReview {
+        response: Some(response),
+        request: Some(admission_request.clone()),
+        types: TypeMeta {
+            api_version: "admission.k8s.io/v1".to_string(),
+            kind: "AdmissionReview".to_string(),
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use controller::apis::postgres_parameters::{ConfigValue, PgConfig};
+
+    fn coredb(runtime_config: Vec<(&str, &str)>) -> CoreDB {
+        let mut cdb = CoreDB::new("test", serde_json::from_str("{}").unwrap());
+        cdb.spec.runtime_config = Some(
+            runtime_config
+                .into_iter()
+                .map(|(name, value)| PgConfig {
+                    name: name.to_string(),
+                    value: ConfigValue::Single(value.to_string()),
+                })
+                .collect(),
+        );
+        cdb
+    }
+
+    #[test]
+    fn test_new_validation_errors() {
+        let valid = coredb(vec![("work_mem", "64MB")]);
+        let invalid = coredb(vec![("data_directory", "/tmp")]);
+
+        assert!(new_validation_errors(&valid, None, "default", None).is_empty());
+        let errors = new_validation_errors(&invalid, None, "default", None);
+        assert_eq!(
+            errors[0].to_string(),
+            "spec.runtime_config[0]: data_directory can not be configured"
+        );
+        assert_eq!(
+            new_validation_errors(&invalid, Some(&valid), "default", None),
+            errors
+        );
+
+        // Updates of objects that were already invalid are not rejected for the same errors
+        assert!(new_validation_errors(&invalid, Some(&invalid), "default", None).is_empty());
+
+        // The names of the resources of the instance are checked with its name
+        let mut long_job_name = valid.clone();
+        long_job_name.spec.jobs = serde_json::from_value(serde_json::json!([{
+            "name": "refresh-the-materialized-views-of-the-monthly-reports",
+            "image": "postgres:16",
+            "run": "Schedule",
+            "schedule": "0 * * * *",
+        }]))
+        .unwrap();
+        let errors = new_validation_errors(&long_job_name, None, "default", None);
+        assert_eq!(
+            errors[0].to_string(),
+            "spec.jobs[0].name: 'test-refresh-the-materialized-views-of-the-monthly-reports' must be at most 52 characters"
+        );
+    }
+}